@@ -0,0 +1,314 @@
+// Archives: several files compressed into one. Each file is cut into
+// content-defined chunks (see huffman::chunk) and a chunk is stored once
+// however many files, or places in one file, have it, so copies and
+// versions of a file cost little more than one of them.
+//
+//   magic "\x89CAR\n", then a format version (u8)
+//   chunk count (u32 LE), then each chunk's length (u32 LE)
+//   entry count (u32 LE)
+//   index, per entry:
+//     name length (u32 LE) + name, UTF-8, relative, '/' between components
+//     original size (u64 LE)
+//     CRC-32 of the original (u32 LE)
+//     compressed size (u64 LE)
+//     chunk count (u32 LE), then the number of each of its chunks (u32 LE)
+//   the entries' segments, in index order, each a whole hz file with the
+//   attributes of the file it was made from, as compress records them
+//
+// Chunks are numbered in the order they first turn up, and an entry's
+// segment is the chunks it was the first to have, in that order. An entry
+// of its own makes one big segment, so it compresses as well as it would
+// alone. Version 1 had no chunks: each entry's compressed data was the
+// whole file, which reads as a segment of one chunk.
+//
+// This is only the layout: reading files in, and writing them out with
+// their names checked, is up to whoever has the files.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+use huffman::chunk::{self, MAX_CHUNK};
+use huffman::crc32::crc32;
+
+pub const MAGIC: [u8; 5] = *b"\x89CAR\n";
+const VERSION: u8 = 2;
+// The fixed-size fields of an index entry, after its name
+const ENTRY_FIELDS: usize = 8 + 4 + 8;
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub size: u64,
+    pub checksum: u32,
+    pub compressed_size: u64,
+    pub chunks: Vec<u32>,
+}
+
+impl Entry {
+    // The original data, from the archive's chunks
+    pub fn data(&self, chunks: &[&[u8]]) -> Vec<u8> {
+        self.chunks.iter().flat_map(|&n| chunks[n as usize]).copied().collect()
+    }
+}
+
+// The entries, each with its segment's compressed data, and the length of
+// each chunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index<'a> {
+    pub entries: Vec<(Entry, &'a [u8])>,
+    pub chunk_lens: Vec<u64>,
+}
+
+impl Index<'_> {
+    // Every chunk, cut from the entries' decoded segments. A segment that
+    // isn't as long as its chunks add up to names its entry as corrupt
+    pub fn chunks<'s>(&self, segments: &'s [Vec<u8>]) -> std::io::Result<Vec<&'s [u8]>> {
+        let mut lens = self.chunk_lens.iter();
+        let mut chunks = Vec::with_capacity(self.chunk_lens.len());
+        for ((entry, _), segment) in self.entries.iter().zip(segments) {
+            let mut rest = &segment[..];
+            for _ in 0..new_chunks(entry, chunks.len()) {
+                let len = *lens.next().unwrap() as usize;
+                let (chunk, tail) = rest.split_at_checked(len).ok_or_else(|| invalid(format!("{} is corrupt", entry.name)))?;
+                chunks.push(chunk);
+                rest = tail;
+            }
+            if !rest.is_empty() {
+                return Err(invalid(format!("{} is corrupt", entry.name)));
+            }
+        }
+        Ok(chunks)
+    }
+}
+
+// How many of an entry's chunks it was the first to have, given how many
+// entries before it had
+fn new_chunks(entry: &Entry, earlier: usize) -> usize {
+    entry.chunks.iter().fold(earlier, |next, &n| if n as usize == next { next + 1 } else { next }) - earlier
+}
+
+// Inputs cut into chunks and numbered, each distinct chunk once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunked<'a> {
+    pub chunks: Vec<&'a [u8]>,
+    // Each input's chunk numbers
+    pub entries: Vec<Vec<u32>>,
+    // Where each input's segment starts in `chunks`
+    starts: Vec<usize>,
+}
+
+impl<'a> Chunked<'a> {
+    pub fn new(contents: &[&'a [u8]]) -> Chunked<'a> {
+        let mut numbers = HashMap::new();
+        let mut chunked = Chunked { chunks: Vec::new(), entries: Vec::new(), starts: Vec::new() };
+        for data in contents {
+            chunked.starts.push(chunked.chunks.len());
+            let entry = chunk::split(data)
+                .into_iter()
+                .map(|c| {
+                    *numbers.entry(c).or_insert_with(|| {
+                        chunked.chunks.push(c);
+                        chunked.chunks.len() as u32 - 1
+                    })
+                })
+                .collect();
+            chunked.entries.push(entry);
+        }
+        chunked
+    }
+
+    // The chunks input `i` was the first to have, which is what gets
+    // compressed of it
+    pub fn segment(&self, i: usize) -> Vec<u8> {
+        let end = self.starts.get(i + 1).copied().unwrap_or(self.chunks.len());
+        self.chunks[self.starts[i]..end].concat()
+    }
+}
+
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+// An archive of each named original and its compressed segment of
+// `chunked`, which the originals were cut into
+pub fn write(entries: &[(&str, &[u8], Vec<u8>)], chunked: &Chunked) -> Vec<u8> {
+    let mut output = MAGIC.to_vec();
+    output.push(VERSION);
+    output.extend_from_slice(&(chunked.chunks.len() as u32).to_le_bytes());
+    for chunk in &chunked.chunks {
+        output.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    }
+    output.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for ((name, data, compressed), chunks) in entries.iter().zip(&chunked.entries) {
+        output.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        output.extend_from_slice(name.as_bytes());
+        output.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        output.extend_from_slice(&crc32(data).to_le_bytes());
+        output.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+        output.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+        for n in chunks {
+            output.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+    for (_, _, compressed) in entries {
+        output.extend_from_slice(compressed);
+    }
+    output
+}
+
+// The index, checked to hold together: every chunk number in the table,
+// numbered in order, and adding up to its entry's size
+pub fn read_index(data: &[u8]) -> std::io::Result<Index<'_>> {
+    let truncated = || invalid("archive is truncated".to_string());
+    let rest = data.strip_prefix(&MAGIC[..]).ok_or_else(|| invalid("not an archive".to_string()))?;
+    let (&version, mut rest) = rest.split_first().ok_or_else(truncated)?;
+    if !(1..=VERSION).contains(&version) {
+        return Err(invalid(format!("unknown archive version {}, possibly written by a newer version", version)));
+    }
+    let mut take = |n: usize| -> std::io::Result<&[u8]> {
+        let (field, tail) = rest.split_at_checked(n).ok_or_else(truncated)?;
+        rest = tail;
+        Ok(field)
+    };
+    let mut chunk_lens = Vec::new();
+    if version > 1 {
+        for _ in 0..u32::from_le_bytes(take(4)?.try_into().unwrap()) {
+            let len = u32::from_le_bytes(take(4)?.try_into().unwrap());
+            if !(1..=MAX_CHUNK as u32).contains(&len) {
+                return Err(invalid(format!("bad chunk length {}", len)));
+            }
+            chunk_lens.push(len as u64);
+        }
+    }
+    let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let mut entries = Vec::new();
+    // The chunk the next new one has to be
+    let mut next = 0;
+    for _ in 0..count {
+        let name_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let name = String::from_utf8(take(name_len)?.to_vec()).map_err(|_| invalid("entry name is not UTF-8".to_string()))?;
+        let fields = take(ENTRY_FIELDS)?;
+        let mut entry = Entry {
+            name,
+            size: u64::from_le_bytes(fields[..8].try_into().unwrap()),
+            checksum: u32::from_le_bytes(fields[8..12].try_into().unwrap()),
+            compressed_size: u64::from_le_bytes(fields[12..].try_into().unwrap()),
+            chunks: Vec::new(),
+        };
+        if version == 1 {
+            if entry.size > 0 {
+                entry.chunks.push(chunk_lens.len() as u32);
+                chunk_lens.push(entry.size);
+            }
+            next = chunk_lens.len();
+            entries.push(entry);
+            continue;
+        }
+        let mut size = 0u64;
+        for _ in 0..u32::from_le_bytes(take(4)?.try_into().unwrap()) {
+            let n = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            match n {
+                n if n == next && n < chunk_lens.len() => next += 1,
+                n if n < next => {}
+                _ => return Err(invalid(format!("{} has a chunk out of order", entry.name))),
+            }
+            entry.chunks.push(n as u32);
+            size += chunk_lens[n];
+        }
+        if size != entry.size {
+            return Err(invalid(format!("{}'s chunks don't add up to its size", entry.name)));
+        }
+        entries.push(entry);
+    }
+    if next != chunk_lens.len() {
+        return Err(invalid("chunks no entry has".to_string()));
+    }
+    let mut indexed = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let len = usize::try_from(entry.compressed_size).map_err(|_| truncated())?;
+        let compressed = take(len)?;
+        indexed.push((entry, compressed));
+    }
+    if !rest.is_empty() {
+        return Err(invalid("data after the last entry".to_string()));
+    }
+    Ok(Index { entries: indexed, chunk_lens })
+}
+
+// A line per entry, under a heading, as gzip -l does: compressed and
+// original sizes, the space saved, the CRC-32 and the name. An entry's
+// compressed size is its segment's, so chunks it shares with earlier
+// entries come free
+pub fn listing(data: &[u8]) -> std::io::Result<String> {
+    let mut listing = format!("{:>12} {:>12} {:>6}  {:8}  name\n", "compressed", "uncompressed", "saved", "crc32");
+    for (entry, _) in read_index(data)?.entries {
+        let saved = match entry.size {
+            0 => 0.0,
+            size => 100.0 * (1.0 - entry.compressed_size as f64 / size as f64),
+        };
+        listing += &format!("{:>12} {:>12} {:>5.1}%  {:08x}  {}\n", entry.compressed_size, entry.size, saved, entry.checksum, entry.name);
+    }
+    Ok(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Noise, which no coding shrinks, so only sharing chunks can
+    fn noise(len: usize, mut state: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shared_chunks_are_stored_once() {
+        let a = noise(200_000, 1);
+        // a with a few bytes put in near the middle, and a again
+        let b = [&a[..100_000], b"inserted", &a[100_000..]].concat();
+        let chunked = Chunked::new(&[&a, &b, &a]);
+        let segments: Vec<_> = (0..3).map(|i| chunked.segment(i)).collect();
+        assert_eq!(segments[0], a);
+        assert!(segments[1].len() < 3 * MAX_CHUNK, "{}", segments[1].len());
+        assert!(segments[2].is_empty());
+        assert_eq!(chunked.entries[2], chunked.entries[0]);
+
+        // Stored, as an hz file's payload would be
+        let entries: Vec<_> = [("a", &a), ("b", &b), ("c", &a)]
+            .iter()
+            .zip(&segments)
+            .map(|(&(name, data), segment)| (name, &data[..], segment.clone()))
+            .collect();
+        let archive = write(&entries, &chunked);
+        let index = read_index(&archive).unwrap();
+        let chunks = index.chunks(&segments).unwrap();
+        for ((entry, _), data) in index.entries.iter().zip([&a, &b, &a]) {
+            assert!(entry.data(&chunks) == *data, "{}", entry.name);
+        }
+        assert!(index.chunks(&[segments[0].clone(), segments[1][1..].to_vec(), Vec::new()]).is_err());
+    }
+
+    #[test]
+    fn chunk_numbers_are_checked() {
+        let chunked = Chunked::new(&[b"ab"]);
+        let archive = write(&[("a", b"ab", Vec::new())], &chunked);
+        assert!(read_index(&archive).is_ok());
+        // The one chunk numbered 1, past the table
+        let mut bad = archive.clone();
+        let at = bad.len() - 4;
+        bad[at] = 1;
+        assert!(read_index(&bad).is_err());
+        // The chunk 1 byte long where the entry is 2
+        let mut bad = archive;
+        bad[10] = 1;
+        assert!(read_index(&bad).is_err());
+    }
+}
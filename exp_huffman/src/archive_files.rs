@@ -0,0 +1,176 @@
+// Archives (see test_huffman::archive) made from files, and extracted to
+// them with the attributes each entry recorded.
+//
+// Names are checked on the way in and again on the way out, so an archive
+// can't write outside the directory it is extracted into, whoever made it,
+// nor write one file twice.
+
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::io::{Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+use huffman::crc32::crc32;
+use rayon::prelude::*;
+use test_huffman::archive::{self, read_index, Chunked, Entry, Index};
+use test_huffman::attributes::Attributes;
+use test_huffman::Format;
+
+use crate::files;
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+// The name a file is archived under: its path with any root and "." left
+// out. Paths that climb out with ".." are refused rather than guessed at
+fn entry_name(path: &str) -> std::io::Result<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(
+                part.to_str()
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{} is not UTF-8, which archive names must be", path)))?,
+            ),
+            Component::ParentDir => {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("{} reaches outside the current directory", path)));
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    if parts.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{} names no file", path)));
+    }
+    Ok(parts.join("/"))
+}
+
+// Where an entry goes under `dir`, if its name is a plain relative path
+fn entry_path(dir: &Path, name: &str) -> std::io::Result<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for part in name.split('/') {
+        if part.is_empty() || part == "." || part == ".." || part.contains(['\\', ':']) {
+            return Err(invalid(format!("bad entry name {:?}", name)));
+        }
+        path.push(part);
+    }
+    Ok(path)
+}
+
+// The first name that lands on the same file as an earlier one. Windows
+// and macOS file systems ignore case, so there "A" and "a" are one file
+fn duplicate<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<(&'a str, &'a str)> {
+    let mut seen = HashMap::new();
+    for name in names {
+        let key = match cfg!(any(windows, target_os = "macos")) {
+            true => name.to_lowercase(),
+            false => name.to_string(),
+        };
+        if let Some(earlier) = seen.insert(key, name) {
+            return Some((earlier, name));
+        }
+    }
+    None
+}
+
+// Reads each input and compresses its segment, the chunks no earlier input
+// had, with `compress`, all of them at once. `compress` gets the input's
+// metadata too, for its attributes
+pub fn create(
+    inputs: &[String],
+    compress: impl Fn(&[u8], Option<&Metadata>) -> std::io::Result<Vec<u8>> + Sync,
+) -> std::io::Result<Vec<u8>> {
+    let names = inputs.iter().map(|path| entry_name(path)).collect::<std::io::Result<Vec<_>>>()?;
+    if let Some((earlier, name)) = duplicate(names.iter().map(String::as_str)) {
+        let msg = match earlier == name {
+            true => format!("{} is given twice", name),
+            false => format!("{} and {} would be extracted to the same file", earlier, name),
+        };
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    let contents = inputs.iter().map(|path| files::read_file(path)).collect::<std::io::Result<Vec<_>>>()?;
+    let chunked = Chunked::new(&contents.iter().map(|(data, _)| data.as_slice()).collect::<Vec<_>>());
+    let compressed = contents
+        .par_iter()
+        .enumerate()
+        .map(|(i, (_, metadata))| compress(&chunked.segment(i), metadata.as_ref()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let entries: Vec<_> = names
+        .iter()
+        .zip(&contents)
+        .zip(compressed)
+        .map(|((name, (data, _)), compressed)| (name.as_str(), data.as_slice(), compressed))
+        .collect();
+    Ok(archive::write(&entries, &chunked))
+}
+
+// Every entry's original data, checked against its size and CRC-32, and
+// the attributes it recorded. Entries share chunks, so their segments are
+// all decoded first
+fn decode(index: &Index) -> std::io::Result<Vec<(Vec<u8>, Option<Attributes>)>> {
+    let (segments, attributes): (Vec<_>, Vec<_>) = index
+        .entries
+        .par_iter()
+        .map(|(entry, compressed)| {
+            crate::decompress_contents(compressed, Format::Hz, None).map_err(|e| invalid(format!("{}: {}", entry.name, e)))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    let chunks = index.chunks(&segments)?;
+    let mut decoded = Vec::with_capacity(index.entries.len());
+    for ((entry, _), attributes) in index.entries.iter().zip(attributes) {
+        let original = entry.data(&chunks);
+        if original.len() as u64 != entry.size || crc32(&original) != entry.checksum {
+            return Err(invalid(format!("{} is corrupt", entry.name)));
+        }
+        decoded.push((original, attributes));
+    }
+    Ok(decoded)
+}
+
+// Where each entry goes under `dir`, if every name is safe and no two
+// land on the same file
+fn entry_paths(dir: &Path, entries: &[(Entry, &[u8])]) -> std::io::Result<Vec<PathBuf>> {
+    let paths = entries.iter().map(|(entry, _)| entry_path(dir, &entry.name)).collect::<std::io::Result<Vec<_>>>()?;
+    if let Some((earlier, name)) = duplicate(entries.iter().map(|(entry, _)| entry.name.as_str())) {
+        return Err(invalid(format!("entries {:?} and {:?} name the same file", earlier, name)));
+    }
+    Ok(paths)
+}
+
+// Checks what extract would, writing nothing
+pub fn verify(data: &[u8]) -> std::io::Result<()> {
+    let index = read_index(data)?;
+    entry_paths(Path::new(""), &index.entries)?;
+    decode(&index).map(drop)
+}
+
+// Writes every entry under `dir`, creating directories as needed. Nothing
+// is written unless every name is safe and unique
+pub fn extract(data: &[u8], dir: &str, options: files::Options) -> std::io::Result<()> {
+    let index = read_index(data)?;
+    let paths = entry_paths(Path::new(dir), &index.entries)?;
+    for ((original, attributes), path) in decode(&index)?.into_iter().zip(paths) {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let path = path.to_str().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "output directory is not UTF-8"))?;
+        files::write_output(path, &original, options)?;
+        if let Some(attributes) = attributes.filter(|_| !options.no_preserve) {
+            files::restore_attributes(path, &attributes)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_follow_the_file_system() {
+        assert_eq!(duplicate(["a", "b/a", "a"]), Some(("a", "a")));
+        assert_eq!(duplicate(["a", "b/a"]), None);
+        assert_eq!(duplicate(["Read.me", "read.ME"]).is_some(), cfg!(any(windows, target_os = "macos")));
+    }
+}
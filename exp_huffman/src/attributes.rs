@@ -0,0 +1,105 @@
+use std::fs::Metadata;
+use std::io::{ErrorKind, Read};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::CompressionError;
+
+// Timestamps and permission bits of a compressed file, kept in its header
+// so decompressing restores them. Permissions are Unix mode bits; on
+// Windows only the read-only flag maps across. Reading them back only
+// describes them: restoring them to a file is up to whoever wrote it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attributes {
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    pub mode: Option<u32>,
+}
+
+const HAS_MODIFIED: u8 = 1;
+const HAS_ACCESSED: u8 = 2;
+const HAS_MODE: u8 = 4;
+
+fn write_time(out: &mut Vec<u8>, time: SystemTime) {
+    // Seconds may be negative, for files older than 1970
+    let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+        Err(e) => {
+            let d = e.duration();
+            let secs = -(d.as_secs() as i64) - (d.subsec_nanos() > 0) as i64;
+            (secs, (1_000_000_000 - d.subsec_nanos()) % 1_000_000_000)
+        }
+    };
+    out.extend_from_slice(&secs.to_le_bytes());
+    out.extend_from_slice(&nanos.to_le_bytes());
+}
+
+fn read_time(reader: &mut impl Read) -> std::io::Result<SystemTime> {
+    let mut secs = [0u8; 8];
+    let mut nanos = [0u8; 4];
+    reader.read_exact(&mut secs)?;
+    reader.read_exact(&mut nanos)?;
+    let (secs, nanos) = (i64::from_le_bytes(secs), u32::from_le_bytes(nanos));
+    if nanos >= 1_000_000_000 {
+        return Err(std::io::Error::new(ErrorKind::InvalidData, "bad timestamp"));
+    }
+    let time = if secs >= 0 {
+        UNIX_EPOCH.checked_add(Duration::new(secs as u64, nanos))
+    } else {
+        UNIX_EPOCH
+            .checked_sub(Duration::from_secs(secs.unsigned_abs()))
+            .and_then(|t| t.checked_add(Duration::from_nanos(nanos as u64)))
+    };
+    time.ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "timestamp out of range"))
+}
+
+impl Attributes {
+    pub fn of(metadata: &Metadata) -> Attributes {
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.permissions().mode() & 0o7777
+        };
+        #[cfg(not(unix))]
+        let mode = if metadata.permissions().readonly() { 0o444 } else { 0o644 };
+        Attributes {
+            modified: metadata.modified().ok(),
+            accessed: metadata.accessed().ok(),
+            mode: Some(mode),
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let flags = if self.modified.is_some() { HAS_MODIFIED } else { 0 }
+            | if self.accessed.is_some() { HAS_ACCESSED } else { 0 }
+            | if self.mode.is_some() { HAS_MODE } else { 0 };
+        out.push(flags);
+        if let Some(t) = self.modified {
+            write_time(out, t);
+        }
+        if let Some(t) = self.accessed {
+            write_time(out, t);
+        }
+        if let Some(mode) = self.mode {
+            out.extend_from_slice(&mode.to_le_bytes());
+        }
+    }
+
+    pub fn read(reader: &mut impl Read) -> Result<Attributes, CompressionError> {
+        let mut flags = [0u8];
+        reader.read_exact(&mut flags)?;
+        let flags = flags[0];
+        if flags & !(HAS_MODIFIED | HAS_ACCESSED | HAS_MODE) != 0 {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "unknown file attributes").into());
+        }
+        let modified = if flags & HAS_MODIFIED != 0 { Some(read_time(reader)?) } else { None };
+        let accessed = if flags & HAS_ACCESSED != 0 { Some(read_time(reader)?) } else { None };
+        let mode = if flags & HAS_MODE != 0 {
+            let mut mode = [0u8; 4];
+            reader.read_exact(&mut mode)?;
+            Some(u32::from_le_bytes(mode) & 0o7777)
+        } else {
+            None
+        };
+        Ok(Attributes { modified, accessed, mode })
+    }
+}
@@ -0,0 +1,79 @@
+// Every algorithm on one file: how small each gets it, and how fast it
+// compresses and decompresses, as a table. Each run is checked to round-trip,
+// so a broken codec can't look good here.
+
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+
+use test_huffman::{compress_payload, decompress_payload, parse_algorithm};
+
+// Everything --algorithm takes on its own, with default settings. New
+// algorithms go here to be benchmarked too
+const ALGORITHMS: &[&str] = &[
+    "huffman",
+    "shannon-fano",
+    "adaptive",
+    "arithmetic",
+    "range",
+    "rans",
+    "tans",
+    "rle",
+    "lz77",
+    "lz78",
+    "lzss",
+    "deflate",
+    "bwt",
+    "ppm",
+    "fast",
+];
+
+// Small inputs are run again and again until this much time has gone by,
+// so their speeds aren't all noise
+const MIN_TIME: Duration = Duration::from_millis(200);
+
+// The result of `f`, and how long one run of it takes
+fn timed<R>(mut f: impl FnMut() -> std::io::Result<R>) -> std::io::Result<(R, Duration)> {
+    let start = Instant::now();
+    let mut runs = 1;
+    let result = f()?;
+    while start.elapsed() < MIN_TIME {
+        f()?;
+        runs += 1;
+    }
+    Ok((result, start.elapsed() / runs))
+}
+
+fn mib_per_sec(bytes: usize, time: Duration) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0) / time.as_secs_f64().max(1e-9)
+}
+
+// A line per algorithm under a heading: its output size (without the file
+// header, the same for all), that size as a share of the input, and its
+// speeds in MiB of input per second
+pub fn report(data: &[u8]) -> std::io::Result<String> {
+    let mut report = format!("{:<14} {:>12} {:>8} {:>15} {:>15}\n", "algorithm", "size", "ratio", "compress", "decompress");
+    for &name in ALGORITHMS {
+        let unit = match name {
+            "huffman" => None,
+            name => Some(parse_algorithm(name).expect("benchmarked algorithms parse")),
+        };
+        let (compressed, compress_time) = timed(|| Ok(compress_payload(data, unit.clone(), 1)?))?;
+        let (decompressed, decompress_time) = timed(|| Ok(decompress_payload(&compressed[..])?))?;
+        if decompressed != data {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} did not round-trip", name)));
+        }
+        let ratio = match data.len() {
+            0 => 0.0,
+            len => 100.0 * compressed.len() as f64 / len as f64,
+        };
+        report += &format!(
+            "{:<14} {:>12} {:>7.2}% {:>10.1} MiB/s {:>10.1} MiB/s\n",
+            name,
+            compressed.len(),
+            ratio,
+            mib_per_sec(data.len(), compress_time),
+            mib_per_sec(data.len(), decompress_time)
+        );
+    }
+    Ok(report)
+}
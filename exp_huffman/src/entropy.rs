@@ -0,0 +1,69 @@
+// Why a file does or doesn't compress: how much information its bytes carry
+// on their own and given the byte before, the size no coder of bytes one at
+// a time can beat, what a Huffman code gets, and which bytes make it up.
+
+use huffman::codes::{build_byte_frequency_table, build_huffman_tree, code_lengths, code_stats, CodeStats};
+
+const BAR_WIDTH: f64 = 40.0;
+
+// Bits per byte knowing the byte before it, the bound for coders that
+// model one byte of context. The first byte has none, so isn't counted
+fn order1_entropy(data: &[u8]) -> f64 {
+    let mut counts = vec![0u32; 256 * 256];
+    let mut contexts = [0u32; 256];
+    for pair in data.windows(2) {
+        counts[pair[0] as usize * 256 + pair[1] as usize] += 1;
+        contexts[pair[0] as usize] += 1;
+    }
+    let total = data.len().saturating_sub(1) as f64;
+    let mut bits = 0.0;
+    for (context, row) in counts.chunks(256).enumerate() {
+        for &count in row.iter().filter(|&&count| count > 0) {
+            bits += count as f64 * (contexts[context] as f64 / count as f64).log2();
+        }
+    }
+    if total > 0.0 { bits / total } else { 0.0 }
+}
+
+// A byte as it would appear in a Rust byte string
+fn show(byte: u8) -> String {
+    match byte {
+        b' ' => "' '".to_string(),
+        byte => std::ascii::escape_default(byte).to_string(),
+    }
+}
+
+pub fn report(data: &[u8]) -> String {
+    let freq_table = build_byte_frequency_table(data);
+    let stats = match freq_table.len() {
+        0 => CodeStats { symbols: 0, entropy: 0.0, average_length: 0.0 },
+        _ => code_stats(&freq_table, &code_lengths(&build_huffman_tree(&freq_table))),
+    };
+    let bound = (stats.entropy * data.len() as f64 / 8.0).ceil() as u64;
+    let mut report = format!("size:           {} bytes\n", data.len());
+    report += &format!("entropy:        {:.3} bits per byte\n", stats.entropy);
+    report += &format!("given previous: {:.3} bits per byte\n", order1_entropy(data));
+    report += &format!("lower bound:    {} bytes, coding bytes one at a time\n", bound);
+    report += &format!(
+        "huffman code:   {:.3} bits per byte, {} bytes\n",
+        stats.average_length,
+        (stats.average_length * data.len() as f64 / 8.0).ceil() as u64
+    );
+    report += &format!("distinct bytes: {}\n\n", freq_table.len());
+
+    // Commonest first, ties in byte order
+    let mut histogram = freq_table;
+    histogram.sort_by_key(|&(byte, count)| (std::cmp::Reverse(count), byte));
+    let most = histogram.first().map_or(1, |&(_, count)| count) as f64;
+    report += &format!("{:<6} {:>12} {:>7}\n", "byte", "count", "share");
+    for (byte, count) in histogram {
+        report += &format!(
+            "{:<6} {:>12} {:>6.2}%  {}\n",
+            show(byte),
+            count,
+            100.0 * count as f64 / data.len() as f64,
+            "#".repeat((BAR_WIDTH * count as f64 / most).ceil() as usize)
+        );
+    }
+    report
+}
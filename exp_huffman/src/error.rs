@@ -0,0 +1,64 @@
+// What compressing or decompressing can fail with, for callers to tell the
+// cases apart. The codecs underneath report io::Errors, which are sorted
+// by kind into truncated, corrupt or other; the rest come from the format
+// itself. Each converts back into the io::Error, of the same kind, that
+// the library returned before, so `?` works in functions returning either
+
+use std::io::{Error, ErrorKind};
+use std::str::Utf8Error;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum CompressionError {
+    #[error("bad magic number, not a compressed file")]
+    BadMagic,
+    #[error("unknown format version {0}, possibly written by a newer version")]
+    UnknownVersion(u8),
+    #[error("unknown compression mode {0:#04x}, possibly written by a newer version")]
+    UnknownMode(u8),
+    #[error("checksum mismatch, the file is corrupt")]
+    ChecksumMismatch,
+    // The data ran out partway, as a cut-short download or copy does
+    #[error("{0}")]
+    Truncated(Error),
+    // A table, code or stream that doesn't decode
+    #[error("{0}")]
+    Corrupt(Error),
+    // A text unit given data that isn't UTF-8
+    #[error("{0}")]
+    NotText(#[from] Utf8Error),
+    // Options out of range, or that don't go together
+    #[error("{0}")]
+    InvalidOptions(&'static str),
+    #[error("compressed with a preset dictionary, which --dict has to give")]
+    NeedsDictionary,
+    #[error("compressed with a different preset dictionary")]
+    WrongDictionary,
+    // Anything else, e.g. reading from a failing reader
+    #[error(transparent)]
+    Io(Error),
+}
+
+impl From<Error> for CompressionError {
+    fn from(e: Error) -> CompressionError {
+        match e.kind() {
+            ErrorKind::UnexpectedEof => CompressionError::Truncated(e),
+            ErrorKind::InvalidData => CompressionError::Corrupt(e),
+            _ => CompressionError::Io(e),
+        }
+    }
+}
+
+impl From<CompressionError> for Error {
+    fn from(e: CompressionError) -> Error {
+        let kind = match e {
+            CompressionError::Truncated(e) | CompressionError::Corrupt(e) | CompressionError::Io(e) => return e,
+            CompressionError::InvalidOptions(_) | CompressionError::NeedsDictionary | CompressionError::WrongDictionary => {
+                ErrorKind::InvalidInput
+            }
+            _ => ErrorKind::InvalidData,
+        };
+        // As a message, which is what main's Debug output shows
+        Error::new(kind, e.to_string())
+    }
+}
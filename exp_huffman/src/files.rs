@@ -0,0 +1,541 @@
+// Opening inputs and writing outputs. Everything is read and written front
+// to back with no seeking and no sizing from metadata, so FIFOs work at
+// either end of a pipeline (`mkfifo p; producer > p & compress p out`).
+// Devices are refused up front: reading /dev/zero or a disk whole would
+// never finish or exhaust memory, and overwriting a disk is never wanted.
+//
+// "-" stands for standard input or output, as in most Unix tools; a file
+// of that name can still be given as ./-.
+//
+// On Windows the same goes for the reserved device names (CON, COM1, ...),
+// which exist in every directory, and long paths get the \\?\ prefix so
+// they aren't cut off at MAX_PATH.
+//
+// With --mmap a regular input file is mapped into memory rather than
+// copied into it; FIFOs are read as usual.
+//
+// The one exception to front-to-back reading is estimate --sample, which
+// seeks to parts of a regular file; anything else is read whole.
+
+use std::fs::{File, FileTimes, Metadata, OpenOptions, TryLockError};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use test_huffman::attributes::Attributes;
+
+use crate::progress::{Progress, CHUNK};
+
+const MAX_PATH: usize = 260;
+
+pub const STDIO: &str = "-";
+
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn refuse(path: &str, what: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidInput, format!("{} is {}", path, what))
+}
+
+// Only detected on Unix
+#[cfg_attr(not(unix), allow(dead_code))]
+#[derive(Clone, Copy, PartialEq)]
+enum Special {
+    BlockDevice,
+    CharDevice,
+    Socket,
+}
+
+impl Special {
+    fn describe(self) -> &'static str {
+        match self {
+            Special::BlockDevice => "a block device",
+            Special::CharDevice => "a character device",
+            Special::Socket => "a socket",
+        }
+    }
+}
+
+#[cfg(unix)]
+fn special(metadata: &Metadata) -> Option<Special> {
+    use std::os::unix::fs::FileTypeExt;
+    let file_type = metadata.file_type();
+    if file_type.is_block_device() {
+        Some(Special::BlockDevice)
+    } else if file_type.is_char_device() {
+        Some(Special::CharDevice)
+    } else if file_type.is_socket() {
+        Some(Special::Socket)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn special(_metadata: &Metadata) -> Option<Special> {
+    None
+}
+
+// Windows ignores the extension and trailing spaces, so "con.txt" and
+// "Nul .tar" are devices too
+fn reserved_name(path: &str) -> Option<&'static str> {
+    let name = Path::new(path).file_name()?.to_str()?;
+    let stem = name.split('.').next()?.trim_end_matches(' ');
+    RESERVED_NAMES.iter().copied().find(|r| r.eq_ignore_ascii_case(stem))
+}
+
+// The path to hand to the OS: unchanged except for long paths on Windows,
+// which are made absolute and given the extended-length prefix (\\?\C:\...
+// or \\?\UNC\server\share\... for UNC paths)
+fn native_path(path: &str) -> std::io::Result<PathBuf> {
+    if !cfg!(windows) || path.starts_with(r"\\?\") {
+        return Ok(PathBuf::from(path));
+    }
+    // A short relative path can still be long once resolved
+    let absolute = std::path::absolute(path)?;
+    let Some(text) = absolute.to_str().filter(|t| t.len() >= MAX_PATH) else {
+        return Ok(PathBuf::from(path));
+    };
+    Ok(match text.strip_prefix(r"\\") {
+        Some(_) if text.starts_with(r"\\?\") => absolute,
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", text)),
+    })
+}
+
+// Regular files and FIFOs can be read
+pub fn open_input(path: &str) -> std::io::Result<File> {
+    if let Some(name) = reserved_name(path).filter(|_| cfg!(windows)) {
+        return Err(refuse(path, &format!("the {} device", name)));
+    }
+    let native = native_path(path)?;
+    let metadata = std::fs::metadata(&native)?;
+    if metadata.is_dir() {
+        return Err(refuse(path, "a directory"));
+    }
+    if let Some(kind) = special(&metadata) {
+        return Err(refuse(path, kind.describe()));
+    }
+    File::open(native)
+}
+
+pub fn read_input(path: &str) -> std::io::Result<Vec<u8>> {
+    // Not std::fs::read, which sizes its buffer from the metadata
+    let mut data = Vec::new();
+    if path == STDIO {
+        std::io::stdin().lock().read_to_end(&mut data)?;
+    } else {
+        open_input(path)?.read_to_end(&mut data)?;
+    }
+    Ok(data)
+}
+
+// The data and, unless it is standard input, the metadata of what it was
+// read from, taken before reading changes the access time
+pub fn read_file(path: &str) -> std::io::Result<(Vec<u8>, Option<Metadata>)> {
+    if path == STDIO {
+        return Ok((read_input(path)?, None));
+    }
+    let mut input = open_input(path)?;
+    let metadata = input.metadata()?;
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    Ok((data, Some(metadata)))
+}
+
+// `parts` pieces of `part_size` bytes spread evenly over a regular file,
+// and the size of the whole. Files no bigger than the pieces, and inputs
+// that can't seek, come back whole as a single piece
+pub fn read_sample(path: &str, parts: u64, part_size: u64) -> std::io::Result<(Vec<Vec<u8>>, u64)> {
+    if path != STDIO {
+        let mut input = open_input(path)?;
+        let metadata = input.metadata()?;
+        if metadata.is_file() && metadata.len() > parts * part_size {
+            let stride = metadata.len() / parts;
+            let mut pieces = Vec::new();
+            for i in 0..parts {
+                input.seek(SeekFrom::Start(i * stride))?;
+                let mut piece = Vec::new();
+                (&mut input).take(part_size).read_to_end(&mut piece)?;
+                pieces.push(piece);
+            }
+            return Ok((pieces, metadata.len()));
+        }
+    }
+    let data = read_input(path)?;
+    let len = data.len() as u64;
+    Ok((vec![data], len))
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Options {
+    // Delete the input once the output is written
+    pub remove_source: bool,
+    // fsync the output and its directory entry before returning
+    pub fsync: bool,
+    // Skip recording (on compress) or restoring (on decompress) the
+    // input's timestamps and permissions
+    pub no_preserve: bool,
+    // Map a regular input file instead of reading it
+    pub mmap: bool,
+    // Show a progress line while reading the input and writing the output
+    pub progress: bool,
+}
+
+// read_to_end a chunk at a time, for the progress line
+fn read_all(reader: &mut impl Read, total: Option<u64>, progress: bool) -> std::io::Result<Vec<u8>> {
+    let mut progress = Progress::new(progress, "reading", total);
+    let mut data = Vec::new();
+    loop {
+        match reader.take(CHUNK as u64).read_to_end(&mut data)? {
+            0 => return Ok(data),
+            n => progress.advance(n),
+        }
+    }
+}
+
+// write_all a chunk at a time, for the progress line
+fn write_all(writer: &mut impl Write, data: &[u8], progress: bool) -> std::io::Result<()> {
+    let mut progress = Progress::new(progress, "writing", Some(data.len() as u64));
+    for chunk in data.chunks(CHUNK) {
+        writer.write_all(chunk)?;
+        progress.advance(chunk.len());
+    }
+    Ok(())
+}
+
+// The whole of a file mapped read-only, which the page cache backs, so a
+// large input takes no memory of its own and every pass over it shares the
+// same pages. A mapping's bytes are only as stable as the file: truncating
+// it meanwhile can kill the process with SIGBUS, which is why this is an
+// option
+struct Mapping(memmap2::Mmap);
+
+impl Mapping {
+    // None for what can't be mapped: FIFOs, and empty files, which mmap
+    // refuses
+    fn of(file: &File, metadata: &Metadata) -> std::io::Result<Option<Mapping>> {
+        if !metadata.is_file() || metadata.len() == 0 {
+            return Ok(None);
+        }
+        // SAFETY: the mapping lives no longer than the open file, and --mmap
+        // is documented as needing the file left alone meanwhile
+        let map = unsafe { memmap2::Mmap::map(file)? };
+        Ok(Some(Mapping(map)))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// Regular files, FIFOs and character devices such as /dev/null (or NUL)
+// can be written; block devices can't.
+//
+// A regular file is written to a temporary file next to it and renamed
+// into place once complete, so a crash or full disk never leaves a
+// truncated file under the final name; an existing file's permissions
+// carry over. FIFOs and devices are written directly. With
+// `remove_source`, fails rather than replace a file another process holds
+// a lock on.
+pub fn write_output(path: &str, data: &[u8], options: Options) -> std::io::Result<()> {
+    if path == STDIO {
+        let mut stdout = std::io::stdout().lock();
+        write_all(&mut stdout, data, options.progress)?;
+        return stdout.flush();
+    }
+    if let Some(name) = reserved_name(path).filter(|&n| cfg!(windows) && n != "NUL") {
+        return Err(refuse(path, &format!("the {} device", name)));
+    }
+    let native = native_path(path)?;
+    let existing = match std::fs::metadata(&native) {
+        Ok(metadata) if metadata.is_dir() => return Err(refuse(path, "a directory")),
+        Ok(metadata) => match special(&metadata) {
+            Some(kind) if kind != Special::CharDevice => return Err(refuse(path, kind.describe())),
+            _ => Some(metadata),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    if existing.as_ref().is_some_and(|m| !m.is_file()) {
+        let mut file = OpenOptions::new().write(true).open(native)?;
+        return write_all(&mut file, data, options.progress);
+    }
+
+    // Held until the replacement is renamed over it
+    let _lock = match &existing {
+        Some(_) if options.remove_source => {
+            let file = File::open(&native)?;
+            try_lock(&file, path)?;
+            Some(file)
+        }
+        _ => None,
+    };
+    replace(&native, existing.as_ref(), options.fsync, |temp| write_all(temp, data, options.progress))
+}
+
+// Has `write` fill a temporary file next to `path`, then renames it over
+// `path`, which keeps the permissions of the `existing` file there. If
+// anything fails the temporary file is removed and `path` left as it was
+fn replace(
+    path: &Path,
+    existing: Option<&Metadata>,
+    fsync: bool,
+    write: impl FnOnce(&mut File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let (temp_path, mut temp) = create_temp(path)?;
+    let written = (|| {
+        write(&mut temp)?;
+        if let Some(metadata) = existing {
+            temp.set_permissions(metadata.permissions())?;
+        }
+        if fsync {
+            temp.sync_all()?;
+        }
+        drop(temp);
+        std::fs::rename(&temp_path, path)
+    })();
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    if fsync {
+        sync_parent(path)?;
+    }
+    Ok(())
+}
+
+fn create_temp(path: &Path) -> std::io::Result<(PathBuf, File)> {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("output");
+    for attempt in 0.. {
+        let temp_path = path.with_file_name(format!(".{}.{}-{}.tmp", name, std::process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && attempt < 100 => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+// Makes a rename durable. Only possible, and only needed, on Unix
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn try_lock(file: &File, path: &str) -> std::io::Result<()> {
+    file.try_lock().map_err(|e| match e {
+        TryLockError::WouldBlock => {
+            std::io::Error::new(ErrorKind::WouldBlock, format!("{} is locked by another process", path))
+        }
+        TryLockError::Error(e) => e,
+    })
+}
+
+// Size and modification time, to notice a file changing while it's read
+#[derive(PartialEq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    fn of(file: &File) -> std::io::Result<Stamp> {
+        let metadata = file.metadata()?;
+        Ok(Stamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+// Whether both paths lead to one existing file, through symlinks, "." and
+// the like
+fn same_file(a: &str, b: &str) -> std::io::Result<bool> {
+    let resolve = |path| std::fs::canonicalize(native_path(path)?);
+    Ok(match (resolve(a), resolve(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    })
+}
+
+// Times first: once the file is read-only it can't be opened to set them
+fn apply_attributes(attributes: &Attributes, path: &Path) -> std::io::Result<()> {
+    let mut times = FileTimes::new();
+    if let Some(t) = attributes.modified {
+        times = times.set_modified(t);
+    }
+    if let Some(t) = attributes.accessed {
+        times = times.set_accessed(t);
+    }
+    OpenOptions::new().write(true).open(path)?.set_times(times)?;
+    if let Some(mode) = attributes.mode {
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::Permissions::from_mode(mode)
+        };
+        #[cfg(not(unix))]
+        let permissions = {
+            let mut permissions = std::fs::metadata(path)?.permissions();
+            permissions.set_readonly(mode & 0o222 == 0);
+            permissions
+        };
+        std::fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+// Gives a file written by write_output the attributes its original had
+pub fn restore_attributes(path: &str, attributes: &Attributes) -> std::io::Result<()> {
+    apply_attributes(attributes, &native_path(path)?)
+}
+
+// Reads `input_path`, runs it through `f` and writes the result, then
+// gives the output any attributes `f` returns along with it. `f` gets the
+// input's metadata, which standard input has none of. With
+// `remove_source` the input is deleted afterwards, as gzip does. Both files
+// are advisory-locked meanwhile, and the input must keep its size and
+// mtime throughout: a file something else is still writing is left alone
+// (and the output removed) instead of being replaced by half of itself.
+pub fn transform(
+    input_path: &str,
+    output_path: &str,
+    options: Options,
+    f: impl FnOnce(&[u8], Option<&Metadata>) -> std::io::Result<(Vec<u8>, Option<Attributes>)>,
+) -> std::io::Result<()> {
+    let remove_source = options.remove_source;
+    if input_path != STDIO && output_path != STDIO && same_file(input_path, output_path)? {
+        return Err(refuse(input_path, "both the input and the output"));
+    }
+    if input_path == STDIO {
+        if remove_source {
+            return Err(refuse("standard input", "not a regular file, so it can't be removed"));
+        }
+        let (output, _) = f(&read_all(&mut std::io::stdin().lock(), None, options.progress)?, None)?;
+        return write_output(output_path, &output, options);
+    }
+    // Checked before opening, which blocks on a FIFO with no writer
+    if remove_source && !std::fs::metadata(native_path(input_path)?)?.is_file() {
+        return Err(refuse(input_path, "not a regular file, so it can't be removed"));
+    }
+    let mut input = open_input(input_path)?;
+    let mut before = None;
+    if remove_source {
+        try_lock(&input, input_path)?;
+        before = Some(Stamp::of(&input)?);
+    }
+    // Before reading, which updates the access time
+    let metadata = input.metadata()?;
+    let mapping = if options.mmap { Mapping::of(&input, &metadata)? } else { None };
+    let read;
+    let data = match &mapping {
+        Some(mapping) => mapping.bytes(),
+        None => {
+            read = read_all(&mut input, Some(metadata.len()).filter(|_| metadata.is_file()), options.progress)?;
+            &read[..]
+        }
+    };
+    let (output, attributes) = f(data, Some(&metadata))?;
+    write_output(output_path, &output, options)?;
+
+    let native_output = native_path(output_path)?;
+    let output_is_file = output_path != STDIO && std::fs::metadata(&native_output).is_ok_and(|m| m.is_file());
+    if let Some(before) = &before {
+        if data.len() as u64 != before.len || Stamp::of(&input)? != *before {
+            if output_is_file {
+                std::fs::remove_file(&native_output)?;
+            }
+            return Err(std::io::Error::other(format!(
+                "{} changed while being read; it was kept and the output removed",
+                input_path
+            )));
+        }
+    }
+    // Devices and FIFOs keep their own
+    if let Some(attributes) = attributes.filter(|_| output_is_file) {
+        apply_attributes(&attributes, &native_output)?;
+    }
+    if before.is_none() {
+        return Ok(());
+    }
+    // Windows won't delete a file that is still open
+    drop(input);
+    std::fs::remove_file(native_path(input_path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("files-unit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name).to_str().unwrap().to_string()
+    }
+
+    // `change` runs on the input between its stamp and the check after
+    fn transform_changing(name: &str, change: impl FnOnce(&str)) -> std::io::Result<()> {
+        let (input, output) = (scratch(name), scratch(&format!("{}.out", name)));
+        std::fs::write(&input, "written in full").unwrap();
+        let options = Options { remove_source: true, ..Options::default() };
+        let result = transform(&input, &output, options, |data, _| {
+            change(&input);
+            Ok((data.to_vec(), None))
+        });
+        assert!(Path::new(&input).exists(), "a changing input was removed");
+        assert!(!Path::new(&output).exists(), "the output of a changing input was kept");
+        result
+    }
+
+    #[test]
+    fn failed_writes_leave_the_old_output() {
+        // A directory of its own, for no other test's temporary files
+        let dir = PathBuf::from(scratch("kept"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("kept.txt");
+        std::fs::write(&output, "the old output").unwrap();
+        let existing = std::fs::metadata(&output).unwrap();
+        let e = replace(&output, Some(&existing), false, |temp| {
+            temp.write_all(b"the first half of the new")?;
+            Err(std::io::Error::new(ErrorKind::StorageFull, "no space left on device"))
+        })
+        .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::StorageFull);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "the old output");
+        let left: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert!(!left.iter().any(|name| name.to_string_lossy().ends_with(".tmp")), "{:?}", left);
+    }
+
+    #[test]
+    fn notices_the_input_growing() {
+        let e = transform_changing("grown", |input| {
+            OpenOptions::new().append(true).open(input).unwrap().write_all(b" and then some").unwrap();
+        })
+        .unwrap_err();
+        assert!(e.to_string().contains("changed while being read"), "{}", e);
+    }
+
+    #[test]
+    fn notices_the_input_rewritten_in_place() {
+        // The same size, but a new mtime
+        let e = transform_changing("rewritten", |input| {
+            let file = OpenOptions::new().write(true).open(input).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        })
+        .unwrap_err();
+        assert!(e.to_string().contains("changed while being read"), "{}", e);
+    }
+}
@@ -0,0 +1,1319 @@
+//! The hz format, which the test_huffman program reads and writes, for
+//! applications to compress and decompress buffers with it in memory.
+//! `compress_bytes` gives what `compress` would write for a file of the
+//! same bytes, less its attributes, and `decompress_bytes` takes anything
+//! `decompress` does: hz data of any version, the first version's files,
+//! gzip and zlib. Failures are
+//! [`CompressionError`]s, which tell truncated data from corrupt data, an
+//! unknown mode or version, a bad checksum and bad options.
+//!
+//! ```
+//! use test_huffman::{compress_bytes, decompress_bytes, parse_unit, Options};
+//!
+//! let log = "GET /index.html 200\nGET /style.css 200\nGET /index.html 304\n".repeat(100);
+//! let compressed = compress_bytes(log.as_bytes(), &Options::default()).unwrap();
+//! assert!(compressed.len() < log.len() * 2 / 3);
+//! assert_eq!(decompress_bytes(&compressed).unwrap(), log.as_bytes());
+//!
+//! // What `compress --log --streams 4` writes, and `compress --level 9`
+//! let options = Options { unit: parse_unit("log"), streams: 4, ..Options::default() };
+//! assert_eq!(decompress_bytes(&compress_bytes(log.as_bytes(), &options).unwrap()).unwrap(), log.as_bytes());
+//! let best = compress_bytes(log.as_bytes(), &Options { level: Some(9), ..Options::default() }).unwrap();
+//! assert!(best.len() < compressed.len());
+//! ```
+
+use std::io::{Read, BufRead};
+use rayon::prelude::*;
+use huffman::adaptive::AdaptiveHuffman;
+use huffman::arithmetic::Arithmetic;
+use huffman::bitio::{BitOrder, BitReader, BitWriter};
+use huffman::blocksort::BlockSort;
+use huffman::codec::{Compressor, Decompressor, Huffman, ShannonFano};
+use huffman::deflate::{Deflate, DeflateWithChain, DeflateWithDictionary};
+use huffman::deltafilter::DeltaFilter;
+use huffman::gzip::{self, Gzip};
+use huffman::zlib::{self, Zlib};
+use huffman::lz77::{Lz77, DEFAULT_CHAIN};
+use huffman::lz4::Lz4;
+use huffman::lz78::Lz78;
+use huffman::lzss::Lzss;
+use huffman::ppm::Ppm;
+use huffman::rangecoder::RangeCoder;
+use huffman::rans::Rans;
+use huffman::rle::Rle;
+use huffman::snappy::Snappy;
+use huffman::tans::Tans;
+use huffman::codes::{build_byte_frequency_table, build_char_frequency_table, build_encoding_table, build_frequency_table_parallel, build_huffman_tree, code_lengths, decode_streams, encode_streams, try_build_huffman_tree};
+use huffman::codes::{read_lengths_table, read_tree, write_lengths_table, write_tree, CanonicalDecoder, SymbolDecoder};
+use huffman::{columnar, gorilla, grapheme, json, logtok, protobuf, timeseries};
+use huffman::{sniff, ContentKind};
+use huffman::crc32::crc32;
+
+pub mod archive;
+pub mod attributes;
+pub mod error;
+
+use attributes::Attributes;
+pub use error::CompressionError;
+
+// The first version wrote a text table and the codes, with nothing before
+// them (see decode_first_version). Those files are still read, but that
+// version can't read anything written since, which starts with a header.
+//
+// Files start with a magic number, a format version and, since version 2,
+// the CRC-32 of the original data as a u32. The magic's first
+// byte is no mode byte, so files from before the header, which start with
+// their mode, still decode, and decoders from then refuse newer files as an
+// unknown mode. 0x89 and the newline catch transfers that strip the high bit
+// or rewrite line endings, as in PNG
+const MAGIC: [u8; 4] = *b"\x89HZ\n";
+const FORMAT_VERSION: u8 = 2;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+// After the header comes the mode byte. Once released, a mode's layout is frozen: a
+// new layout gets a new byte, and decoding refuses bytes it doesn't know
+// rather than guess, so older versions fail cleanly on newer files.
+// tests/compat.rs holds files from released versions to enforce this
+const MODE_STORED: u8 = b'S';
+const MODE_CSV: u8 = b'C';
+const MODE_JSON: u8 = b'J';
+const MODE_PROTOBUF: u8 = b'P';
+const MODE_FLOAT64: u8 = b'F';
+const MODE_INT64: u8 = b'i';
+// Huffman-coded symbols: a table of code lengths (see read_lengths_table),
+// the symbol count and the codes. The upper-case bytes H, G, U, N and L were
+// the same with frequency tables, and are no longer read
+const MODE_CHARS: u8 = b'h';
+const MODE_GRAPHEME: u8 = b'g';
+const MODE_UTF16: u8 = b'u';
+const MODE_BITS: u8 = b'n';
+const MODE_LOG: u8 = b'l';
+// Chars again, with the canonical code's tree in place of the table (see
+// codes::write_tree), its leaves holding each char in UTF-8
+const MODE_CHARS_TREE: u8 = b'c';
+// Raw bytes, for input that isn't text: codec::Huffman's output
+const MODE_BYTES: u8 = b'b';
+// Algorithms other than Huffman, each followed by its library codec's
+// output
+const MODE_LZ77: u8 = b'Z';
+const MODE_LZ78: u8 = b'Y';
+const MODE_LZSS: u8 = b'X';
+const MODE_RLE: u8 = b'R';
+const MODE_BLOCK_SORT: u8 = b'W';
+const MODE_DEFLATE: u8 = b'D';
+const MODE_ARITHMETIC: u8 = b'A';
+const MODE_RANGE: u8 = b'K';
+const MODE_RANS: u8 = b'T';
+const MODE_TANS: u8 = b'V';
+const MODE_ADAPTIVE: u8 = b'O';
+const MODE_SHANNON_FANO: u8 = b'E';
+const MODE_PPM: u8 = b'B';
+const MODE_DELTA_FILTER: u8 = b'd';
+const MODE_FAST: u8 = b'f';
+// DEFLATE with a preset dictionary, after the dictionary's Adler-32
+// checksum, which the same dictionary has to be given to decode
+pub const MODE_PRESET_DEFLATE: u8 = b'p';
+// Algorithms chained with +: a stage count, then the last stage's output,
+// whose mode byte is followed by the previous stage's output, and so on
+const MODE_PIPELINE: u8 = b'Q';
+const MAX_STAGES: usize = 8;
+// Not a coding: the input file's timestamps and permissions, followed by
+// the compressed data with its own mode byte
+pub const MODE_ATTRIBUTES: u8 = b'M';
+// Not a coding either: a stream count, then compressed data whose Huffman
+// payloads are each split that many ways (see encode_streams)
+const MODE_INTERLEAVED: u8 = b'I';
+pub const MAX_STREAMS: usize = 64;
+// Not a coding either: the input cut into blocks that each decode on their
+// own (see compress_blocks)
+const MODE_BLOCKS: u8 = b'k';
+pub const MAX_BLOCK_SIZE: usize = 1 << 30;
+// The blocks of the fastest --level settings
+const LEVEL_BLOCK_SIZE: usize = 1 << 20;
+// A block's entry in the index: its length, CRC-32 and payload length
+const BLOCK_ENTRY_LEN: usize = 8 + 4 + 8;
+pub fn write_header(output: &mut Vec<u8>, data: &[u8]) {
+    output.extend_from_slice(&MAGIC);
+    output.push(FORMAT_VERSION);
+    output.extend_from_slice(&crc32(data).to_le_bytes());
+}
+
+// Checks and skips the header, if there is one, returning the checksum the
+// decoded data should have
+pub fn read_header(reader: &mut impl BufRead) -> Result<Option<u32>, CompressionError> {
+    if reader.fill_buf()?.first() != Some(&MAGIC[0]) {
+        return Ok(None);
+    }
+    let mut header = [0u8; MAGIC.len() + 1];
+    reader.read_exact(&mut header)?;
+    if header[..MAGIC.len()] != MAGIC {
+        return Err(CompressionError::BadMagic);
+    }
+    let version = header[MAGIC.len()];
+    if !(1..=FORMAT_VERSION).contains(&version) {
+        return Err(CompressionError::UnknownVersion(version));
+    }
+    match version {
+        1 => Ok(None),
+        _ => Ok(Some(read_u32(reader)?)),
+    }
+}
+
+pub fn verify_checksum(expected: Option<u32>, data: &[u8]) -> Result<(), CompressionError> {
+    match expected {
+        Some(expected) if crc32(data) != expected => Err(CompressionError::ChecksumMismatch),
+        _ => Ok(()),
+    }
+}
+
+fn store(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + 1);
+    output.push(MODE_STORED);
+    output.extend_from_slice(data);
+    output
+}
+
+// Multi-char and non-char symbols can contain the ':' '|' and newline a
+// text table would rely on, so tables are length-prefixed binary (see
+// codes::write_lengths_table). After the table come the symbol count and
+// the coded symbols
+fn read_symbols<R: Read, S: Clone + Ord + Send + Sync>(
+    reader: &mut R,
+    streams: usize,
+    read_symbol: impl Fn(&mut R) -> std::io::Result<S>,
+) -> std::io::Result<Vec<S>> {
+    let decoder = CanonicalDecoder::new(read_lengths_table(reader, read_symbol)?)?;
+    let count = read_u64(reader)?;
+    read_coded(reader, &decoder, count, streams)
+}
+
+fn read_coded<S: Send>(reader: &mut impl Read, decoder: &(impl SymbolDecoder<S> + Sync), count: u64, streams: usize) -> std::io::Result<Vec<S>> {
+    let mut encoded_data = Vec::new();
+    reader.read_to_end(&mut encoded_data)?;
+    decode_streams(&encoded_data, decoder, count, streams)
+}
+
+// A char as a tree leaf holds it: its UTF-8 bytes, the first telling how
+// many there are
+pub fn read_utf8_char(bits: &mut BitReader) -> std::io::Result<char> {
+    let mut utf8 = [bits.read_bits(8)? as u8, 0, 0, 0];
+    let len = match utf8[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+    for b in &mut utf8[1..len] {
+        *b = bits.read_bits(8)? as u8;
+    }
+    let c = std::str::from_utf8(&utf8[..len]).ok().and_then(|s| s.chars().next());
+    c.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "bad char in tree"))
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn write_string(output: &mut Vec<u8>, g: &&str) {
+    output.extend_from_slice(&(g.len() as u32).to_le_bytes());
+    output.extend_from_slice(g.as_bytes());
+}
+
+// The length is untrusted, so the string is read up to it rather than
+// allocated at it
+pub fn read_string(reader: &mut impl Read) -> std::io::Result<String> {
+    let len = read_u32(reader)?;
+    let mut g = Vec::new();
+    reader.take(len as u64).read_to_end(&mut g)?;
+    if g.len() as u64 != len as u64 {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "string is truncated"));
+    }
+    String::from_utf8(g).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolUnit {
+    Char,
+    Grapheme,
+    Utf16 { big_endian: bool },
+    Bits(u32),
+    LogTokens,
+    Csv,
+    Json,
+    Protobuf,
+    Float64,
+    Int64,
+    Bytes,
+    Lz77(Lz77),
+    Lz78(Lz78),
+    Lzss(Lzss),
+    Rle,
+    BlockSort(BlockSort),
+    Deflate(DeflateWithChain),
+    Arithmetic,
+    Range,
+    Rans,
+    Tans,
+    Adaptive,
+    ShannonFano,
+    Ppm(Ppm),
+    DeltaFilter(DeltaFilter),
+    Fast,
+    PresetDeflate { deflate: DeflateWithDictionary, id: u32 },
+    Pipeline(Vec<SymbolUnit>),
+}
+
+const MAX_SYMBOL_BITS: u32 = 32;
+
+// What compress writes: this program's own format, or a standard one other
+// tools read, which decompress recognizes by its magic number. Snappy
+// blocks have none, so decompress has to be told
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Hz,
+    Gzip,
+    Zlib,
+    Snappy,
+}
+
+impl Format {
+    // A standard format's data, if `data` starts like it. zlib has no magic
+    // number, so only headers declaring the usual 32 KiB window count:
+    // their first byte, 'x', is no mode byte of headerless files
+    pub fn detect(data: &[u8]) -> Option<Format> {
+        if data.starts_with(&gzip::MAGIC) {
+            Some(Format::Gzip)
+        } else if data.first() == Some(&0x78) && zlib::is_header(data) {
+            Some(Format::Zlib)
+        } else {
+            None
+        }
+    }
+
+    // Only zlib takes a preset dictionary
+    pub fn compress(self, data: &[u8], dictionary: Option<&[u8]>) -> Vec<u8> {
+        match self {
+            Format::Hz => unreachable!("hz data has a mode and attributes"),
+            Format::Gzip => Gzip.compress(data),
+            Format::Zlib => match dictionary {
+                Some(dictionary) => Zlib::with_dictionary(dictionary).compress(data),
+                None => Zlib.compress(data),
+            },
+            Format::Snappy => Snappy.compress(data),
+        }
+    }
+
+    pub fn decompress(self, data: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>, CompressionError> {
+        let decompressed = match self {
+            Format::Hz => return decompress_bytes(data),
+            Format::Gzip => Gzip.decompress(data),
+            Format::Zlib => match dictionary {
+                Some(dictionary) => Zlib::with_dictionary(dictionary).decompress(data),
+                None => Zlib.decompress(data),
+            },
+            Format::Snappy => Snappy.decompress(data),
+        };
+        Ok(decompressed?)
+    }
+}
+
+// Symbol unit for a `--mode` name
+pub fn parse_unit(name: &str) -> Option<SymbolUnit> {
+    Some(match name {
+        "chars" => SymbolUnit::Char,
+        "graphemes" => SymbolUnit::Grapheme,
+        "utf16le" => SymbolUnit::Utf16 { big_endian: false },
+        "utf16be" => SymbolUnit::Utf16 { big_endian: true },
+        "log" => SymbolUnit::LogTokens,
+        "csv" => SymbolUnit::Csv,
+        "json" => SymbolUnit::Json,
+        "protobuf" => SymbolUnit::Protobuf,
+        "float64" => SymbolUnit::Float64,
+        "int64" => SymbolUnit::Int64,
+        "bytes" => SymbolUnit::Bytes,
+        name => match name.strip_prefix("bits=") {
+            Some(width) => match width.parse() {
+                Ok(width @ 1..=MAX_SYMBOL_BITS) => SymbolUnit::Bits(width),
+                _ => return None,
+            },
+            None => return parse_algorithm(name),
+        },
+    })
+}
+
+// An `--algorithm` other than the default, Huffman: lz77, or
+// lz77=WINDOW,LOOKAHEAD; lz78, or lz78=DICTIONARY; lzss, or
+// lzss=WINDOW,LOOKAHEAD,MIN_MATCH; rle; bwt, or bwt=BLOCK_SIZE; deflate;
+// arithmetic; range; rans; tans; adaptive; shannon-fano; ppm, or ppm=ORDER;
+// delta, or delta=STRIDE; fast; or
+// several joined with +, applied left to right, where huffman codes bytes
+pub fn parse_algorithm(name: &str) -> Option<SymbolUnit> {
+    if name.contains('+') {
+        let stages = name
+            .split('+')
+            .map(|stage| match stage {
+                "huffman" => Some(SymbolUnit::Bytes),
+                _ => parse_algorithm(stage),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        return (stages.len() <= MAX_STAGES).then_some(SymbolUnit::Pipeline(stages));
+    }
+    let (name, params) = name.split_once('=').map_or((name, None), |(n, p)| (n, Some(p)));
+    Some(match (name, params) {
+        ("lz77", None) => SymbolUnit::Lz77(Lz77::new()),
+        ("lz77", Some(sizes)) => {
+            let (window, lookahead) = sizes.split_once(',')?;
+            SymbolUnit::Lz77(Lz77::with_sizes(window.parse().ok()?, lookahead.parse().ok()?).ok()?)
+        }
+        ("lz78", None) => SymbolUnit::Lz78(Lz78::new()),
+        ("lz78", Some(size)) => SymbolUnit::Lz78(Lz78::with_dictionary(size.parse().ok()?).ok()?),
+        ("lzss", None) => SymbolUnit::Lzss(Lzss::new()),
+        ("lzss", Some(sizes)) => {
+            let sizes: Vec<usize> = sizes.split(',').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+            let &[window, lookahead, min_match] = &sizes[..] else {
+                return None;
+            };
+            SymbolUnit::Lzss(Lzss::with_sizes(window, lookahead, min_match).ok()?)
+        }
+        ("rle", None) => SymbolUnit::Rle,
+        ("bwt", None) => SymbolUnit::BlockSort(BlockSort::new()),
+        ("bwt", Some(size)) => SymbolUnit::BlockSort(BlockSort::with_block_size(size.parse().ok()?).ok()?),
+        ("deflate", None) => SymbolUnit::Deflate(Deflate::with_chain(DEFAULT_CHAIN).ok()?),
+        ("deflate", Some(chain)) => SymbolUnit::Deflate(Deflate::with_chain(chain.parse().ok()?).ok()?),
+        ("arithmetic", None) => SymbolUnit::Arithmetic,
+        ("range", None) => SymbolUnit::Range,
+        ("rans", None) => SymbolUnit::Rans,
+        ("tans", None) => SymbolUnit::Tans,
+        ("adaptive", None) => SymbolUnit::Adaptive,
+        ("shannon-fano", None) => SymbolUnit::ShannonFano,
+        ("ppm", None) => SymbolUnit::Ppm(Ppm::new()),
+        ("ppm", Some(order)) => SymbolUnit::Ppm(Ppm::with_order(order.parse().ok()?).ok()?),
+        ("delta", None) => SymbolUnit::DeltaFilter(DeltaFilter::new()),
+        ("delta", Some(stride)) => SymbolUnit::DeltaFilter(DeltaFilter::with_stride(stride.parse().ok()?).ok()?),
+        ("fast", None) => SymbolUnit::Fast,
+        _ => return None,
+    })
+}
+
+// What --level picks: the codecs to try, keeping whichever output is
+// smallest, and for the fastest levels blocks that big inputs are cut into,
+// so every core works at once. Levels up to 7 are DEFLATE searching further
+// and further for matches. PPM models text best but misses long repeats
+// that DEFLATE finds, so 8 and 9 try both, and 9 more orders and bwt as well
+fn level_settings(level: u8) -> (Vec<SymbolUnit>, Option<usize>) {
+    let deflate = |chain| SymbolUnit::Deflate(Deflate::with_chain(chain).unwrap());
+    let ppm = |order| SymbolUnit::Ppm(Ppm::with_order(order).unwrap());
+    match level {
+        1 => (vec![SymbolUnit::Fast], Some(LEVEL_BLOCK_SIZE)),
+        2 => (vec![deflate(4)], Some(LEVEL_BLOCK_SIZE)),
+        3 => (vec![deflate(8)], Some(LEVEL_BLOCK_SIZE)),
+        4 => (vec![deflate(16)], None),
+        5 => (vec![deflate(32)], None),
+        6 => (vec![deflate(DEFAULT_CHAIN)], None),
+        7 => (vec![deflate(256)], None),
+        8 => (vec![deflate(256), ppm(3)], None),
+        9 => (vec![deflate(1024), ppm(3), ppm(4), SymbolUnit::BlockSort(BlockSort::new())], None),
+        _ => unreachable!("levels are 1 to 9"),
+    }
+}
+
+// Splits data into MSB-first `width`-bit symbols. Bits left over at the end
+// are returned separately as (value, bit count).
+fn unpack_bits(data: &[u8], width: u32) -> (Vec<u32>, u32, u32) {
+    let mut reader = BitReader::with_order(data, BitOrder::MsbFirst);
+    let count = reader.bits_left() / width as u64;
+    // Counted out beforehand, so none of the reads can run short
+    let symbols = (0..count).map(|_| reader.read_bits(width).unwrap() as u32).collect();
+    let rest_bits = reader.bits_left() as u32;
+    let rest = reader.read_bits(rest_bits).unwrap() as u32;
+    (symbols, rest, rest_bits)
+}
+
+fn pack_bits(symbols: &[u32], width: u32, rest: u32, rest_bits: u32) -> Vec<u8> {
+    let mut writer = BitWriter::with_order(BitOrder::MsbFirst);
+    for &symbol in symbols {
+        writer.write_bits(symbol as u64, width);
+    }
+    writer.write_bits(rest as u64, rest_bits);
+    writer.finish()
+}
+
+// For packed formats whose fields don't line up with bytes, e.g. 4-bit
+// nibbles or 12-bit samples
+fn compress_bits(data: &[u8], width: u32, streams: usize) -> Vec<u8> {
+    let (symbols, rest, rest_bits) = unpack_bits(data, width);
+    // Too short for a single symbol, so there is no code to build
+    if symbols.is_empty() {
+        return store(data);
+    }
+    let lengths = code_lengths(&build_huffman_tree(&build_frequency_table_parallel(&symbols)));
+    let encoding_table = build_encoding_table(&lengths);
+    let encoded = encode_streams(symbols.iter().copied(), &encoding_table, streams);
+    
+    let mut output = vec![MODE_BITS, width as u8, rest_bits as u8];
+    output.extend_from_slice(&rest.to_le_bytes());
+    write_lengths_table(&mut output, &lengths, |out, s| out.extend_from_slice(&s.to_le_bytes()));
+    output.extend_from_slice(&(symbols.len() as u64).to_le_bytes());
+    output.extend_from_slice(&encoded);
+    output
+}
+
+fn compress_chars(text: &str, streams: usize) -> Vec<u8> {
+    let freq_table = build_char_frequency_table(text);
+    let lengths = code_lengths(&build_huffman_tree(&freq_table));
+    let encoding_table = build_encoding_table(&lengths);
+    let encoded = encode_streams(text.chars(), &encoding_table, streams);
+    
+    let mut tree = BitWriter::new();
+    write_tree(&mut tree, &lengths, |w, c| {
+        for &b in c.encode_utf8(&mut [0; 4]).as_bytes() {
+            w.write_bits(b as u64, 8);
+        }
+    });
+    let mut output = vec![MODE_CHARS_TREE];
+    output.extend_from_slice(&tree.finish());
+    output.extend_from_slice(&(text.chars().count() as u64).to_le_bytes());
+    output.extend_from_slice(&encoded);
+    output
+}
+
+fn compress_byte_symbols(data: &[u8], streams: usize) -> Vec<u8> {
+    let mut output = vec![MODE_BYTES];
+    output.extend_from_slice(&Huffman::with_streams(streams).compress(data));
+    output
+}
+
+// Codes string symbols (grapheme clusters, log tokens) that concatenate back
+// to the original text
+fn compress_strings(symbols: &[&str], mode: u8, streams: usize) -> Vec<u8> {
+    let lengths = code_lengths(&build_huffman_tree(&build_frequency_table_parallel(symbols)));
+    let encoding_table = build_encoding_table(&lengths);
+    let encoded = encode_streams(symbols.iter().copied(), &encoding_table, streams);
+    
+    let mut output = vec![mode];
+    write_lengths_table(&mut output, &lengths, write_string);
+    // The symbol count tells the decoder where the padding bits start
+    output.extend_from_slice(&(symbols.len() as u64).to_le_bytes());
+    output.extend_from_slice(&encoded);
+    output
+}
+
+// Codes UTF-16 code units directly, so the file round-trips byte for byte
+// (BOM, unpaired surrogates and all) without transcoding
+fn compress_utf16(data: &[u8], big_endian: bool, streams: usize) -> Vec<u8> {
+    let units = utf16_units(data, big_endian);
+    if units.is_empty() {
+        return store(data);
+    }
+    let lengths = code_lengths(&build_huffman_tree(&build_frequency_table_parallel(&units)));
+    let encoding_table = build_encoding_table(&lengths);
+    let encoded = encode_streams(units.iter().copied(), &encoding_table, streams);
+    
+    let mut output = vec![MODE_UTF16, big_endian as u8];
+    // A stray odd byte can't be a code unit; carry it along verbatim
+    match data.len() % 2 {
+        1 => output.extend_from_slice(&[1, data[data.len() - 1]]),
+        _ => output.push(0),
+    }
+    write_lengths_table(&mut output, &lengths, |out, unit| out.extend_from_slice(&unit.to_le_bytes()));
+    output.extend_from_slice(&(units.len() as u64).to_le_bytes());
+    output.extend_from_slice(&encoded);
+    output
+}
+
+fn utf16_units(data: &[u8], big_endian: bool) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|pair| {
+            let pair = [pair[0], pair[1]];
+            if big_endian { u16::from_be_bytes(pair) } else { u16::from_le_bytes(pair) }
+        })
+        .collect()
+}
+
+fn utf16_bom(data: &[u8]) -> Option<bool> {
+    match data {
+        [0xFF, 0xFE, ..] => Some(false),
+        [0xFE, 0xFF, ..] => Some(true),
+        _ => None,
+    }
+}
+
+// Transposes delimiter-separated text into per-column streams and codes
+// those as bytes; text that isn't a clean table is coded as chars instead
+fn compress_csv(text: &str, streams: usize) -> Vec<u8> {
+    let columns = columnar::detect(text).and_then(|delimiter| columnar::encode(text, delimiter));
+    match columns {
+        Some(columns) => {
+            let mut output = vec![MODE_CSV];
+            output.extend_from_slice(&compress_bits(&columns, 8, streams));
+            output
+        }
+        None => compress_chars(text, streams),
+    }
+}
+
+// One of the JSON or protobuf streams, coded with its own 8-bit model. Streams that are
+// empty, have a single distinct byte or don't shrink are kept raw
+fn code_stream(output: &mut Vec<u8>, stream: &[u8], streams: usize) {
+    let distinct = stream.iter().collect::<std::collections::HashSet<_>>().len();
+    let coded = if distinct > 1 { compress_bits(stream, 8, streams) } else { Vec::new() };
+    let (flag, body) = if distinct > 1 && coded.len() < stream.len() {
+        (coded[0], &coded[1..])
+    } else {
+        (MODE_STORED, stream)
+    };
+    output.push(flag);
+    output.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    output.extend_from_slice(body);
+}
+
+fn read_stream(reader: &mut impl BufRead, streams: usize) -> std::io::Result<Vec<u8>> {
+    let mut flag = [0u8];
+    reader.read_exact(&mut flag)?;
+    let len = read_u64(reader)?;
+    let mut body = Vec::new();
+    reader.take(len).read_to_end(&mut body)?;
+    if body.len() as u64 != len {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "stream is truncated"));
+    }
+    match flag[0] {
+        MODE_BITS => decode_bits(&mut body.as_slice(), streams),
+        MODE_STORED => Ok(body),
+        _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad stream")),
+    }
+}
+
+// Keys, string values, numbers and structure each get their own model, since
+// mixing them drowns out the very regular structure; input that isn't JSON
+// is coded as chars instead
+fn compress_json(text: &str, streams: usize) -> Vec<u8> {
+    let Some(json) = json::split(text) else {
+        return compress_chars(text, streams);
+    };
+    let mut output = vec![MODE_JSON];
+    for stream in [&json.structure, &json.keys, &json.strings, &json.numbers] {
+        code_stream(&mut output, stream, streams);
+    }
+    output
+}
+
+// Regroups serialized protobuf into tag, varint, fixed-width and payload
+// streams, each with its own model; other data, including varints that
+// wouldn't re-encode to the same bytes (see protobuf::split), is coded as
+// plain bytes
+fn compress_protobuf(data: &[u8], streams: usize) -> Vec<u8> {
+    let Some(message) = protobuf::split(data) else {
+        return compress_bits(data, 8, streams);
+    };
+    let mut output = vec![MODE_PROTOBUF, message.delimited as u8];
+    for stream in [&message.frames, &message.tags, &message.varints, &message.lengths, &message.fixed, &message.payloads] {
+        code_stream(&mut output, stream, streams);
+    }
+    output
+}
+
+// Little-endian doubles, XORed with their predecessor. A trailing partial
+// value is kept raw after the coded series
+fn compress_float64(data: &[u8]) -> Vec<u8> {
+    let values: Vec<f64> = data
+        .chunks_exact(8)
+        .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+        .collect();
+    let encoded = gorilla::encode_f64(&values);
+    let mut output = vec![MODE_FLOAT64];
+    output.extend_from_slice(&(encoded.len() as u64).to_le_bytes());
+    output.extend_from_slice(&encoded);
+    output.extend_from_slice(&data[values.len() * 8..]);
+    output
+}
+
+// Little-endian signed integers, such as timestamps, coded by their change
+// in step. A trailing partial value is kept raw, as for doubles
+fn compress_int64(data: &[u8]) -> Vec<u8> {
+    let values: Vec<i64> = data
+        .chunks_exact(8)
+        .map(|c| i64::from_le_bytes(c.try_into().unwrap()))
+        .collect();
+    let encoded = timeseries::encode(&values);
+    let mut output = vec![MODE_INT64];
+    output.extend_from_slice(&(encoded.len() as u64).to_le_bytes());
+    output.extend_from_slice(&encoded);
+    output.extend_from_slice(&data[values.len() * 8..]);
+    output
+}
+
+// Modes whose Huffman payloads come with symbol counts, which interleaving
+// needs to know where each stream's padding starts
+fn interleavable(mode: u8) -> bool {
+    matches!(
+        mode,
+        MODE_CHARS | MODE_CHARS_TREE | MODE_GRAPHEME | MODE_LOG | MODE_BITS | MODE_UTF16 | MODE_CSV | MODE_JSON
+            | MODE_PROTOBUF | MODE_BYTES
+    )
+}
+
+// Without an explicit unit, a UTF-16 BOM selects 16-bit symbols and input
+// that isn't UTF-8 is coded as bytes
+fn default_unit(data: &[u8], unit: Option<SymbolUnit>) -> SymbolUnit {
+    unit.unwrap_or(match utf16_bom(data) {
+        Some(big_endian) => SymbolUnit::Utf16 { big_endian },
+        None if std::str::from_utf8(data).is_err() => SymbolUnit::Bytes,
+        None => SymbolUnit::Char,
+    })
+}
+
+// How `compress_bytes` codes data, as compress's flags would have it. The
+// default is what compress does given none
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    // The symbol unit or algorithm, as --mode and --algorithm give it; None
+    // picks chars, bytes or UTF-16 from the data
+    pub unit: Option<SymbolUnit>,
+    // Huffman payloads split into this many interleaved streams, up to 64
+    pub streams: usize,
+    // Cut the data into blocks this big, each coded on its own
+    pub block_size: Option<usize>,
+    // 1 to 9, instead of a unit and block size
+    pub level: Option<u8>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { unit: None, streams: 1, block_size: None, level: None }
+    }
+}
+
+// Header and payload, as a file's would be without its attributes
+pub fn compress_bytes(data: &[u8], options: &Options) -> Result<Vec<u8>, CompressionError> {
+    let mut output = Vec::new();
+    write_header(&mut output, data);
+    output.extend_from_slice(&compress_payload_with(data, options)?);
+    Ok(output)
+}
+
+// The payload `options` asks for. A level tries each of its codecs on all
+// cores and keeps whichever output is smallest
+pub fn compress_payload_with(data: &[u8], options: &Options) -> Result<Vec<u8>, CompressionError> {
+    let invalid = CompressionError::InvalidOptions;
+    if !(1..=MAX_STREAMS).contains(&options.streams) {
+        return Err(invalid("streams must be 1 to 64"));
+    }
+    if options.block_size.is_some_and(|size| !(1..=MAX_BLOCK_SIZE).contains(&size)) {
+        return Err(invalid("block size must be 1 byte to 1 GiB"));
+    }
+    let (candidates, level_blocks) = match options.level {
+        Some(level @ 1..=9) if options.unit.is_none() && options.block_size.is_none() => {
+            let (units, blocks) = level_settings(level);
+            (units.into_iter().map(Some).collect(), blocks)
+        }
+        Some(1..=9) => return Err(invalid("a level stands in for the unit and block size")),
+        Some(_) => return Err(invalid("levels are 1 to 9")),
+        None => (vec![options.unit.clone()], None),
+    };
+    // A level's blocks are only worth it for more than one
+    let blocks = options.block_size.or(level_blocks.filter(|&size| data.len() > size));
+    let outputs = candidates
+        .par_iter()
+        .map(|unit| match blocks {
+            Some(block_size) => compress_blocks(data, unit.clone(), options.streams, block_size),
+            None => compress_payload(data, unit.clone(), options.streams),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(outputs.into_iter().min_by_key(Vec::len).unwrap())
+}
+
+// The mode byte and what follows. `streams` above 1 interleaves every
+// Huffman payload that has a symbol count
+pub fn compress_payload(data: &[u8], unit: Option<SymbolUnit>, streams: usize) -> Result<Vec<u8>, CompressionError> {
+    // JPEG, zip, gz etc. won't shrink any further, so skip the Huffman pass.
+    // Empty input has nothing to build a code from, and stores as the mode
+    // byte alone
+    if data.is_empty() || sniff(data) == ContentKind::Compressed {
+        return Ok(store(data));
+    }
+    
+    let unit = default_unit(data, unit);
+    let output = match unit {
+        SymbolUnit::Utf16 { big_endian } => compress_utf16(data, big_endian, streams),
+        SymbolUnit::Bits(width) => compress_bits(data, width, streams),
+        SymbolUnit::Protobuf => compress_protobuf(data, streams),
+        SymbolUnit::Float64 => compress_float64(data),
+        SymbolUnit::Int64 => compress_int64(data),
+        SymbolUnit::Bytes => compress_byte_symbols(data, streams),
+        SymbolUnit::Lz77(lz77) => [&[MODE_LZ77][..], &lz77.compress(data)].concat(),
+        SymbolUnit::Lz78(lz78) => [&[MODE_LZ78][..], &lz78.compress(data)].concat(),
+        SymbolUnit::Lzss(lzss) => [&[MODE_LZSS][..], &lzss.compress(data)].concat(),
+        SymbolUnit::Rle => [&[MODE_RLE][..], &Rle.compress(data)].concat(),
+        SymbolUnit::BlockSort(block_sort) => [&[MODE_BLOCK_SORT][..], &block_sort.compress(data)].concat(),
+        SymbolUnit::Deflate(deflate) => [&[MODE_DEFLATE][..], &deflate.compress(data)].concat(),
+        SymbolUnit::Arithmetic => [&[MODE_ARITHMETIC][..], &Arithmetic.compress(data)].concat(),
+        SymbolUnit::Range => [&[MODE_RANGE][..], &RangeCoder.compress(data)].concat(),
+        SymbolUnit::Rans => [&[MODE_RANS][..], &Rans.compress(data)].concat(),
+        SymbolUnit::Tans => [&[MODE_TANS][..], &Tans.compress(data)].concat(),
+        SymbolUnit::Adaptive => [&[MODE_ADAPTIVE][..], &AdaptiveHuffman.compress(data)].concat(),
+        SymbolUnit::ShannonFano => [&[MODE_SHANNON_FANO][..], &ShannonFano.compress(data)].concat(),
+        SymbolUnit::Ppm(ppm) => [&[MODE_PPM][..], &ppm.compress(data)].concat(),
+        SymbolUnit::DeltaFilter(filter) => [&[MODE_DELTA_FILTER][..], &filter.compress(data)].concat(),
+        SymbolUnit::Fast => [&[MODE_FAST][..], &Lz4.compress(data)].concat(),
+        SymbolUnit::PresetDeflate { ref deflate, id } => [&[MODE_PRESET_DEFLATE][..], &id.to_be_bytes(), &deflate.compress(data)].concat(),
+        SymbolUnit::Pipeline(ref stages) => {
+            let mut output = data.to_vec();
+            for stage in stages {
+                output = compress_payload(&output, Some(stage.clone()), 1)?;
+            }
+            [&[MODE_PIPELINE, stages.len() as u8][..], &output].concat()
+        }
+        SymbolUnit::Char | SymbolUnit::Grapheme | SymbolUnit::LogTokens | SymbolUnit::Csv | SymbolUnit::Json => {
+            let text = std::str::from_utf8(data)?;
+            match unit {
+                SymbolUnit::Grapheme => compress_strings(&grapheme::graphemes(text), MODE_GRAPHEME, streams),
+                SymbolUnit::LogTokens => compress_strings(&logtok::tokenize(text), MODE_LOG, streams),
+                SymbolUnit::Csv => compress_csv(text, streams),
+                SymbolUnit::Json => compress_json(text, streams),
+                _ => compress_chars(text, streams),
+            }
+        }
+    };
+    let output = if streams > 1 && interleavable(output[0]) {
+        [&[MODE_INTERLEAVED, streams as u8][..], &output].concat()
+    } else {
+        output
+    };
+    
+    // Never make the file bigger: if the table and bits outweigh the savings,
+    // keep the input as-is behind the mode byte. Filters never shrink
+    // anything, and leave the shrinking to the stage after them
+    if output.len() > data.len() && !matches!(unit, SymbolUnit::DeltaFilter(_)) {
+        return Ok(store(data));
+    }
+    
+    Ok(output)
+}
+
+// Cuts the input into blocks of `block_size` bytes, each coded by
+// compress_payload with a model of its own, so any block decodes without
+// the others: in parallel, one at a time in bounded memory, or around a
+// corrupt one. After the mode byte come the block count as a u64 and an
+// index of the blocks' lengths, CRC-32s and payload lengths, so a block
+// can be found without decoding those before it, then the payloads. Blocks
+// are coded on all cores, and come out in order whichever finishes first
+fn compress_blocks(data: &[u8], unit: Option<SymbolUnit>, streams: usize, block_size: usize) -> Result<Vec<u8>, CompressionError> {
+    let payloads = data
+        .par_chunks(block_size)
+        .map(|block| compress_payload(block, unit.clone(), streams))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut output = vec![MODE_BLOCKS];
+    output.extend_from_slice(&(payloads.len() as u64).to_le_bytes());
+    for (block, payload) in data.chunks(block_size).zip(&payloads) {
+        output.extend_from_slice(&(block.len() as u64).to_le_bytes());
+        output.extend_from_slice(&crc32(block).to_le_bytes());
+        output.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    }
+    output.extend(payloads.concat());
+    if output.len() > data.len() {
+        return Ok(store(data));
+    }
+    Ok(output)
+}
+
+fn decompress_blocks(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    let count = data.get(..8).ok_or_else(|| invalid("truncated block index".to_string()))?;
+    let count = u64::from_le_bytes(count.try_into().unwrap());
+    let index_len = usize::try_from(count)
+        .ok()
+        .and_then(|count| count.checked_mul(BLOCK_ENTRY_LEN))
+        .filter(|&len| len <= data.len() - 8)
+        .ok_or_else(|| invalid("truncated block index".to_string()))?;
+    let (index, mut payloads) = data[8..].split_at(index_len);
+    let mut blocks = Vec::new();
+    for (i, entry) in index.chunks_exact(BLOCK_ENTRY_LEN).enumerate() {
+        let len = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        let checksum = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+        let payload_len = u64::from_le_bytes(entry[12..].try_into().unwrap());
+        let payload_len = usize::try_from(payload_len)
+            .ok()
+            .filter(|&len| len <= payloads.len())
+            .ok_or_else(|| invalid(format!("block {} is truncated", i)))?;
+        let (payload, rest) = payloads.split_at(payload_len);
+        payloads = rest;
+        // Blocks in blocks would only let a crafted file nest deep enough to
+        // overflow the stack
+        if payload.first() == Some(&MODE_BLOCKS) {
+            return Err(invalid(format!("block {} holds blocks", i)));
+        }
+        blocks.push((i, len, checksum, payload));
+    }
+    if !payloads.is_empty() {
+        return Err(invalid("data after the last block".to_string()));
+    }
+    let decoded = blocks
+        .par_iter()
+        .map(|&(i, len, checksum, payload)| {
+            let block = decompress_payload(payload).map_err(|e| invalid(format!("block {}: {}", i, e)))?;
+            if block.len() as u64 != len || crc32(&block) != checksum {
+                return Err(invalid(format!("block {} is corrupt", i)));
+            }
+            Ok(block)
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    Ok(decoded.concat())
+}
+
+// Size estimates: the same modeling as compression (frequency counts, stream
+// splits) and the exact bytes each mode spends on headers and tables, but no
+// tree and no encoding. The coded symbols are taken at the Shannon bound,
+// which Huffman codes exceed by less than a bit per symbol
+
+fn estimate_coded<S>(freq_table: &[(S, usize)]) -> u64 {
+    let total = freq_table.iter().map(|(_, freq)| *freq).sum::<usize>() as f64;
+    // A lone symbol carries no information but still takes its 1-bit code
+    if freq_table.len() == 1 {
+        return (total / 8.0).ceil() as u64;
+    }
+    let bits: f64 = freq_table.iter().map(|(_, freq)| *freq as f64 * (total / *freq as f64).log2()).sum();
+    (bits / 8.0).ceil() as u64
+}
+
+// As written by write_lengths_table
+fn binary_table_size<S>(freq_table: &[(S, usize)], symbol_size: impl Fn(&S) -> usize) -> u64 {
+    4 + freq_table.iter().map(|(s, _)| symbol_size(s) as u64 + 1).sum::<u64>()
+}
+
+fn estimate_bits(data: &[u8], width: u32) -> u64 {
+    let (symbols, _, _) = unpack_bits(data, width);
+    let freq_table = build_frequency_table_parallel(&symbols);
+    3 + 4 + binary_table_size(&freq_table, |_| 4) + 8 + estimate_coded(&freq_table)
+}
+
+// As written by write_tree: a bit per node, of which there are one less
+// than twice the leaves, and the leaves' symbols
+fn tree_size<S>(freq_table: &[(S, usize)], symbol_bits: impl Fn(&S) -> usize) -> u64 {
+    let bits: usize = 2 * freq_table.len() - 1 + freq_table.iter().map(|(s, _)| symbol_bits(s)).sum::<usize>();
+    bits.div_ceil(8) as u64
+}
+
+fn estimate_chars(text: &str) -> u64 {
+    let freq_table = build_char_frequency_table(text);
+    1 + tree_size(&freq_table, |c| 8 * c.len_utf8()) + 8 + estimate_coded(&freq_table)
+}
+
+fn estimate_strings(symbols: &[&str]) -> u64 {
+    let freq_table = build_frequency_table_parallel(symbols);
+    1 + binary_table_size(&freq_table, |s| 4 + s.len()) + 8 + estimate_coded(&freq_table)
+}
+
+fn estimate_utf16(data: &[u8], big_endian: bool) -> u64 {
+    let freq_table = build_frequency_table_parallel(&utf16_units(data, big_endian));
+    3 + (data.len() % 2) as u64 + binary_table_size(&freq_table, |_| 2) + 8 + estimate_coded(&freq_table)
+}
+
+// Mirrors code_stream's choice between coding and storing
+fn estimate_stream(stream: &[u8]) -> u64 {
+    let distinct = stream.iter().collect::<std::collections::HashSet<_>>().len();
+    let raw = stream.len() as u64;
+    let body = if distinct > 1 { (estimate_bits(stream, 8) - 1).min(raw) } else { raw };
+    1 + 8 + body
+}
+
+// Predicts the length of compress_data's output without producing it
+pub fn estimate_compressed_size(data: &[u8], unit: Option<SymbolUnit>) -> Result<u64, CompressionError> {
+    if data.is_empty() || sniff(data) == ContentKind::Compressed {
+        return Ok(HEADER_LEN as u64 + 1 + data.len() as u64);
+    }
+    
+    let unit = default_unit(data, unit);
+    let estimate = match unit {
+        SymbolUnit::Utf16 { big_endian } => estimate_utf16(data, big_endian),
+        SymbolUnit::Bits(width) => estimate_bits(data, width),
+        SymbolUnit::Protobuf => match protobuf::split(data) {
+            Some(streams) => {
+                let streams = [&streams.frames, &streams.tags, &streams.varints, &streams.lengths, &streams.fixed, &streams.payloads];
+                2 + streams.iter().map(|s| estimate_stream(s)).sum::<u64>()
+            }
+            None => estimate_bits(data, 8),
+        },
+        // XOR and delta coding have no separate model to run, and are cheap anyway
+        SymbolUnit::Float64 => compress_float64(data).len() as u64,
+        SymbolUnit::Int64 => compress_int64(data).len() as u64,
+        SymbolUnit::Lz77(lz77) => 1 + lz77.compress(data).len() as u64,
+        SymbolUnit::Lz78(lz78) => 1 + lz78.compress(data).len() as u64,
+        SymbolUnit::Lzss(lzss) => 1 + lzss.compress(data).len() as u64,
+        SymbolUnit::Rle => 1 + Rle.compress(data).len() as u64,
+        SymbolUnit::BlockSort(block_sort) => 1 + block_sort.compress(data).len() as u64,
+        SymbolUnit::Deflate(deflate) => 1 + deflate.compress(data).len() as u64,
+        SymbolUnit::Arithmetic => 1 + Arithmetic.compress(data).len() as u64,
+        SymbolUnit::Range => 1 + RangeCoder.compress(data).len() as u64,
+        SymbolUnit::Rans => 1 + Rans.compress(data).len() as u64,
+        SymbolUnit::Tans => 1 + Tans.compress(data).len() as u64,
+        SymbolUnit::Adaptive => 1 + AdaptiveHuffman.compress(data).len() as u64,
+        SymbolUnit::ShannonFano => 1 + ShannonFano.compress(data).len() as u64,
+        SymbolUnit::Ppm(ppm) => 1 + ppm.compress(data).len() as u64,
+        SymbolUnit::DeltaFilter(filter) => 1 + filter.compress(data).len() as u64,
+        SymbolUnit::Fast => 1 + Lz4.compress(data).len() as u64,
+        SymbolUnit::PresetDeflate { ref deflate, .. } => 5 + deflate.compress(data).len() as u64,
+        // Later stages see what earlier ones output, so there is nothing
+        // for it but to run them
+        SymbolUnit::Pipeline(_) => compress_payload(data, Some(unit), 1)?.len() as u64,
+        SymbolUnit::Bytes => {
+            let freq_table = build_byte_frequency_table(data);
+            1 + binary_table_size(&freq_table, |_| 1) + 8 + estimate_coded(&freq_table)
+        }
+        SymbolUnit::Char | SymbolUnit::Grapheme | SymbolUnit::LogTokens | SymbolUnit::Csv | SymbolUnit::Json => {
+            let text = std::str::from_utf8(data)?;
+            match unit {
+                SymbolUnit::Grapheme => estimate_strings(&grapheme::graphemes(text)),
+                SymbolUnit::LogTokens => estimate_strings(&logtok::tokenize(text)),
+                SymbolUnit::Csv => match columnar::detect(text).and_then(|delimiter| columnar::encode(text, delimiter)) {
+                    Some(columns) => 1 + estimate_bits(&columns, 8),
+                    None => estimate_chars(text),
+                },
+                SymbolUnit::Json => match json::split(text) {
+                    Some(streams) => {
+                        let streams = [&streams.structure, &streams.keys, &streams.strings, &streams.numbers];
+                        1 + streams.iter().map(|s| estimate_stream(s)).sum::<u64>()
+                    }
+                    None => estimate_chars(text),
+                },
+                _ => estimate_chars(text),
+            }
+        }
+    };
+    
+    // The stored fallback caps every mode
+    Ok(HEADER_LEN as u64 + estimate.min(1 + data.len() as u64))
+}
+
+// A piece of a file cut at arbitrary offsets, trimmed of the partial UTF-8
+// characters at its ends so a sample of a text file is text too
+fn char_aligned(piece: &[u8]) -> &[u8] {
+    let continuation = |b: &u8| b & 0xc0 == 0x80;
+    let start = piece.iter().take(3).take_while(|b| continuation(b)).count();
+    let piece = &piece[start..];
+    // The last character's first byte, and how long it says it is
+    let Some(lead) = piece.iter().rev().take(4).position(|b| !continuation(b)).map(|i| piece.len() - 1 - i) else {
+        return piece;
+    };
+    let len = match piece[lead] {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    };
+    if lead + len > piece.len() { &piece[..lead] } else { piece }
+}
+
+// estimate_compressed_size of the sampled pieces, scaled up to the whole
+// `len` bytes. Tables and headers are counted once per file, so big files
+// come out a little over
+pub fn estimate_sampled(pieces: &[Vec<u8>], len: u64, unit: Option<SymbolUnit>) -> Result<u64, CompressionError> {
+    if let [whole] = pieces {
+        return estimate_compressed_size(whole, unit);
+    }
+    let sample: Vec<u8> = pieces.iter().flat_map(|piece| char_aligned(piece)).copied().collect();
+    let estimate = estimate_compressed_size(&sample, unit)? - HEADER_LEN as u64;
+    Ok(HEADER_LEN as u64 + (estimate as u128 * len as u128 / sample.len().max(1) as u128) as u64)
+}
+
+fn decode_bits(reader: &mut impl BufRead, streams: usize) -> std::io::Result<Vec<u8>> {
+    let mut widths = [0u8; 2];
+    reader.read_exact(&mut widths)?;
+    let (width, rest_bits) = (widths[0] as u32, widths[1] as u32);
+    if width == 0 || width > MAX_SYMBOL_BITS || rest_bits >= width {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad symbol width"));
+    }
+    let rest = read_u32(reader)?;
+    let symbols = read_symbols(reader, streams, |r| read_u32(r))?;
+    // Whole bytes went in, so whole bytes come out
+    if !(symbols.len() as u64 * width as u64 + rest_bits as u64).is_multiple_of(8) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad symbol count"));
+    }
+    Ok(pack_bits(&symbols, width, rest, rest_bits))
+}
+
+// The original of what compress_bytes or compress wrote, or of gzip or
+// zlib data. Attributes are skipped, having no file to go to
+pub fn decompress_bytes(mut data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    if let Some(format) = Format::detect(data) {
+        return format.decompress(data, None);
+    }
+    let len = data.len();
+    let checksum = read_header(&mut data).map_err(ends_at(len))?;
+    if checksum.is_none() {
+        if let Some(decoded) = decode_first_version(data) {
+            return Ok(decoded?);
+        }
+    }
+    let decompressed = decompress_payload(data).map_err(ends_at(len))?;
+    verify_checksum(checksum, &decompressed)?;
+    Ok(decompressed)
+}
+
+// Running out of data is reported wherever it happens, often as just
+// "failed to fill whole buffer", so say where the data ends
+pub fn ends_at(len: usize) -> impl Fn(CompressionError) -> CompressionError {
+    move |e| match e {
+        CompressionError::Truncated(e) => {
+            CompressionError::Truncated(std::io::Error::new(e.kind(), format!("data ends early, at byte {}: {}", len, e)))
+        }
+        e => e,
+    }
+}
+
+pub fn decompress_payload(mut reader: impl BufRead) -> Result<Vec<u8>, CompressionError> {
+    // The mode byte says how the payload was coded
+    let mut mode = [0u8];
+    reader.read_exact(&mut mode)?;
+    // Only files have attributes to restore; elsewhere they are skipped
+    if mode[0] == MODE_ATTRIBUTES {
+        Attributes::read(&mut reader)?;
+        reader.read_exact(&mut mode)?;
+    }
+    let mut streams = 1;
+    if mode[0] == MODE_INTERLEAVED {
+        let mut count = [0u8];
+        reader.read_exact(&mut count)?;
+        streams = count[0] as usize;
+        reader.read_exact(&mut mode)?;
+        if !(1..=MAX_STREAMS).contains(&streams) || !interleavable(mode[0]) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad interleaved stream header").into());
+        }
+    }
+    // Only files can be given a dictionary
+    if mode[0] == MODE_PRESET_DEFLATE {
+        let mut payload = Vec::new();
+        reader.read_to_end(&mut payload)?;
+        return decompress_preset_deflate(&payload, None);
+    }
+    if mode[0] == MODE_BLOCKS {
+        let mut blocks = Vec::new();
+        reader.read_to_end(&mut blocks)?;
+        return Ok(decompress_blocks(&blocks)?);
+    }
+    if mode[0] == MODE_STORED {
+        let mut stored = Vec::new();
+        reader.read_to_end(&mut stored)?;
+        return Ok(stored);
+    }
+    if matches!(mode[0], MODE_GRAPHEME | MODE_LOG) {
+        let symbols = read_symbols(&mut reader, streams, read_string)?;
+        return Ok(symbols.concat().into_bytes());
+    }
+    if mode[0] == MODE_CHARS {
+        let chars: Vec<char> = read_symbols(&mut reader, streams, |r| {
+            char::from_u32(read_u32(r)?).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "bad char in table"))
+        })?;
+        return Ok(chars.into_iter().collect::<String>().into_bytes());
+    }
+    if mode[0] == MODE_CHARS_TREE {
+        let mut payload = Vec::new();
+        reader.read_to_end(&mut payload)?;
+        let mut bits = BitReader::new(&payload);
+        let decoder = CanonicalDecoder::new(read_tree(&mut bits, read_utf8_char)?)?;
+        bits.align();
+        let mut rest = bits.rest();
+        let count = read_u64(&mut rest)?;
+        let chars = read_coded(&mut rest, &decoder, count, streams)?;
+        return Ok(chars.into_iter().collect::<String>().into_bytes());
+    }
+    if mode[0] == MODE_BITS {
+        return Ok(decode_bits(&mut reader, streams)?);
+    }
+    if mode[0] == MODE_JSON {
+        let streams = json::Streams {
+            structure: read_stream(&mut reader, streams)?,
+            keys: read_stream(&mut reader, streams)?,
+            strings: read_stream(&mut reader, streams)?,
+            numbers: read_stream(&mut reader, streams)?,
+        };
+        return Ok(json::join(&streams)?.into_bytes());
+    }
+    if mode[0] == MODE_PROTOBUF {
+        let mut delimited = [0u8];
+        reader.read_exact(&mut delimited)?;
+        let streams = protobuf::Streams {
+            delimited: delimited[0] == 1,
+            frames: read_stream(&mut reader, streams)?,
+            tags: read_stream(&mut reader, streams)?,
+            varints: read_stream(&mut reader, streams)?,
+            lengths: read_stream(&mut reader, streams)?,
+            fixed: read_stream(&mut reader, streams)?,
+            payloads: read_stream(&mut reader, streams)?,
+        };
+        return Ok(protobuf::join(&streams)?);
+    }
+    if mode[0] == MODE_PIPELINE {
+        let mut count = [0u8];
+        reader.read_exact(&mut count)?;
+        if !(1..=MAX_STAGES).contains(&(count[0] as usize)) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad pipeline stage count").into());
+        }
+        let mut payload = Vec::new();
+        reader.read_to_end(&mut payload)?;
+        for _ in 0..count[0] {
+            // Stages don't nest, which bounds the recursion
+            if payload.first() == Some(&MODE_PIPELINE) {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "pipeline inside a pipeline").into());
+            }
+            payload = decompress_payload(&payload[..])?;
+        }
+        return Ok(payload);
+    }
+    if [MODE_LZ77, MODE_LZ78, MODE_LZSS, MODE_RLE, MODE_BLOCK_SORT, MODE_DEFLATE, MODE_ARITHMETIC, MODE_RANGE, MODE_RANS, MODE_TANS, MODE_ADAPTIVE, MODE_SHANNON_FANO, MODE_PPM, MODE_DELTA_FILTER, MODE_FAST].contains(&mode[0]) {
+        let mut payload = Vec::new();
+        reader.read_to_end(&mut payload)?;
+        let decompressed = match mode[0] {
+            MODE_LZ77 => Lz77::new().decompress(&payload),
+            MODE_LZ78 => Lz78::new().decompress(&payload),
+            MODE_LZSS => Lzss::new().decompress(&payload),
+            MODE_RLE => Rle.decompress(&payload),
+            MODE_BLOCK_SORT => BlockSort::new().decompress(&payload),
+            MODE_DEFLATE => Deflate.decompress(&payload),
+            MODE_ARITHMETIC => Arithmetic.decompress(&payload),
+            MODE_RANGE => RangeCoder.decompress(&payload),
+            MODE_RANS => Rans.decompress(&payload),
+            MODE_TANS => Tans.decompress(&payload),
+            MODE_ADAPTIVE => AdaptiveHuffman.decompress(&payload),
+            MODE_SHANNON_FANO => ShannonFano.decompress(&payload),
+            MODE_PPM => Ppm::new().decompress(&payload),
+            MODE_DELTA_FILTER => DeltaFilter::new().decompress(&payload),
+            _ => Lz4.decompress(&payload),
+        };
+        return Ok(decompressed?);
+    }
+    if mode[0] == MODE_FLOAT64 {
+        let len = read_u64(&mut reader)?;
+        let mut encoded = Vec::new();
+        (&mut reader).take(len).read_to_end(&mut encoded)?;
+        let mut output: Vec<u8> = gorilla::decode_f64(&encoded)?
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        reader.read_to_end(&mut output)?;
+        return Ok(output);
+    }
+    if mode[0] == MODE_INT64 {
+        let len = read_u64(&mut reader)?;
+        let mut encoded = Vec::new();
+        (&mut reader).take(len).read_to_end(&mut encoded)?;
+        let mut output: Vec<u8> = timeseries::decode(&encoded)?
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        reader.read_to_end(&mut output)?;
+        return Ok(output);
+    }
+    if mode[0] == MODE_CSV {
+        // The columns are an N-bit stream of their own, mode byte included
+        reader.read_exact(&mut mode)?;
+        if mode[0] != MODE_BITS {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad column stream").into());
+        }
+        let columns = decode_bits(&mut reader, streams)?;
+        return Ok(columnar::decode(&columns)?.into_bytes());
+    }
+    if mode[0] == MODE_BYTES {
+        let mut payload = Vec::new();
+        reader.read_to_end(&mut payload)?;
+        return Ok(Huffman::with_streams(streams).decompress(&payload)?);
+    }
+    if mode[0] == MODE_UTF16 {
+        let mut flags = [0u8; 2];
+        reader.read_exact(&mut flags)?;
+        let big_endian = flags[0] == 1;
+        let mut trailing = vec![0u8; flags[1] as usize];
+        reader.read_exact(&mut trailing)?;
+        let units = read_symbols(&mut reader, streams, |r| {
+            let mut unit = [0u8; 2];
+            r.read_exact(&mut unit)?;
+            Ok(u16::from_le_bytes(unit))
+        })?;
+        let mut decoded: Vec<u8> = units
+            .iter()
+            .flat_map(|u| if big_endian { u.to_be_bytes() } else { u.to_le_bytes() })
+            .collect();
+        decoded.extend_from_slice(&trailing);
+        return Ok(decoded);
+    }
+    Err(CompressionError::UnknownMode(mode[0]))
+}
+
+// The first version's files have no header and no mode byte: a table of
+// each char, ':', its count in decimal and '|', in the order a HashMap
+// gave them, ended by a newline, then the chars' codes LSB first, from the
+// tree the table builds in that order. The entry for a newline holds one
+// too, so of the places the table could end, the one whose codes exactly
+// fill the rest of the file is it. None if `data` isn't laid out like that
+pub fn decode_first_version(data: &[u8]) -> Option<std::io::Result<Vec<u8>>> {
+    let mut entries: Vec<(char, usize)> = Vec::new();
+    // Tables as they stand where a newline could end them, and where the
+    // codes would start
+    let mut ends = Vec::new();
+    let mut pos = 0;
+    loop {
+        if data.get(pos) == Some(&b'\n') && !entries.is_empty() {
+            ends.push((entries.clone(), pos + 1));
+        }
+        let Some((entry, len)) = first_version_entry(&data[pos..]) else { break };
+        if entries.iter().any(|&(c, _)| c == entry.0) {
+            break;
+        }
+        entries.push(entry);
+        pos += len;
+    }
+    let (table, tree, start) = ends.into_iter().rev().find_map(|(table, start)| {
+        let tree = try_build_huffman_tree(&table).ok()?;
+        let lengths: std::collections::HashMap<char, u8> = code_lengths(&tree).into_iter().collect();
+        let bits = table.iter().try_fold(0u64, |sum, &(c, freq)| {
+            let len = if table.len() == 1 { 0 } else { lengths[&c] as u64 };
+            sum.checked_add((freq as u64).checked_mul(len)?)
+        })?;
+        (bits.div_ceil(8) == (data.len() - start) as u64).then_some((table, tree, start))
+    })?;
+    let count: usize = table.iter().map(|&(_, freq)| freq).sum();
+    // A lone char has no code, and is its count over again
+    if let [(c, freq)] = table[..] {
+        if freq > MAX_BLOCK_SIZE {
+            return Some(Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "first-version run is too long")));
+        }
+        return Some(Ok(c.to_string().repeat(freq).into_bytes()));
+    }
+    let decoded = decode_streams(&data[start..], &tree, count as u64, 1);
+    Some(decoded.map(|chars| chars.into_iter().collect::<String>().into_bytes()))
+}
+
+// A char, ':', a count and '|', and the bytes they take
+fn first_version_entry(data: &[u8]) -> Option<((char, usize), usize)> {
+    let len = match *data.first()? {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+    let c = std::str::from_utf8(data.get(..len)?).ok()?.chars().next()?;
+    let rest = data[len..].strip_prefix(b":")?;
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    rest.get(digits).filter(|&&b| b == b'|')?;
+    let freq: usize = std::str::from_utf8(&rest[..digits]).ok()?.parse().ok().filter(|&freq| freq > 0)?;
+    Some(((c, freq), len + 1 + digits + 1))
+}
+
+// The dictionary's checksum, then DEFLATE with it preset
+pub fn decompress_preset_deflate(data: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>, CompressionError> {
+    let dictionary = dictionary.ok_or(CompressionError::NeedsDictionary)?;
+    let (id, deflated) = data
+        .split_first_chunk()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "truncated preset dictionary checksum"))?;
+    if u32::from_be_bytes(*id) != zlib::adler32(dictionary) {
+        return Err(CompressionError::WrongDictionary);
+    }
+    Ok(Deflate::with_dictionary(dictionary).decompress(deflated)?)
+}
@@ -1,263 +1,494 @@
-use std::collections::BinaryHeap;
-use std::cmp::Ordering;
-use std::fs::{File, read_to_string, write};
-use std::io::{Read, Write, BufRead};
-use bitvec::prelude::*;
-
-#[derive(Debug, Eq, PartialEq)]
-struct HuffmanNode {
-    frequency: usize,
-    character: Option<char>,
-    left: Option<Box<HuffmanNode>>,
-    right: Option<Box<HuffmanNode>>,
+use std::fs::Metadata;
+use std::io::{Read, IsTerminal};
+use huffman::deflate::Deflate;
+use huffman::zlib;
+use huffman::codes::{build_byte_frequency_table, build_char_frequency_table, build_huffman_tree, code_lengths, code_stats};
+use huffman::delta;
+use test_huffman::archive;
+use test_huffman::attributes::Attributes;
+use test_huffman::{compress_bytes, compress_payload_with, ends_at, estimate_compressed_size, estimate_sampled, parse_algorithm, parse_unit, read_header, verify_checksum, write_header};
+use test_huffman::{decode_first_version, decompress_bytes, decompress_payload, decompress_preset_deflate, CompressionError, Format, Options, SymbolUnit, MAX_BLOCK_SIZE, MAX_STREAMS, MODE_ATTRIBUTES, MODE_PRESET_DEFLATE};
+
+mod archive_files;
+mod bench;
+mod entropy;
+mod files;
+mod progress;
+mod serve;
+
+// estimate --sample reads this many pieces of this size from big files
+const SAMPLE_PARTS: u64 = 64;
+const SAMPLE_PART_SIZE: u64 = 256 * 1024;
+
+// What --stats prints: the sizes, and how a Huffman code of the input's
+// chars (or bytes, if it isn't UTF-8) compares with their entropy. That is
+// the order-0 bound; modes that model more can beat it
+fn stats_report(data: &[u8], compressed_len: usize) -> String {
+    let (stats, unit) = match std::str::from_utf8(data) {
+        Ok(text) if !text.is_empty() => {
+            let freq_table = build_char_frequency_table(text);
+            (code_stats(&freq_table, &code_lengths(&build_huffman_tree(&freq_table))), "char")
+        }
+        _ if !data.is_empty() => {
+            let freq_table = build_byte_frequency_table(data);
+            (code_stats(&freq_table, &code_lengths(&build_huffman_tree(&freq_table))), "byte")
+        }
+        _ => (code_stats::<u8>(&[], &[]), "byte"),
+    };
+    let ratio = match data.len() {
+        0 => 0.0,
+        len => 100.0 * compressed_len as f64 / len as f64,
+    };
+    format!(
+        "input:        {} bytes\noutput:       {} bytes\nratio:        {:.2}%\nentropy:      {:.3} bits per {}\naverage code: {:.3} bits per {}\n",
+        data.len(),
+        compressed_len,
+        ratio,
+        stats.entropy,
+        unit,
+        stats.average_length,
+        unit
+    )
 }
 
-impl Ord for HuffmanNode {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.frequency.cmp(&self.frequency)
+// An hz file of `data`: the header, the attributes of the regular file
+// `metadata` describes, if any, and `payload`
+fn hz_file(data: &[u8], metadata: Option<&Metadata>, payload: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    write_header(&mut output, data);
+    if let Some(metadata) = metadata.filter(|m| m.is_file()) {
+        output.push(MODE_ATTRIBUTES);
+        Attributes::of(metadata).write(&mut output);
     }
+    output.extend_from_slice(payload);
+    output
 }
 
-impl PartialOrd for HuffmanNode {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+// `payload` codes hz files after their header and attributes. `dictionary`
+// is for zlib; hz files get theirs through `payload`'s unit
+fn compress_file(
+    input_path: &str,
+    output_path: &str,
+    format: Format,
+    dictionary: Option<&[u8]>,
+    options: files::Options,
+    stats: bool,
+    payload: impl Fn(&[u8]) -> Result<Vec<u8>, CompressionError>,
+) -> std::io::Result<()> {
+    files::transform(input_path, output_path, options, |data, metadata| {
+        let output = match format {
+            Format::Hz => hz_file(data, metadata.filter(|_| !options.no_preserve), &payload(data)?),
+            _ => format.compress(data, dictionary),
+        };
+        if stats {
+            eprint!("{}", stats_report(data, output.len()));
+        }
+        Ok((output, None))
+    })
 }
 
-fn build_frequency_table(text: &str) -> Vec<(char, usize)> {
-    let mut freq_table = std::collections::HashMap::new();
-    for c in text.chars() {
-        *freq_table.entry(c).or_insert(0) += 1;
-    }
-    freq_table.into_iter().collect()
+// `format`, unless hz, is taken as given rather than detected
+fn decompress_file(input_path: &str, output_path: &str, format: Format, dictionary: Option<&[u8]>, options: files::Options) -> std::io::Result<()> {
+    files::transform(input_path, output_path, options, |data, _| {
+        let (decompressed, attributes) = decompress_contents(data, format, dictionary)?;
+        Ok((decompressed, attributes.filter(|_| !options.no_preserve)))
+    })
 }
 
-fn build_huffman_tree(freq_table: &[(char, usize)]) -> HuffmanNode {
-    let mut heap = BinaryHeap::new();
-    
-    for &(c, freq) in freq_table {
-        heap.push(HuffmanNode {
-            frequency: freq,
-            character: Some(c),
-            left: None,
-            right: None,
-        });
+// A compressed file's original data, checked against its checksum, and the
+// attributes it recorded
+fn decompress_contents(mut data: &[u8], format: Format, dictionary: Option<&[u8]>) -> std::io::Result<(Vec<u8>, Option<Attributes>)> {
+    if format != Format::Hz {
+        return Ok((format.decompress(data, dictionary)?, None));
     }
-    
-    while heap.len() > 1 {
-        let left = Box::new(heap.pop().unwrap());
-        let right = Box::new(heap.pop().unwrap());
-        let combined_freq = left.frequency + right.frequency;
-        
-        heap.push(HuffmanNode {
-            frequency: combined_freq,
-            character: None,
-            left: Some(left),
-            right: Some(right),
-        });
+    if let Some(format) = Format::detect(data) {
+        return Ok((format.decompress(data, dictionary)?, None));
     }
-    
-    heap.pop().unwrap()
-}
-
-fn build_encoding_table(root: &HuffmanNode) -> std::collections::HashMap<char, BitVec<u8>> {
-    let mut encoding_table = std::collections::HashMap::new();
-    
-    fn traverse(node: &HuffmanNode, current_code: &mut BitVec<u8>, table: &mut std::collections::HashMap<char, BitVec<u8>>) {
-        if let Some(c) = node.character {
-            table.insert(c, current_code.clone());
-        } else {
-            if let Some(left) = &node.left {
-                current_code.push(false);
-                traverse(left, current_code, table);
-                current_code.pop();
-            }
-            if let Some(right) = &node.right {
-                current_code.push(true);
-                traverse(right, current_code, table);
-                current_code.pop();
-            }
+    let len = data.len();
+    let checksum = read_header(&mut data).map_err(ends_at(len))?;
+    if checksum.is_none() {
+        if let Some(decoded) = decode_first_version(data) {
+            return Ok((decoded?, None));
         }
     }
-    
-    traverse(root, &mut BitVec::new(), &mut encoding_table);
-    encoding_table
-}
-
-fn encode_text(text: &str, encoding_table: &std::collections::HashMap<char, BitVec<u8>>) -> BitVec<u8> {
-    let mut encoded = BitVec::new();
-    for c in text.chars() {
-        encoded.extend(encoding_table.get(&c).unwrap());
+    let mut attributes = None;
+    if data.first() == Some(&MODE_ATTRIBUTES) {
+        data = &data[1..];
+        attributes = Some(Attributes::read(&mut data).map_err(ends_at(len))?);
     }
-    encoded
+    let decompressed = match data.split_first() {
+        Some((&MODE_PRESET_DEFLATE, rest)) => decompress_preset_deflate(rest, dictionary)?,
+        _ => decompress_payload(data).map_err(ends_at(len))?,
+    };
+    verify_checksum(checksum, &decompressed)?;
+    Ok((decompressed, attributes))
 }
 
-fn decode_text(encoded: &BitVec<u8>, root: &HuffmanNode) -> String {
-    let mut decoded = String::new();
-    let mut current_node = root;
-    
-    for bit in encoded {
-        current_node = if *bit {
-            current_node.right.as_ref().unwrap()
-        } else {
-            current_node.left.as_ref().unwrap()
-        };
-        
-        if let Some(c) = current_node.character {
-            decoded.push(c);
-            current_node = root;
-        }
+// Like gzip -t: decodes the whole file in memory and checks it, archives
+// entry by entry, and writes nothing
+fn verify_file(path: &str, format: Format, dictionary: Option<&[u8]>) -> std::io::Result<()> {
+    let data = files::read_input(path)?;
+    if format == Format::Hz && archive::is_archive(&data) {
+        return archive_files::verify(&data);
     }
-    
-    decoded
+    decompress_contents(&data, format, dictionary).map(|_| ())
 }
 
-fn compress_file(input_path: &str, output_path: &str) -> std::io::Result<()> {
-    let text = read_to_string(input_path)?;
-    let freq_table = build_frequency_table(&text);
-    let huffman_tree = build_huffman_tree(&freq_table);
-    let encoding_table = build_encoding_table(&huffman_tree);
-    let encoded = encode_text(&text, &encoding_table);
-    
-    let mut file = File::create(output_path)?;
-    
-    // Write the frequency table
-    for (c, freq) in &freq_table {
-        write!(file, "{}:{}|", c, freq)?;
-        print!("{}:{}|", c, freq);
+
+// Only regular files are looked at: the bytes read from a FIFO would be
+// gone for decompressing
+fn is_archive_file(path: &str) -> std::io::Result<bool> {
+    if path == files::STDIO || !std::fs::metadata(path).is_ok_and(|m| m.is_file()) {
+        return Ok(false);
     }
-    writeln!(file)?;
-    
-    // Write the encoded data
-    file.write_all(&encoded.into_vec())?;
-    
-    Ok(())
+    let mut magic = Vec::new();
+    files::open_input(path)?.take(archive::MAGIC.len() as u64).read_to_end(&mut magic)?;
+    Ok(archive::is_archive(&magic))
 }
 
-fn decompress_file(input_path: &str, output_path: &str) -> std::io::Result<()> {
-    let mut file = File::open(input_path)?;
-    let mut contents = String::new();
-
-    // First, read the frequency table, which is in UTF-8
-    let mut freq_table_part = String::new();
-    let mut file_reader = std::io::BufReader::new(&file);
-    file_reader.read_line(&mut freq_table_part)?;
-
-    // Parse the frequency table
-    let freq_table: Vec<(char, usize)> = freq_table_part
-        .trim()
-        .split('|')
-        .filter(|s| !s.is_empty())  // the last element after delimiter maybe empty e.g. a,b,c,
-        .map(|s| {
-            let mut parts = s.splitn(2, ':');
-            let c = parts.next().unwrap().chars().next().unwrap();
-            let freq = parts.next().unwrap().parse().unwrap();
-            (c, freq)
-        })
-        .collect();
-
-    println!("The frequency table is: \n {:?}", freq_table);
-
-    // Now read the remaining file as raw binary data (for encoded bits)
-    let mut encoded_data = Vec::new();
-    file_reader.read_to_end(&mut encoded_data)?;
-
-    let encoded = BitVec::from_slice(&encoded_data);
-
-    let huffman_tree = build_huffman_tree(&freq_table);
-    let decoded = decode_text(&encoded, &huffman_tree);
-
-    write(output_path, decoded)?;
+// Patches are written as hz files, which code the inserted bytes
+fn delta_files(old_path: &str, new_path: &str, patch_path: &str) -> std::io::Result<()> {
+    let old = files::read_input(old_path)?;
+    let new = files::read_input(new_path)?;
+    let patch = compress_bytes(&delta::diff(&old, &new), &Options::default())?;
+    files::write_output(patch_path, &patch, files::Options::default())
+}
 
-    Ok(())
+// Bare patches, as earlier versions wrote them, are read too
+fn apply_patch(old_path: &str, patch_path: &str, output_path: &str) -> std::io::Result<()> {
+    let old = files::read_input(old_path)?;
+    let mut patch = files::read_input(patch_path)?;
+    if !patch.starts_with(delta::MAGIC) {
+        patch = decompress_bytes(&patch)?;
+    }
+    files::write_output(output_path, &delta::apply(&old, &patch)?, files::Options::default())
 }
 
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} <mode> <input_file> <output_file>", program);
+    eprintln!("       {} compress [options] <archive.car> <file>...", program);
+    eprintln!("       {} decompress <archive.car> <directory>", program);
+    eprintln!("       {} list <archive.car>", program);
+    eprintln!("       {} verify [--format snappy] [--dict <file>] <compressed_file>...", program);
+    eprintln!("       {} bench <input_file>", program);
+    eprintln!("       {} entropy <input_file>", program);
+    eprintln!("       {} compress [--mode chars|graphemes|utf16le|utf16be|log|csv|json|protobuf|float64|int64|bytes|bits=N] [--algorithm huffman|lz77[=W,L]|lz78[=D]|lzss[=W,L,M]|rle|bwt[=B]|deflate[=C]|arithmetic|range|rans|tans|adaptive|shannon-fano|ppm[=N]|delta[=S]|fast|<a>+<b>...] [--level 1-9] [--streams N] [--block-size N] [--format hz|gz|zlib|snappy] [--dict <file>] <input_file> <output_file>", program);
+    eprintln!("UTF-16 input with a byte order mark is detected automatically, and input that isn't UTF-8 is coded as bytes");
+    eprintln!("bits=N codes N-bit symbols (1 to 32), for bit-packed data; log codes the repeated text, words and digits of log lines;");
+    eprintln!("csv codes delimiter-separated tables column by column; json codes structure, keys and values separately;");
+    eprintln!("protobuf regroups tags, varints and payloads of serialized protobuf messages;");
+    eprintln!("float64 XORs little-endian doubles with their predecessor, for numeric series");
+    eprintln!("int64 codes little-endian integers by their change in step, for timestamps and counters");
+    eprintln!("Each mode can also be given as a flag of its own, e.g. --graphemes");
+    eprintln!("--algorithm lz77 replaces repeats with references into a window of W bytes (default 32768), matches up to");
+    eprintln!("L bytes long (default 258); lz78 builds a dictionary of up to D phrases (default 65536), each an earlier");
+    eprintln!("one plus a byte; lzss is lz77 with literals and matches told apart by a flag bit, and matches shorter");
+    eprintln!("than M bytes (default 3) left as literals; rle shortens runs of a repeated byte; bwt compresses like bzip2,");
+    eprintln!("sorting blocks of B bytes (default 900000) with the Burrows-Wheeler transform, then move-to-front, rle and");
+    eprintln!("huffman; deflate codes as gzip and zip do (RFC 1951), trying C earlier matches at each byte (default 128); arithmetic codes bytes in fractions of a bit, from the");
+    eprintln!("same frequencies huffman uses; range does the same a byte at a time, faster and very nearly as small;");
+    eprintln!("rans too, decoding faster still, and tans, zstd's coder, from tables; adaptive builds its huffman code as");
+    eprintln!("it goes, in one pass with no table; shannon-fano codes bytes with huffman's predecessor, for comparison;");
+    eprintln!("ppm predicts each byte from the N before it (default 4, up to 8), slowly but very compactly for text;");
+    eprintln!("delta replaces each byte with its difference from the one S bytes before (default 1), for numbers, samples");
+    eprintln!("and bitmaps with records S bytes wide, and codes nothing itself: follow it with a coder, e.g. delta=4+huffman;");
+    eprintln!("fast trades ratio for speed, with LZ4's byte-aligned matches and literals and no coding after them;");
+    eprintln!("huffman, the default, codes symbols as the mode says.");
+    eprintln!("Algorithms joined with + run left to right, e.g. rle+huffman, where huffman codes bytes");
+    eprintln!("--format gz writes a gzip file, for gunzip and other tools, and zlib a zlib stream, as in PNG and HTTP,");
+    eprintln!("and snappy a Snappy block, for Snappy libraries, instead of this program's own format (hz); they take no --mode,");
+    eprintln!("--algorithm or --streams. decompress tells hz, gz and zlib apart, and needs --format snappy for Snappy blocks");
+    eprintln!("--dict <file> (compress or decompress) presets the file as a dictionary that deflate matches can refer back into,");
+    eprintln!("in hz files or zlib streams, so many small files like it shrink as if they were one; decompress needs it too");
+    eprintln!("--streams N (compress, 1 to 64) deals symbols round-robin into N bit streams that decompress decodes in parallel");
+    eprintln!("--block-size N (compress) cuts the input into blocks of N bytes, each with its own model, which are coded");
+    eprintln!("and decoded on all cores at once");
+    eprintln!("--level N (compress, 1 to 9) picks the algorithm instead, trading speed for ratio as gzip's levels do: 1 is fast");
+    eprintln!("in 1 MiB blocks, 2 to 7 deflate searching further for matches (2 and 3 also in blocks), and 8 and 9 keep the smallest of deflate, ppm and,");
+    eprintln!("at 9, bwt");
+    eprintln!("--rm (compress or decompress) deletes the input once the output is written; the input is locked");
+    eprintln!("meanwhile and nothing is deleted if it changes while being read");
+    eprintln!("Outputs are written to a temporary file and renamed into place; --fsync also flushes them to disk");
+    eprintln!("compress with more than one input, or an output named .car, puts them all in an archive, storing the");
+    eprintln!("content-defined chunks they have in common once; decompress extracts an archive into the directory given,");
+    eprintln!("creating it if need be, and list shows each entry's sizes, CRC-32 and name without extracting it");
+    eprintln!("verify decompresses each file in memory and checks it against its checksum, as gzip -t does, writing");
+    eprintln!("nothing; it names each file that fails and exits with status 1");
+    eprintln!("bench runs every algorithm on the file, checking each round-trips, and prints its size, ratio and speeds");
+    eprintln!("entropy prints the file's entropy per byte, alone and given the byte before, the smallest size coding bytes");
+    eprintln!("one at a time can reach, the huffman code's, and how often each byte occurs");
+    eprintln!("Big files show progress while being read and written, unless standard error isn't a terminal or --quiet");
+    eprintln!("is given");
+    eprintln!("--stats prints the input and output sizes, and the entropy of the input's chars (or bytes) against the average Huffman code length");
+    eprintln!("--mmap (compress or decompress) maps the input file into memory instead of reading it;");
+    eprintln!("the file must not be truncated meanwhile");
+    eprintln!("A file name of - means standard input or output, e.g. cat file | {} compress - - > file.hz", program);
+    eprintln!("compress records the input's timestamps and permissions and decompress restores them, unless --no-preserve");
+    eprintln!("       {} estimate [--mode <name>] [--sample] <input_file>", program);
+    eprintln!("estimate (or compress --estimate) prints the size compress would produce, from the symbol statistics alone,");
+    eprintln!("without coding anything or writing output; --sample bases it on 16 MiB read from across big files, for a");
+    eprintln!("quick prediction however big they are");
+    eprintln!("       {} delta <old_file> <new_file> -o <patch_file>", program);
+    eprintln!("       {} apply <old_file> <patch_file> -o <output_file>", program);
+    eprintln!("delta writes a compressed patch with the CRC-32s of both files, which apply checks");
+    eprintln!("       {} serve --socket <path> | --http <addr:port> [--max-body <bytes>] [--max-connections <n>]", program);
+    eprintln!("serve answers compress/decompress requests until killed, either length-prefixed on a Unix socket");
+    eprintln!("or over HTTP: POST /compress?mode=<name>, POST /decompress, GET /stats");
+    eprintln!("Mode: 'compress' or 'decompress'");
+    std::process::exit(1);
+}
 
 fn main() -> std::io::Result<()> {
     let args: Vec<String> = std::env::args().collect();
     
-    if args.len() != 4 {
-        eprintln!("Usage: {} <mode> <input_file> <output_file>", args[0]);
-        eprintln!("Mode: 'compress' or 'decompress'");
-        std::process::exit(1);
+    if args.len() < 2 {
+        usage(&args[0]);
     }
     
     let mode = &args[1];
-    let input_file = &args[2];
-    let output_file = &args[3];
     
     match mode.as_str() {
-        "compress" => compress_file(input_file, output_file)?,
-        "decompress" => decompress_file(input_file, output_file)?,
+        "compress" | "decompress" | "estimate" | "verify" => {
+            // Optional symbol unit for compress and estimate
+            let mut unit = None;
+            let mut streams = 1;
+            let mut block_size = None;
+            let mut format = Format::Hz;
+            let mut dictionary = None;
+            let mut quiet = false;
+            let mut stats = false;
+            let mut level = None;
+            let mut estimate = false;
+            let mut sample = false;
+            let mut options = files::Options::default();
+            let mut files = &args[2..];
+            while let Some(flag) = files.first().filter(|a| a.starts_with("--")) {
+                match flag.as_str() {
+                    "--rm" => options.remove_source = true,
+                    "--fsync" => options.fsync = true,
+                    "--no-preserve" => options.no_preserve = true,
+                    "--mmap" => options.mmap = true,
+                    "--quiet" => quiet = true,
+                    "--stats" => stats = true,
+                    "--estimate" => estimate = true,
+                    "--sample" => sample = true,
+                    "--algorithm" => {
+                        files = &files[1..];
+                        let name = files.first().unwrap_or_else(|| usage(&args[0]));
+                        if name != "huffman" {
+                            unit = Some(parse_algorithm(name).unwrap_or_else(|| usage(&args[0])));
+                        }
+                    }
+                    "--block-size" => {
+                        files = &files[1..];
+                        block_size = match files.first().map(|n| n.parse()) {
+                            Some(Ok(n @ 1..=MAX_BLOCK_SIZE)) => Some(n),
+                            _ => usage(&args[0]),
+                        };
+                    }
+                    "--level" => {
+                        files = &files[1..];
+                        level = match files.first().map(|n| n.parse()) {
+                            Some(Ok(n @ 1..=9)) => Some(n),
+                            _ => usage(&args[0]),
+                        };
+                    }
+                    "--dict" => {
+                        files = &files[1..];
+                        dictionary = Some(files::read_input(files.first().unwrap_or_else(|| usage(&args[0])))?);
+                    }
+                    "--format" => {
+                        files = &files[1..];
+                        format = match files.first().map(String::as_str) {
+                            Some("hz") => Format::Hz,
+                            Some("gz") => Format::Gzip,
+                            Some("zlib") => Format::Zlib,
+                            Some("snappy") => Format::Snappy,
+                            _ => usage(&args[0]),
+                        };
+                    }
+                    "--mode" => {
+                        files = &files[1..];
+                        let name = files.first().unwrap_or_else(|| usage(&args[0]));
+                        unit = Some(parse_unit(name).unwrap_or_else(|| usage(&args[0])));
+                    }
+                    "--streams" => {
+                        files = &files[1..];
+                        streams = match files.first().map(|n| n.parse()) {
+                            Some(Ok(n @ 1..=MAX_STREAMS)) => n,
+                            _ => usage(&args[0]),
+                        };
+                    }
+                    flag => unit = Some(parse_unit(&flag[2..]).unwrap_or_else(|| usage(&args[0]))),
+                }
+                files = &files[1..];
+            }
+            // compress --estimate is the estimate command
+            let mode = match estimate {
+                true if mode == "compress" => "estimate",
+                true => usage(&args[0]),
+                false => mode.as_str(),
+            };
+            if sample && mode != "estimate" {
+                usage(&args[0]);
+            }
+            // A level stands in for the codec and block size, not alongside them
+            if level.is_some() && (unit.is_some() || block_size.is_some() || dictionary.is_some() || format != Format::Hz || mode != "compress") {
+                usage(&args[0]);
+            }
+            // Both decode, and take only what decoding needs
+            let decoding = matches!(mode, "decompress" | "verify");
+            if (unit.is_some() || streams > 1 || block_size.is_some() || stats) && decoding {
+                usage(&args[0]);
+            }
+            // Standard formats fix their own coding
+            if format != Format::Hz && (unit.is_some() || streams > 1 || block_size.is_some() || mode == "estimate") {
+                usage(&args[0]);
+            }
+            // Blocks decode alone, so can't need a dictionary
+            if block_size.is_some() && (dictionary.is_some() || mode == "estimate") {
+                usage(&args[0]);
+            }
+            // Preset dictionaries seed DEFLATE's window, in hz files or zlib
+            // streams
+            if let Some(dictionary) = &dictionary {
+                match (format, &unit) {
+                    // deflate=C keeps its chain, which is already in range
+                    (Format::Hz, None | Some(SymbolUnit::Deflate(_))) if !decoding => {
+                        let mut deflate = Deflate::with_dictionary(dictionary);
+                        if let Some(SymbolUnit::Deflate(chained)) = &unit {
+                            deflate = deflate.with_chain(chained.chain()).unwrap();
+                        }
+                        unit = Some(SymbolUnit::PresetDeflate { deflate, id: zlib::adler32(dictionary) });
+                    }
+                    (Format::Hz | Format::Zlib, None) => {}
+                    _ => usage(&args[0]),
+                }
+            }
+            if mode == "verify" {
+                if files.is_empty() || options != files::Options::default() {
+                    usage(&args[0]);
+                }
+                // Every file is checked, and each problem reported
+                let mut failed = false;
+                for path in files {
+                    if let Err(e) = verify_file(path, format, dictionary.as_deref()) {
+                        eprintln!("{}: {}", path, e);
+                        failed = true;
+                    }
+                }
+                if failed {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            if mode == "estimate" {
+                if files.len() != 1 || streams > 1 || stats || options != files::Options::default() {
+                    usage(&args[0]);
+                }
+                let estimate = match sample {
+                    true => {
+                        let (pieces, len) = files::read_sample(&files[0], SAMPLE_PARTS, SAMPLE_PART_SIZE)?;
+                        estimate_sampled(&pieces, len, unit)?
+                    }
+                    false => estimate_compressed_size(&files::read_input(&files[0])?, unit)?,
+                };
+                println!("{}", estimate);
+                return Ok(());
+            }
+            // Only once there is something to show, and someone to see it
+            options.progress = !quiet && std::io::stderr().is_terminal();
+            let coding = Options { unit, streams, block_size, level };
+            let payload = |data: &[u8]| compress_payload_with(data, &coding);
+            // Archives hold an hz file per entry, of the chunks no earlier entry had
+            if mode == "compress" && (files.len() > 2 || files.first().is_some_and(|f| f.ends_with(".car"))) {
+                if files.len() < 2 || format != Format::Hz || dictionary.is_some() || stats || options.remove_source {
+                    usage(&args[0]);
+                }
+                let archive = archive_files::create(&files[1..], |data, metadata| {
+                    Ok(hz_file(data, metadata.filter(|_| !options.no_preserve), &compress_payload_with(data, &coding)?))
+                })?;
+                return files::write_output(&files[0], &archive, options);
+            }
+            if files.len() != 2 {
+                usage(&args[0]);
+            }
+            let input_file = &files[0];
+            let output_file = &files[1];
+            // Like gzip: compressed data is of no use on a terminal, and can garble it
+            if mode == "compress" && output_file == files::STDIO && std::io::stdout().is_terminal() {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "refusing to write compressed data to a terminal"));
+            }
+            if mode == "compress" {
+                compress_file(input_file, output_file, format, dictionary.as_deref(), options, stats, payload)?;
+            } else if format == Format::Hz && is_archive_file(input_file)? {
+                if output_file == files::STDIO || options.remove_source {
+                    usage(&args[0]);
+                }
+                archive_files::extract(&files::read_input(input_file)?, output_file, options)?;
+            } else {
+                decompress_file(input_file, output_file, format, dictionary.as_deref(), options)?;
+            }
+        }
+        "list" => {
+            if args.len() != 3 {
+                usage(&args[0]);
+            }
+            print!("{}", archive::listing(&files::read_input(&args[2])?)?);
+        }
+        "bench" => {
+            if args.len() != 3 {
+                usage(&args[0]);
+            }
+            print!("{}", bench::report(&files::read_input(&args[2])?)?);
+        }
+        "entropy" => {
+            if args.len() != 3 {
+                usage(&args[0]);
+            }
+            print!("{}", entropy::report(&files::read_input(&args[2])?));
+        }
+        "delta" | "apply" => {
+            if args.len() != 6 || args[4] != "-o" {
+                usage(&args[0]);
+            }
+            if mode == "delta" {
+                delta_files(&args[2], &args[3], &args[5])?;
+            } else {
+                apply_patch(&args[2], &args[3], &args[5])?;
+            }
+        }
+        "serve" => {
+            let mut limits = serve::Limits::default();
+            let mut endpoint = None;
+            for pair in args[2..].chunks(2) {
+                let [flag, value] = pair else { usage(&args[0]) };
+                match flag.as_str() {
+                    "--socket" | "--http" if endpoint.is_none() => endpoint = Some((flag.as_str(), value.as_str())),
+                    "--max-body" => limits.max_body = value.parse().unwrap_or_else(|_| usage(&args[0])),
+                    "--max-connections" => match value.parse() {
+                        Ok(n @ 1..) => limits.max_connections = n,
+                        _ => usage(&args[0]),
+                    },
+                    _ => usage(&args[0]),
+                }
+            }
+            match endpoint {
+                Some(("--http", addr)) => serve::serve_http(addr, limits)?,
+                #[cfg(unix)]
+                Some((_, path)) => serve::serve_unix(path, limits)?,
+                #[cfg(not(unix))]
+                Some(_) => {
+                    return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--socket needs Unix domain sockets"));
+                }
+                None => usage(&args[0]),
+            }
+        }
         _ => {
-            eprintln!("Invalid mode. Use 'compress' or 'decompress'");
+            eprintln!("Invalid mode. Use 'compress', 'decompress', 'estimate', 'verify', 'list', 'bench', 'entropy', 'delta', 'apply' or 'serve'");
             std::process::exit(1);
         }
     }
     
     Ok(())
 }
-
-
-// cargo run -- compress gatsby.txt compressed.bin
-// cargo run -- decompress compressed.bin output.txt
-
-
-// P:1|':8| :437|N:1|B:1|H:2|e:233|h:136|M:1|,:16|v:17|.:16|E:2|g:47|
-// :41|w:54|Y:1|y:38|d:103|k:13|A:2|o:144|-:4|i:115|m:41|O:4|z:2|b:27|S:2|G:4|:128|
-
-// [('P', 1), ('\'', 8), (' ', 437), ('N', 1), ('B', 1), ('H', 2), ('e', 233), ('h', 136), ('M', 1), (',', 16), ('v', 17), ('.', 16), ('E', 2), ('g', 47)]
-
-
-
-use std::iter::repeat;
-use std::iter::zip;
-
-
-fn add_binary(a: String, b: String) -> String {
-        
-    let max_len: usize = a.len().max(b.len());    
-
-    let a_padded: Vec<usize> = a.chars()
-        .rev()
-        .map(|c| c.to_digit(10).unwrap() as usize)
-        .chain(repeat(0))
-        .take(max_len)
-        .collect();
-
-    let b_padded: Vec<usize> = b.chars()
-        .rev()
-        .map(|c| c.to_digit(10).unwrap() as usize)
-        .chain(repeat(0))
-        .take(max_len)
-        .collect();
-
-    println!("a = {:?}", a_padded);
-    println!("b = {:?}", b_padded);
-
-    let mut carry = 0;
-    let mut result = vec![];
-    for (x, y) in zip(a_padded.into_iter(), b_padded.into_iter()) {
-        let mut digit_sum = x + y + carry;
-        if digit_sum >= 2 {
-            digit_sum -= 2;
-            carry = 1;
-        } else {
-            carry = 0;
-        }
-        result.push(digit_sum);
-    }   
-    if carry != 0 {
-        result.push(1);
-    }
-
-    println!("result = {:?}", result);
-
-    let result_str: String = result
-        .into_iter()
-        .map(|num| num.to_string() )
-        .rev()
-        .collect();
-
-    result_str
-}
\ No newline at end of file
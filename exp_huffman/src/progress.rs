@@ -0,0 +1,72 @@
+// A progress line on standard error for big inputs and outputs: what is
+// being done, a bar and the bytes so far, drawn by indicatif as reading and
+// writing go on. Nothing is drawn until an operation has taken a moment, so
+// small files never flash one, and the line is cleared when it is done.
+
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+const SHOW_AFTER: Duration = Duration::from_millis(100);
+// What readers and writers hand on between updates
+pub const CHUNK: usize = 1 << 20;
+
+pub struct Progress {
+    bar: ProgressBar,
+    started: Instant,
+    enabled: bool,
+    shown: bool,
+}
+
+impl Progress {
+    // `total` is the byte count, where it is known up front
+    pub fn new(enabled: bool, label: &'static str, total: Option<u64>) -> Progress {
+        let template = match total.filter(|&total| total > 0) {
+            Some(_) => "{msg} [{bar:30}] {percent}% {binary_bytes}/{binary_total_bytes}",
+            None => "{msg} {binary_bytes}",
+        };
+        // Hidden until there has been time enough to be worth showing
+        let bar = ProgressBar::with_draw_target(total, ProgressDrawTarget::hidden());
+        bar.set_style(ProgressStyle::with_template(template).unwrap().progress_chars("#-"));
+        bar.set_message(label);
+        Progress { bar, started: Instant::now(), enabled, shown: false }
+    }
+
+    pub fn advance(&mut self, bytes: usize) {
+        self.bar.inc(bytes as u64);
+        if self.enabled && !self.shown && self.started.elapsed() >= SHOW_AFTER {
+            self.bar.set_draw_target(ProgressDrawTarget::stderr());
+            self.shown = true;
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_only_once_enabled_and_slow() {
+        let mut progress = Progress::new(true, "reading", Some(3 * CHUNK as u64));
+        progress.advance(CHUNK);
+        assert!(!progress.shown, "drawn before it had taken a moment");
+        progress.started -= SHOW_AFTER;
+        progress.advance(CHUNK);
+        assert!(progress.shown);
+        assert_eq!(progress.bar.position(), 2 * CHUNK as u64);
+        assert_eq!(progress.bar.length(), Some(3 * CHUNK as u64));
+
+        let mut quiet = Progress::new(false, "writing", None);
+        quiet.started -= SHOW_AFTER;
+        quiet.advance(CHUNK);
+        assert!(!quiet.shown);
+        assert_eq!(quiet.bar.position(), CHUNK as u64);
+        assert_eq!(quiet.bar.length(), None);
+    }
+}
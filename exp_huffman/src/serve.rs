@@ -0,0 +1,366 @@
+// `serve`: a long-running process that answers compress and decompress
+// requests, so callers that handle many small payloads don't pay for a
+// process start each time. That is all it saves: nothing is kept warm
+// between requests, and each one builds its code tables or model from its
+// own body, exactly as a compress or decompress run would. Two front ends
+// share the limits and counters.
+//
+// --socket <path> speaks a binary protocol over a Unix domain socket. A
+// connection carries any number of requests, one after another:
+//
+//   request:  op (u8, b'c' or b'd')
+//             mode name length (u8) + mode name, as for --mode; empty for
+//             automatic detection, and always empty for decompress
+//             body length (u64 LE) + body
+//   response: status (u8, 0 = ok, 1 = error)
+//             length (u64 LE) + result, or a UTF-8 error message
+//
+// A malformed request gets an error response and closes the connection,
+// since the stream can't be resynced.
+//
+// --http <addr> is plain HTTP/1.1, one request per connection:
+//
+//   POST /compress[?mode=<name>]  body in, compressed body out
+//   POST /decompress              compressed body in, original out
+//   GET  /stats                   counters as JSON
+//
+// Either way each connection gets its own thread, up to --max-connections at
+// once; beyond that new connections are turned away (503 over HTTP) rather
+// than queued. Bodies over --max-body are refused before being read.
+
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use test_huffman::{compress_bytes, decompress_bytes, parse_unit, Options};
+
+#[cfg(unix)]
+const OP_COMPRESS: u8 = b'c';
+#[cfg(unix)]
+const OP_DECOMPRESS: u8 = b'd';
+
+#[cfg(unix)]
+const STATUS_OK: u8 = 0;
+#[cfg(unix)]
+const STATUS_ERROR: u8 = 1;
+
+// Request line plus headers, for HTTP
+const MAX_HEADER: usize = 8 << 10;
+
+// A client that stops sending mid-request shouldn't hold a slot forever
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+const ACCEPT_RETRY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    // Bodies are held in memory, so cap them
+    pub max_body: u64,
+    pub max_connections: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_body: 256 << 20,
+            max_connections: 64,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    active: AtomicUsize,
+    requests: AtomicU64,
+    failed: AtomicU64,
+    rejected: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl Stats {
+    fn record(&self, body_len: usize, result: &std::io::Result<Vec<u8>>) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(body_len as u64, Ordering::Relaxed);
+        match result {
+            Ok(out) => self.bytes_out.fetch_add(out.len() as u64, Ordering::Relaxed),
+            Err(_) => self.failed.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"active_connections\":{},\"requests\":{},\"failed\":{},\"rejected\":{},\"bytes_in\":{},\"bytes_out\":{}}}\n",
+            self.active.load(Ordering::Relaxed),
+            self.requests.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+            self.rejected.load(Ordering::Relaxed),
+            self.bytes_in.load(Ordering::Relaxed),
+            self.bytes_out.load(Ordering::Relaxed),
+        )
+    }
+}
+
+// Holds one of the `max_connections` slots until dropped
+struct Slot(Arc<Stats>);
+
+impl Slot {
+    fn take(stats: &Arc<Stats>, limits: Limits) -> Option<Slot> {
+        let taken = stats
+            .active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < limits.max_connections).then_some(n + 1));
+        match taken {
+            Ok(_) => Some(Slot(stats.clone())),
+            Err(_) => {
+                stats.rejected.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// Runs a request body through the codec
+fn process(compress: bool, mode: Option<&str>, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    match (compress, mode) {
+        (true, None) => Ok(compress_bytes(body, &Options::default())?),
+        (true, Some(name)) => match parse_unit(name) {
+            Some(unit) => Ok(compress_bytes(body, &Options { unit: Some(unit), ..Options::default() })?),
+            None => Err(invalid("unknown mode")),
+        },
+        (false, None) => Ok(decompress_bytes(body)?),
+        (false, Some(_)) => Err(invalid("decompress takes no mode")),
+    }
+}
+
+fn invalid(msg: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(unix)]
+// Reads one request and works out the reply. The outer error means the
+// connection is done (EOF or garbage); the inner one is a failed request.
+fn handle(reader: &mut impl Read, limits: Limits, stats: &Stats) -> std::io::Result<std::io::Result<Vec<u8>>> {
+    let mut op = [0u8; 2];
+    reader.read_exact(&mut op)?;
+    let mut name = vec![0u8; op[1] as usize];
+    reader.read_exact(&mut name)?;
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+    if len > limits.max_body {
+        return Err(invalid("request body is too large"));
+    }
+    let mut body = vec![0u8; len as usize];
+    reader.read_exact(&mut body)?;
+
+    let name = std::str::from_utf8(&name).map_err(|_| invalid("mode name is not UTF-8"))?;
+    let mode = Some(name).filter(|n| !n.is_empty());
+    let result = match op[0] {
+        OP_COMPRESS => process(true, mode, &body),
+        OP_DECOMPRESS => process(false, mode, &body),
+        _ => return Err(invalid("unknown request")),
+    };
+    stats.record(body.len(), &result);
+    Ok(result)
+}
+
+#[cfg(unix)]
+fn respond(writer: &mut impl Write, result: &std::io::Result<Vec<u8>>) -> std::io::Result<()> {
+    let message;
+    let (status, body) = match result {
+        Ok(body) => (STATUS_OK, body.as_slice()),
+        Err(e) => {
+            message = e.to_string();
+            (STATUS_ERROR, message.as_bytes())
+        }
+    };
+    writer.write_all(&[status])?;
+    writer.write_all(&(body.len() as u64).to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+#[cfg(unix)]
+fn serve_connection(stream: UnixStream, limits: Limits, stats: &Stats) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        match handle(&mut reader, limits, stats) {
+            Ok(result) => respond(&mut writer, &result)?,
+            // The client hung up between requests
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return respond(&mut writer, &Err(e)),
+        }
+    }
+}
+
+// A failed accept, as when the process is out of file descriptors, is the
+// server's trouble of the moment, not a reason to stop serving everyone
+// else. The pause keeps a lasting one from spinning the loop
+fn accepted<S>(stream: std::io::Result<S>) -> Option<S> {
+    match stream {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            eprintln!("Accepting a connection failed: {}", e);
+            std::thread::sleep(ACCEPT_RETRY);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn serve_unix(path: &str, limits: Limits) -> std::io::Result<()> {
+    // A socket file left behind by a previous run that nobody answers on
+    // can be replaced; a live one means another server is running.
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(std::io::Error::new(ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path)));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(ErrorKind::AddrInUse, format!("{} is already being served", path)));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    eprintln!("Listening on {}", path);
+    let stats = Arc::new(Stats::default());
+    for stream in listener.incoming() {
+        let Some(mut stream) = accepted(stream) else { continue };
+        let Some(slot) = Slot::take(&stats, limits) else {
+            let _ = respond(&mut stream, &Err(std::io::Error::other("server is busy")));
+            continue;
+        };
+        std::thread::spawn(move || {
+            if let Err(e) = serve_connection(stream, limits, &slot.0) {
+                eprintln!("Connection failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn http_response(stream: &mut impl Write, status: &str, content_type: &str, body: &[u8]) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn http_error(stream: &mut impl Write, status: &str, message: &str) -> std::io::Result<()> {
+    http_response(stream, status, "text/plain; charset=utf-8", format!("{}\n", message).as_bytes())
+}
+
+fn serve_http_connection(stream: TcpStream, limits: Limits, stats: &Stats) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    // Request line and headers, bounded so a client can't make us buffer
+    // an endless line
+    let mut head = Vec::new();
+    let mut header_bytes = 0;
+    loop {
+        let mut line = String::new();
+        let n = (&mut reader).take((MAX_HEADER - header_bytes + 1) as u64).read_line(&mut line)?;
+        header_bytes += n;
+        if header_bytes > MAX_HEADER {
+            return http_error(&mut writer, "431 Request Header Fields Too Large", "headers are too large");
+        }
+        if n == 0 {
+            return Ok(());
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        head.push(line.to_string());
+    }
+
+    // A blank first line ends the head before any request line
+    let Some(request_line) = head.first() else {
+        return http_error(&mut writer, "400 Bad Request", "missing request line");
+    };
+    let mut request_line = request_line.split(' ');
+    let (method, target) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return http_error(&mut writer, "400 Bad Request", "malformed request line"),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let header = |name: &str| {
+        head[1..].iter().find_map(|h| {
+            let (key, value) = h.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    };
+
+    let compress = match (method, path) {
+        ("GET", "/stats") => return http_response(&mut writer, "200 OK", "application/json", stats.to_json().as_bytes()),
+        ("POST", "/compress") => true,
+        ("POST", "/decompress") => false,
+        (_, "/stats" | "/compress" | "/decompress") => {
+            return http_error(&mut writer, "405 Method Not Allowed", "method not allowed")
+        }
+        _ => return http_error(&mut writer, "404 Not Found", "no such endpoint"),
+    };
+    if header("Transfer-Encoding").is_some() {
+        return http_error(&mut writer, "411 Length Required", "send the body with a Content-Length");
+    }
+    let Some(Ok(len)) = header("Content-Length").map(str::parse::<u64>) else {
+        return http_error(&mut writer, "411 Length Required", "send the body with a Content-Length");
+    };
+    if len > limits.max_body {
+        stats.rejected.fetch_add(1, Ordering::Relaxed);
+        return http_error(&mut writer, "413 Content Too Large", &format!("bodies are limited to {} bytes", limits.max_body));
+    }
+    if header("Expect").is_some_and(|e| e.eq_ignore_ascii_case("100-continue")) {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        writer.flush()?;
+    }
+    let mut body = vec![0u8; len as usize];
+    reader.read_exact(&mut body)?;
+
+    let mode = query.split('&').find_map(|param| param.strip_prefix("mode="));
+    let result = process(compress, mode, &body);
+    stats.record(body.len(), &result);
+    match result {
+        Ok(out) => http_response(&mut writer, "200 OK", "application/octet-stream", &out),
+        Err(e) => http_error(&mut writer, "400 Bad Request", &e.to_string()),
+    }
+}
+
+pub fn serve_http(addr: &str, limits: Limits) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let stats = Arc::new(Stats::default());
+    for stream in listener.incoming() {
+        let Some(stream) = accepted(stream) else { continue };
+        let Some(slot) = Slot::take(&stats, limits) else {
+            let _ = http_error(&mut BufWriter::new(stream), "503 Service Unavailable", "too many connections");
+            continue;
+        };
+        std::thread::spawn(move || {
+            if let Err(e) = serve_http_connection(stream, limits, &slot.0) {
+                eprintln!("Connection failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+// Archives: several files in one, extracted into a directory. Nothing an
+// archive holds may land outside that directory.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("archive-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_test_huffman")).args(args).output().unwrap()
+}
+
+fn path(p: &std::path::Path) -> &str {
+    p.to_str().unwrap()
+}
+
+// An archive of one entry named `name`: "hi", stored, with a CRC-32 of 0
+// that doesn't match it
+fn crafted(name: &str) -> Vec<u8> {
+    crafted_entries(&[name])
+}
+
+// The same, with an entry like it under each name
+fn crafted_entries(names: &[&str]) -> Vec<u8> {
+    let entry = b"Shi";
+    let mut data = b"\x89CAR\n\x01".to_vec();
+    data.extend_from_slice(&(names.len() as u32).to_le_bytes());
+    for name in names {
+        data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&2u64.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(entry.len() as u64).to_le_bytes());
+    }
+    data.extend(entry.repeat(names.len()));
+    data
+}
+
+#[test]
+fn nested_paths_round_trip() {
+    let src = scratch("src");
+    std::fs::create_dir_all(src.join("logs/old")).unwrap();
+    std::fs::write(src.join("logs/old/a.log"), "GET / 200\n".repeat(100)).unwrap();
+    std::fs::write(src.join("b.txt"), "to be or not to be").unwrap();
+    let archive = scratch("nested.car");
+    let output = Command::new(env!("CARGO_BIN_EXE_test_huffman"))
+        .current_dir(&src)
+        .args(["compress", "--chars", path(&archive), "logs/old/a.log", "b.txt"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let out = scratch("nested");
+    let output = run(&["decompress", path(&archive), path(&out)]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(std::fs::read_to_string(out.join("logs/old/a.log")).unwrap(), "GET / 200\n".repeat(100));
+    assert_eq!(std::fs::read_to_string(out.join("b.txt")).unwrap(), "to be or not to be");
+}
+
+#[test]
+fn names_leaving_the_directory_are_refused() {
+    let outside = scratch("outside.car");
+    let output = run(&["compress", path(&outside), "../x"]);
+    assert!(!output.status.success());
+
+    for name in ["../escaped", "/etc/escaped", "a//b", "a/./b"] {
+        let archive = scratch("crafted.car");
+        std::fs::write(&archive, crafted(name)).unwrap();
+        let out = scratch("crafted");
+        let output = run(&["decompress", path(&archive), path(&out)]);
+        assert_eq!(output.status.code(), Some(1), "{}", name);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("bad entry name"), "{}", name);
+        assert!(!scratch("escaped").exists());
+    }
+}
+
+#[test]
+fn corrupt_entries_are_named() {
+    let archive = scratch("corrupt.car");
+    let mut data = crafted("greeting");
+    // The stored bytes no longer match the CRC-32 of 0
+    std::fs::write(&archive, &data).unwrap();
+    let output = run(&["decompress", path(&archive), path(&scratch("corrupt"))]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("greeting is corrupt"));
+
+    data.truncate(data.len() - 1);
+    std::fs::write(&archive, &data).unwrap();
+    let output = run(&["decompress", path(&archive), path(&scratch("corrupt"))]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("truncated"));
+}
+
+// Listing reads the index alone, so it doesn't notice the bad CRC-32
+#[test]
+fn lists_without_extracting() {
+    let archive = scratch("listed.car");
+    std::fs::write(&archive, crafted("greeting")).unwrap();
+    let output = run(&["list", path(&archive)]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "  compressed uncompressed  saved  crc32     name\n           3            2 -50.0%  00000000  greeting\n"
+    );
+    assert!(!run(&["list", path(&scratch("missing.car"))]).status.success());
+}
+
+#[test]
+fn duplicate_names_are_refused() {
+    let src = scratch("dup-src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("x"), "one file").unwrap();
+    let archive = scratch("dup.car");
+    let output = Command::new(env!("CARGO_BIN_EXE_test_huffman"))
+        .current_dir(&src)
+        .args(["compress", path(&archive), "x", "./x"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("x is given twice"));
+
+    // Names differing only in case are one file where case is ignored
+    let mut names = vec![["same", "same"]];
+    if cfg!(any(windows, target_os = "macos")) {
+        names.push(["Same", "same"]);
+    }
+    for names in names {
+        std::fs::write(&archive, crafted_entries(&names)).unwrap();
+        let out = scratch("dup");
+        let output = run(&["decompress", path(&archive), path(&out)]);
+        assert_eq!(output.status.code(), Some(1), "{:?}", names);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("name the same file"), "{:?}", names);
+        assert!(!out.exists());
+        assert!(!run(&["verify", path(&archive)]).status.success());
+    }
+}
+
+// A file and a copy with a few bytes put in: only the chunks around those
+// bytes are stored twice, so the archive of both is hardly bigger than
+// the archive of one. The data is noise, which no coding shrinks
+#[test]
+fn shared_content_is_stored_once() {
+    let src = scratch("shared-src");
+    std::fs::create_dir_all(&src).unwrap();
+    let mut state = 1u32;
+    let a: Vec<u8> = (0..300_000)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+    let b = [&a[..150_000], b"put in", &a[150_000..]].concat();
+    std::fs::write(src.join("a.bin"), &a).unwrap();
+    std::fs::write(src.join("b.bin"), &b).unwrap();
+    let archive = |name: &str, files: &[&str]| {
+        let archive = scratch(name);
+        let output = Command::new(env!("CARGO_BIN_EXE_test_huffman"))
+            .current_dir(&src)
+            .args(["compress", path(&archive)].iter().chain(files))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        archive
+    };
+    let one = std::fs::metadata(archive("one.car", &["a.bin"])).unwrap().len();
+    let both = archive("both.car", &["a.bin", "b.bin"]);
+    assert!(std::fs::metadata(&both).unwrap().len() < one + one / 4, "{} vs {}", std::fs::metadata(&both).unwrap().len(), one);
+
+    let out = scratch("shared");
+    let output = run(&["decompress", path(&both), path(&out)]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(std::fs::read(out.join("a.bin")).unwrap() == a);
+    assert!(std::fs::read(out.join("b.bin")).unwrap() == b);
+}
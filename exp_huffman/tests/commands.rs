@@ -0,0 +1,122 @@
+// Commands that report on a file rather than convert it
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn input(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/inputs").join(name)
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_test_huffman")).args(args).output().unwrap()
+}
+
+#[test]
+fn bench_runs_every_algorithm() {
+    let output = run(&["bench", input("app.log").to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report = String::from_utf8(output.stdout).unwrap();
+    let mut lines = report.lines();
+    assert!(lines.next().unwrap().starts_with("algorithm"));
+    let names: Vec<&str> = lines.map(|line| line.split_whitespace().next().unwrap()).collect();
+    for name in ["huffman", "deflate", "ppm", "fast"] {
+        assert!(names.contains(&name), "{}", report);
+    }
+    // The sizes are the codecs' own, so they don't vary from run to run
+    assert!(report.lines().any(|line| line.split_whitespace().take(3).eq(["deflate", "260", "6.16%"])), "{}", report);
+    assert!(!run(&["bench", "missing.txt"]).status.success());
+}
+
+#[test]
+fn entropy_explains_the_bytes() {
+    let file = std::env::temp_dir().join(format!("entropy-{}.txt", std::process::id()));
+    std::fs::write(&file, "aaaabbcd").unwrap();
+    let output = run(&["entropy", file.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // One byte of context takes 'a' after 'a' from 1 bit to 0.415, and
+    // leaves 'd' after 'c' nothing to say
+    let expected = "\
+size:           8 bytes
+entropy:        1.750 bits per byte
+given previous: 0.749 bits per byte
+lower bound:    2 bytes, coding bytes one at a time
+huffman code:   1.750 bits per byte, 2 bytes
+distinct bytes: 4
+
+byte          count   share
+a                 4  50.00%  ########################################
+b                 2  25.00%  ####################
+c                 1  12.50%  ##########
+d                 1  12.50%  ##########
+";
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn estimates_need_no_output() {
+    let log = input("app.log");
+    let whole = run(&["estimate", log.to_str().unwrap()]);
+    assert!(whole.status.success());
+    // Small files are read whole, sampled or not
+    for args in [&["compress", "--estimate"][..], &["estimate", "--sample"]] {
+        let output = run(&[args, &[log.to_str().unwrap()]].concat());
+        assert_eq!(output.stdout, whole.stdout, "{:?}", args);
+    }
+    assert!(!run(&["decompress", "--estimate", log.to_str().unwrap()]).status.success());
+    assert!(!run(&["compress", "--sample", log.to_str().unwrap(), "out.hz"]).status.success());
+}
+
+// Pieces cut mid-character are trimmed, so a text file's sample is coded
+// as text, like the file
+#[test]
+fn samples_predict_big_files() {
+    let file = std::env::temp_dir().join(format!("sampled-{}.txt", std::process::id()));
+    let mut text = String::new();
+    let mut i = 0u64;
+    while text.len() < 20 << 20 {
+        text += &format!("{} café {} naïve {}\n", i, i * i % 7919, ["rouge", "vert", "bleu"][i as usize % 3]);
+        i += 1;
+    }
+    std::fs::write(&file, &text).unwrap();
+    let estimate = |args: &[&str]| -> u64 {
+        let output = run(&[&["estimate"], args, &[file.to_str().unwrap()]].concat());
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap().trim().parse().unwrap()
+    };
+    let (whole, sampled) = (estimate(&[]), estimate(&["--sample"]));
+    std::fs::remove_file(&file).unwrap();
+    assert!(whole.abs_diff(sampled) < whole / 50, "{} vs {}", whole, sampled);
+}
+
+#[test]
+fn verify_checks_without_writing() {
+    let expected = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/expected");
+    let good: Vec<String> = ["chars.hz", "blocks.hz", "app.log.gz", "app.log.zlib", "inputs.car"]
+        .iter()
+        .map(|name| expected.join(name).to_str().unwrap().to_string())
+        .collect();
+    let output = run(&[&["verify"], &good.iter().map(String::as_str).collect::<Vec<_>>()[..]].concat());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+    let snappy = expected.join("app.log.snappy");
+    assert!(run(&["verify", "--format", "snappy", snappy.to_str().unwrap()]).status.success());
+
+    // A flipped bit in the coded data, and a file cut short
+    let mut flipped = std::fs::read(expected.join("chars.hz")).unwrap();
+    let last = flipped.len() - 1;
+    flipped[last] ^= 0x10;
+    let flipped_path = std::env::temp_dir().join(format!("flipped-{}.hz", std::process::id()));
+    std::fs::write(&flipped_path, flipped).unwrap();
+    let mut archive = std::fs::read(expected.join("inputs.car")).unwrap();
+    archive.truncate(archive.len() - 10);
+    let truncated_path = std::env::temp_dir().join(format!("truncated-{}.car", std::process::id()));
+    std::fs::write(&truncated_path, archive).unwrap();
+
+    let (flipped_path, truncated_path) = (flipped_path.to_str().unwrap(), truncated_path.to_str().unwrap());
+    let output = run(&["verify", flipped_path, &good[0], truncated_path]);
+    assert_eq!(output.status.code(), Some(1));
+    let errors = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(errors.lines().count(), 2, "{}", errors);
+    assert!(errors.contains(flipped_path) && errors.contains(truncated_path) && !errors.contains(&good[0]), "{}", errors);
+    assert!(output.stdout.is_empty());
+}
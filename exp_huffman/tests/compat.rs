@@ -0,0 +1,364 @@
+// Format compatibility across versions. The policy (see the mode bytes in
+// lib.rs): a released mode's layout never changes, new layouts get a new
+// mode byte, and a decoder refuses a mode it doesn't know with an error
+// instead of guessing or panicking.
+//
+// testdata/compat/<release>/<input>.hz are files written by released
+// versions, one per file in testdata/inputs that the release could
+// compress; today's decoder must turn each back into that input. baseline/
+// is the first version's, which only took text and had no flags. To vendor
+// a new release's outputs, or to check old decoders against today's files,
+// name the release tags to build:
+//
+//   COMPAT_REVS="v0.2.0" UPDATE_COMPAT=1 cargo test --test compat
+//
+// Each is checked out into a temporary git worktree and built there. Its
+// encodes must decode with today's binary, and its decoder must either
+// read today's golden files correctly or reject them cleanly. UPDATE_COMPAT
+// stores its encodes under testdata/compat/<tag>. Only tags belong here:
+// commits on a branch are no release, and their hashes don't survive a
+// rebase. The baseline's decoder panics on files it can't read, so it
+// can't be checked this way.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+// (golden file, compress flags, input file), as in tests/golden.rs
+const VECTORS: &[(&str, &str, &str)] = &[
+    ("chars.hz", "--chars", "chars.txt"),
+    ("graphemes.hz", "--graphemes", "graphemes.txt"),
+    ("utf16le.hz", "--utf16le", "utf16le.txt"),
+    ("utf16be.hz", "--utf16be", "utf16be.txt"),
+    ("log.hz", "--log", "app.log"),
+    ("csv.hz", "--csv", "table.csv"),
+    ("json.hz", "--json", "doc.json"),
+    ("protobuf.hz", "--protobuf", "messages.pb"),
+    ("float64.hz", "--float64", "series.f64"),
+    ("int64.hz", "--int64", "series.i64"),
+    ("bits4.hz", "--bits=4", "nibbles.bin"),
+    ("bits12.hz", "--bits=12", "samples.bin"),
+    ("bytes.hz", "--bytes", "program.bin"),
+    ("lz77.hz", "--algorithm lz77", "app.log"),
+    ("lz78.hz", "--algorithm lz78", "app.log"),
+    ("lzss.hz", "--algorithm lzss", "app.log"),
+    ("rle.hz", "--algorithm rle", "bitmap.bin"),
+    ("rle_huffman.hz", "--algorithm rle+huffman", "bitmap.bin"),
+    ("bwt.hz", "--algorithm bwt", "app.log"),
+    ("deflate.hz", "--algorithm deflate", "app.log"),
+    ("arithmetic.hz", "--algorithm arithmetic", "app.log"),
+    ("range.hz", "--algorithm range", "app.log"),
+    ("rans.hz", "--algorithm rans", "app.log"),
+    ("tans.hz", "--algorithm tans", "app.log"),
+    ("adaptive.hz", "--algorithm adaptive", "app.log"),
+    ("shannon_fano.hz", "--algorithm shannon-fano", "app.log"),
+    ("ppm.hz", "--algorithm ppm", "app.log"),
+    ("delta_huffman.hz", "--algorithm delta+huffman", "bitmap.bin"),
+    ("fast.hz", "--algorithm fast", "app.log"),
+    ("app.log.gz", "--format gz", "app.log"),
+    ("app.log.zlib", "--format zlib", "app.log"),
+    ("stored.hz", "--chars", "packed.gz"),
+];
+
+// Every mode byte this version reads
+const KNOWN_MODES: &[u8] = b"SCJPFMIhgunlbZYXRQWDAKTVOEBdfpck";
+
+fn testdata() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata")
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("compat-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+fn run(binary: &Path, args: &[&str]) -> Output {
+    Command::new(binary).args(args).output().unwrap()
+}
+
+fn current() -> PathBuf {
+    PathBuf::from(env!("CARGO_BIN_EXE_test_huffman"))
+}
+
+// A refusal is an ordinary error exit: not a panic (101), not a signal
+fn assert_refused(output: &Output, what: &str) {
+    assert!(!output.status.success(), "{} was accepted", what);
+    assert_eq!(output.status.code(), Some(1), "{} wasn't rejected cleanly: {}", what, String::from_utf8_lossy(&output.stderr));
+}
+
+// Decodes `compressed` with `binary` and checks it gives back `input`
+fn assert_decodes(binary: &Path, compressed: &Path, input: &str) {
+    let out = scratch(&format!("{}.out", input));
+    let output = run(binary, &["decompress", "--no-preserve", path(compressed), path(&out)]);
+    assert!(output.status.success(), "{}: {}", compressed.display(), String::from_utf8_lossy(&output.stderr));
+    let original = std::fs::read(testdata().join("inputs").join(input)).unwrap();
+    assert!(std::fs::read(&out).unwrap() == original, "{} decodes to the wrong bytes", compressed.display());
+}
+
+#[test]
+fn vendored_outputs_still_decode() {
+    let mut revisions: Vec<_> = std::fs::read_dir(testdata().join("compat")).unwrap().map(|e| e.unwrap().path()).collect();
+    revisions.sort();
+    assert!(!revisions.is_empty());
+    for revision in revisions {
+        for &(_, _, input) in VECTORS {
+            let compressed = revision.join(format!("{}.hz", input));
+            if compressed.exists() {
+                assert_decodes(&current(), &compressed, input);
+            }
+        }
+    }
+}
+
+// The first version's files: a text table, with a newline entry of its own
+// in the middle, and the codes, with no header or mode byte. baseline/ has
+// what it wrote for gatsby.txt, whose line ends were CRLF then
+#[test]
+fn first_version_files_decode() {
+    assert_decodes(&current(), &testdata().join("compat/baseline/gatsby.txt.hz"), "gatsby.txt");
+    // A lone char has no code, and nothing follows the table
+    let file = scratch("first-version.hz");
+    let out = scratch("first-version.out");
+    std::fs::write(&file, b"a:4|\n").unwrap();
+    let output = run(&current(), &["decompress", path(&file), path(&out)]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(std::fs::read(&out).unwrap(), b"aaaa");
+    // Codes that don't fill the rest of the file exactly mean it isn't one
+    std::fs::write(&file, b"a:4|b:4|\n\x0f\x0f").unwrap();
+    assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), "codes past the table's count");
+}
+
+// The first version's table has no symbol count, but its counts add up to
+// it, so the padding in the last byte must not decode as text. "ab" codes
+// to the bits 0 and 1, followed by six bits of padding
+#[test]
+fn first_version_padding_is_not_decoded() {
+    let file = scratch("first-version-padding.hz");
+    let out = scratch("first-version-padding.out");
+    std::fs::write(&file, b"a:1|b:1|\n\x02").unwrap();
+    let output = run(&current(), &["decompress", path(&file), path(&out)]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(std::fs::read(&out).unwrap(), b"ab");
+}
+
+// ':' and ' ' are chars like any other: "::2" counts ':'. Damaged tables,
+// and counts that overflow, are refused rather than panicking
+#[test]
+fn first_version_tables_are_parsed_strictly() {
+    let file = scratch("first-version-table.hz");
+    let out = scratch("first-version-table.out");
+    for (table, decoded) in [(&b"::2|b:1|\n\x03"[..], &b"::b"[..]), (b" :2|b:1|\n\x03", b"  b")] {
+        std::fs::write(&file, table).unwrap();
+        let output = run(&current(), &["decompress", path(&file), path(&out)]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(std::fs::read(&out).unwrap(), decoded);
+    }
+    std::fs::remove_file(&out).unwrap();
+    for table in [&b"ab\n"[..], b"a:x|\n", b"a:1|b\n", b"a:18446744073709551615|b:1|\n\x01"] {
+        std::fs::write(&file, table).unwrap();
+        assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), &String::from_utf8_lossy(table));
+        assert!(!out.exists());
+    }
+}
+
+// The frequency-table layouts that came before canonical codes are no
+// longer read
+#[test]
+fn frequency_table_modes_are_refused() {
+    let file = scratch("frequency-table.hz");
+    let out = scratch("frequency-table.out");
+    for mode in *b"HGUNL" {
+        std::fs::write(&file, [&[mode][..], b"a:1|b:1|\n\x02"].concat()).unwrap();
+        assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), &format!("mode {}", mode as char));
+        assert!(!out.exists());
+    }
+}
+
+#[test]
+fn unknown_modes_are_refused() {
+    let out = scratch("unknown.out");
+    for mode in (0..=255u8).filter(|m| !KNOWN_MODES.contains(m)) {
+        let file = scratch("unknown.hz");
+        std::fs::write(&file, [mode, 1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), &format!("mode {:#04x}", mode));
+        assert!(!out.exists(), "output left behind for mode {:#04x}", mode);
+    }
+
+    // Behind an attributes block, and attributes with a flag from the future
+    let file = scratch("unknown-attributes.hz");
+    std::fs::write(&file, [b'M', 0, b'?', 1, 2, 3]).unwrap();
+    assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), "unknown mode after attributes");
+    std::fs::write(&file, [b'M', 0x80, b'S', 1, 2, 3]).unwrap();
+    assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), "unknown attribute flag");
+    assert!(!out.exists());
+
+    // Headers from a later format version, or damaged ones
+    let file = scratch("unknown-version.hz");
+    std::fs::write(&file, [0x89, b'H', b'Z', b'\n', 99, b'S', 1, 2, 3]).unwrap();
+    assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), "unknown format version");
+    std::fs::write(&file, [0x89, b'H', b'Z', b'\r', 1, b'S', 1, 2, 3]).unwrap();
+    assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), "bad magic number");
+    std::fs::write(&file, [0x89, b'H']).unwrap();
+    assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), "truncated header");
+    assert!(!out.exists());
+
+    // Pipelines with no stages, or one inside another
+    let file = scratch("bad-pipeline.hz");
+    std::fs::write(&file, [b'Q', 0, b'S', 1, 2, 3]).unwrap();
+    assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), "empty pipeline");
+    std::fs::write(&file, [b'Q', 1, b'Q', 1, b'S', 1, 2, 3]).unwrap();
+    assert_refused(&run(&current(), &["decompress", path(&file), path(&out)]), "nested pipeline");
+    assert!(!out.exists());
+}
+
+// Cut short anywhere, Huffman-coded files say where they end
+#[test]
+fn truncated_files_say_where_they_end() {
+    let out = scratch("truncated.out");
+    for name in ["bytes", "chars", "bits12", "graphemes", "log", "utf16le", "interleaved", "csv"] {
+        let compressed = std::fs::read(testdata().join("expected").join(format!("{}.hz", name))).unwrap();
+        for cut in [12, compressed.len() / 2, compressed.len() - 1] {
+            let file = scratch("truncated.hz");
+            std::fs::write(&file, &compressed[..cut]).unwrap();
+            let output = run(&current(), &["decompress", path(&file), path(&out)]);
+            assert_refused(&output, &format!("{} cut at {}", name, cut));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains(&format!("ends early, at byte {}", cut)), "{} cut at {}: {}", name, cut, stderr);
+            assert!(!out.exists());
+        }
+    }
+}
+
+// What a fuzzer would try on the decoder, which lives in the binary where
+// no fuzz target reaches it: golden files cut short, with bytes changed and
+// with bytes inserted. Each must decode or be refused, and in good time
+#[test]
+fn corrupt_files_are_refused_cleanly() {
+    let mut state = 0x2545_f491u32;
+    let mut random = move |n: usize| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (state >> 8) as usize % n
+    };
+    let out = scratch("corrupt.out");
+    let file = scratch("corrupt.hz");
+    let mut goldens: Vec<PathBuf> = std::fs::read_dir(testdata().join("expected"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "hz"))
+        .collect();
+    goldens.sort();
+    for golden in goldens {
+        let original = std::fs::read(&golden).unwrap();
+        for i in 0..9 {
+            let mut corrupt = original.clone();
+            match i % 3 {
+                0 => corrupt.truncate(random(original.len())),
+                1 => {
+                    for _ in 0..1 + random(3) {
+                        corrupt[random(original.len())] = random(256) as u8;
+                    }
+                }
+                _ => {
+                    let at = random(original.len());
+                    corrupt.splice(at..at, (0..1 + random(8)).map(|_| random(256) as u8));
+                }
+            }
+            std::fs::write(&file, &corrupt).unwrap();
+            let what = format!("{} corrupted as {:02x?}", golden.display(), &corrupt[..corrupt.len().min(32)]);
+
+            let mut child = Command::new(current())
+                .args(["decompress", path(&file), path(&out)])
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap();
+            let deadline = Instant::now() + Duration::from_secs(60);
+            let status = loop {
+                if let Some(status) = child.try_wait().unwrap() {
+                    break status;
+                }
+                if Instant::now() > deadline {
+                    child.kill().unwrap();
+                    panic!("{} still decoding after a minute", what);
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            };
+            let output = child.wait_with_output().unwrap();
+            assert!(matches!(status.code(), Some(0 | 1)), "{}: {:?} {}", what, status, String::from_utf8_lossy(&output.stderr));
+        }
+    }
+}
+
+// Checks out and builds `revision`, returning its binary
+fn build_revision(revision: &str) -> PathBuf {
+    let repo = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let worktree = scratch(&format!("worktree-{}", revision));
+    let status = Command::new("git")
+        .current_dir(repo)
+        .args(["worktree", "add", "--detach", "--force", path(&worktree), revision])
+        .status()
+        .unwrap();
+    assert!(status.success(), "cannot check out {}", revision);
+    let status = Command::new("cargo")
+        .args(["build", "--quiet", "--manifest-path", path(&worktree.join("exp_huffman/Cargo.toml"))])
+        .env("CARGO_TARGET_DIR", scratch("target"))
+        .status()
+        .unwrap();
+    let binary = scratch("target").join("debug/test_huffman");
+    let copy = scratch(&format!("test_huffman-{}", revision));
+    if status.success() {
+        std::fs::copy(&binary, &copy).unwrap();
+    }
+    Command::new("git").current_dir(repo).args(["worktree", "remove", "--force", path(&worktree)]).status().unwrap();
+    assert!(status.success(), "cannot build {}", revision);
+    copy
+}
+
+#[test]
+fn previous_revisions() {
+    let Ok(revisions) = std::env::var("COMPAT_REVS") else {
+        eprintln!("COMPAT_REVS not set, skipping");
+        return;
+    };
+    for revision in revisions.split_whitespace() {
+        let old = build_revision(revision);
+
+        // Old encodes, new decoder. A mode the revision doesn't have yet
+        // makes it fail, and that case is skipped.
+        let vendor = testdata().join("compat").join(revision);
+        for &(_, flags, input) in VECTORS {
+            let compressed = scratch(&format!("{}-{}.hz", revision, input));
+            let input_path = testdata().join("inputs").join(input);
+            let args: Vec<&str> = ["compress"].into_iter().chain(flags.split(' ')).chain([path(&input_path), path(&compressed)]).collect();
+            let output = run(&old, &args);
+            if !output.status.success() {
+                continue;
+            }
+            assert_decodes(&current(), &compressed, input);
+            if std::env::var_os("UPDATE_COMPAT").is_some() {
+                std::fs::create_dir_all(&vendor).unwrap();
+                std::fs::copy(&compressed, vendor.join(format!("{}.hz", input))).unwrap();
+            }
+        }
+
+        // New encodes, old decoder: right or refused, never wrong or a crash
+        for &(golden, _, input) in VECTORS {
+            let compressed = testdata().join("expected").join(golden);
+            let out = scratch(&format!("{}-{}", revision, golden));
+            let output = run(&old, &["decompress", path(&compressed), path(&out)]);
+            if output.status.success() {
+                let original = std::fs::read(testdata().join("inputs").join(input)).unwrap();
+                assert!(std::fs::read(&out).unwrap() == original, "{} misread by {}", golden, revision);
+            } else {
+                assert_refused(&output, &format!("{} under {}", golden, revision));
+            }
+        }
+        let file = scratch("future.hz");
+        std::fs::write(&file, [b'?', 1, 2, 3]).unwrap();
+        assert_refused(&run(&old, &["decompress", path(&file), path(&scratch("future.out"))]), &format!("unknown mode under {}", revision));
+    }
+}
@@ -0,0 +1,192 @@
+// Files given by name: what compress and decompress do to the inputs and
+// outputs around the data itself
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("files-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_test_huffman")).args(args).output().unwrap()
+}
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+#[test]
+fn input_that_is_also_the_output_is_refused() {
+    let file = scratch("same.txt");
+    std::fs::write(&file, "the one and only copy").unwrap();
+    let dotted = file.parent().unwrap().join(".").join("same.txt");
+    for args in [&["compress", "--rm"][..], &["compress"], &["decompress"]] {
+        let output = run(&[args, &[path(&file), path(&dotted)]].concat());
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("both the input and the output"), "{}", stderr);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "the one and only copy");
+    }
+}
+
+#[test]
+fn removed_inputs_come_back() {
+    let (file, compressed) = (scratch("removed.txt"), scratch("removed.hz"));
+    std::fs::write(&file, "gone, but not for good").unwrap();
+    let output = run(&["compress", "--rm", path(&file), path(&compressed)]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!file.exists());
+
+    let output = run(&["decompress", "--rm", path(&compressed), path(&file)]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!compressed.exists());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "gone, but not for good");
+}
+
+#[test]
+fn locked_inputs_are_left_alone() {
+    let (file, compressed) = (scratch("locked.txt"), scratch("locked.hz"));
+    std::fs::write(&file, "in use elsewhere").unwrap();
+    let holder = std::fs::File::open(&file).unwrap();
+    holder.lock().unwrap();
+    let output = run(&["compress", "--rm", path(&file), path(&compressed)]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("locked by another process"));
+    assert!(file.exists());
+    assert!(!compressed.exists());
+
+    holder.unlock().unwrap();
+    assert!(run(&["compress", "--rm", path(&file), path(&compressed)]).status.success());
+}
+
+#[cfg(unix)]
+fn mkfifo(name: &str) -> PathBuf {
+    let fifo = scratch(name);
+    let _ = std::fs::remove_file(&fifo);
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+    fifo
+}
+
+// Both ends of a pipeline through named pipes, which can't be sized or
+// seeked: mkfifo p; producer > p & compress p out
+#[cfg(unix)]
+#[test]
+fn fifos_work_at_either_end() {
+    let text = "through a named pipe\n".repeat(10_000);
+    let (input, output) = (mkfifo("in.fifo"), mkfifo("out.fifo"));
+    let compressed = scratch("fifo.hz");
+
+    let producer = {
+        let (input, text) = (input.clone(), text.clone());
+        std::thread::spawn(move || std::fs::write(input, text).unwrap())
+    };
+    let output_of = run(&["compress", path(&input), path(&compressed)]);
+    assert!(output_of.status.success(), "{}", String::from_utf8_lossy(&output_of.stderr));
+    producer.join().unwrap();
+
+    let consumer = {
+        let output = output.clone();
+        std::thread::spawn(move || std::fs::read_to_string(output).unwrap())
+    };
+    let output_of = run(&["decompress", path(&compressed), path(&output)]);
+    assert!(output_of.status.success(), "{}", String::from_utf8_lossy(&output_of.stderr));
+    assert_eq!(consumer.join().unwrap(), text);
+    // Still a FIFO, not replaced by a regular file
+    use std::os::unix::fs::FileTypeExt;
+    assert!(std::fs::metadata(&output).unwrap().file_type().is_fifo());
+}
+
+// Reading /dev/zero would never end; writing to /dev/null is fine
+#[cfg(unix)]
+#[test]
+fn devices_are_refused_as_input() {
+    let output = run(&["compress", "/dev/zero", path(&scratch("zero.hz"))]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("/dev/zero is a character device"));
+    assert!(!scratch("zero.hz").exists());
+
+    let file = scratch("to-null.txt");
+    std::fs::write(&file, "discarded").unwrap();
+    let output = run(&["compress", path(&file), "/dev/null"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+// A file with a modification time and permissions of its own, to see them
+// come back
+fn dated(name: &str, contents: &str) -> (PathBuf, SystemTime) {
+    let file = scratch(name);
+    std::fs::write(&file, contents).unwrap();
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    std::fs::File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o640)).unwrap();
+    }
+    (file, modified)
+}
+
+fn modified(file: &Path) -> SystemTime {
+    std::fs::metadata(file).unwrap().modified().unwrap()
+}
+
+#[cfg(unix)]
+fn mode(file: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(file).unwrap().permissions().mode() & 0o7777
+}
+
+#[test]
+fn attributes_are_restored() {
+    let (file, then) = dated("dated.txt", "from long ago");
+    let (compressed, restored) = (scratch("dated.hz"), scratch("dated.out"));
+    assert!(run(&["compress", path(&file), path(&compressed)]).status.success());
+    assert!(run(&["decompress", path(&compressed), path(&restored)]).status.success());
+    assert_eq!(std::fs::read_to_string(&restored).unwrap(), "from long ago");
+    assert_eq!(modified(&restored), then);
+    #[cfg(unix)]
+    assert_eq!(mode(&restored), 0o640);
+
+    // Not restored when decompressing with --no-preserve
+    let fresh = scratch("dated.fresh");
+    assert!(run(&["decompress", "--no-preserve", path(&compressed), path(&fresh)]).status.success());
+    assert_ne!(modified(&fresh), then);
+
+    // Nor recorded when compressing with it
+    let bare = scratch("dated-bare.hz");
+    assert!(run(&["compress", "--no-preserve", path(&file), path(&bare)]).status.success());
+    assert!(std::fs::metadata(&bare).unwrap().len() < std::fs::metadata(&compressed).unwrap().len());
+    assert!(run(&["decompress", path(&bare), path(&fresh)]).status.success());
+    assert_ne!(modified(&fresh), then);
+}
+
+#[test]
+fn archive_entries_keep_their_attributes() {
+    let (file, then) = dated("entry.txt", "archived long ago");
+    std::fs::write(scratch("other.txt"), "archived just now").unwrap();
+    // Entries are named by the paths given, so compress from their directory
+    let archive = scratch("dated.car");
+    let output = Command::new(env!("CARGO_BIN_EXE_test_huffman"))
+        .current_dir(file.parent().unwrap())
+        .args(["compress", path(&archive), "entry.txt", "other.txt"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let out = scratch("dated-car");
+    let output = run(&["decompress", path(&archive), path(&out)]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(std::fs::read_to_string(out.join("entry.txt")).unwrap(), "archived long ago");
+    assert_eq!(modified(&out.join("entry.txt")), then);
+    #[cfg(unix)]
+    assert_eq!(mode(&out.join("entry.txt")), 0o640);
+    assert_ne!(modified(&out.join("other.txt")), then);
+
+    let out = scratch("dated-car-fresh");
+    assert!(run(&["decompress", "--no-preserve", path(&archive), path(&out)]).status.success());
+    assert_ne!(modified(&out.join("entry.txt")), then);
+}
@@ -0,0 +1,263 @@
+// Golden vectors: for every mode, a fixed input in testdata/inputs and the
+// exact bytes it compresses to in testdata/expected. Each vector is checked
+// both ways: the stored bytes must still decode to the input (old files stay
+// readable), and compressing the input afresh must give the stored bytes
+// (the format hasn't drifted by accident). Compression runs with
+// --no-preserve, since timestamps would make the output differ per checkout.
+//
+// After an intended format change, regenerate the expected files with
+//
+//   UPDATE_GOLDEN=1 cargo test --test golden
+//
+// and review the diff.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// (expected file, compress flags, input file)
+const VECTORS: &[(&str, &str, &str)] = &[
+    ("chars.hz", "--chars", "chars.txt"),
+    ("graphemes.hz", "--graphemes", "graphemes.txt"),
+    ("utf16le.hz", "--utf16le", "utf16le.txt"),
+    ("utf16be.hz", "--utf16be", "utf16be.txt"),
+    ("log.hz", "--log", "app.log"),
+    ("csv.hz", "--csv", "table.csv"),
+    ("json.hz", "--json", "doc.json"),
+    ("protobuf.hz", "--protobuf", "messages.pb"),
+    ("float64.hz", "--float64", "series.f64"),
+    ("int64.hz", "--int64", "series.i64"),
+    ("bits4.hz", "--bits=4", "nibbles.bin"),
+    ("bits12.hz", "--bits=12", "samples.bin"),
+    ("bytes.hz", "--bytes", "program.bin"),
+    ("lz77.hz", "--algorithm lz77", "app.log"),
+    ("lz78.hz", "--algorithm lz78", "app.log"),
+    ("lzss.hz", "--algorithm lzss", "app.log"),
+    ("rle.hz", "--algorithm rle", "bitmap.bin"),
+    ("rle_huffman.hz", "--algorithm rle+huffman", "bitmap.bin"),
+    ("bwt.hz", "--algorithm bwt", "app.log"),
+    ("deflate.hz", "--algorithm deflate", "app.log"),
+    ("arithmetic.hz", "--algorithm arithmetic", "app.log"),
+    ("range.hz", "--algorithm range", "app.log"),
+    ("rans.hz", "--algorithm rans", "app.log"),
+    ("tans.hz", "--algorithm tans", "app.log"),
+    ("adaptive.hz", "--algorithm adaptive", "app.log"),
+    ("shannon_fano.hz", "--algorithm shannon-fano", "app.log"),
+    ("ppm.hz", "--algorithm ppm", "app.log"),
+    ("delta_huffman.hz", "--algorithm delta+huffman", "bitmap.bin"),
+    ("fast.hz", "--algorithm fast", "app.log"),
+    ("app.log.gz", "--format gz", "app.log"),
+    ("app.log.zlib", "--format zlib", "app.log"),
+    ("stored.hz", "--chars", "packed.gz"),
+    ("interleaved.hz", "--log --streams 3", "app.log"),
+    ("blocks.hz", "--log --block-size 1024", "app.log"),
+];
+
+fn testdata() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata")
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("golden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn run(args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_test_huffman")).args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+// Writes `actual` over the expected file when regenerating, otherwise
+// compares against it
+fn check(expected: &Path, actual: &[u8]) {
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(expected, actual).unwrap();
+        return;
+    }
+    let stored = std::fs::read(expected).unwrap_or_else(|e| panic!("{}: {}", expected.display(), e));
+    assert!(stored == actual, "{} no longer matches what the encoder produces", expected.display());
+}
+
+#[test]
+fn stored_vectors_decode() {
+    for &(expected, _, input) in VECTORS {
+        let out = scratch(&format!("{}.out", expected));
+        run(&["decompress", path(&testdata().join("expected").join(expected)), path(&out)]);
+        let original = std::fs::read(testdata().join("inputs").join(input)).unwrap();
+        assert!(std::fs::read(&out).unwrap() == original, "{} decodes to the wrong bytes", expected);
+    }
+}
+
+// log.hz as it was before repeated text became tokens of its own, when
+// log lines were split into words alone. The encoder no longer writes it,
+// so it is only decoded
+#[test]
+fn word_log_tokens_decode() {
+    let out = scratch("log_words.out");
+    run(&["decompress", path(&testdata().join("expected").join("log_words.hz")), path(&out)]);
+    assert!(std::fs::read(&out).unwrap() == std::fs::read(testdata().join("inputs").join("app.log")).unwrap());
+}
+
+#[test]
+fn fresh_encodes_match() {
+    for &(expected, flags, input) in VECTORS {
+        let out = scratch(expected);
+        let input = testdata().join("inputs").join(input);
+        let args: Vec<&str> = ["compress", "--no-preserve"].into_iter().chain(flags.split(' ')).chain([path(&input), path(&out)]).collect();
+        run(&args);
+        check(&testdata().join("expected").join(expected), &std::fs::read(&out).unwrap());
+    }
+}
+
+#[test]
+fn delta_patch_matches() {
+    let inputs = testdata().join("inputs");
+    let (old, new) = (inputs.join("delta.old"), inputs.join("delta.new"));
+    let patch = scratch("delta.patch");
+    run(&["delta", path(&old), path(&new), "-o", path(&patch)]);
+    check(&testdata().join("expected").join("delta.patch"), &std::fs::read(&patch).unwrap());
+
+    let rebuilt = scratch("delta.rebuilt");
+    run(&["apply", path(&old), path(&testdata().join("expected").join("delta.patch")), "-o", path(&rebuilt)]);
+    assert!(std::fs::read(&rebuilt).unwrap() == std::fs::read(&new).unwrap());
+
+    // As written before patches had checksums and were compressed
+    let rebuilt = scratch("delta_v1.rebuilt");
+    run(&["apply", path(&old), path(&testdata().join("expected").join("delta_v1.patch")), "-o", path(&rebuilt)]);
+    assert!(std::fs::read(&rebuilt).unwrap() == std::fs::read(&new).unwrap());
+}
+
+// Snappy blocks have no magic number to detect, so both directions name
+// the format
+#[test]
+fn snappy_blocks_match() {
+    let input = testdata().join("inputs").join("app.log");
+    let block = scratch("app.log.snappy");
+    run(&["compress", "--format", "snappy", path(&input), path(&block)]);
+    check(&testdata().join("expected").join("app.log.snappy"), &std::fs::read(&block).unwrap());
+
+    let out = scratch("app.log.snappy.out");
+    run(&["decompress", "--format", "snappy", path(&testdata().join("expected").join("app.log.snappy")), path(&out)]);
+    assert!(std::fs::read(&out).unwrap() == std::fs::read(&input).unwrap());
+}
+
+// The new version of a file shrinks far more with the old one preset as a
+// dictionary, which decompressing needs to be given too
+#[test]
+fn preset_dictionaries_match() {
+    let inputs = testdata().join("inputs");
+    let (old, new) = (inputs.join("delta.old"), inputs.join("delta.new"));
+    for (expected, format) in [("preset_dictionary.hz", "hz"), ("delta.new.zlib", "zlib")] {
+        let compressed = scratch(expected);
+        run(&["compress", "--no-preserve", "--format", format, "--dict", path(&old), path(&new), path(&compressed)]);
+        check(&testdata().join("expected").join(expected), &std::fs::read(&compressed).unwrap());
+
+        let stored = testdata().join("expected").join(expected);
+        let out = scratch(&format!("{}.out", expected));
+        run(&["decompress", "--dict", path(&old), path(&stored), path(&out)]);
+        assert!(std::fs::read(&out).unwrap() == std::fs::read(&new).unwrap());
+
+        let output = Command::new(env!("CARGO_BIN_EXE_test_huffman")).args(["decompress", path(&stored), path(&scratch("undictionaried"))]).output().unwrap();
+        assert!(!output.status.success(), "{} decoded without its dictionary", expected);
+    }
+
+    // deflate=C's chain carries over to the dictionary: one try per match
+    // finds less of the old file than the default does
+    let quick = scratch("preset_quick.hz");
+    run(&["compress", "--no-preserve", "--algorithm", "deflate=1", "--dict", path(&old), path(&new), path(&quick)]);
+    let default = std::fs::read(testdata().join("expected").join("preset_dictionary.hz")).unwrap();
+    assert!(std::fs::read(&quick).unwrap().len() > default.len());
+    let out = scratch("preset_quick.out");
+    run(&["decompress", "--dict", path(&old), path(&quick), path(&out)]);
+    assert!(std::fs::read(&out).unwrap() == std::fs::read(&new).unwrap());
+}
+
+// A mapped input is the same bytes as a read one
+#[test]
+fn mapped_inputs_match() {
+    let input = testdata().join("inputs").join("app.log");
+    let compressed = scratch("mapped.hz");
+    run(&["compress", "--no-preserve", "--mmap", "--log", path(&input), path(&compressed)]);
+    assert!(std::fs::read(&compressed).unwrap() == std::fs::read(testdata().join("expected").join("log.hz")).unwrap());
+    let out = scratch("mapped.out");
+    run(&["decompress", "--mmap", path(&compressed), path(&out)]);
+    assert!(std::fs::read(&out).unwrap() == std::fs::read(&input).unwrap());
+}
+
+// Entries are named by the paths given, so compress from the inputs'
+// directory
+#[test]
+fn archives_match() {
+    let archive = scratch("inputs.car");
+    let output = Command::new(env!("CARGO_BIN_EXE_test_huffman"))
+        .current_dir(testdata().join("inputs"))
+        .args(["compress", "--no-preserve", path(&archive), "app.log", "./table.csv", "chars.txt"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    check(&testdata().join("expected").join("inputs.car"), &std::fs::read(&archive).unwrap());
+
+    // inputs_v1.car is as written before archives shared chunks
+    for expected in ["inputs.car", "inputs_v1.car"] {
+        let dir = scratch(&format!("{}.out", expected));
+        run(&["decompress", path(&testdata().join("expected").join(expected)), path(&dir)]);
+        for name in ["app.log", "table.csv", "chars.txt"] {
+            assert!(std::fs::read(dir.join(name)).unwrap() == std::fs::read(testdata().join("inputs").join(name)).unwrap());
+        }
+    }
+}
+
+// Stored data decodes whatever its bytes are, so only the checksum can
+// notice that one changed
+#[test]
+fn corruption_is_detected() {
+    let mut stored = std::fs::read(testdata().join("expected").join("stored.hz")).unwrap();
+    *stored.last_mut().unwrap() ^= 1;
+    let corrupt = scratch("corrupt.hz");
+    std::fs::write(&corrupt, &stored).unwrap();
+    let out = scratch("corrupt.out");
+    let output = Command::new(env!("CARGO_BIN_EXE_test_huffman")).args(["decompress", path(&corrupt), path(&out)]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("checksum"));
+    assert!(!out.exists());
+}
+
+// Each block has a checksum of its own, which names the one that changed
+#[test]
+fn corrupt_blocks_are_named() {
+    let mut blocks = std::fs::read(testdata().join("expected").join("blocks.hz")).unwrap();
+    *blocks.last_mut().unwrap() ^= 0x80;
+    let corrupt = scratch("corrupt_blocks.hz");
+    std::fs::write(&corrupt, &blocks).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_test_huffman")).args(["decompress", path(&corrupt), path(&scratch("corrupt_blocks.out"))]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("block 4"), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+// A grapheme table whose one symbol claims to be 4 GiB long, with two
+// bytes behind it. It is refused as truncated, without the 4 GiB
+#[test]
+fn symbol_lengths_are_not_trusted() {
+    let corrupt = scratch("long_symbol.hz");
+    std::fs::write(&corrupt, [b'g', 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, b'a', b'b']).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_test_huffman")).args(["decompress", path(&corrupt), path(&scratch("long_symbol.out"))]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("truncated"), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+// Written by gzip itself
+#[test]
+fn gzip_files_decode() {
+    let out = scratch("packed.out");
+    run(&["decompress", path(&testdata().join("inputs").join("packed.gz")), path(&out)]);
+    assert!(std::fs::read(&out).unwrap().starts_with(b"already compressed already compressed"));
+}
@@ -0,0 +1,127 @@
+// compress_bytes and decompress_bytes, the format without the program: they
+// must give the golden vectors' bytes as compress does, and read them back
+
+use std::path::{Path, PathBuf};
+
+use test_huffman::{compress_bytes, decompress_bytes, parse_unit, CompressionError, Options};
+
+// (expected file, options, input file), a few of tests/golden.rs's
+const VECTORS: &[(&str, &str, &str)] = &[
+    ("chars.hz", "chars", "chars.txt"),
+    ("utf16le.hz", "utf16le", "utf16le.txt"),
+    ("log.hz", "log", "app.log"),
+    ("bits12.hz", "bits=12", "samples.bin"),
+    ("bytes.hz", "bytes", "program.bin"),
+    ("deflate.hz", "deflate", "app.log"),
+    ("rle_huffman.hz", "rle+huffman", "bitmap.bin"),
+    ("stored.hz", "chars", "packed.gz"),
+    ("interleaved.hz", "log streams=3", "app.log"),
+    ("blocks.hz", "log block-size=1024", "app.log"),
+];
+
+fn testdata() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata")
+}
+
+fn options(spec: &str) -> Options {
+    let mut options = Options::default();
+    for part in spec.split(' ') {
+        match part.split_once('=') {
+            Some(("streams", n)) => options.streams = n.parse().unwrap(),
+            Some(("block-size", n)) => options.block_size = Some(n.parse().unwrap()),
+            _ => options.unit = Some(parse_unit(part).unwrap()),
+        }
+    }
+    options
+}
+
+#[test]
+fn buffers_compress_as_files_do() {
+    for (expected, spec, input) in VECTORS {
+        let input = std::fs::read(testdata().join("inputs").join(input)).unwrap();
+        let expected_bytes = std::fs::read(testdata().join("expected").join(expected)).unwrap();
+        assert!(compress_bytes(&input, &options(spec)).unwrap() == expected_bytes, "{} differs", expected);
+        assert!(decompress_bytes(&expected_bytes).unwrap() == input, "{} decodes wrongly", expected);
+    }
+}
+
+#[test]
+fn standard_formats_decompress_too() {
+    let input = std::fs::read(testdata().join("inputs/app.log")).unwrap();
+    for expected in ["app.log.gz", "app.log.zlib"] {
+        let compressed = std::fs::read(testdata().join("expected").join(expected)).unwrap();
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input, "{}", expected);
+    }
+}
+
+#[test]
+fn levels_round_trip() {
+    let input = std::fs::read(testdata().join("inputs/app.log")).unwrap();
+    for level in 1..=9 {
+        let compressed = compress_bytes(&input, &Options { level: Some(level), ..Options::default() }).unwrap();
+        assert!(compressed.len() < input.len() / 3, "level {}", level);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input, "level {}", level);
+    }
+}
+
+#[test]
+fn options_out_of_range_are_refused() {
+    let bad = [
+        Options { streams: 0, ..Options::default() },
+        Options { streams: 65, ..Options::default() },
+        Options { block_size: Some(0), ..Options::default() },
+        Options { level: Some(0), ..Options::default() },
+        Options { level: Some(10), ..Options::default() },
+        Options { level: Some(5), unit: parse_unit("bytes"), ..Options::default() },
+        Options { level: Some(5), block_size: Some(1024), ..Options::default() },
+    ];
+    for options in bad {
+        let e = compress_bytes(b"data", &options).unwrap_err();
+        assert!(matches!(e, CompressionError::InvalidOptions(_)), "{:?}: {}", options, e);
+    }
+}
+
+#[test]
+fn text_units_refuse_other_bytes() {
+    let e = compress_bytes(&[0xff, 0xfe, 0x00, 0x80], &Options { unit: parse_unit("chars"), ..Options::default() }).unwrap_err();
+    assert!(matches!(e, CompressionError::NotText(_)), "{}", e);
+}
+
+#[test]
+fn corrupt_data_is_an_error() {
+    let compressed = compress_bytes(b"hello, hello, hello", &Options::default()).unwrap();
+    assert!(decompress_bytes(&compressed[..compressed.len() - 1]).is_err());
+    assert!(matches!(decompress_bytes(b""), Err(CompressionError::Truncated(_))));
+}
+
+// Each way of failing is told apart by its variant, and converts back to
+// the io::Error kind it always had
+#[test]
+fn errors_say_what_went_wrong() {
+    let text = "hello, hello, hello\n".repeat(20);
+    let compressed = compress_bytes(text.as_bytes(), &Options::default()).unwrap();
+    let with = |at: usize, byte: u8| {
+        let mut damaged = compressed.clone();
+        damaged[at] = byte;
+        decompress_bytes(&damaged).unwrap_err()
+    };
+    assert!(matches!(with(1, b'X'), CompressionError::BadMagic));
+    assert!(matches!(with(4, 9), CompressionError::UnknownVersion(9)));
+    assert!(matches!(with(9, 0xfe), CompressionError::UnknownMode(0xfe)));
+    assert!(matches!(with(5, compressed[5] ^ 1), CompressionError::ChecksumMismatch));
+    let e = decompress_bytes(&compressed[..12]).unwrap_err();
+    assert!(matches!(e, CompressionError::Truncated(_)) && e.to_string().contains("ends early, at byte 12"), "{}", e);
+    assert_eq!(std::io::Error::from(e).kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(std::io::Error::from(with(9, 0xfe)).kind(), std::io::ErrorKind::InvalidData);
+    let options = Options { streams: 0, ..Options::default() };
+    assert_eq!(std::io::Error::from(compress_bytes(b"data", &options).unwrap_err()).kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn protobuf_falls_back_to_bytes_rather_than_lose_data() {
+    // A varint's tenth byte holding more than the u64's last bit, which
+    // protobuf's streams would join back with a last byte of 0x01
+    let overflowing = [0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+    let compressed = compress_bytes(&overflowing, &Options { unit: parse_unit("protobuf"), ..Options::default() }).unwrap();
+    assert_eq!(decompress_bytes(&compressed).unwrap(), overflowing);
+}
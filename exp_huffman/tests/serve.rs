@@ -0,0 +1,164 @@
+// `serve`, driven over its sockets as a client would
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process::{Child, Command, Stdio};
+
+// The server, killed when the test is done with it
+struct Server {
+    child: Child,
+    // Where it said it is listening
+    address: String,
+}
+
+impl Server {
+    fn start(args: &[&str]) -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_test_huffman"))
+            .arg("serve")
+            .args(args)
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let mut line = String::new();
+        stderr.read_line(&mut line).unwrap();
+        let address = line.trim().strip_prefix("Listening on ").unwrap_or_else(|| panic!("{}", line));
+        let address = address.trim_start_matches("http://").to_string();
+        // Later messages go nowhere, rather than fill the pipe
+        std::thread::spawn(move || std::io::copy(&mut stderr, &mut std::io::sink()));
+        Server { child, address }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn http(server: &Server, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    stream.write_all(request).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[test]
+fn http_refuses_a_missing_request_line() {
+    let server = Server::start(&["--http", "127.0.0.1:0"]);
+    assert!(http(&server, b"\r\n").starts_with("HTTP/1.1 400 Bad Request"));
+    // The connection's thread survived to answer the next one
+    let response = http(&server, b"POST /compress HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello");
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(http(&server, b"GET /stats HTTP/1.1\r\n\r\n").contains("\"requests\":1"));
+}
+
+#[cfg(unix)]
+fn socket_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("serve-{}-{}.sock", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_str().unwrap().to_string()
+}
+
+#[cfg(unix)]
+fn request(stream: &mut UnixStream, op: u8, mode: &str, body: &[u8]) {
+    let mut request = vec![op, mode.len() as u8];
+    request.extend_from_slice(mode.as_bytes());
+    request.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    request.extend_from_slice(body);
+    stream.write_all(&request).unwrap();
+}
+
+// The status and the result or error message
+#[cfg(unix)]
+fn response(stream: &mut UnixStream) -> (u8, Vec<u8>) {
+    let mut status = [0u8];
+    stream.read_exact(&mut status).unwrap();
+    let mut len = [0u8; 8];
+    stream.read_exact(&mut len).unwrap();
+    let mut body = vec![0u8; u64::from_le_bytes(len) as usize];
+    stream.read_exact(&mut body).unwrap();
+    (status[0], body)
+}
+
+#[cfg(unix)]
+#[test]
+fn socket_requests_round_trip() {
+    let server = Server::start(&["--socket", &socket_path("round-trip")]);
+    let mut stream = UnixStream::connect(&server.address).unwrap();
+    let text = "to be or not to be, that is the question\n".repeat(50);
+    // Several requests on one connection, with and without a mode
+    for mode in ["", "chars", "bytes"] {
+        request(&mut stream, b'c', mode, text.as_bytes());
+        let (status, compressed) = response(&mut stream);
+        assert_eq!(status, 0, "{}", String::from_utf8_lossy(&compressed));
+        assert!(compressed.len() < text.len() / 2);
+        request(&mut stream, b'd', "", &compressed);
+        assert_eq!(response(&mut stream), (0, text.clone().into_bytes()));
+    }
+    // A failed request leaves the connection usable
+    request(&mut stream, b'd', "", b"not compressed");
+    assert_eq!(response(&mut stream).0, 1);
+    request(&mut stream, b'c', "no-such-mode", b"x");
+    assert_eq!(response(&mut stream), (1, b"unknown mode".to_vec()));
+    request(&mut stream, b'c', "", b"");
+    assert_eq!(response(&mut stream).0, 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn socket_refuses_oversized_bodies_unread() {
+    let server = Server::start(&["--socket", &socket_path("oversized"), "--max-body", "1000"]);
+    let mut stream = UnixStream::connect(&server.address).unwrap();
+    // Only the length is sent: the body is refused before it would be read
+    stream.write_all(&[b'c', 0]).unwrap();
+    stream.write_all(&1001u64.to_le_bytes()).unwrap();
+    assert_eq!(response(&mut stream), (1, b"request body is too large".to_vec()));
+    // And the connection is closed, as the stream can't be resynced
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+
+    let mut stream = UnixStream::connect(&server.address).unwrap();
+    request(&mut stream, b'c', "", &[b'a'; 1000]);
+    assert_eq!(response(&mut stream).0, 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn socket_refuses_unknown_ops() {
+    let server = Server::start(&["--socket", &socket_path("unknown-op")]);
+    let mut stream = UnixStream::connect(&server.address).unwrap();
+    request(&mut stream, b'x', "", b"hello");
+    assert_eq!(response(&mut stream), (1, b"unknown request".to_vec()));
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn socket_turns_away_connections_over_the_limit() {
+    let server = Server::start(&["--socket", &socket_path("busy"), "--max-connections", "1"]);
+    let mut first = UnixStream::connect(&server.address).unwrap();
+    request(&mut first, b'c', "", b"hello");
+    assert_eq!(response(&mut first).0, 0);
+    // The first connection still holds the only slot
+    let mut second = UnixStream::connect(&server.address).unwrap();
+    assert_eq!(response(&mut second), (1, b"server is busy".to_vec()));
+    assert_eq!(second.read(&mut [0u8; 1]).unwrap(), 0);
+
+    drop(first);
+    // The slot is given back once the first connection's thread sees it go
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    loop {
+        let mut third = UnixStream::connect(&server.address).unwrap();
+        // Refused, the server may have hung up before the request is sent
+        let _ = third.write_all(&[b'c', 0, 1, 0, 0, 0, 0, 0, 0, 0, b'x']);
+        match response(&mut third).0 {
+            0 => break,
+            _ => assert!(std::time::Instant::now() < deadline, "the slot was never given back"),
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
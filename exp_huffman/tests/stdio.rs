@@ -0,0 +1,156 @@
+// "-" as a file name: compress and decompress as the two ends of a pipeline
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_test_huffman"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Written from a thread, as the child may fill its stdout before reading all of it
+    let mut input = child.stdin.take().unwrap();
+    let stdin = stdin.to_vec();
+    let writer = std::thread::spawn(move || {
+        let _ = input.write_all(&stdin);
+    });
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    output
+}
+
+#[test]
+fn pipeline_round_trips() {
+    let input = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/inputs/app.log")).unwrap();
+    let compressed = run(&["compress", "-", "-"], &input);
+    assert!(compressed.status.success(), "{}", String::from_utf8_lossy(&compressed.stderr));
+    assert!(compressed.stdout.len() < input.len());
+
+    let decompressed = run(&["decompress", "-", "-"], &compressed.stdout);
+    assert!(decompressed.status.success(), "{}", String::from_utf8_lossy(&decompressed.stderr));
+    assert!(decompressed.stdout == input);
+}
+
+// The chars the old text table split on, and chars of every UTF-8 length
+#[test]
+fn chars_of_any_kind_round_trip() {
+    let input = "a:1|b:22|\nc:333|\r\n\u{e9}:\u{20ac}|\u{1f600}\n".repeat(20);
+    let compressed = run(&["compress", "--chars", "-", "-"], input.as_bytes());
+    assert!(compressed.status.success(), "{}", String::from_utf8_lossy(&compressed.stderr));
+    assert!(compressed.stdout.len() < input.len());
+
+    let decompressed = run(&["decompress", "-", "-"], &compressed.stdout);
+    assert!(decompressed.status.success(), "{}", String::from_utf8_lossy(&decompressed.stderr));
+    assert!(decompressed.stdout == input.as_bytes());
+}
+
+// Nothing, or too little for one symbol, is stored as it is rather than coded
+#[test]
+fn empty_input_round_trips() {
+    let units = [
+        &[][..],
+        &["--chars"],
+        &["--graphemes"],
+        &["--utf16le"],
+        &["--log"],
+        &["--csv"],
+        &["--protobuf"],
+        &["--bits=4"],
+        &["--bits=12"],
+        &["--streams", "4"],
+        &["--level", "9"],
+        &["--algorithm", "deflate"],
+    ];
+    for input in [&b""[..], b"a"] {
+        for unit in units {
+            let args = [&["compress"][..], unit, &["-", "-"]].concat();
+            let compressed = run(&args, input);
+            assert!(compressed.status.success(), "{:?}: {}", unit, String::from_utf8_lossy(&compressed.stderr));
+
+            let decompressed = run(&["decompress", "-", "-"], &compressed.stdout);
+            assert!(decompressed.status.success(), "{:?}: {}", unit, String::from_utf8_lossy(&decompressed.stderr));
+            assert_eq!(decompressed.stdout, input, "{:?}", unit);
+        }
+    }
+    let estimate = run(&["estimate", "-"], b"");
+    assert!(estimate.status.success(), "{}", String::from_utf8_lossy(&estimate.stderr));
+}
+
+// One distinct symbol still gets a 1-bit code, so a run of it shrinks eightfold
+#[test]
+fn single_symbol_inputs_round_trip() {
+    let input = vec![b'a'; 1000];
+    for unit in [&[][..], &["--chars"], &["--bytes"], &["--utf16le"], &["--csv"], &["--streams", "4"], &["--block-size", "100"]] {
+        let args = [&["compress"][..], unit, &["-", "-"]].concat();
+        let compressed = run(&args, &input);
+        assert!(compressed.status.success(), "{:?}: {}", unit, String::from_utf8_lossy(&compressed.stderr));
+        // Small blocks each have their own header and code
+        assert!(unit.first() == Some(&"--block-size") || compressed.stdout.len() < input.len() / 4, "{:?}", unit);
+
+        let decompressed = run(&["decompress", "-", "-"], &compressed.stdout);
+        assert!(decompressed.status.success(), "{:?}: {}", unit, String::from_utf8_lossy(&decompressed.stderr));
+        assert_eq!(decompressed.stdout, input, "{:?}", unit);
+    }
+}
+
+// Progress is only drawn on a terminal, and --quiet turns it off there too
+#[test]
+fn progress_stays_off_the_pipeline() {
+    let input = vec![7u8; 3 << 20];
+    for args in [&["compress", "--bytes", "-", "-"][..], &["compress", "--quiet", "--bytes", "-", "-"]] {
+        let compressed = run(args, &input);
+        assert!(compressed.status.success());
+        assert!(compressed.stderr.is_empty(), "{}", String::from_utf8_lossy(&compressed.stderr));
+    }
+}
+
+// Nothing else is said on stderr unless asked for
+#[test]
+fn stats_are_reported_on_request() {
+    let quiet = run(&["compress", "--chars", "-", "-"], b"aaaabbcd");
+    assert!(quiet.status.success());
+    assert!(quiet.stderr.is_empty(), "{}", String::from_utf8_lossy(&quiet.stderr));
+
+    let output = run(&["compress", "--stats", "--chars", "-", "-"], b"aaaabbcd");
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stderr).unwrap();
+    let expected = format!(
+        "input:        8 bytes\noutput:       {} bytes\nratio:        {:.2}%\nentropy:      1.750 bits per char\naverage code: 1.750 bits per char\n",
+        output.stdout.len(),
+        100.0 * output.stdout.len() as f64 / 8.0
+    );
+    assert_eq!(report, expected);
+    assert!(!run(&["decompress", "--stats", "-", "-"], &output.stdout).status.success());
+}
+
+#[test]
+fn levels_trade_speed_for_ratio() {
+    let mut input = Vec::new();
+    for i in 0..4000u32 {
+        input.extend_from_slice(format!("{} {} {}\n", i, i * i % 7919, ["red", "green", "blue"][i as usize % 3]).as_bytes());
+    }
+    let mut sizes = Vec::new();
+    for level in 1..=9 {
+        let compressed = run(&["compress", "--level", &level.to_string(), "-", "-"], &input);
+        assert!(compressed.status.success(), "{}", String::from_utf8_lossy(&compressed.stderr));
+        assert_eq!(run(&["decompress", "-", "-"], &compressed.stdout).stdout, input);
+        sizes.push(compressed.stdout.len());
+    }
+    assert!(sizes[0] > sizes[5] && sizes[5] >= sizes[8], "{:?}", sizes);
+    for args in [&["--level", "0"][..], &["--level", "10"], &["--level", "6", "--algorithm", "rle"], &["--level", "6", "--format", "gz"]] {
+        let output = run(&[&["compress"], args, &["-", "-"]].concat(), &input);
+        assert!(!output.status.success(), "{:?}", args);
+    }
+}
+
+#[test]
+fn stdin_cannot_be_removed() {
+    let out = std::env::temp_dir().join(format!("stdio-{}.hz", std::process::id()));
+    let output = run(&["compress", "--rm", "-", out.to_str().unwrap()], b"some text");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!out.exists());
+}
@@ -0,0 +1,40 @@
+//! compress and decompress for JavaScript, on `Uint8Array`s, through
+//! wasm-bindgen. They are test_huffman's `compress_bytes` and
+//! `decompress_bytes`, so what the browser writes the program reads and
+//! the other way round, less file attributes, which a buffer hasn't got.
+//! Browsers give wasm one thread, so everything runs on it.
+//!
+//! ```js
+//! import init, { compress, decompress } from "./pkg/test_huffman_wasm.js";
+//!
+//! await init();
+//! const data = new TextEncoder().encode("GET /index.html 200\n".repeat(100));
+//! const compressed = compress(data, "log", undefined);
+//! console.log(new TextDecoder().decode(decompress(compressed)));
+//! ```
+//!
+//! www/index.html is a page that does this with a file or typed text.
+
+use test_huffman::{compress_bytes, decompress_bytes, parse_unit, Options};
+use wasm_bindgen::prelude::*;
+
+/// `data` as an hz file, coded as `--mode` names it (chars, log, lz77,
+/// deflate+huffman, ...), or at a `--level` from 1 to 9, or neither for the
+/// program's default. Throws on an unknown mode or bad level, or a text
+/// mode given data that isn't UTF-8.
+#[wasm_bindgen]
+pub fn compress(data: &[u8], mode: Option<String>, level: Option<u8>) -> Result<Vec<u8>, JsError> {
+    let unit = match mode.as_deref() {
+        None | Some("") => None,
+        Some(name) => Some(parse_unit(name).ok_or_else(|| JsError::new(&format!("unknown mode {}", name)))?),
+    };
+    let options = Options { unit, level, ..Options::default() };
+    compress_bytes(data, &options).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// The original of what `compress` or the program wrote, or of gzip or zlib
+/// data. Throws on anything else, saying where it went wrong.
+#[wasm_bindgen]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, JsError> {
+    decompress_bytes(data).map_err(|e| JsError::new(&e.to_string()))
+}
@@ -0,0 +1,9 @@
+#![no_main]
+
+use huffman::arithmetic::Arithmetic;
+use huffman::codec::Decompressor;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Arithmetic.decompress(data);
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use huffman::bsdiff;
+use libfuzzer_sys::fuzz_target;
+
+// The first byte says how much of the rest is the base file; what follows
+// is tried both as a patch against it and as a new version of it
+fuzz_target!(|data: &[u8]| {
+    let Some((&n, rest)) = data.split_first() else { return };
+    let (old, new) = rest.split_at((n as usize).min(rest.len()));
+    let _ = bsdiff::apply(old, new);
+    assert_eq!(bsdiff::apply(old, &bsdiff::diff(old, new)).unwrap(), new);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use huffman::bwt::Bwt;
+use huffman::codec::Decompressor;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Bwt::new().decompress(data);
+});
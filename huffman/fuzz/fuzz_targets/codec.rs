@@ -0,0 +1,9 @@
+#![no_main]
+
+use huffman::codec::{Decompressor, Huffman};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Huffman::new().decompress(data);
+    let _ = Huffman::with_streams(3).decompress(data);
+});
@@ -0,0 +1,60 @@
+#![no_main]
+
+use std::io::Read;
+
+use huffman::bitio::BitReader;
+use huffman::codes::{self, CanonicalDecoder};
+use libfuzzer_sys::fuzz_target;
+
+fn read_byte(reader: &mut &[u8]) -> std::io::Result<u8> {
+    let mut b = [0u8];
+    reader.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+// The tables and trees Huffman-coded data starts with, then the symbols
+// after them: a stream count and symbol count, then a lengths table, a
+// tree, or frequencies to build a tree from, which the leading byte picks
+fuzz_target!(|data: &[u8]| {
+    let Some((&[choice, streams, count], mut rest)) = data.split_first_chunk::<3>() else {
+        return;
+    };
+    let streams = streams as usize % 4 + 1;
+    let count = count as u64 * 8;
+    match choice % 3 {
+        0 => {
+            let Ok(lengths) = codes::read_lengths_table(&mut rest, read_byte) else { return };
+            if let Ok(decoder) = CanonicalDecoder::new(lengths) {
+                let _ = codes::decode_streams(rest, &decoder, count, streams);
+            }
+        }
+        1 => {
+            let mut bits = BitReader::new(rest);
+            let Ok(lengths) = codes::read_tree(&mut bits, |r| Ok(r.read_bits(8)? as u8)) else { return };
+            bits.align();
+            if let Ok(decoder) = CanonicalDecoder::new(lengths) {
+                let _ = codes::decode_streams(bits.rest(), &decoder, count, streams);
+            }
+        }
+        _ => {
+            // Byte and frequency pairs, ahead of the coded symbols
+            let Some((&pairs, rest)) = rest.split_first() else { return };
+            let Some((table, coded)) = rest.split_at_checked(pairs as usize * 2) else { return };
+            let freq_table: Vec<(u8, usize)> = table.chunks_exact(2).map(|p| (p[0], p[1] as usize + 1)).collect();
+            if freq_table.is_empty() {
+                return;
+            }
+            let tree = codes::build_huffman_tree(&freq_table);
+            let _ = codes::decode_streams(coded, &tree, count, streams);
+
+            // Whatever the tree decodes, the canonical code of its lengths
+            // writes and reads back
+            let lengths = codes::code_lengths(&tree);
+            if let Ok(symbols) = codes::decode_symbols(coded, &tree) {
+                let encoded = codes::encode_symbols(symbols.iter().copied(), &codes::build_encoding_table(&lengths));
+                let decoder = CanonicalDecoder::new(lengths).unwrap();
+                assert_eq!(codes::decode_streams(&encoded, &decoder, symbols.len() as u64, 1).unwrap(), symbols);
+            }
+        }
+    }
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use huffman::columnar;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = columnar::decode(data);
+
+    // Any table the encoder accepts must come back unchanged
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Some(encoded) = columnar::detect(text).and_then(|d| columnar::encode(text, d)) {
+            assert_eq!(columnar::decode(&encoded).unwrap(), text);
+        }
+    }
+});
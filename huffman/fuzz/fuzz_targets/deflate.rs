@@ -0,0 +1,9 @@
+#![no_main]
+
+use huffman::codec::Decompressor;
+use huffman::deflate::Deflate;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Deflate.decompress(data);
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use huffman::gorilla;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = gorilla::decode_f64(data);
+    let _ = gorilla::decode_f32(data);
+
+    // Compared by bit pattern, so NaNs count as equal to themselves
+    let doubles: Vec<f64> = data.chunks_exact(8).map(|c| f64::from_le_bytes(c.try_into().unwrap())).collect();
+    let decoded = gorilla::decode_f64(&gorilla::encode_f64(&doubles)).unwrap();
+    assert!(decoded.iter().map(|v| v.to_bits()).eq(doubles.iter().map(|v| v.to_bits())));
+
+    let singles: Vec<f32> = data.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect();
+    let decoded = gorilla::decode_f32(&gorilla::encode_f32(&singles)).unwrap();
+    assert!(decoded.iter().map(|v| v.to_bits()).eq(singles.iter().map(|v| v.to_bits())));
+});
@@ -0,0 +1,44 @@
+#![no_main]
+
+use huffman::bitio::BitReader;
+use huffman::codes::{read_lengths_table, read_tree, CanonicalDecoder};
+use libfuzzer_sys::fuzz_target;
+use test_huffman::archive;
+use test_huffman::attributes::Attributes;
+use test_huffman::{read_header, read_string, read_utf8_char};
+
+fn read_u32(reader: &mut &[u8]) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    std::io::Read::read_exact(reader, &mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+// The headers and tables an hz file or archive starts with, as decompress
+// reads them; the leading byte picks which. Each must parse or fail
+// cleanly, whatever lengths and counts it claims
+fuzz_target!(|data: &[u8]| {
+    let Some((&choice, mut rest)) = data.split_first() else {
+        return;
+    };
+    match choice % 6 {
+        0 => drop(read_header(&mut rest)),
+        1 => drop(Attributes::read(&mut rest)),
+        2 => drop(archive::read_index(rest)),
+        3 => {
+            if let Ok(lengths) = read_lengths_table(&mut rest, read_string) {
+                let _ = CanonicalDecoder::new(lengths);
+            }
+        }
+        4 => {
+            if let Ok(lengths) = read_lengths_table(&mut rest, read_u32) {
+                let _ = CanonicalDecoder::new(lengths);
+            }
+        }
+        _ => {
+            let mut bits = BitReader::new(rest);
+            if let Ok(lengths) = read_tree(&mut bits, read_utf8_char) {
+                let _ = CanonicalDecoder::new(lengths);
+            }
+        }
+    }
+});
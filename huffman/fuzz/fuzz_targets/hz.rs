@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use test_huffman::{compress_bytes, decompress_bytes, Options};
+
+// The hz container around the codecs: any bytes must decode or fail
+// cleanly, and any bytes must come back from being compressed
+fuzz_target!(|data: &[u8]| {
+    let _ = decompress_bytes(data);
+    let compressed = compress_bytes(data, &Options::default()).unwrap();
+    assert_eq!(decompress_bytes(&compressed).unwrap(), data);
+});
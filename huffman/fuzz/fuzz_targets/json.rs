@@ -0,0 +1,33 @@
+#![no_main]
+
+use huffman::json::{self, Streams};
+use libfuzzer_sys::fuzz_target;
+
+// Splits the input into `N` streams, the leading bytes giving all but the
+// last one's length
+fn streams<const N: usize>(data: &[u8]) -> Option<[&[u8]; N]> {
+    let (lens, mut rest) = data.split_at_checked(N - 1)?;
+    let mut out = [&[][..]; N];
+    for (stream, &len) in out.iter_mut().zip(lens) {
+        (*stream, rest) = rest.split_at((len as usize).min(rest.len()));
+    }
+    out[N - 1] = rest;
+    Some(out)
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Some([structure, keys, strings, numbers]) = streams(data) {
+        let _ = json::join(&Streams {
+            structure: structure.to_vec(),
+            keys: keys.to_vec(),
+            strings: strings.to_vec(),
+            numbers: numbers.to_vec(),
+        });
+    }
+
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Some(split) = json::split(text) {
+            assert_eq!(json::join(&split).unwrap(), text);
+        }
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use huffman::codec::Decompressor;
+use huffman::lz4::Lz4;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Lz4.decompress(data);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use huffman::codec::Decompressor;
+use huffman::lz77::Lz77;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Lz77::new().decompress(data);
+});
@@ -0,0 +1,34 @@
+#![no_main]
+
+use huffman::protobuf::{self, Streams};
+use libfuzzer_sys::fuzz_target;
+
+// Splits the input into `N` streams, the leading bytes giving all but the
+// last one's length
+fn streams<const N: usize>(data: &[u8]) -> Option<[&[u8]; N]> {
+    let (lens, mut rest) = data.split_at_checked(N - 1)?;
+    let mut out = [&[][..]; N];
+    for (stream, &len) in out.iter_mut().zip(lens) {
+        (*stream, rest) = rest.split_at((len as usize).min(rest.len()));
+    }
+    out[N - 1] = rest;
+    Some(out)
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Some([frames, tags, varints, lengths, fixed, payloads]) = streams(data) {
+        let _ = protobuf::join(&Streams {
+            delimited: data[0] & 1 == 1,
+            frames: frames.to_vec(),
+            tags: tags.to_vec(),
+            varints: varints.to_vec(),
+            lengths: lengths.to_vec(),
+            fixed: fixed.to_vec(),
+            payloads: payloads.to_vec(),
+        });
+    }
+
+    if let Some(split) = protobuf::split(data) {
+        assert_eq!(protobuf::join(&split).unwrap(), data);
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use std::io::Read;
+
+use huffman::stream::HuffmanDecoder;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = HuffmanDecoder::new(data).read_to_end(&mut Vec::new());
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use huffman::timeseries;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = timeseries::decode(data);
+
+    let values: Vec<i64> = data.chunks_exact(8).map(|c| i64::from_le_bytes(c.try_into().unwrap())).collect();
+    assert_eq!(timeseries::decode(&timeseries::encode(&values)).unwrap(), values);
+});
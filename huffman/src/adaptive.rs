@@ -0,0 +1,228 @@
+//! Adaptive Huffman coding (the FGK algorithm): encoder and decoder start
+//! from the same empty tree and update it after every byte, so the code
+//! follows the frequencies seen so far. There is no table to send and no
+//! first pass to count with, which suits data that arrives as a stream.
+//! The output comes out about the size of static Huffman coding's, for the
+//! price of a tree update per byte.
+//!
+//! The tree keeps the sibling property: numbered bottom to top, nodes
+//! have non-decreasing weights, and siblings are numbered next to each
+//! other. Before a node's weight goes up it is swapped with the highest
+//! numbered node of the same weight, which keeps the property and so keeps
+//! the tree a Huffman tree. A byte not seen before is coded as the path to
+//! the "not yet transmitted" leaf, followed by the byte itself; that leaf
+//! then splits into itself and a new leaf for the byte.
+//!
+//! The output is the coded bits, least significant first, ending in a code
+//! for an end-of-data symbol: nothing, not even the length, is needed
+//! before the first byte can go out.
+//!
+//! ```
+//! use huffman::adaptive::AdaptiveHuffman;
+//! use huffman::codec::{Compressor, Decompressor};
+//!
+//! let text = "a a a a b b a a a c a a a ".repeat(20);
+//! let compressed = AdaptiveHuffman.compress(text.as_bytes());
+//! assert!(compressed.len() < text.len() / 4);
+//! assert_eq!(AdaptiveHuffman.decompress(&compressed).unwrap(), text.as_bytes());
+//! ```
+
+use std::io;
+
+use crate::bitio::{BitReader, BitWriter};
+use crate::codec::{Compressor, Decompressor, EntropyCoder};
+
+// Bytes, the end of the data and the not-yet-transmitted leaf
+const END: u16 = 256;
+const NYT: u16 = 257;
+// How new symbols are sent after the NYT code
+const SYMBOL_BITS: u32 = 9;
+const MAX_NODES: usize = 2 * (NYT as usize + 1) - 1;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Adaptive Huffman coding of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AdaptiveHuffman;
+
+impl EntropyCoder for AdaptiveHuffman {}
+
+#[derive(Debug, Clone, Copy)]
+enum Node {
+    Leaf(u16),
+    Internal { left: usize, right: usize },
+}
+
+// Nodes by number, the root at the top. Numbers below `nyt` are unused
+struct Tree {
+    nodes: Vec<Node>,
+    weights: Vec<u64>,
+    parents: Vec<usize>,
+    leaves: Vec<Option<usize>>,
+    nyt: usize,
+}
+
+impl Tree {
+    fn new() -> Tree {
+        let root = MAX_NODES - 1;
+        let mut leaves = vec![None; NYT as usize + 1];
+        leaves[NYT as usize] = Some(root);
+        Tree {
+            nodes: vec![Node::Leaf(NYT); MAX_NODES],
+            weights: vec![0; MAX_NODES],
+            parents: vec![root; MAX_NODES],
+            leaves,
+            nyt: root,
+        }
+    }
+
+    fn root(&self) -> usize {
+        MAX_NODES - 1
+    }
+
+    fn leaf(&self, symbol: u16) -> Option<usize> {
+        self.leaves[symbol as usize]
+    }
+
+    // The node's code, root first
+    fn code(&self, mut node: usize, out: &mut BitWriter) {
+        let mut bits = Vec::new();
+        while node != self.root() {
+            let parent = self.parents[node];
+            bits.push(matches!(self.nodes[parent], Node::Internal { right, .. } if right == node));
+            node = parent;
+        }
+        for &bit in bits.iter().rev() {
+            out.write_bit(bit);
+        }
+    }
+
+    fn place(&mut self, at: usize, node: Node) {
+        self.nodes[at] = node;
+        match node {
+            Node::Leaf(symbol) => self.leaves[symbol as usize] = Some(at),
+            Node::Internal { left, right } => {
+                self.parents[left] = at;
+                self.parents[right] = at;
+            }
+        }
+    }
+
+    // Counts `symbol` once more, adding a leaf for it if it is new
+    fn update(&mut self, symbol: u16) {
+        let mut node = match self.leaf(symbol) {
+            Some(leaf) => leaf,
+            None => {
+                // The NYT leaf becomes the parent of a new NYT leaf and the
+                // symbol's leaf, numbered just below it
+                let (parent, leaf, nyt) = (self.nyt, self.nyt - 1, self.nyt - 2);
+                self.parents[leaf] = parent;
+                self.parents[nyt] = parent;
+                self.place(leaf, Node::Leaf(symbol));
+                self.place(nyt, Node::Leaf(NYT));
+                self.place(parent, Node::Internal { left: nyt, right: leaf });
+                self.nyt = nyt;
+                leaf
+            }
+        };
+        loop {
+            let weight = self.weights[node];
+            let mut leader = node;
+            while leader + 1 < MAX_NODES && self.weights[leader + 1] == weight {
+                leader += 1;
+            }
+            if leader != node && leader != self.parents[node] {
+                let (a, b) = (self.nodes[node], self.nodes[leader]);
+                self.place(node, b);
+                self.place(leader, a);
+                node = leader;
+            }
+            self.weights[node] += 1;
+            if node == self.root() {
+                return;
+            }
+            node = self.parents[node];
+        }
+    }
+}
+
+impl Compressor for AdaptiveHuffman {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut tree = Tree::new();
+        let mut out = BitWriter::new();
+        for symbol in data.iter().map(|&b| b as u16).chain([END]) {
+            match tree.leaf(symbol) {
+                Some(leaf) => tree.code(leaf, &mut out),
+                None => {
+                    tree.code(tree.nyt, &mut out);
+                    out.write_bits(symbol as u64, SYMBOL_BITS);
+                }
+            }
+            tree.update(symbol);
+        }
+        out.finish()
+    }
+}
+
+impl Decompressor for AdaptiveHuffman {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut tree = Tree::new();
+        let mut input = BitReader::new(data);
+        let mut out = Vec::new();
+        loop {
+            let mut node = tree.root();
+            let symbol = loop {
+                match tree.nodes[node] {
+                    Node::Internal { left, right } => node = if input.read_bit()? { right } else { left },
+                    Node::Leaf(NYT) => match input.read_bits(SYMBOL_BITS)? as u16 {
+                        symbol if symbol <= END && tree.leaf(symbol).is_none() => break symbol,
+                        _ => return Err(invalid("bad new symbol in adaptive Huffman data")),
+                    },
+                    Node::Leaf(symbol) => break symbol,
+                }
+            };
+            if symbol == END {
+                break;
+            }
+            out.push(symbol as u8);
+            tree.update(symbol);
+        }
+        if input.bits_left() >= 8 {
+            return Err(invalid("adaptive Huffman data goes on after its end"));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Huffman;
+
+    #[test]
+    fn round_trips() {
+        let mut state = 5u32;
+        let skewed: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((state >> 16) as u16).trailing_zeros() as u8 + b'a'
+            })
+            .collect();
+        for data in [&b""[..], b"x", &[7; 5000], b"abracadabra", &skewed, &(0..=255).rev().collect::<Vec<u8>>()] {
+            assert_eq!(AdaptiveHuffman.decompress(&AdaptiveHuffman.compress(data)).unwrap(), data);
+        }
+        let static_size = Huffman::new().compress(&skewed).len();
+        assert!(AdaptiveHuffman.compress(&skewed).len() < static_size + static_size / 100);
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let compressed = AdaptiveHuffman.compress(b"some bytes to code");
+        assert!(AdaptiveHuffman.decompress(&compressed[..compressed.len() - 2]).is_err());
+        assert!(AdaptiveHuffman.decompress(&[&compressed[..], &[0]].concat()).is_err());
+        assert!(AdaptiveHuffman.decompress(&[0xff, 0xff]).is_err(), "symbol 511");
+        assert!(AdaptiveHuffman.decompress(&[]).is_err());
+    }
+}
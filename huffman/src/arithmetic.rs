@@ -0,0 +1,264 @@
+//! Arithmetic coding: the whole input becomes one number in `[0, 1)`,
+//! narrowed down byte by byte in proportion to each byte's frequency. A
+//! byte costs `-log2(p)` bits, fractions included, where Huffman coding has
+//! to round every code to whole bits and can't go below one bit a byte.
+//! It helps most where a few bytes dominate.
+//!
+//! This is the classic integer coder of Witten, Neal and Cleary. The
+//! interval is kept in 32-bit `low` and `high`, and shifted out a bit at a
+//! time once both ends agree on it; when they straddle the middle too
+//! closely to agree, the coder remembers how many opposite bits are owed
+//! and sends them after the next settled one. At the end, two more bits
+//! pick a point that lies inside the final interval whatever the bits
+//! after the stream's end, which the decoder takes to be zeros.
+//!
+//! The output is a [frequency model](crate::model) of the data, the byte
+//! count as a varint and the coded bits, most significant first.
+//!
+//! ```
+//! use huffman::arithmetic::Arithmetic;
+//! use huffman::codec::{Compressor, Decompressor, Huffman};
+//!
+//! // Huffman can't code the commonest byte in less than a bit
+//! let data = [&[b'.'; 10_000][..], b"a few other bytes"].concat();
+//! let compressed = Arithmetic.compress(&data);
+//! assert!(compressed.len() < Huffman::new().compress(&data).len() / 5);
+//! assert_eq!(Arithmetic.decompress(&compressed).unwrap(), data);
+//! ```
+
+use std::io;
+
+use crate::bitio::{BitOrder, BitReader, BitWriter};
+use crate::codec::{Compressor, Decompressor, EntropyCoder};
+use crate::model::{FrequencyModel, MAX_RUN, TOTAL};
+use crate::varint::{self, Reader};
+
+const BITS: u32 = 32;
+const MAX: u64 = (1 << BITS) - 1;
+const HALF: u64 = 1 << (BITS - 1);
+const QUARTER: u64 = 1 << (BITS - 2);
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Arithmetic coding of bytes with a static frequency model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Arithmetic;
+
+// The coder itself, for models other than a static one: each symbol is
+// the range `start..start + freq` of `0..total`, where `total` is at most
+// 2^16 so the interval never gets too narrow to split
+pub(crate) struct Encoder {
+    low: u64,
+    high: u64,
+    // Bits owed, each the opposite of the next one settled
+    pending: u64,
+    out: BitWriter,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Encoder {
+        Encoder {
+            low: 0,
+            high: MAX,
+            pending: 0,
+            out: BitWriter::with_order(BitOrder::MsbFirst),
+        }
+    }
+
+    fn settle(&mut self, bit: bool) {
+        self.out.write_bit(bit);
+        for _ in 0..self.pending {
+            self.out.write_bit(!bit);
+        }
+        self.pending = 0;
+    }
+
+    pub(crate) fn encode(&mut self, start: u32, freq: u32, total: u32) {
+        let range = self.high - self.low + 1;
+        self.high = self.low + range * (start + freq) as u64 / total as u64 - 1;
+        self.low += range * start as u64 / total as u64;
+        loop {
+            if self.high < HALF {
+                self.settle(false);
+            } else if self.low >= HALF {
+                self.settle(true);
+                self.low -= HALF;
+                self.high -= HALF;
+            } else if self.low >= QUARTER && self.high < HALF + QUARTER {
+                self.pending += 1;
+                self.low -= QUARTER;
+                self.high -= QUARTER;
+            } else {
+                break;
+            }
+            self.low <<= 1;
+            self.high = self.high << 1 | 1;
+        }
+    }
+
+    // The interval holds at least a quarter, so it contains either
+    // [1/4, 1/2) or [1/2, 3/4): two bits name that quarter, and zeros
+    // after them stay inside it
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        self.pending += 1;
+        self.settle(self.low >= QUARTER);
+        self.out.finish()
+    }
+}
+
+pub(crate) struct Decoder<'a> {
+    low: u64,
+    high: u64,
+    value: u64,
+    input: BitReader<'a>,
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Decoder<'a> {
+        let mut decoder = Decoder {
+            low: 0,
+            high: MAX,
+            value: 0,
+            input: BitReader::with_order(data, BitOrder::MsbFirst),
+        };
+        for _ in 0..BITS {
+            decoder.value = decoder.value << 1 | decoder.next_bit();
+        }
+        decoder
+    }
+
+    // Past the end, zeros
+    fn next_bit(&mut self) -> u64 {
+        self.input.read_bits(1).unwrap_or(0)
+    }
+
+    // Where in `0..total` the coded value lies, which picks the symbol to
+    // pass to `consume`
+    pub(crate) fn target(&self, total: u32) -> u32 {
+        let range = self.high - self.low + 1;
+        // `value` never leaves [low, high], so this is below `total`
+        (((self.value - self.low + 1) * total as u64 - 1) / range) as u32
+    }
+
+    pub(crate) fn consume(&mut self, start: u32, freq: u32, total: u32) {
+        let range = self.high - self.low + 1;
+        self.high = self.low + range * (start + freq) as u64 / total as u64 - 1;
+        self.low += range * start as u64 / total as u64;
+        loop {
+            // The same steps as the encoder's
+            let shift = if self.high < HALF {
+                0
+            } else if self.low >= HALF {
+                HALF
+            } else if self.low >= QUARTER && self.high < HALF + QUARTER {
+                QUARTER
+            } else {
+                break;
+            };
+            self.low = (self.low - shift) << 1;
+            self.high = (self.high - shift) << 1 | 1;
+            self.value = (self.value - shift) << 1 | self.next_bit();
+        }
+    }
+}
+
+impl EntropyCoder for Arithmetic {}
+
+impl Compressor for Arithmetic {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let model = FrequencyModel::from_data(data);
+        let mut out = Vec::new();
+        model.write(&mut out);
+        varint::put(&mut out, data.len() as u64);
+        if data.is_empty() {
+            return out;
+        }
+        let mut encoder = Encoder::new();
+        for &b in data {
+            encoder.encode(model.start(b), model.freq(b), TOTAL);
+        }
+        out.extend_from_slice(&encoder.finish());
+        out
+    }
+}
+
+impl Decompressor for Arithmetic {
+    /// Corruption in the coded bits goes unnoticed unless it leaves too
+    /// few of them; wrap the output in a checksummed format to catch it.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = Reader::new(data);
+        let model = FrequencyModel::read(&mut reader)?;
+        let len = reader.varint()?;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        if model.is_empty() {
+            return Err(invalid("arithmetic coded data has no frequency table"));
+        }
+        if let Some(b) = model.only_symbol() {
+            if len > MAX_RUN {
+                return Err(invalid("arithmetic coded run is too long"));
+            }
+            return Ok(vec![b; len as usize]);
+        }
+        let coded = reader.rest();
+        if !model.fits(len, coded.len()) {
+            return Err(invalid("arithmetic coded data is truncated"));
+        }
+        let symbols = model.symbol_table();
+        let mut decoder = Decoder::new(coded);
+        let mut out = Vec::new();
+        for _ in 0..len {
+            let b = symbols[decoder.target(TOTAL) as usize];
+            decoder.consume(model.start(b), model.freq(b), TOTAL);
+            out.push(b);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..50_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 24) as u8
+            })
+            .collect();
+        let skewed: Vec<u8> = noise.iter().map(|&b| b.trailing_zeros() as u8).collect();
+        for data in [&b""[..], b"x", &[7; 5000], b"abracadabra", &noise, &skewed, &(0..=255).collect::<Vec<u8>>()] {
+            let compressed = Arithmetic.compress(data);
+            assert_eq!(Arithmetic.decompress(&compressed).unwrap(), data);
+        }
+        // Within a few bytes of the model's entropy, plus the table
+        let model = FrequencyModel::from_data(&skewed);
+        let entropy: f64 = skewed.iter().map(|&b| -(model.freq(b) as f64 / TOTAL as f64).log2()).sum();
+        assert!((Arithmetic.compress(&skewed).len() as f64) < entropy / 8.0 + 40.0);
+    }
+
+    #[test]
+    fn rejects_impossible_lengths() {
+        let mut huge = Vec::new();
+        FrequencyModel::from_data(b"hello").write(&mut huge);
+        varint::put(&mut huge, u64::MAX);
+        assert!(Arithmetic.decompress(&huge).is_err());
+        assert!(Arithmetic.decompress(&[0, 3]).is_err());
+    }
+
+    #[test]
+    fn codes_long_runs_rather_than_trust_their_length() {
+        // Found by fuzzing: 13 bytes claiming 3.5 GB of one byte
+        assert!(Arithmetic.decompress(&[1, 3, 0x80, 0x80, 2, 0x80, 0x80, 0x80, 0x80, 0x0d, 0xfc, 0x7f, 0xfd]).is_err());
+        let run = vec![7; MAX_RUN as usize + 1];
+        let compressed = Arithmetic.compress(&run);
+        assert!(compressed.len() < 100, "{} bytes", compressed.len());
+        assert_eq!(Arithmetic.decompress(&compressed).unwrap(), run);
+    }
+}
@@ -0,0 +1,232 @@
+//! Arbitrary-precision unsigned integers for exact bit arithmetic, e.g. to
+//! show the full-precision interval an arithmetic coder narrows down, or to
+//! compute code probabilities without rounding. Only the handful of
+//! operations those need are provided: addition, shifts and comparison,
+//! plus conversion to and from binary strings.
+//!
+//! ```
+//! use huffman::bigbit::{add_binary, BigBits};
+//!
+//! assert_eq!(add_binary("1011", "0110").unwrap(), "10001");
+//!
+//! let one: BigBits = 1u64.into();
+//! let big = &one << 100;
+//! assert_eq!(big.bit_len(), 101);
+//! assert!(big > BigBits::from(u64::MAX));
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Shl, Shr};
+
+/// An unsigned integer stored as 64-bit limbs, least significant first,
+/// with no zero limbs at the top.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct BigBits {
+    limbs: Vec<u64>,
+}
+
+impl BigBits {
+    pub fn zero() -> BigBits {
+        BigBits::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn normalize(mut self) -> BigBits {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        self
+    }
+
+    /// Parses a string of `0`s and `1`s, most significant bit first.
+    /// Leading zeros are allowed; anything else, or an empty string, is not.
+    pub fn parse(bits: &str) -> Option<BigBits> {
+        if bits.is_empty() {
+            return None;
+        }
+        let mut limbs = vec![0u64; bits.len().div_ceil(64)];
+        for (i, c) in bits.bytes().rev().enumerate() {
+            match c {
+                b'0' => {}
+                b'1' => limbs[i / 64] |= 1 << (i % 64),
+                _ => return None,
+            }
+        }
+        Some(BigBits { limbs }.normalize())
+    }
+
+    /// Number of bits up to and including the highest set one; 0 for zero.
+    pub fn bit_len(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => self.limbs.len() * 64 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    /// Bit `i`, counting from the least significant.
+    pub fn bit(&self, i: usize) -> bool {
+        self.limbs.get(i / 64).is_some_and(|limb| limb >> (i % 64) & 1 == 1)
+    }
+}
+
+impl From<u64> for BigBits {
+    fn from(v: u64) -> BigBits {
+        BigBits { limbs: vec![v] }.normalize()
+    }
+}
+
+/// Binary digits, most significant first, without leading zeros ("0" for
+/// zero). A width pads with zeros, e.g. `format!("{:08}", n)`.
+impl fmt::Display for BigBits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits: String = match self.bit_len() {
+            0 => "0".to_string(),
+            len => (0..len).rev().map(|i| if self.bit(i) { '1' } else { '0' }).collect(),
+        };
+        f.pad_integral(true, "", &digits)
+    }
+}
+
+impl Add for &BigBits {
+    type Output = BigBits;
+
+    fn add(self, other: &BigBits) -> BigBits {
+        let len = self.limbs.len().max(other.limbs.len());
+        let mut limbs = Vec::with_capacity(len + 1);
+        let mut carry = false;
+        for i in 0..len {
+            let a = self.limbs.get(i).copied().unwrap_or(0);
+            let b = other.limbs.get(i).copied().unwrap_or(0);
+            let (sum, c1) = a.overflowing_add(b);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            limbs.push(sum);
+            carry = c1 || c2;
+        }
+        if carry {
+            limbs.push(1);
+        }
+        BigBits { limbs }
+    }
+}
+
+impl Shl<usize> for &BigBits {
+    type Output = BigBits;
+
+    fn shl(self, shift: usize) -> BigBits {
+        if self.is_zero() {
+            return BigBits::zero();
+        }
+        let (whole, part) = (shift / 64, (shift % 64) as u32);
+        let mut limbs = vec![0u64; whole];
+        let mut carry = 0u64;
+        for &limb in &self.limbs {
+            limbs.push(limb << part | carry);
+            carry = if part == 0 { 0 } else { limb >> (64 - part) };
+        }
+        limbs.push(carry);
+        BigBits { limbs }.normalize()
+    }
+}
+
+impl Shr<usize> for &BigBits {
+    type Output = BigBits;
+
+    fn shr(self, shift: usize) -> BigBits {
+        let (whole, part) = (shift / 64, (shift % 64) as u32);
+        let limbs = self.limbs.get(whole..).unwrap_or(&[]);
+        let limbs = (0..limbs.len())
+            .map(|i| {
+                let high = if part == 0 { 0 } else { limbs.get(i + 1).map_or(0, |&l| l << (64 - part)) };
+                limbs[i] >> part | high
+            })
+            .collect();
+        BigBits { limbs }.normalize()
+    }
+}
+
+impl Ord for BigBits {
+    fn cmp(&self, other: &BigBits) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigBits {
+    fn partial_cmp(&self, other: &BigBits) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Adds two binary strings. The result is at least as wide as the wider
+/// input, so `"0011" + "0001"` gives `"0100"`; `None` if either isn't
+/// binary.
+pub fn add_binary(a: &str, b: &str) -> Option<String> {
+    let sum = &BigBits::parse(a)? + &BigBits::parse(b)?;
+    Some(format!("{:0width$}", sum, width = a.len().max(b.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(v: u128) -> BigBits {
+        &(&BigBits::from((v >> 64) as u64) << 64) + &BigBits::from(v as u64)
+    }
+
+    const SAMPLES: [u128; 8] = [0, 1, 2, 0xff, u64::MAX as u128, 1 << 64, 0x1234_5678_9abc_def0_0fed_cba9, u128::MAX >> 1];
+
+    #[test]
+    fn add_binary_keeps_width() {
+        assert_eq!(add_binary("11", "1").unwrap(), "100");
+        assert_eq!(add_binary("0011", "0001").unwrap(), "0100");
+        assert_eq!(add_binary("0", "0").unwrap(), "0");
+        assert_eq!(add_binary("1010", "").as_deref(), None);
+        assert_eq!(add_binary("102", "1").as_deref(), None);
+    }
+
+    #[test]
+    fn parses_and_prints_binary() {
+        for v in SAMPLES {
+            let text = format!("{:b}", v);
+            assert_eq!(BigBits::parse(&text).unwrap(), big(v));
+            assert_eq!(big(v).to_string(), text);
+            assert_eq!(big(v).bit_len(), 128 - v.leading_zeros() as usize);
+        }
+        assert_eq!(BigBits::parse("000").unwrap(), BigBits::zero());
+    }
+
+    #[test]
+    fn matches_native_arithmetic() {
+        for a in SAMPLES {
+            for b in SAMPLES {
+                if let Some(sum) = a.checked_add(b) {
+                    assert_eq!(&big(a) + &big(b), big(sum));
+                }
+                assert_eq!(big(a).cmp(&big(b)), a.cmp(&b));
+            }
+            for shift in 0..128 {
+                assert_eq!(&big(a) >> shift, big(a >> shift), "{:#x} >> {}", a, shift);
+                if shift <= a.leading_zeros() as usize {
+                    assert_eq!(&big(a) << shift, big(a << shift), "{:#x} << {}", a, shift);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grows_past_any_native_width() {
+        let one = BigBits::from(1);
+        let huge = &one << 1000;
+        assert_eq!(huge.bit_len(), 1001);
+        assert!(huge.bit(1000) && !huge.bit(999));
+        assert_eq!(&(&huge + &huge) >> 1001, one);
+        assert_eq!(&huge >> 2000, BigBits::zero());
+        assert!(huge > big(u128::MAX));
+    }
+}
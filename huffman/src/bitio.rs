@@ -0,0 +1,335 @@
+//! Bit-level reading and writing for codes that don't line up with bytes.
+//!
+//! Each stream picks a [`BitOrder`]. By default bits are packed least
+//! significant first: the first bit written lands in bit 0 of the first
+//! byte, and `write_bits(value, n)` emits the low `n` bits of `value`
+//! starting from its lowest. This is the DEFLATE convention and the layout
+//! the Huffman streams have always used (it matches `bitvec`'s default
+//! `Lsb0` order), so existing files still decode. [`BitOrder::MsbFirst`]
+//! is the mirror image, as in JPEG and most textbook descriptions: bytes
+//! fill from bit 7 down and values are emitted from their highest bit.
+//!
+//! A partial byte stays in the writer until [`BitWriter::align`] or
+//! [`BitWriter::finish`] pads it with zero bits; the reader has no way to
+//! tell padding from data, so formats that care store a symbol or bit count
+//! of their own.
+//!
+//! ```
+//! use huffman::bitio::{BitReader, BitWriter};
+//!
+//! let mut writer = BitWriter::new();
+//! writer.write_bits(0b101, 3);
+//! writer.write_bit(true);
+//! let bytes = writer.finish();
+//! assert_eq!(bytes, [0b1101]);
+//!
+//! let mut reader = BitReader::new(&bytes);
+//! assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+//! assert!(reader.read_bit().unwrap());
+//! ```
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use crate::io;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+    #[default]
+    LsbFirst,
+    MsbFirst,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BitWriter {
+    order: BitOrder,
+    out: Vec<u8>,
+    // Pending bits, fewer than 8 between calls.
+    acc: u64,
+    acc_bits: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter::default()
+    }
+
+    pub fn with_order(order: BitOrder) -> BitWriter {
+        BitWriter {
+            order,
+            ..BitWriter::default()
+        }
+    }
+
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Writes the low `n` bits of `value`, `n` at most 64.
+    pub fn write_bits(&mut self, value: u64, n: u32) {
+        assert!(n <= 64, "cannot write {} bits at once", n);
+        if n > 32 {
+            // Keep the accumulator from overflowing; which half goes first
+            // depends on the order.
+            match self.order {
+                BitOrder::LsbFirst => {
+                    self.write_bits(value, 32);
+                    self.write_bits(value >> 32, n - 32);
+                }
+                BitOrder::MsbFirst => {
+                    self.write_bits(value >> 32, n - 32);
+                    self.write_bits(value, 32);
+                }
+            }
+            return;
+        }
+        let value = value & ((1u64 << n) - 1);
+        match self.order {
+            BitOrder::LsbFirst => {
+                self.acc |= value << self.acc_bits;
+                self.acc_bits += n;
+                while self.acc_bits >= 8 {
+                    self.out.push(self.acc as u8);
+                    self.acc >>= 8;
+                    self.acc_bits -= 8;
+                }
+            }
+            BitOrder::MsbFirst => {
+                self.acc = (self.acc << n) | value;
+                self.acc_bits += n;
+                while self.acc_bits >= 8 {
+                    self.acc_bits -= 8;
+                    self.out.push((self.acc >> self.acc_bits) as u8);
+                }
+                self.acc &= (1 << self.acc_bits) - 1;
+            }
+        }
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        self.write_bits(bit as u64, 1);
+    }
+
+    /// Pads with zero bits up to the next byte boundary, flushing the
+    /// partial byte. Does nothing if already aligned.
+    pub fn align(&mut self) {
+        if self.acc_bits > 0 {
+            self.write_bits(0, 8 - self.acc_bits);
+        }
+    }
+
+    /// Number of bits written so far, padding included.
+    pub fn bit_len(&self) -> u64 {
+        self.out.len() as u64 * 8 + self.acc_bits as u64
+    }
+
+    /// Aligns and returns the bytes written.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    order: BitOrder,
+    data: &'a [u8],
+    // Position in bits from the start of `data`.
+    pos: u64,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader::with_order(data, BitOrder::default())
+    }
+
+    pub fn with_order(data: &'a [u8], order: BitOrder) -> BitReader<'a> {
+        BitReader { order, data, pos: 0 }
+    }
+
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Bits not consumed yet, including any padding at the end.
+    pub fn bits_left(&self) -> u64 {
+        self.data.len() as u64 * 8 - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits_left() == 0
+    }
+
+    /// Reads `n` bits, `n` at most 64, as written by
+    /// [`BitWriter::write_bits`].
+    pub fn read_bits(&mut self, n: u32) -> io::Result<u64> {
+        assert!(n <= 64, "cannot read {} bits at once", n);
+        if n as u64 > self.bits_left() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("bit stream is truncated at byte {}", self.data.len())));
+        }
+        let mut value = 0u64;
+        let mut got = 0;
+        while got < n {
+            let offset = (self.pos % 8) as u32;
+            let take = (8 - offset).min(n - got);
+            let byte = self.data[(self.pos / 8) as usize];
+            let mask = (1u64 << take) - 1;
+            match self.order {
+                BitOrder::LsbFirst => value |= ((byte >> offset) as u64 & mask) << got,
+                BitOrder::MsbFirst => value = (value << take) | ((byte >> (8 - offset - take)) as u64 & mask),
+            }
+            got += take;
+            self.pos += take as u64;
+        }
+        Ok(value)
+    }
+
+    pub fn read_bit(&mut self) -> io::Result<bool> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    /// Skips to the next byte boundary, mirroring [`BitWriter::align`].
+    pub fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    /// The whole bytes after the current position, which must be aligned.
+    pub fn rest(&self) -> &'a [u8] {
+        debug_assert!(self.pos.is_multiple_of(8), "reader is not byte aligned");
+        &self.data[self.pos.div_ceil(8) as usize..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_mixed_widths() {
+        let fields: Vec<(u64, u32)> = (0..=64).map(|n| (u64::MAX.wrapping_mul(n as u64 + 3) / 7, n)).collect();
+        let mut writer = BitWriter::new();
+        for &(value, n) in &fields {
+            writer.write_bits(value, n);
+        }
+        let total: u64 = fields.iter().map(|&(_, n)| n as u64).sum();
+        assert_eq!(writer.bit_len(), total);
+        let bytes = writer.finish();
+        assert_eq!(bytes.len() as u64, total.div_ceil(8));
+
+        let mut reader = BitReader::new(&bytes);
+        for &(value, n) in &fields {
+            let mask = if n == 64 { u64::MAX } else { (1 << n) - 1 };
+            assert_eq!(reader.read_bits(n).unwrap(), value & mask);
+        }
+        assert!(reader.bits_left() < 8);
+    }
+
+    #[test]
+    fn packs_least_significant_bit_first() {
+        let mut writer = BitWriter::new();
+        for bit in [true, false, false, false, false, false, false, false, false, true] {
+            writer.write_bit(bit);
+        }
+        assert_eq!(writer.finish(), [0b0000_0001, 0b0000_0010]);
+    }
+
+    #[test]
+    fn align_pads_and_skips() {
+        let mut writer = BitWriter::new();
+        writer.write_bits(0b11, 2);
+        writer.align();
+        writer.align();
+        writer.write_bits(0xab, 8);
+        let bytes = writer.finish();
+        assert_eq!(bytes, [0b11, 0xab]);
+
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read_bits(2).unwrap(), 0b11);
+        reader.align();
+        assert_eq!(reader.rest(), [0xab]);
+        assert_eq!(reader.read_bits(8).unwrap(), 0xab);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn reading_past_the_end_fails() {
+        let mut reader = BitReader::new(&[0xff]);
+        assert!(reader.read_bits(9).is_err());
+        assert_eq!(reader.read_bits(8).unwrap(), 0xff);
+        assert!(reader.read_bit().is_err());
+    }
+
+    const ORDERS: [BitOrder; 2] = [BitOrder::LsbFirst, BitOrder::MsbFirst];
+
+    #[test]
+    fn packs_most_significant_bit_first() {
+        let mut writer = BitWriter::with_order(BitOrder::MsbFirst);
+        writer.write_bits(0b101, 3);
+        writer.write_bits(0x1ff, 9);
+        assert_eq!(writer.finish(), [0b1011_1111, 0b1111_0000]);
+    }
+
+    // Every value of every width up to 12 bits, at every offset within a
+    // byte, in both orders.
+    #[test]
+    fn round_trips_every_small_field_in_both_orders() {
+        for order in ORDERS {
+            for offset in 0..8 {
+                for n in 1..=12 {
+                    for value in 0..1u64 << n {
+                        let mut writer = BitWriter::with_order(order);
+                        writer.write_bits(0, offset);
+                        writer.write_bits(value, n);
+                        writer.write_bit(true);
+                        let bytes = writer.finish();
+                        let mut reader = BitReader::with_order(&bytes, order);
+                        assert_eq!(reader.read_bits(offset).unwrap(), 0);
+                        assert_eq!(reader.read_bits(n).unwrap(), value, "{:?} {} {}", order, offset, n);
+                        assert!(reader.read_bit().unwrap());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_wide_fields_in_both_orders() {
+        for order in ORDERS {
+            for offset in 0..8 {
+                for n in 0..=64 {
+                    let value = 0x9e37_79b9_7f4a_7c15u64.rotate_left(n + offset);
+                    let mask = if n == 64 { u64::MAX } else { (1 << n) - 1 };
+                    let mut writer = BitWriter::with_order(order);
+                    writer.write_bits(u64::MAX, offset);
+                    writer.write_bits(value, n);
+                    writer.write_bits(value, 64);
+                    let bytes = writer.finish();
+                    let mut reader = BitReader::with_order(&bytes, order);
+                    assert_eq!(reader.read_bits(offset).unwrap(), (1 << offset) - 1);
+                    assert_eq!(reader.read_bits(n).unwrap(), value & mask);
+                    assert_eq!(reader.read_bits(64).unwrap(), value);
+                }
+            }
+        }
+    }
+
+    // Single bits come out in the same sequence whatever the order, so one
+    // order's bytes are the other's with each byte reversed.
+    #[test]
+    fn single_bit_streams_mirror_across_orders() {
+        for pattern in 0..1u32 << 12 {
+            let bits: Vec<bool> = (0..12).map(|i| pattern >> i & 1 == 1).collect();
+            let [lsb, msb] = ORDERS.map(|order| {
+                let mut writer = BitWriter::with_order(order);
+                bits.iter().for_each(|&b| writer.write_bit(b));
+                writer.finish()
+            });
+            let reversed: Vec<u8> = lsb.iter().map(|b| b.reverse_bits()).collect();
+            assert_eq!(msb, reversed);
+
+            let mut reader = BitReader::with_order(&reversed, BitOrder::MsbFirst);
+            let read: Vec<bool> = (0..12).map(|_| reader.read_bit().unwrap()).collect();
+            assert_eq!(read, bits);
+        }
+    }
+}
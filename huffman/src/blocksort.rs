@@ -0,0 +1,92 @@
+//! Block-sorting compression in the style of bzip2: the
+//! [Burrows-Wheeler transform](crate::bwt) gathers bytes with the same
+//! context, [move-to-front](crate::mtf) turns the result into mostly small
+//! numbers with long runs of zeros, [run-length encoding](crate::rle)
+//! shortens those runs, and [Huffman coding](crate::codec::Huffman) codes
+//! what is left. On text this does much better than Huffman coding alone,
+//! as it takes advantage of context rather than just symbol frequencies.
+//!
+//! The output is that of each stage in turn, so the BWT's block size is
+//! recorded in it. Another [`EntropyCoder`] can replace Huffman coding,
+//! though the decompressor must then be given the same one:
+//!
+//! ```
+//! use huffman::blocksort::BlockSort;
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::rans::Rans;
+//!
+//! let text = "so it goes. ".repeat(100);
+//! let block_sort = BlockSort::new().with_coder(Rans);
+//! let compressed = block_sort.compress(text.as_bytes());
+//! assert_eq!(block_sort.decompress(&compressed).unwrap(), text.as_bytes());
+//! ```
+//!
+//! ```
+//! use huffman::blocksort::BlockSort;
+//! use huffman::codec::{Compressor, Decompressor, Huffman};
+//!
+//! let text = "Whan that Aprill with his shoures soote \
+//!             The droghte of March hath perced to the roote, \
+//!             And bathed every veyne in swich licour, \
+//!             Of which vertu engendred is the flour; ".repeat(20);
+//! let compressed = BlockSort::new().compress(text.as_bytes());
+//! assert!(compressed.len() < Huffman::new().compress(text.as_bytes()).len() / 4);
+//! assert_eq!(BlockSort::new().decompress(&compressed).unwrap(), text.as_bytes());
+//! ```
+
+use std::io;
+
+use crate::bwt::Bwt;
+use crate::codec::{Compressor, Decompressor, EntropyCoder, Huffman, Pipeline};
+use crate::mtf::Mtf;
+use crate::rle::Rle;
+
+/// BWT, MTF, RLE and an entropy coder, Huffman unless told otherwise, with
+/// BWT blocks of up to `block_size` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockSort<E = Huffman> {
+    bwt: Bwt,
+    coder: E,
+}
+
+impl BlockSort {
+    pub fn new() -> BlockSort {
+        BlockSort {
+            bwt: Bwt::new(),
+            coder: Huffman::new(),
+        }
+    }
+
+    /// Fails unless `block_size` is 1 to
+    /// [`MAX_BLOCK_SIZE`](crate::bwt::MAX_BLOCK_SIZE).
+    pub fn with_block_size(block_size: usize) -> io::Result<BlockSort> {
+        Ok(BlockSort {
+            bwt: Bwt::with_block_size(block_size)?,
+            coder: Huffman::new(),
+        })
+    }
+}
+
+impl<E: EntropyCoder + Copy + 'static> BlockSort<E> {
+    /// The same, with `coder` as the last stage.
+    pub fn with_coder<C: EntropyCoder + Copy + 'static>(self, coder: C) -> BlockSort<C> {
+        BlockSort { bwt: self.bwt, coder }
+    }
+
+    fn pipeline(&self) -> Pipeline {
+        Pipeline::new().then(self.bwt).then(Mtf).then(Rle).then(self.coder)
+    }
+}
+
+impl<E: EntropyCoder + Copy + 'static> Compressor for BlockSort<E> {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        self.pipeline().compress(data)
+    }
+}
+
+impl<E: EntropyCoder + Copy + 'static> Decompressor for BlockSort<E> {
+    /// Any block size is read back from the data.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        self.pipeline().decompress(data)
+    }
+}
@@ -0,0 +1,386 @@
+//! bsdiff-style binary patches. Approximate matches between the old and new
+//! file are found through a suffix array of the old file; each match is
+//! stored as a bytewise difference (mostly zeros for recompiled binaries,
+//! where only embedded addresses shift) and unmatched bytes go to an extra
+//! stream. The three streams are kept separate and each is deflated on its
+//! own, as bsdiff does with bzip2: the diff stream's runs of zeros and the
+//! controls' mostly-zero high bytes are what make patches small.
+
+use std::borrow::Cow;
+use std::io;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::deflate::Deflate;
+
+const MAGIC: &[u8; 4] = b"HZBD";
+// Version 1 stored the streams uncoded
+const VERSION: u8 = 2;
+
+/// FNV-1a, used to tie a patch to the exact base file and to check the
+/// patched output.
+pub fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Suffix array of `data` by prefix doubling. Includes the empty suffix,
+/// which always sorts first.
+pub fn suffix_array(data: &[u8]) -> Vec<usize> {
+    let n = data.len();
+    let mut sa: Vec<usize> = (0..=n).collect();
+    // rank[n] is the empty suffix, smaller than every byte.
+    let mut rank: Vec<usize> = data.iter().map(|&b| b as usize + 1).collect();
+    rank.push(0);
+    let mut tmp = vec![0; n + 1];
+    let mut k = 1;
+    loop {
+        let key = |i: usize| (rank[i], if i + k <= n { rank[i + k] } else { 0 });
+        sa.sort_unstable_by_key(|&i| key(i));
+        tmp[sa[0]] = 0;
+        for w in 1..=n {
+            tmp[sa[w]] = tmp[sa[w - 1]] + usize::from(key(sa[w - 1]) != key(sa[w]));
+        }
+        std::mem::swap(&mut rank, &mut tmp);
+        if rank[sa[n]] == n || k > n {
+            break;
+        }
+        k *= 2;
+    }
+    sa
+}
+
+fn match_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// Longest prefix of `new` occurring in `old`, as (position, length).
+fn search(sa: &[usize], old: &[u8], new: &[u8], mut st: usize, mut en: usize) -> (usize, usize) {
+    while en - st >= 2 {
+        let mid = st + (en - st) / 2;
+        let suffix = &old[sa[mid]..];
+        let n = suffix.len().min(new.len());
+        if suffix[..n] < new[..n] {
+            st = mid;
+        } else {
+            en = mid;
+        }
+    }
+    let x = match_len(&old[sa[st]..], new);
+    let y = match_len(&old[sa[en]..], new);
+    if x > y {
+        (sa[st], x)
+    } else {
+        (sa[en], y)
+    }
+}
+
+/// One step of a patch: add `diff` bytes onto the old file, append `extra`
+/// bytes verbatim, then move the old-file cursor by `seek`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Control {
+    pub diff: u64,
+    pub extra: u64,
+    pub seek: i64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub controls: Vec<Control>,
+    pub diff: Vec<u8>,
+    pub extra: Vec<u8>,
+}
+
+/// Computes the control, diff and extra streams that turn `old` into `new`.
+pub fn compute(old: &[u8], new: &[u8]) -> Patch {
+    let sa = suffix_array(old);
+    let old_len = old.len() as isize;
+    let new_len = new.len() as isize;
+    let at_old = |i: isize| old[i as usize];
+    let at_new = |i: isize| new[i as usize];
+
+    let mut patch = Patch::default();
+    let (mut scan, mut len, mut pos) = (0isize, 0isize, 0isize);
+    let (mut last_scan, mut last_pos, mut last_offset) = (0isize, 0isize, 0isize);
+    while scan < new_len {
+        let mut old_score = 0isize;
+        scan += len;
+        let mut scsc = scan;
+        while scan < new_len {
+            let (p, l) = search(&sa, old, &new[scan as usize..], 0, old.len());
+            pos = p as isize;
+            len = l as isize;
+            while scsc < scan + len {
+                if scsc + last_offset < old_len && at_old(scsc + last_offset) == at_new(scsc) {
+                    old_score += 1;
+                }
+                scsc += 1;
+            }
+            if (len == old_score && len != 0) || len > old_score + 8 {
+                break;
+            }
+            if scan + last_offset < old_len && at_old(scan + last_offset) == at_new(scan) {
+                old_score -= 1;
+            }
+            scan += 1;
+        }
+
+        if len == old_score && scan != new_len {
+            continue;
+        }
+
+        // Extend the previous match forwards and this one backwards as long
+        // as at least half the bytes still agree.
+        let (mut s, mut best, mut len_f) = (0isize, 0isize, 0isize);
+        let mut i = 0;
+        while last_scan + i < scan && last_pos + i < old_len {
+            if at_old(last_pos + i) == at_new(last_scan + i) {
+                s += 1;
+            }
+            i += 1;
+            if s * 2 - i > best * 2 - len_f {
+                best = s;
+                len_f = i;
+            }
+        }
+
+        let mut len_b = 0isize;
+        if scan < new_len {
+            let (mut s, mut best) = (0isize, 0isize);
+            let mut i = 1;
+            while scan >= last_scan + i && pos >= i {
+                if at_old(pos - i) == at_new(scan - i) {
+                    s += 1;
+                }
+                if s * 2 - i > best * 2 - len_b {
+                    best = s;
+                    len_b = i;
+                }
+                i += 1;
+            }
+        }
+
+        if last_scan + len_f > scan - len_b {
+            let overlap = (last_scan + len_f) - (scan - len_b);
+            let (mut s, mut best, mut len_s) = (0isize, 0isize, 0isize);
+            for i in 0..overlap {
+                if at_new(last_scan + len_f - overlap + i) == at_old(last_pos + len_f - overlap + i)
+                {
+                    s += 1;
+                }
+                if at_new(scan - len_b + i) == at_old(pos - len_b + i) {
+                    s -= 1;
+                }
+                if s > best {
+                    best = s;
+                    len_s = i + 1;
+                }
+            }
+            len_f += len_s - overlap;
+            len_b -= len_s;
+        }
+
+        for i in 0..len_f {
+            patch
+                .diff
+                .push(at_new(last_scan + i).wrapping_sub(at_old(last_pos + i)));
+        }
+        let extra_len = (scan - len_b) - (last_scan + len_f);
+        let extra_start = (last_scan + len_f) as usize;
+        patch
+            .extra
+            .extend_from_slice(&new[extra_start..extra_start + extra_len as usize]);
+        patch.controls.push(Control {
+            diff: len_f as u64,
+            extra: extra_len as u64,
+            seek: ((pos - len_b) - (last_pos + len_f)) as i64,
+        });
+
+        last_scan = scan - len_b;
+        last_pos = pos - len_b;
+        last_offset = pos - scan;
+    }
+    patch
+}
+
+/// Produces a serialized patch from `old` to `new`.
+pub fn diff(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let patch = compute(old, new);
+    let mut controls = Vec::with_capacity(patch.controls.len() * 24);
+    for c in &patch.controls {
+        controls.extend_from_slice(&c.diff.to_le_bytes());
+        controls.extend_from_slice(&c.extra.to_le_bytes());
+        controls.extend_from_slice(&c.seek.to_le_bytes());
+    }
+    let streams = [controls, patch.diff, patch.extra].map(|stream| Deflate.compress(&stream));
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    for field in [
+        old.len() as u64,
+        checksum(old),
+        new.len() as u64,
+        checksum(new),
+        streams[0].len() as u64,
+        streams[1].len() as u64,
+        streams[2].len() as u64,
+    ] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    for stream in &streams {
+        out.extend_from_slice(stream);
+    }
+    out
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn take<'a>(data: &mut &'a [u8], n: u64) -> io::Result<&'a [u8]> {
+    let n = usize::try_from(n).map_err(|_| invalid("patch is truncated"))?;
+    if data.len() < n {
+        return Err(invalid("patch is truncated"));
+    }
+    let (head, rest) = data.split_at(n);
+    *data = rest;
+    Ok(head)
+}
+
+fn take_u64(data: &mut &[u8]) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(take(data, 8)?.try_into().unwrap()))
+}
+
+/// Rebuilds the new file from `old` and a patch produced by [`diff`],
+/// checking both the base file and the result against the stored checksums.
+pub fn apply(old: &[u8], mut patch: &[u8]) -> io::Result<Vec<u8>> {
+    if take(&mut patch, 4)? != MAGIC {
+        return Err(invalid("not a binary diff patch"));
+    }
+    let version = take(&mut patch, 1)?[0];
+    if !(1..=VERSION).contains(&version) {
+        return Err(invalid("unsupported binary diff patch version"));
+    }
+    let old_len = take_u64(&mut patch)?;
+    let old_sum = take_u64(&mut patch)?;
+    if old_len != old.len() as u64 || old_sum != checksum(old) {
+        return Err(invalid("patch was made against a different base file"));
+    }
+    let new_len = take_u64(&mut patch)?;
+    let new_sum = take_u64(&mut patch)?;
+    let controls_len = take_u64(&mut patch)?;
+    let diff_len = take_u64(&mut patch)?;
+    let extra_len = take_u64(&mut patch)?;
+    let mut streams = Vec::new();
+    for len in [controls_len, diff_len, extra_len] {
+        let stream = take(&mut patch, len)?;
+        streams.push(match version {
+            1 => Cow::Borrowed(stream),
+            _ => Cow::Owned(Deflate.decompress(stream)?),
+        });
+    }
+    let [mut controls, mut diff, mut extra] = [&streams[0][..], &streams[1][..], &streams[2][..]];
+    if !patch.is_empty() || controls.len() % 24 != 0 {
+        return Err(invalid("malformed binary diff patch"));
+    }
+
+    let mut out = Vec::new();
+    let mut old_pos: i64 = 0;
+    while !controls.is_empty() {
+        let add = take_u64(&mut controls)?;
+        let copy = take_u64(&mut controls)?;
+        let seek = take_u64(&mut controls)? as i64;
+        if (out.len() as u64).saturating_add(add.saturating_add(copy)) > new_len {
+            return Err(invalid("patch writes past the end of the output"));
+        }
+        for &d in take(&mut diff, add)? {
+            let base = usize::try_from(old_pos)
+                .ok()
+                .and_then(|p| old.get(p))
+                .copied()
+                .unwrap_or(0);
+            out.push(d.wrapping_add(base));
+            old_pos += 1;
+        }
+        out.extend_from_slice(take(&mut extra, copy)?);
+        old_pos = old_pos
+            .checked_add(seek)
+            .ok_or_else(|| invalid("malformed binary diff patch"))?;
+    }
+    if out.len() as u64 != new_len || checksum(&out) != new_sum {
+        return Err(invalid(
+            "patched output does not match the expected checksum",
+        ));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A "binary" of little-endian addresses, and the same one rebuilt with
+    // every address shifted and a few bytes patched in
+    fn versions() -> (Vec<u8>, Vec<u8>) {
+        let old: Vec<u8> = (0..4000u32).flat_map(|i| (0x40_0000 + i * 12).to_le_bytes()).collect();
+        let mut new: Vec<u8> = (0..4000u32).flat_map(|i| (0x40_0040 + i * 12).to_le_bytes()).collect();
+        new.splice(5000..5000, *b"a new function");
+        (old, new)
+    }
+
+    #[test]
+    fn suffix_array_is_sorted() {
+        let data = b"mississippi banana";
+        let sa = suffix_array(data);
+        assert_eq!(sa.len(), data.len() + 1);
+        assert!(sa.windows(2).all(|w| data[w[0]..] < data[w[1]..]));
+    }
+
+    #[test]
+    fn round_trips() {
+        let (old, new) = versions();
+        for (old, new) in [(&old[..], &new[..]), (b"", b"all new"), (b"all gone", b""), (b"same", b"same")] {
+            assert_eq!(apply(old, &diff(old, new)).unwrap(), new);
+        }
+    }
+
+    #[test]
+    fn codes_the_streams() {
+        let (old, new) = versions();
+        let patch = compute(&old, &new);
+        let uncoded = patch.controls.len() * 24 + patch.diff.len() + patch.extra.len();
+        let coded = diff(&old, &new).len();
+        // The diffs are mostly zeros, and the rest repeat
+        assert!(coded * 4 < uncoded, "{} bytes coded, {} not", coded, uncoded);
+        assert!(coded * 4 < new.len(), "{} bytes", coded);
+    }
+
+    #[test]
+    fn checks_the_base_and_the_result() {
+        let (old, new) = versions();
+        let patch = diff(&old, &new);
+        let mut other = old.clone();
+        other[100] ^= 1;
+        let e = apply(&other, &patch).unwrap_err();
+        assert!(e.to_string().contains("different base file"), "{}", e);
+        assert!(apply(&old, &patch[..patch.len() - 1]).is_err());
+        assert!(apply(&old, &[&patch[..], &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn reads_version_1_patches() {
+        let (old, new) = (b"hello world", b"jello world!");
+        let mut patch = MAGIC.to_vec();
+        patch.push(1);
+        let controls = [11u64, 1, 0].map(u64::to_le_bytes).concat();
+        let diff = [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        for field in [old.len() as u64, checksum(old), new.len() as u64, checksum(new), 24, 11, 1] {
+            patch.extend_from_slice(&field.to_le_bytes());
+        }
+        patch.extend_from_slice(&controls);
+        patch.extend_from_slice(&diff);
+        patch.push(b'!');
+        assert_eq!(apply(old, &patch).unwrap(), new);
+    }
+}
@@ -0,0 +1,188 @@
+//! The Burrows-Wheeler transform: a block's bytes are reordered so that
+//! bytes followed by the same context end up together, which turns repeats
+//! anywhere in the block into runs. It doesn't compress anything itself;
+//! it is a stage ahead of move-to-front and an entropy coder.
+//!
+//! Each block is sorted on its own, so the block size trades memory and
+//! time for how far apart repeats can be. The output starts with the block
+//! size as a varint. Then for each block comes the varint row of the
+//! original block among its sorted rotations, and the last column of the
+//! sorted rotations, as long as the block. All blocks but the last are
+//! full.
+//!
+//! ```
+//! use huffman::bwt::Bwt;
+//! use huffman::codec::{Compressor, Decompressor};
+//!
+//! let transformed = Bwt::new().compress(b"banana");
+//! assert_eq!(&transformed[transformed.len() - 6..], b"nnbaaa");
+//! assert_eq!(Bwt::new().decompress(&transformed).unwrap(), b"banana");
+//! ```
+
+use std::io;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::varint::{self, Reader};
+
+/// bzip2's largest block.
+pub const DEFAULT_BLOCK_SIZE: usize = 900_000;
+pub const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// The rotations of `block` in sorted order, as their starting positions.
+/// Sorts by prefix doubling: rotations ranked by their first `k` bytes are
+/// ranked by the first `2k` from the ranks of their two halves.
+fn sort_rotations(block: &[u8]) -> Vec<usize> {
+    let n = block.len();
+    let mut rotations: Vec<usize> = (0..n).collect();
+    let mut rank: Vec<u32> = block.iter().map(|&b| b as u32).collect();
+    let mut next_rank = vec![0u32; n];
+    let mut k = 1;
+    loop {
+        let key = |i: usize| (rank[i], rank[(i + k) % n]);
+        rotations.sort_unstable_by_key(|&i| key(i));
+        next_rank[rotations[0]] = 0;
+        for w in 1..n {
+            let distinct = key(rotations[w - 1]) != key(rotations[w]);
+            next_rank[rotations[w]] = next_rank[rotations[w - 1]] + distinct as u32;
+        }
+        std::mem::swap(&mut rank, &mut next_rank);
+        // Done when every rank differs, or when rotations still tied are
+        // equal, the block being periodic.
+        if rank[rotations[n - 1]] as usize == n - 1 || 2 * k >= n {
+            return rotations;
+        }
+        k *= 2;
+    }
+}
+
+/// The Burrows-Wheeler transform of blocks of up to `block_size` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bwt {
+    block_size: usize,
+}
+
+impl Bwt {
+    pub fn new() -> Bwt {
+        Bwt {
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    /// Fails unless `block_size` is 1 to [`MAX_BLOCK_SIZE`].
+    pub fn with_block_size(block_size: usize) -> io::Result<Bwt> {
+        if !(1..=MAX_BLOCK_SIZE).contains(&block_size) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "BWT block size out of range"));
+        }
+        Ok(Bwt { block_size })
+    }
+}
+
+impl Default for Bwt {
+    fn default() -> Bwt {
+        Bwt::new()
+    }
+}
+
+impl Compressor for Bwt {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 16);
+        varint::put(&mut out, self.block_size as u64);
+        for block in data.chunks(self.block_size) {
+            let rotations = sort_rotations(block);
+            let primary = rotations.iter().position(|&r| r == 0).unwrap();
+            varint::put(&mut out, primary as u64);
+            out.extend(rotations.iter().map(|&r| block[(r + block.len() - 1) % block.len()]));
+        }
+        out
+    }
+}
+
+impl Decompressor for Bwt {
+    /// Reads the block size from the data itself, so any `Bwt` inverts the
+    /// output of any other.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut input = Reader::new(data);
+        let block_size = input.varint()?;
+        if !(1..=MAX_BLOCK_SIZE as u64).contains(&block_size) {
+            return Err(invalid("bad BWT block size"));
+        }
+        let mut out = Vec::with_capacity(data.len());
+        while !input.is_empty() {
+            let primary = input.varint()?;
+            let last = input.take(block_size.min(input.rest().len() as u64))?;
+            if primary >= last.len() as u64 {
+                return Err(invalid("bad BWT primary index"));
+            }
+            // Row i's last byte comes just before its first byte in the
+            // block, and the rotation starting there is row lf[i]: the rows
+            // ending in a byte keep their order among the rows starting
+            // with it.
+            let mut starts = [0usize; 256];
+            for &b in last {
+                starts[b as usize] += 1;
+            }
+            let mut total = 0;
+            for start in starts.iter_mut() {
+                (*start, total) = (total, total + *start);
+            }
+            let lf: Vec<usize> = last
+                .iter()
+                .map(|&b| {
+                    starts[b as usize] += 1;
+                    starts[b as usize] - 1
+                })
+                .collect();
+            let mut block = vec![0u8; last.len()];
+            let mut row = primary as usize;
+            for slot in block.iter_mut().rev() {
+                *slot = last[row];
+                row = lf[row];
+            }
+            out.extend_from_slice(&block);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_across_block_sizes() {
+        let mut data = Vec::new();
+        for i in 0..400u32 {
+            data.extend_from_slice(format!("{} bottles of beer, take {} down\n", i % 99, i % 3).as_bytes());
+        }
+        data.extend_from_slice(&[0; 300]);
+        for block_size in [1, 2, 7, 100, 4096, DEFAULT_BLOCK_SIZE] {
+            let bwt = Bwt::with_block_size(block_size).unwrap();
+            assert_eq!(Bwt::new().decompress(&bwt.compress(&data)).unwrap(), data, "block size {}", block_size);
+        }
+        for data in [&b""[..], b"a", b"abab", b"aaaaaaa", b"mississippi"] {
+            assert_eq!(Bwt::new().decompress(&Bwt::new().compress(data)).unwrap(), data);
+        }
+        assert!(Bwt::with_block_size(0).is_err());
+    }
+
+    #[test]
+    fn groups_bytes_by_context() {
+        let text = b"she sells sea shells by the sea shore, the shells she sells are sea shells";
+        let transformed = Bwt::new().compress(text);
+        let last = &transformed[transformed.len() - text.len()..];
+        let runs = |data: &[u8]| data.windows(2).filter(|w| w[0] != w[1]).count();
+        assert!(runs(last) < runs(text) / 2);
+    }
+
+    #[test]
+    fn rejects_bad_primary_index() {
+        let mut data = Vec::new();
+        varint::put(&mut data, 4);
+        data.extend_from_slice(&[4, b'a', b'b', b'c', b'd']);
+        assert!(Bwt::new().decompress(&data).is_err());
+    }
+}
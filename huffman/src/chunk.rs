@@ -0,0 +1,269 @@
+//! FastCDC-style content-defined chunking. Cut points are chosen by a
+//! rolling gear hash over the content itself, so an insertion only changes
+//! the chunks around it and the rest of the stream chunks identically.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use huffman::chunk::Chunker;
+//!
+//! for chunk in Chunker::new(File::open("disk.img")?) {
+//!     let chunk = chunk?;
+//!     println!("{} +{}", chunk.offset, chunk.data.len());
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, Read};
+
+pub const MIN_CHUNK: usize = 2 * 1024;
+pub const AVG_CHUNK: usize = 8 * 1024;
+pub const MAX_CHUNK: usize = 64 * 1024;
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64, so the table is fixed across builds and platforms
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+/// Chunk size limits. The average must be a power of two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSizes {
+    pub min: usize,
+    pub avg: usize,
+    pub max: usize,
+}
+
+impl Default for ChunkSizes {
+    fn default() -> ChunkSizes {
+        ChunkSizes {
+            min: MIN_CHUNK,
+            avg: AVG_CHUNK,
+            max: MAX_CHUNK,
+        }
+    }
+}
+
+impl ChunkSizes {
+    fn validate(&self) -> io::Result<()> {
+        if self.min == 0
+            || self.min > self.avg
+            || self.avg > self.max
+            || !self.avg.is_power_of_two()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "chunk sizes must satisfy 0 < min <= avg <= max with avg a power of two",
+            ));
+        }
+        Ok(())
+    }
+
+    // Normalized chunking: a stricter mask before the average size and a
+    // looser one after it pull chunk sizes towards the average.
+    fn masks(&self) -> (u64, u64) {
+        let bits = self.avg.trailing_zeros();
+        let strict = (1u64 << (bits + 2).min(63)) - 1;
+        let loose = (1u64 << bits.saturating_sub(2)) - 1;
+        (strict, loose)
+    }
+
+    /// Length of the first chunk of `data`, assuming `data` is either the
+    /// rest of the stream or at least `max` bytes long.
+    pub fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min {
+            return data.len();
+        }
+        let (mask_s, mask_l) = self.masks();
+        let end = data.len().min(self.max);
+        let normal = end.min(self.avg);
+        let mut hash: u64 = 0;
+        for (i, &b) in data.iter().enumerate().take(normal).skip(self.min) {
+            hash = (hash << 1).wrapping_add(GEAR[b as usize]);
+            if hash & mask_s == 0 {
+                return i + 1;
+            }
+        }
+        for (i, &b) in data.iter().enumerate().take(end).skip(normal) {
+            hash = (hash << 1).wrapping_add(GEAR[b as usize]);
+            if hash & mask_l == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+}
+
+/// Splits an in-memory buffer into chunks with the default sizes.
+pub fn split(mut data: &[u8]) -> Vec<&[u8]> {
+    let sizes = ChunkSizes::default();
+    let mut out = Vec::new();
+    while !data.is_empty() {
+        let (chunk, rest) = data.split_at(sizes.cut_point(data));
+        out.push(chunk);
+        data = rest;
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// Position of the chunk's first byte in the stream.
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// Iterator over the chunks of a reader. Buffers at most one maximum-size
+/// chunk at a time.
+pub struct Chunker<R> {
+    reader: R,
+    sizes: ChunkSizes,
+    buf: Vec<u8>,
+    offset: u64,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R) -> Chunker<R> {
+        Chunker {
+            reader,
+            sizes: ChunkSizes::default(),
+            buf: Vec::new(),
+            offset: 0,
+            eof: false,
+        }
+    }
+
+    pub fn with_sizes(reader: R, sizes: ChunkSizes) -> io::Result<Chunker<R>> {
+        sizes.validate()?;
+        Ok(Chunker {
+            sizes,
+            ..Chunker::new(reader)
+        })
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut tmp = [0u8; 8192];
+        while !self.eof && self.buf.len() < self.sizes.max {
+            let want = tmp.len().min(self.sizes.max - self.buf.len());
+            match self.reader.read(&mut tmp[..want]) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.buf.extend_from_slice(&tmp[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Chunk>;
+
+    fn next(&mut self) -> Option<io::Result<Chunk>> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+        let len = self.sizes.cut_point(&self.buf);
+        let data: Vec<u8> = self.buf.drain(..len).collect();
+        let chunk = Chunk {
+            offset: self.offset,
+            data,
+        };
+        self.offset += len as u64;
+        Some(Ok(chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    // Chunks of `data` read through a Chunker, checked to follow on from
+    // each other
+    fn chunks(data: &[u8], sizes: ChunkSizes) -> Vec<Vec<u8>> {
+        let mut offset = 0;
+        let mut out = Vec::new();
+        for chunk in Chunker::with_sizes(data, sizes).unwrap() {
+            let chunk = chunk.unwrap();
+            assert_eq!(chunk.offset, offset);
+            offset += chunk.data.len() as u64;
+            out.push(chunk.data);
+        }
+        out
+    }
+
+    #[test]
+    fn covers_the_input_exactly() {
+        for len in [0, 1, MIN_CHUNK, MIN_CHUNK + 1, MAX_CHUNK, 300_000] {
+            let data = noise(len, 1);
+            let chunks = chunks(&data, ChunkSizes::default());
+            assert_eq!(chunks.concat(), data);
+            assert_eq!(split(&data), chunks);
+        }
+    }
+
+    #[test]
+    fn respects_the_sizes() {
+        let sizes = ChunkSizes { min: 512, avg: 1024, max: 4096 };
+        for data in [noise(200_000, 2), vec![0; 50_000]] {
+            let chunks = chunks(&data, sizes);
+            let (last, rest) = chunks.split_last().unwrap();
+            for chunk in rest {
+                assert!((sizes.min..=sizes.max).contains(&chunk.len()), "{} bytes", chunk.len());
+            }
+            assert!(last.len() <= sizes.max);
+        }
+        // Zeros never cut the hash, so every chunk runs to the maximum
+        assert!(split(&[0; 3 * MAX_CHUNK]).iter().all(|chunk| chunk.len() == MAX_CHUNK));
+        // Noise cuts near the average
+        let count = split(&noise(1 << 20, 3)).len();
+        assert!((1 << 20) / count > AVG_CHUNK / 2 && (1 << 20) / count < 2 * AVG_CHUNK, "{} chunks", count);
+    }
+
+    #[test]
+    fn boundaries_survive_an_insertion() {
+        let data = noise(500_000, 4);
+        let mut edited = data.clone();
+        edited.splice(100_000..100_000, b"inserted".iter().copied());
+        let before = split(&data);
+        let after = split(&edited);
+        // The chunks before the edit are untouched, and those after it come
+        // back once a cut point lines up again
+        let head = before.iter().zip(&after).take_while(|(a, b)| a == b).count();
+        let tail = before.iter().rev().zip(after.iter().rev()).take_while(|(a, b)| a == b).count();
+        assert!(head > 0 && tail > 0);
+        assert!(before.len() - head - tail <= 2, "{} of {} chunks changed", before.len() - head - tail, before.len());
+    }
+
+    #[test]
+    fn refuses_bad_sizes() {
+        for (min, avg, max) in [(0, 1024, 4096), (2048, 1024, 4096), (512, 1000, 4096), (512, 4096, 1024)] {
+            assert!(Chunker::with_sizes(&[][..], ChunkSizes { min, avg, max }).is_err());
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Whole-buffer compression behind a common interface, so callers can
+//! switch backends without changing code.
+//!
+//! [`Huffman`] codes bytes with a canonical Huffman code. Its output is a
+//! code-lengths table (see [`codes::write_lengths_table`]) with one-byte
+//! symbols, the byte count as a u64, and the coded bytes, optionally split
+//! into interleaved streams (see [`codes::encode_streams`]).
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor, Huffman};
+//!
+//! let data = b"a man, a plan, a canal: panama";
+//! let compressed = Huffman::new().compress(data);
+//! assert!(compressed.len() < data.len() + 64);
+//! assert_eq!(Huffman::new().decompress(&compressed).unwrap(), data);
+//! ```
+//!
+//! A [`Pipeline`] chains codecs, each compressing the previous one's
+//! output, so transforms that only rearrange the data can run ahead of a
+//! coder:
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor, Huffman, Pipeline};
+//! use huffman::rle::Rle;
+//!
+//! let pipeline = Pipeline::new().then(Rle).then(Huffman::new());
+//! let data = [&[0u8; 5000][..], &[1; 5000]].concat();
+//! let compressed = pipeline.compress(&data);
+//! assert!(compressed.len() < Huffman::new().compress(&data).len() / 10);
+//! assert_eq!(pipeline.decompress(&compressed).unwrap(), data);
+//! ```
+//!
+//! Any [`EntropyCoder`] can stand in for Huffman there:
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor, EntropyCoder, Huffman, Pipeline};
+//! use huffman::rans::Rans;
+//! use huffman::rle::Rle;
+//!
+//! fn rle_then(coder: impl EntropyCoder + 'static) -> Pipeline {
+//!     Pipeline::new().then(Rle).then(coder)
+//! }
+//!
+//! let data = [&[0u8; 5000][..], &[1; 5000]].concat();
+//! let compressed = rle_then(Rans).compress(&data);
+//! assert!(compressed.len() < rle_then(Huffman::new()).compress(&data).len());
+//! assert_eq!(rle_then(Rans).decompress(&compressed).unwrap(), data);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::codes::{self, CanonicalDecoder};
+use crate::io::{self, Read};
+
+pub trait Compressor {
+    fn compress(&self, data: &[u8]) -> Vec<u8>;
+}
+
+pub trait Decompressor {
+    /// Fails on input that the matching [`Compressor`] can't have written.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Canonical Huffman coding of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Huffman {
+    streams: usize,
+}
+
+impl Huffman {
+    pub fn new() -> Huffman {
+        Huffman { streams: 1 }
+    }
+
+    /// Splits the coded bytes into `streams` interleaved streams, which
+    /// decode in parallel. Decompression needs the same number.
+    ///
+    /// # Panics
+    ///
+    /// If `streams` is zero.
+    pub fn with_streams(streams: usize) -> Huffman {
+        assert!(streams > 0, "at least one stream is needed");
+        Huffman { streams }
+    }
+}
+
+impl Default for Huffman {
+    fn default() -> Huffman {
+        Huffman::new()
+    }
+}
+
+// The format both Huffman and Shannon-Fano coding write: any canonical
+// code does, given its lengths
+fn compress_canonical(data: &[u8], lengths: &[(u8, u8)], streams: usize) -> Vec<u8> {
+    let encoding_table = codes::build_encoding_table(lengths);
+    let encoded = codes::encode_streams(data.iter().copied(), &encoding_table, streams);
+
+    let mut output = Vec::new();
+    codes::write_lengths_table(&mut output, lengths, |out, b| out.push(*b));
+    output.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    output.extend_from_slice(&encoded);
+    output
+}
+
+impl Compressor for Huffman {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let lengths = match data.is_empty() {
+            true => Vec::new(),
+            false => codes::code_lengths(&codes::build_huffman_tree(&codes::build_byte_frequency_table(data))),
+        };
+        compress_canonical(data, &lengths, self.streams)
+    }
+}
+
+impl Decompressor for Huffman {
+    fn decompress(&self, mut data: &[u8]) -> io::Result<Vec<u8>> {
+        let lengths = codes::read_lengths_table(&mut data, |r| {
+            let mut b = [0u8];
+            r.read_exact(&mut b)?;
+            Ok(b[0])
+        })?;
+        let decoder = CanonicalDecoder::new(lengths)?;
+        let mut count = [0u8; 8];
+        data.read_exact(&mut count)?;
+        codes::decode_streams(data, &decoder, u64::from_le_bytes(count), self.streams)
+    }
+}
+
+/// Shannon-Fano coding of bytes, the top-down forerunner of Huffman
+/// coding, for comparison: its codes are never shorter (see
+/// [`codes::shannon_fano_lengths`]). The output has the same layout as
+/// [`Huffman`]'s, and either decompresses the other's.
+///
+/// ```
+/// use huffman::codec::{Compressor, Decompressor, Huffman, ShannonFano};
+///
+/// let data = b"aaaaaaaaaaaaaaabbbbbbbccccccddddddeeeee";
+/// let compressed = ShannonFano.compress(data);
+/// assert!(compressed.len() > Huffman::new().compress(data).len());
+/// assert_eq!(Huffman::new().decompress(&compressed).unwrap(), data);
+/// assert_eq!(ShannonFano.decompress(&ShannonFano.compress(b"zzz")).unwrap(), b"zzz");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ShannonFano;
+
+impl Compressor for ShannonFano {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let lengths = codes::shannon_fano_lengths(&codes::build_byte_frequency_table(data));
+        compress_canonical(data, &lengths, 1)
+    }
+}
+
+impl Decompressor for ShannonFano {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Huffman::new().decompress(data)
+    }
+}
+
+/// Both halves of a codec, as a [`Pipeline`] stage.
+pub trait Codec: Compressor + Decompressor {}
+
+impl<T: Compressor + Decompressor> Codec for T {}
+
+/// Codecs that code bytes by their frequencies alone, and so can take one
+/// another's place as the last stage of a pipeline, after transforms that
+/// make those frequencies skewed: [`Huffman`], [`ShannonFano`],
+/// [`Arithmetic`](crate::arithmetic::Arithmetic),
+/// [`RangeCoder`](crate::rangecoder::RangeCoder),
+/// [`Rans`](crate::rans::Rans), [`Tans`](crate::tans::Tans) and
+/// [`AdaptiveHuffman`](crate::adaptive::AdaptiveHuffman).
+pub trait EntropyCoder: Codec {}
+
+impl EntropyCoder for Huffman {}
+
+impl EntropyCoder for ShannonFano {}
+
+/// Codecs applied one after another on compression, and undone in reverse
+/// order on decompression. An empty pipeline leaves data as it is.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Codec>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Adds `stage` at the end, to compress what the stages so far output.
+    pub fn then(mut self, stage: impl Codec + 'static) -> Pipeline {
+        self.stages.push(Box::new(stage));
+        self
+    }
+}
+
+impl Compressor for Pipeline {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        for stage in &self.stages {
+            data = stage.compress(&data);
+        }
+        data
+    }
+}
+
+impl Decompressor for Pipeline {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut data = data.to_vec();
+        for stage in self.stages.iter().rev() {
+            data = stage.decompress(&data)?;
+        }
+        Ok(data)
+    }
+}
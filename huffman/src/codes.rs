@@ -0,0 +1,962 @@
+//! Huffman codes: building them from symbol frequencies, writing symbols
+//! with them and reading them back.
+//!
+//! Codes are canonical. Only each symbol's code length is kept from the
+//! tree; going through the symbols by (length, symbol), each code is the
+//! previous one plus one, with zeros appended when the length grows. A
+//! decoder therefore needs nothing but the lengths, which is what tables
+//! store.
+//!
+//! Tables list each symbol with its length. [`write_tree`] instead writes
+//! the canonical code's tree, a bit per node, which takes less room when
+//! the symbols are short.
+//!
+//! ```
+//! use huffman::codes::{self, CanonicalDecoder, SymbolDecoder};
+//!
+//! let text = "abracadabra";
+//! let lengths = codes::code_lengths(&codes::build_huffman_tree(&codes::build_frequency_table(text.chars())));
+//! let encoded = codes::encode_symbols(text.chars(), &codes::build_encoding_table(&lengths));
+//!
+//! let decoder = CanonicalDecoder::new(lengths).unwrap();
+//! let decoded: String = decoder.decode(&encoded).unwrap().into_iter().take(text.len()).collect();
+//! assert_eq!(decoded, text);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::cmp::Ordering;
+use core::hash::Hash;
+
+use crate::bitio::{BitReader, BitWriter};
+use crate::io::{self, Read};
+use crate::Map;
+
+/// Longer codes need more symbols than a `u64` can count, so tables
+/// claiming them are corrupt.
+pub const MAX_CODE_LENGTH: u8 = 96;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct HuffmanNode<S> {
+    frequency: usize,
+    symbol: Option<S>,
+    left: Option<Box<HuffmanNode<S>>>,
+    right: Option<Box<HuffmanNode<S>>>,
+}
+
+impl<S: Eq> Ord for HuffmanNode<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.frequency.cmp(&self.frequency)
+    }
+}
+
+impl<S: Eq> PartialOrd for HuffmanNode<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Counts each symbol. Sorted by symbol, so the same input always gives the
+/// same table and tree (`HashMap` order differs from run to run).
+pub fn build_frequency_table<S: Hash + Ord>(symbols: impl IntoIterator<Item = S>) -> Vec<(S, usize)> {
+    let mut freq_table = Map::new();
+    for s in symbols {
+        *freq_table.entry(s).or_insert(0) += 1;
+    }
+    let mut freq_table: Vec<(S, usize)> = freq_table.into_iter().collect();
+    freq_table.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    freq_table
+}
+
+/// Inputs shorter than this are counted on one thread, as starting more
+/// would take longer than the counting.
+pub const PARALLEL_MIN: usize = 1 << 20;
+
+// Parts to split `len` items into: one per core, or one for small inputs
+// and without std
+fn part_count(len: usize) -> usize {
+    match len < PARALLEL_MIN {
+        true => 1,
+        #[cfg(feature = "std")]
+        false => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        #[cfg(not(feature = "std"))]
+        false => 1,
+    }
+}
+
+// `f` of each part, on a thread of its own, or one after another where
+// there is one part or one core, as in a browser, or no std
+fn map_parts<P: Sync, R: Send>(parts: &[P], f: impl Fn(&P) -> R + Sync) -> Vec<R> {
+    #[cfg(feature = "std")]
+    if parts.len() > 1 && std::thread::available_parallelism().is_ok_and(|n| n.get() > 1) {
+        return std::thread::scope(|scope| {
+            let handles: Vec<_> = parts
+                .iter()
+                .map(|part| {
+                    let f = &f;
+                    scope.spawn(move || f(part))
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+    }
+    parts.iter().map(f).collect()
+}
+
+// Each part counted on a thread of its own, then the counts added up
+fn count_parts<P: Sync, S: Hash + Ord + Send>(parts: &[P], count: impl Fn(&P, &mut Map<S, usize>) + Sync) -> Vec<(S, usize)> {
+    let counted = map_parts(parts, |part| {
+        let mut counts = Map::new();
+        count(part, &mut counts);
+        counts
+    });
+    let mut counted = counted.into_iter();
+    let mut freq_table = counted.next().unwrap_or_default();
+    for counts in counted {
+        for (s, n) in counts {
+            *freq_table.entry(s).or_insert(0) += n;
+        }
+    }
+    let mut freq_table: Vec<(S, usize)> = freq_table.into_iter().collect();
+    freq_table.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    freq_table
+}
+
+/// [`build_frequency_table`] of a slice, split into a part per core that
+/// are counted at once. The same table, sooner for big inputs.
+pub fn build_frequency_table_parallel<S: Hash + Ord + Clone + Send + Sync>(symbols: &[S]) -> Vec<(S, usize)> {
+    let parts: Vec<&[S]> = symbols.chunks(symbols.len().div_ceil(part_count(symbols.len())).max(1)).collect();
+    count_parts(&parts, |part, counts| {
+        for s in part.iter() {
+            *counts.entry(s.clone()).or_insert(0) += 1;
+        }
+    })
+}
+
+/// [`build_frequency_table`] of `text`'s chars, counted like
+/// [`build_frequency_table_parallel`] with the parts cut between chars.
+pub fn build_char_frequency_table(text: &str) -> Vec<(char, usize)> {
+    count_parts(&char_parts(text, part_count(text.len())), |part, counts| {
+        for c in part.chars() {
+            *counts.entry(c).or_insert(0) += 1;
+        }
+    })
+}
+
+// `text` in about `parts` pieces, each ending on a char boundary
+fn char_parts(text: &str, parts: usize) -> Vec<&str> {
+    let size = text.len().div_ceil(parts).max(1);
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = size.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        pieces.push(piece);
+        rest = tail;
+    }
+    pieces
+}
+
+/// [`build_frequency_table`] of bytes, counted into an array rather than a
+/// map, on a thread per core for big inputs.
+pub fn build_byte_frequency_table(data: &[u8]) -> Vec<(u8, usize)> {
+    let count = |part: &[u8]| {
+        let mut counts = [0usize; 256];
+        for &b in part {
+            counts[b as usize] += 1;
+        }
+        counts
+    };
+    let size = data.len().div_ceil(part_count(data.len())).max(1);
+    let parts: Vec<&[u8]> = data.chunks(size).collect();
+    let counts = map_parts(&parts, |part| count(part)).into_iter().fold([0usize; 256], |mut total, counts| {
+        for (t, n) in total.iter_mut().zip(counts) {
+            *t += n;
+        }
+        total
+    });
+    (0..=255u8).zip(counts).filter(|&(_, n)| n > 0).collect()
+}
+
+/// # Panics
+///
+/// If `freq_table` is empty, or its frequencies add up past `usize::MAX`,
+/// which counts of real data can't. Tables read from a file go to
+/// [`try_build_huffman_tree`] instead.
+pub fn build_huffman_tree<S: Clone + Eq>(freq_table: &[(S, usize)]) -> HuffmanNode<S> {
+    try_build_huffman_tree(freq_table).expect("a table of symbol counts")
+}
+
+/// [`build_huffman_tree`] for frequencies that can't be trusted: an empty
+/// table, or one whose frequencies overflow when added up, is an error.
+pub fn try_build_huffman_tree<S: Clone + Eq>(freq_table: &[(S, usize)]) -> io::Result<HuffmanNode<S>> {
+    let mut heap = BinaryHeap::new();
+
+    for (s, freq) in freq_table {
+        heap.push(HuffmanNode {
+            frequency: *freq,
+            symbol: Some(s.clone()),
+            left: None,
+            right: None,
+        });
+    }
+
+    while heap.len() > 1 {
+        let left = Box::new(heap.pop().unwrap());
+        let right = Box::new(heap.pop().unwrap());
+        let combined_freq = left
+            .frequency
+            .checked_add(right.frequency)
+            .ok_or_else(|| invalid("symbol frequencies overflow"))?;
+
+        heap.push(HuffmanNode {
+            frequency: combined_freq,
+            symbol: None,
+            left: Some(left),
+            right: Some(right),
+        });
+    }
+
+    heap.pop().ok_or_else(|| invalid("empty frequency table"))
+}
+
+/// Each symbol's depth in the tree, in symbol order. That's all a canonical
+/// code needs; the shape of the tree doesn't matter. A tree that is just one
+/// leaf still gives its symbol a 1-bit code, as a 0-bit one couldn't be
+/// counted in the coded stream.
+pub fn code_lengths<S: Clone + Ord>(root: &HuffmanNode<S>) -> Vec<(S, u8)> {
+    let mut lengths = Vec::new();
+
+    fn traverse<S: Clone>(node: &HuffmanNode<S>, depth: u8, lengths: &mut Vec<(S, u8)>) {
+        if let Some(s) = &node.symbol {
+            lengths.push((s.clone(), depth));
+        } else {
+            if let Some(left) = &node.left {
+                traverse(left, depth + 1, lengths);
+            }
+            if let Some(right) = &node.right {
+                traverse(right, depth + 1, lengths);
+            }
+        }
+    }
+
+    traverse(root, u8::from(root.symbol.is_some()), &mut lengths);
+    lengths.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    lengths
+}
+
+/// Code lengths by Shannon-Fano coding, in symbol order like
+/// [`code_lengths`]: with the symbols sorted by falling frequency, the list
+/// is split where the two halves' totals come closest, each half gets
+/// another bit, and so on until every part is one symbol. Working top down
+/// like this usually gives a few codes a bit too many, so the result is
+/// never shorter than Huffman's and often longer. A lone symbol gets a
+/// 1-bit code.
+pub fn shannon_fano_lengths<S: Clone + Ord>(freq_table: &[(S, usize)]) -> Vec<(S, u8)> {
+    fn split(freqs: &[usize], lengths: &mut [u8]) {
+        if freqs.len() < 2 {
+            return;
+        }
+        let total: usize = freqs.iter().sum();
+        let mut prefix = 0;
+        let mut best = (usize::MAX, 1);
+        for (i, &freq) in freqs[..freqs.len() - 1].iter().enumerate() {
+            prefix += freq;
+            best = best.min(((2 * prefix).abs_diff(total), i + 1));
+        }
+        for len in lengths.iter_mut() {
+            *len += 1;
+        }
+        let (left, right) = lengths.split_at_mut(best.1);
+        split(&freqs[..best.1], left);
+        split(&freqs[best.1..], right);
+    }
+
+    let mut sorted = freq_table.to_vec();
+    sorted.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let freqs: Vec<usize> = sorted.iter().map(|(_, freq)| *freq).collect();
+    let mut lengths = vec![u8::from(sorted.len() == 1); sorted.len()];
+    split(&freqs, &mut lengths);
+    let mut lengths: Vec<(S, u8)> = sorted.into_iter().map(|(s, _)| s).zip(lengths).collect();
+    lengths.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    lengths
+}
+
+/// The best code lengths none of which is over `limit`, in symbol order
+/// like [`code_lengths`], by package-merge. Each symbol starts as a coin of
+/// its frequency for every bit length up to `limit`. Going from the longest
+/// length up, the cheapest coins are paired into packages that join the
+/// next length's coins, and a symbol's code length is how many of its coins
+/// end up among the `2n - 2` cheapest at the shortest length. Plain Huffman
+/// codes are as good where they fit, but skewed frequencies, like a
+/// Fibonacci sequence's, make them as deep as there are symbols, which
+/// table decoders and formats such as DEFLATE can't have. A lone symbol
+/// gets a 1-bit code.
+///
+/// ```
+/// use huffman::codes::{self, package_merge_lengths};
+///
+/// let fibonacci = [(b'a', 1), (b'b', 1), (b'c', 2), (b'd', 3), (b'e', 5), (b'f', 8)];
+/// assert_eq!(codes::code_lengths(&codes::build_huffman_tree(&fibonacci)).iter().map(|l| l.1).max(), Some(5));
+/// assert_eq!(package_merge_lengths(&fibonacci, 3), [(b'a', 3), (b'b', 3), (b'c', 3), (b'd', 3), (b'e', 2), (b'f', 2)]);
+/// ```
+///
+/// # Panics
+///
+/// If `limit` bits can't give every symbol a code, i.e. there are more than
+/// `2^limit` of them.
+pub fn package_merge_lengths<S: Clone + Ord>(freq_table: &[(S, usize)], limit: u8) -> Vec<(S, u8)> {
+    assert!(
+        limit >= 1 && (limit >= 64 || freq_table.len() as u64 <= 1 << limit),
+        "{} symbols don't fit in {}-bit codes",
+        freq_table.len(),
+        limit
+    );
+    let mut lengths: Vec<(S, u8)> = freq_table.iter().map(|(s, _)| (s.clone(), 0)).collect();
+    if lengths.len() == 1 {
+        lengths[0].1 = 1;
+    }
+    if lengths.len() < 2 {
+        return lengths;
+    }
+    let mut order: Vec<usize> = (0..freq_table.len()).collect();
+    order.sort_by_key(|&i| freq_table[i].1);
+    // Each length's coins by weight: a symbol, or None for a package of two
+    // of the next length's coins
+    let leaves: Vec<(u128, Option<usize>)> = order.iter().map(|&i| (freq_table[i].1 as u128, Some(i))).collect();
+    let mut levels = vec![leaves.clone()];
+    // No code needs to be longer than there are symbols less one
+    for _ in 1..(limit as usize).min(lengths.len() - 1) {
+        let previous = levels.last().unwrap();
+        let packages = previous.chunks_exact(2).map(|pair| (pair[0].0 + pair[1].0, None));
+        // Symbols before packages of the same weight, for shorter codes
+        // where it makes no difference to the size
+        let mut merged = Vec::with_capacity(leaves.len() + previous.len() / 2);
+        let mut leaves = leaves.iter().copied().peekable();
+        for package in packages {
+            while let Some(leaf) = leaves.next_if(|leaf| leaf.0 <= package.0) {
+                merged.push(leaf);
+            }
+            merged.push(package);
+        }
+        merged.extend(leaves);
+        levels.push(merged);
+    }
+    // Packages are made from their level's first pairs, so the coins chosen
+    // at each length are a prefix of that length's list
+    let mut chosen = 2 * lengths.len() - 2;
+    for level in levels.iter().rev() {
+        let mut packages = 0;
+        for &(_, coin) in &level[..chosen] {
+            match coin {
+                Some(i) => lengths[i].1 += 1,
+                None => packages += 1,
+            }
+        }
+        chosen = 2 * packages;
+    }
+    lengths.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    lengths
+}
+
+/// How well a code fits the symbols it was built from. `entropy` is the
+/// Shannon entropy of their frequencies, the fewest bits per symbol any
+/// code of them one at a time can average; `average_length` is what the
+/// code averages. Logarithms need std.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodeStats {
+    pub symbols: u64,
+    pub entropy: f64,
+    pub average_length: f64,
+}
+
+/// The statistics of `lengths` coding symbols counted in `freq_table`, both
+/// in symbol order as they are built. No symbols give zeros throughout.
+#[cfg(feature = "std")]
+pub fn code_stats<S: Ord>(freq_table: &[(S, usize)], lengths: &[(S, u8)]) -> CodeStats {
+    let symbols: u64 = freq_table.iter().map(|&(_, freq)| freq as u64).sum();
+    if symbols == 0 {
+        return CodeStats { symbols, entropy: 0.0, average_length: 0.0 };
+    }
+    let total = symbols as f64;
+    let mut entropy = 0.0;
+    let mut bits = 0u64;
+    for ((s, freq), (t, len)) in freq_table.iter().zip(lengths) {
+        debug_assert!(s == t, "frequency and length tables out of step");
+        let p = *freq as f64 / total;
+        if p > 0.0 {
+            entropy -= p * p.log2();
+        }
+        bits += *freq as u64 * *len as u64;
+    }
+    CodeStats { symbols, entropy, average_length: bits as f64 / total }
+}
+
+// Sorts by (length, symbol), the order canonical codes are handed out in.
+fn canonical_order<S: Ord>(lengths: &mut [(S, u8)]) {
+    lengths.sort_unstable_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+}
+
+/// The canonical code of every symbol, from its code length.
+pub fn build_encoding_table<S: Hash + Ord + Clone>(lengths: &[(S, u8)]) -> Map<S, Vec<bool>> {
+    let mut order = lengths.to_vec();
+    canonical_order(&mut order);
+    let mut encoding_table = Map::new();
+    let mut code: u128 = 0;
+    let mut prev_len = 0;
+    for (s, len) in order {
+        code <<= len - prev_len;
+        prev_len = len;
+        encoding_table.insert(s, (0..len).rev().map(|i| code >> i & 1 == 1).collect());
+        code += 1;
+    }
+    encoding_table
+}
+
+/// # Panics
+///
+/// If a symbol is missing from `encoding_table`.
+pub fn encode_symbols<S: Hash + Ord>(symbols: impl IntoIterator<Item = S>, encoding_table: &Map<S, Vec<bool>>) -> Vec<u8> {
+    let mut encoded = BitWriter::new();
+    for s in symbols {
+        for &bit in encoding_table.get(&s).unwrap() {
+            encoded.write_bit(bit);
+        }
+    }
+    encoded.finish()
+}
+
+/// Decodes by walking the tree, for frequency tables that rebuild it. A
+/// tree of one leaf reads each bit as its symbol, matching [`code_lengths`].
+/// Fails on bits that lead to a missing branch, which only a tree that
+/// isn't a Huffman code's has.
+pub fn decode_symbols<S: Clone>(encoded: &[u8], root: &HuffmanNode<S>) -> io::Result<Vec<S>> {
+    let mut decoded = Vec::new();
+    let mut current_node = root;
+    let mut bits = BitReader::new(encoded);
+
+    if let Some(s) = &root.symbol {
+        while bits.read_bit().is_ok() {
+            decoded.push(s.clone());
+        }
+        return Ok(decoded);
+    }
+
+    // Where the code being read starts
+    let mut start = 0;
+    while let Ok(bit) = bits.read_bit() {
+        let next = if bit { &current_node.right } else { &current_node.left };
+        current_node = next.as_deref().ok_or_else(|| no_code(start))?;
+
+        if let Some(s) = &current_node.symbol {
+            decoded.push(s.clone());
+            current_node = root;
+            start = encoded.len() as u64 * 8 - bits.bits_left();
+        }
+    }
+
+    Ok(decoded)
+}
+
+fn no_code(bit: u64) -> io::Error {
+    invalid(&format!("no code matches the bits at byte {} of the coded symbols", bit / 8))
+}
+
+/// Turns a coded bit stream back into symbols. Padding bits may decode to a
+/// few extra symbols at the end, which callers cut off at the symbol count.
+/// Fails, saying at which byte, if the bits stop matching any code.
+pub trait SymbolDecoder<S> {
+    fn decode(&self, encoded: &[u8]) -> io::Result<Vec<S>>;
+}
+
+impl<S: Clone> SymbolDecoder<S> for HuffmanNode<S> {
+    fn decode(&self, encoded: &[u8]) -> io::Result<Vec<S>> {
+        decode_symbols(encoded, self)
+    }
+}
+
+/// Decodes canonical codes without a tree. A table indexed by the next
+/// [`TABLE_BITS`] bits gives the symbol and length of any code that short
+/// in one step. Longer codes, which only skewed frequencies make, are
+/// decoded a bit at a time: codes of one length are consecutive numbers, so
+/// after each bit a subtraction tells whether the code read so far is
+/// complete and which symbol it is.
+pub struct CanonicalDecoder<S> {
+    // counts[n] is the number of codes of length n.
+    counts: Vec<u128>,
+    // In code order, i.e. by (length, symbol).
+    symbols: Vec<S>,
+    // By the next `table_bits` bits, first bit lowest: the code's symbol
+    // index and length, or a length of 0 for codes longer than the table.
+    table: Vec<(u32, u8)>,
+    table_bits: u8,
+}
+
+/// Bits a [`CanonicalDecoder`] looks up at once. Length-limited codes (see
+/// [`package_merge_lengths`]) of at most this many bits never fall back to
+/// reading a bit at a time.
+pub const TABLE_BITS: u8 = 11;
+
+impl<S: Ord> CanonicalDecoder<S> {
+    /// Fails if the lengths can't come from a Huffman code.
+    pub fn new(mut lengths: Vec<(S, u8)>) -> io::Result<CanonicalDecoder<S>> {
+        canonical_order(&mut lengths);
+        let max = lengths.last().map_or(0, |&(_, len)| len);
+        if max > MAX_CODE_LENGTH || (lengths.len() > 1 && lengths[0].1 == 0) || lengths.len() > u32::MAX as usize {
+            return Err(invalid("bad code length"));
+        }
+        let mut counts = vec![0u128; max as usize + 1];
+        for (_, len) in &lengths {
+            counts[*len as usize] += 1;
+        }
+        // More codes of a length than there are bit patterns left would
+        // make some code a prefix of another.
+        let mut left: u128 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1).checked_sub(count).ok_or_else(|| invalid("code lengths are over-subscribed"))?;
+        }
+        // Each code fills every entry it is a prefix of; codes are read
+        // first bit first, so the entries' low bits hold them reversed
+        let table_bits = max.min(TABLE_BITS);
+        let mut table = vec![(0, 0); 1 << table_bits];
+        let mut code: u128 = 0;
+        let mut prev_len = 0;
+        for (index, &(_, len)) in lengths.iter().enumerate() {
+            code <<= len - prev_len;
+            prev_len = len;
+            if len > table_bits {
+                break;
+            }
+            if len > 0 {
+                let reversed = (code as usize).reverse_bits() >> (usize::BITS - len as u32);
+                for entry in table.iter_mut().skip(reversed).step_by(1 << len) {
+                    *entry = (index as u32, len);
+                }
+            }
+            code += 1;
+        }
+        let symbols = lengths.into_iter().map(|(s, _)| s).collect();
+        Ok(CanonicalDecoder { counts, symbols, table, table_bits })
+    }
+}
+
+impl<S: Clone> CanonicalDecoder<S> {
+    // The symbol of a code too long for the table, read a bit at a time,
+    // or None at the end of the bits
+    fn decode_long(&self, bits: &mut Bits) -> io::Result<Option<S>> {
+        let start = bits.read;
+        // `first` is the first code of the current length, `index` the
+        // position of its symbol.
+        let (mut code, mut first, mut index) = (0u128, 0u128, 0usize);
+        for &count in &self.counts[1..] {
+            let Some(bit) = bits.take(1) else { return Ok(None) };
+            code |= bit as u128;
+            if code < first + count {
+                return Ok(Some(self.symbols[index + (code - first) as usize].clone()));
+            }
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        // Only lengths that leave codes unused get here
+        Err(no_code(start))
+    }
+}
+
+// Bits in the order BitWriter packs them, kept a word at a time so the next
+// few can be looked at before deciding how many to take
+struct Bits<'a> {
+    bytes: &'a [u8],
+    // Unread bits, the next lowest, and how many
+    buffer: u64,
+    count: u32,
+    // Bits taken so far
+    read: u64,
+}
+
+impl Bits<'_> {
+    fn refill(&mut self) {
+        while self.count <= 56 {
+            let Some((&byte, rest)) = self.bytes.split_first() else { break };
+            self.buffer |= (byte as u64) << self.count;
+            self.count += 8;
+            self.bytes = rest;
+        }
+    }
+
+    // Up to `n` bits without taking them, zeros past the end
+    fn peek(&mut self, n: u8) -> u64 {
+        if self.count < n as u32 {
+            self.refill();
+        }
+        self.buffer & ((1 << n) - 1)
+    }
+
+    fn consume(&mut self, n: u8) {
+        self.buffer >>= n;
+        self.count -= n as u32;
+        self.read += n as u64;
+    }
+
+    fn at_end(&mut self) -> bool {
+        if self.count == 0 {
+            self.refill();
+        }
+        self.count == 0
+    }
+
+    fn take(&mut self, n: u8) -> Option<u64> {
+        let bits = self.peek(n);
+        if self.count < n as u32 {
+            return None;
+        }
+        self.consume(n);
+        Some(bits)
+    }
+}
+
+impl<S: Clone> SymbolDecoder<S> for CanonicalDecoder<S> {
+    fn decode(&self, encoded: &[u8]) -> io::Result<Vec<S>> {
+        let mut decoded = Vec::new();
+        let mut bits = Bits { bytes: encoded, buffer: 0, count: 0, read: 0 };
+        while !bits.at_end() {
+            let (index, len) = self.table[bits.peek(self.table_bits) as usize];
+            if len == 0 {
+                // A code longer than the table, or the end
+                match self.decode_long(&mut bits)? {
+                    Some(s) => decoded.push(s),
+                    None => break,
+                }
+            } else if len as u32 <= bits.count {
+                bits.consume(len);
+                decoded.push(self.symbols[index as usize].clone());
+            } else {
+                // Only padding after an incomplete code gets here.
+                break;
+            }
+        }
+        Ok(decoded)
+    }
+}
+
+/// Deals symbols round-robin into `streams` independent bit streams, so a
+/// decoder can work on all of them at once. The byte lengths of all streams
+/// but the last come first, as u64s, then the streams back to back. A
+/// single stream is plain [`encode_symbols`] output.
+pub fn encode_streams<S: Hash + Ord>(symbols: impl IntoIterator<Item = S>, encoding_table: &Map<S, Vec<bool>>, streams: usize) -> Vec<u8> {
+    if streams == 1 {
+        return encode_symbols(symbols, encoding_table);
+    }
+    let mut writers = vec![BitWriter::new(); streams];
+    for (i, s) in symbols.into_iter().enumerate() {
+        for &bit in encoding_table.get(&s).unwrap() {
+            writers[i % streams].write_bit(bit);
+        }
+    }
+    let encoded: Vec<Vec<u8>> = writers.into_iter().map(BitWriter::finish).collect();
+    let mut output = Vec::new();
+    for stream in &encoded[..streams - 1] {
+        output.extend_from_slice(&(stream.len() as u64).to_le_bytes());
+    }
+    output.extend(encoded.concat());
+    output
+}
+
+/// Inverse of [`encode_streams`], decoding the streams on threads of their
+/// own where there are cores for them. Returns exactly `count` symbols, or
+/// an error if the streams end before that. Errors give the byte of
+/// `encoded` where decoding failed.
+pub fn decode_streams<S: Send, D: SymbolDecoder<S> + Sync>(encoded: &[u8], decoder: &D, count: u64, streams: usize) -> io::Result<Vec<S>> {
+    let truncated = |msg: String| io::Error::new(io::ErrorKind::UnexpectedEof, format!("coded symbols are truncated, {}", msg));
+    if streams == 1 {
+        let mut symbols = decoder.decode(encoded)?;
+        if (symbols.len() as u64) < count {
+            return Err(truncated(format!("ending at byte {} after {} of {} symbols", encoded.len(), symbols.len(), count)));
+        }
+        symbols.truncate(count as usize);
+        return Ok(symbols);
+    }
+    let (lengths, mut rest) = encoded
+        .split_at_checked((streams - 1) * 8)
+        .ok_or_else(|| truncated(format!("ending at byte {} in the stream lengths", encoded.len())))?;
+    // Each stream and the byte it starts at
+    let mut parts = Vec::with_capacity(streams);
+    let mut start = lengths.len();
+    for len in lengths.chunks_exact(8) {
+        let len = u64::from_le_bytes(len.try_into().unwrap());
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|&len| len <= rest.len())
+            .ok_or_else(|| truncated(format!("ending at byte {} in stream {}", encoded.len(), parts.len())))?;
+        let (part, tail) = rest.split_at(len);
+        parts.push((part, start));
+        start += len;
+        rest = tail;
+    }
+    parts.push((rest, start));
+
+    let mut decoded = map_parts(&parts, |&(part, _)| decoder.decode(part))
+        .into_iter()
+        .zip(&parts)
+        .enumerate()
+        .map(|(i, (symbols, (_, start)))| symbols.map_err(|e| invalid(&format!("stream {}, from byte {}: {}", i, start, e))))
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .map(Vec::into_iter)
+        .collect::<Vec<_>>();
+    let mut symbols = Vec::new();
+    for i in 0..count {
+        let stream = (i % streams as u64) as usize;
+        let symbol = decoded[stream].next().ok_or_else(|| {
+            let (part, start) = parts[stream];
+            truncated(format!("stream {} ending at byte {} after {} of {} symbols", stream, start + part.len(), i, count))
+        })?;
+        symbols.push(symbol);
+    }
+    Ok(symbols)
+}
+
+/// Writes a code-lengths table: a u32 entry count, then each symbol
+/// followed by its u8 code length.
+pub fn write_lengths_table<S>(output: &mut Vec<u8>, lengths: &[(S, u8)], write_symbol: impl Fn(&mut Vec<u8>, &S)) {
+    output.extend_from_slice(&(lengths.len() as u32).to_le_bytes());
+    for (s, len) in lengths {
+        write_symbol(output, s);
+        output.push(*len);
+    }
+}
+
+/// Inverse of [`write_lengths_table`].
+pub fn read_lengths_table<R: Read, S>(reader: &mut R, read_symbol: impl Fn(&mut R) -> io::Result<S>) -> io::Result<Vec<(S, u8)>> {
+    let mut count = [0u8; 4];
+    reader.read_exact(&mut count)?;
+    let mut lengths = Vec::new();
+    for _ in 0..u32::from_le_bytes(count) {
+        let s = read_symbol(reader)?;
+        let mut len = [0u8];
+        reader.read_exact(&mut len)?;
+        lengths.push((s, len[0]));
+    }
+    Ok(lengths)
+}
+
+/// Writes the tree of the canonical code for `lengths` in pre-order: a 0
+/// bit for each branch, followed by its two subtrees, and a 1 bit for each
+/// leaf, followed by its symbol. The tree needs no entry count, and its
+/// shape gives every code length. A lone symbol's 1-bit code has no sibling,
+/// so its tree is written as just the leaf.
+///
+/// ```
+/// use huffman::bitio::{BitReader, BitWriter};
+/// use huffman::codes::{read_tree, write_tree};
+///
+/// // a: 0, b: 10, c: 11 is a branch, leaf a, branch, leaf b, leaf c
+/// let mut writer = BitWriter::new();
+/// write_tree(&mut writer, &[(b'a', 1), (b'b', 2), (b'c', 2)], |w, &s| w.write_bits(s as u64, 8));
+/// assert_eq!(writer.bit_len(), 5 + 3 * 8);
+///
+/// let bytes = writer.finish();
+/// let lengths = read_tree(&mut BitReader::new(&bytes), |r| Ok(r.read_bits(8)? as u8)).unwrap();
+/// assert_eq!(lengths, [(b'a', 1), (b'b', 2), (b'c', 2)]);
+/// ```
+///
+/// # Panics
+///
+/// If `lengths` is empty.
+pub fn write_tree<S: Ord + Clone>(writer: &mut BitWriter, lengths: &[(S, u8)], write_symbol: impl Fn(&mut BitWriter, &S)) {
+    fn write_node<S>(writer: &mut BitWriter, codes: &[(S, u128, u8)], depth: u8, write_symbol: &impl Fn(&mut BitWriter, &S)) {
+        if let [(s, _, len)] = codes {
+            if *len <= depth || depth == 0 {
+                writer.write_bit(true);
+                write_symbol(writer, s);
+                return;
+            }
+        }
+        writer.write_bit(false);
+        // Canonical codes in code order are sorted as bit strings too, so
+        // the left subtree's codes come first
+        let split = codes.partition_point(|&(_, code, len)| code >> (len - depth - 1) & 1 == 0);
+        write_node(writer, &codes[..split], depth + 1, write_symbol);
+        write_node(writer, &codes[split..], depth + 1, write_symbol);
+    }
+
+    assert!(!lengths.is_empty(), "a tree needs a symbol");
+    let mut order = lengths.to_vec();
+    canonical_order(&mut order);
+    let mut codes = Vec::with_capacity(order.len());
+    let mut code: u128 = 0;
+    let mut prev_len = 0;
+    for (s, len) in order {
+        code <<= len - prev_len;
+        prev_len = len;
+        codes.push((s, code, len));
+        code += 1;
+    }
+    write_node(writer, &codes, 0, &write_symbol);
+}
+
+/// Inverse of [`write_tree`], giving each leaf's symbol and depth, which a
+/// [`CanonicalDecoder`] decodes with. A tree that is just a leaf gives it a
+/// length of 1.
+pub fn read_tree<S>(reader: &mut BitReader, read_symbol: impl Fn(&mut BitReader) -> io::Result<S>) -> io::Result<Vec<(S, u8)>> {
+    let mut lengths = Vec::new();
+    // Branches whose right subtree is still to come, by depth
+    let mut pending = Vec::new();
+    let mut depth = 0;
+    loop {
+        if reader.read_bit()? {
+            lengths.push((read_symbol(reader)?, depth.max(1)));
+            match pending.pop() {
+                Some(branch) => depth = branch + 1,
+                None => return Ok(lengths),
+            }
+        } else {
+            if depth == MAX_CODE_LENGTH {
+                return Err(invalid("bad code length"));
+            }
+            pending.push(depth);
+            depth += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cost(freq_table: &[(u32, usize)], lengths: &[(u32, u8)]) -> usize {
+        freq_table.iter().zip(lengths).map(|(&(_, freq), &(_, len))| freq * len as usize).sum()
+    }
+
+    #[test]
+    fn package_merge_is_optimal_within_its_limit() {
+        let mut state = 7u32;
+        for n in 2..60 {
+            let freq_table: Vec<(u32, usize)> = (0..n)
+                .map(|s| {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    (s, 1 + (state >> 16) as usize % 1000)
+                })
+                .collect();
+            // With room to spare it matches Huffman's cost
+            let huffman = code_lengths(&build_huffman_tree(&freq_table));
+            assert_eq!(cost(&freq_table, &package_merge_lengths(&freq_table, 32)), cost(&freq_table, &huffman));
+            // And squeezed, it stays a complete code within the limit
+            let limit = n.next_power_of_two().ilog2() as u8;
+            let lengths = package_merge_lengths(&freq_table, limit);
+            assert!(lengths.iter().all(|&(_, len)| (1..=limit).contains(&len)));
+            assert_eq!(lengths.iter().map(|&(_, len)| 1u128 << (limit - len)).sum::<u128>(), 1 << limit);
+            assert!(CanonicalDecoder::new(lengths).is_ok());
+        }
+        assert_eq!(package_merge_lengths(&[('x', 5)], 1), [('x', 1)]);
+        assert_eq!(package_merge_lengths::<char>(&[], 1), []);
+    }
+
+    #[test]
+    fn parallel_counts_match() {
+        let text = "héllo wörld, \u{1f600} ".repeat(PARALLEL_MIN / 10);
+        assert!(text.len() > PARALLEL_MIN);
+        assert_eq!(build_char_frequency_table(&text), build_frequency_table(text.chars()));
+        assert_eq!(build_byte_frequency_table(text.as_bytes()), build_frequency_table(text.bytes()));
+        let units: Vec<u16> = text.encode_utf16().collect();
+        assert_eq!(build_frequency_table_parallel(&units), build_frequency_table(units.iter().copied()));
+        // Cut between chars however many parts there are
+        for parts in 1..20 {
+            let pieces = char_parts("h\u{e9}llo \u{1f600}\u{1f600}", parts);
+            assert_eq!(pieces.concat(), "h\u{e9}llo \u{1f600}\u{1f600}");
+            let counted = count_parts(&pieces, |piece, counts| {
+                for c in piece.chars() {
+                    *counts.entry(c).or_insert(0) += 1;
+                }
+            });
+            assert_eq!(counted, build_frequency_table("h\u{e9}llo \u{1f600}\u{1f600}".chars()));
+        }
+        assert!(build_char_frequency_table("").is_empty());
+        assert!(build_byte_frequency_table(b"").is_empty());
+    }
+
+    #[test]
+    fn decodes_codes_longer_than_the_table() {
+        // Fibonacci frequencies give codes of every length up to 29
+        let mut freqs = vec![1usize, 1];
+        while freqs.len() < 30 {
+            freqs.push(freqs[freqs.len() - 2] + freqs[freqs.len() - 1]);
+        }
+        let freq_table: Vec<(u32, usize)> = freqs.into_iter().enumerate().map(|(s, f)| (s as u32, f)).collect();
+        let lengths = code_lengths(&build_huffman_tree(&freq_table));
+        assert!(lengths.iter().any(|&(_, len)| len > TABLE_BITS));
+        let symbols: Vec<u32> = (0..3000).map(|i| (i * 7 % 30) as u32).collect();
+        let encoded = encode_symbols(symbols.iter().copied(), &build_encoding_table(&lengths));
+        let decoder = CanonicalDecoder::new(lengths).unwrap();
+        let decoded = decoder.decode(&encoded).unwrap();
+        assert_eq!(decoded[..symbols.len()], symbols);
+        // Padding only ever adds a few symbols of the shortest codes
+        assert!(decoded.len() < symbols.len() + 8);
+    }
+
+    #[test]
+    fn a_lone_symbol_gets_one_bit() {
+        let freq_table = build_frequency_table("aaaa".chars());
+        let root = build_huffman_tree(&freq_table);
+        let lengths = code_lengths(&root);
+        assert_eq!(lengths, [('a', 1)]);
+        let encoded = encode_symbols("aaaa".chars(), &build_encoding_table(&lengths));
+        assert_eq!(encoded.len(), 1);
+        assert_eq!(decode_streams(&encoded, &CanonicalDecoder::new(lengths).unwrap(), 4, 1).unwrap(), ['a'; 4]);
+        assert_eq!(decode_streams(&encoded, &root, 4, 1).unwrap(), ['a'; 4]);
+    }
+
+    #[test]
+    fn untrusted_tables_are_checked() {
+        let error = try_build_huffman_tree(&[('a', usize::MAX), ('b', 1)]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(try_build_huffman_tree::<char>(&[]).is_err());
+        let root = try_build_huffman_tree(&[('a', usize::MAX - 1), ('b', 1)]).unwrap();
+        assert_eq!(code_lengths(&root), [('a', 1), ('b', 1)]);
+    }
+
+    #[test]
+    fn decoding_says_where_it_fails() {
+        // 0 is a and 10 is b, but nothing is 11
+        let decoder = CanonicalDecoder::new(vec![('a', 1), ('b', 2)]).unwrap();
+        assert_eq!(decoder.decode(&[0x00, 0x01]).unwrap().into_iter().collect::<String>(), "aaaaaaaabaaaaaa");
+        let error = decoder.decode(&[0x00, 0xff]).unwrap_err();
+        assert_eq!(error.to_string(), "no code matches the bits at byte 1 of the coded symbols");
+
+        // A tree no Huffman code has, with nothing right of the root
+        let leaf = |s| Some(Box::new(HuffmanNode { frequency: 1, symbol: Some(s), left: None, right: None }));
+        let root = HuffmanNode { frequency: 1, symbol: None, left: leaf('a'), right: None };
+        assert_eq!(decode_symbols(&[0x00, 0x00, 0x80], &root).unwrap_err().to_string(), "no code matches the bits at byte 2 of the coded symbols");
+
+        let error = decode_streams(&[0x00], &decoder, 9, 1).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(error.to_string(), "coded symbols are truncated, ending at byte 1 after 8 of 9 symbols");
+        let error = decode_streams(&[1, 0, 0, 0, 0, 0, 0, 0, 0x00, 0xff], &decoder, 9, 2).unwrap_err();
+        assert_eq!(error.to_string(), "stream 1, from byte 9: no code matches the bits at byte 0 of the coded symbols");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn stats_bound_the_code_by_the_entropy() {
+        let freq_table = build_frequency_table("aaaabbcd".chars());
+        let stats = code_stats(&freq_table, &code_lengths(&build_huffman_tree(&freq_table)));
+        // Probabilities that are powers of two are coded exactly
+        assert_eq!(stats, CodeStats { symbols: 8, entropy: 1.75, average_length: 1.75 });
+
+        let freq_table = build_frequency_table("abracadabra".chars());
+        let stats = code_stats(&freq_table, &code_lengths(&build_huffman_tree(&freq_table)));
+        assert!(stats.entropy < stats.average_length && stats.average_length < stats.entropy + 1.0);
+        assert_eq!(code_stats::<char>(&[], &[]).average_length, 0.0);
+    }
+}
@@ -0,0 +1,253 @@
+//! Column-wise preprocessing for delimiter-separated text (CSV, TSV).
+//!
+//! Rows are transposed so each column's values sit next to each other, and
+//! each column gets the representation that suits it: integer columns are
+//! stored as zigzag varint deltas from the previous row, low-cardinality
+//! columns as a dictionary plus per-row indices, and anything else as raw
+//! length-prefixed strings. The layout (delimiter, line ending, column
+//! kinds) is recorded up front so [`decode`] rebuilds the text exactly.
+//!
+//! Only rectangular input is transformed: quoted fields containing the
+//! delimiter, ragged rows or mixed line endings make [`encode`] decline,
+//! and the caller should fall back to plain coding.
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::varint::{self, Reader};
+
+const VERSION: u8 = 1;
+
+const DELIMITERS: &[u8] = b",\t;|";
+
+// How many leading lines delimiter detection looks at.
+const SAMPLE_LINES: usize = 100;
+
+const COLUMN_RAW: u8 = 0;
+const COLUMN_INT: u8 = 1;
+const COLUMN_DICT: u8 = 2;
+
+const FLAG_CRLF: u8 = 1;
+const FLAG_FINAL_NEWLINE: u8 = 2;
+
+/// Picks the delimiter that splits every sampled line into the same number
+/// (more than one) of fields.
+pub fn detect(text: &str) -> Option<u8> {
+    let lines: Vec<&str> = text.lines().take(SAMPLE_LINES).collect();
+    if lines.len() < 2 {
+        return None;
+    }
+    DELIMITERS.iter().copied().find(|&d| {
+        let fields = lines[0].bytes().filter(|&b| b == d).count();
+        fields > 0 && lines.iter().all(|l| l.bytes().filter(|&b| b == d).count() == fields)
+    })
+}
+
+// Only integers that print back identically ("7", not "07" or "+7")
+// can be stored as numbers without losing the original text.
+fn canonical_int(field: &str) -> Option<i64> {
+    field.parse::<i64>().ok().filter(|v| v.to_string() == field)
+}
+
+fn encode_column(values: &[&str]) -> (u8, Vec<u8>) {
+    let mut out = Vec::new();
+    if let Some(ints) = values.iter().map(|v| canonical_int(v)).collect::<Option<Vec<i64>>>() {
+        let mut prev = 0i64;
+        for v in ints {
+            varint::put(&mut out, varint::zigzag(v.wrapping_sub(prev)));
+            prev = v;
+        }
+        return (COLUMN_INT, out);
+    }
+
+    let mut dict: HashMap<&str, u64> = HashMap::new();
+    let mut order = Vec::new();
+    for &v in values {
+        dict.entry(v).or_insert_with(|| {
+            order.push(v);
+            order.len() as u64 - 1
+        });
+    }
+    if dict.len() * 4 <= values.len() {
+        varint::put(&mut out, order.len() as u64);
+        for v in &order {
+            varint::put_bytes(&mut out, v.as_bytes());
+        }
+        for v in values {
+            varint::put(&mut out, dict[v]);
+        }
+        return (COLUMN_DICT, out);
+    }
+
+    for v in values {
+        varint::put_bytes(&mut out, v.as_bytes());
+    }
+    (COLUMN_RAW, out)
+}
+
+/// Transposes `text` into per-column streams. Returns `None` when the input
+/// isn't cleanly rectangular under `delimiter`.
+pub fn encode(text: &str, delimiter: u8) -> Option<Vec<u8>> {
+    let delimiter_char = delimiter as char;
+    let final_newline = text.ends_with('\n');
+    let body = text.strip_suffix('\n').unwrap_or(text);
+    let crlf = body.split('\n').next()?.ends_with('\r');
+
+    let line_count = body.split('\n').count();
+    let mut rows: Vec<Vec<&str>> = Vec::new();
+    for (i, line) in body.split('\n').enumerate() {
+        // An unterminated last line keeps whatever it ends with.
+        let terminated = final_newline || i + 1 < line_count;
+        let line = match (crlf && terminated, line.strip_suffix('\r')) {
+            (true, Some(line)) => line,
+            (true, None) => return None,
+            (false, Some(_)) if terminated => return None,
+            (false, _) => line,
+        };
+        rows.push(line.split(delimiter_char).collect());
+    }
+    let width = rows[0].len();
+    if width < 2 || rows.iter().any(|r| r.len() != width) {
+        return None;
+    }
+
+    let mut out = vec![VERSION, delimiter];
+    out.push(if crlf { FLAG_CRLF } else { 0 } | if final_newline { FLAG_FINAL_NEWLINE } else { 0 });
+    varint::put(&mut out, rows.len() as u64);
+    varint::put(&mut out, width as u64);
+    for col in 0..width {
+        let values: Vec<&str> = rows.iter().map(|r| r[col]).collect();
+        let (kind, stream) = encode_column(&values);
+        out.push(kind);
+        varint::put_bytes(&mut out, &stream);
+    }
+    Some(out)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_string(input: &mut Reader) -> io::Result<String> {
+    String::from_utf8(input.bytes()?.to_vec()).map_err(|_| invalid("column value is not UTF-8"))
+}
+
+fn decode_column(kind: u8, stream: &[u8], rows: usize) -> io::Result<Vec<String>> {
+    let mut input = Reader::new(stream);
+    let mut values = Vec::with_capacity(rows);
+    match kind {
+        COLUMN_INT => {
+            let mut prev = 0i64;
+            for _ in 0..rows {
+                prev = prev.wrapping_add(varint::unzigzag(input.varint()?));
+                values.push(prev.to_string());
+            }
+        }
+        COLUMN_DICT => {
+            let dict = (0..input.varint()?)
+                .map(|_| read_string(&mut input))
+                .collect::<io::Result<Vec<String>>>()?;
+            for _ in 0..rows {
+                let index = input.varint()?;
+                let value = dict.get(index as usize).ok_or_else(|| invalid("dictionary index out of range"))?;
+                values.push(value.clone());
+            }
+        }
+        COLUMN_RAW => {
+            for _ in 0..rows {
+                values.push(read_string(&mut input)?);
+            }
+        }
+        _ => return Err(invalid("unknown column kind")),
+    }
+    Ok(values)
+}
+
+/// Rebuilds the original text from the output of [`encode`].
+pub fn decode(data: &[u8]) -> io::Result<String> {
+    let mut input = Reader::new(data);
+    if input.byte()? != VERSION {
+        return Err(invalid("unsupported columnar layout version"));
+    }
+    let delimiter = input.byte()? as char;
+    let flags = input.byte()?;
+    let rows = input.varint()? as usize;
+    let width = input.varint()? as usize;
+    // Every column costs a kind and a length byte, and every row at least
+    // a byte per column, which bounds allocations on corrupt headers. encode
+    // writes at least a row, and without rows the width would go unchecked.
+    if width < 2 || rows == 0 || width > data.len() / 2 || rows.saturating_mul(width) > data.len() {
+        return Err(invalid("columnar header is inconsistent"));
+    }
+
+    let mut columns = Vec::with_capacity(width);
+    for _ in 0..width {
+        let kind = input.byte()?;
+        columns.push(decode_column(kind, input.bytes()?, rows)?);
+    }
+
+    let newline = if flags & FLAG_CRLF != 0 { "\r\n" } else { "\n" };
+    let mut text = String::new();
+    for row in 0..rows {
+        if row > 0 {
+            text.push_str(newline);
+        }
+        for (col, values) in columns.iter().enumerate() {
+            if col > 0 {
+                text.push(delimiter);
+            }
+            text.push_str(&values[row]);
+        }
+    }
+    if flags & FLAG_FINAL_NEWLINE != 0 {
+        text.push_str(newline);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let csv = "id,name,city\n1,ann,Oslo\n2,bob,Oslo\n3,cy,Oslo\n5,di,Oslo\n";
+        let encoded = encode(csv, detect(csv).unwrap()).unwrap();
+        assert_eq!(decode(&encoded).unwrap(), csv);
+
+        let tsv = "a\tb\r\n-1\t07\r\n9223372036854775807\t+7";
+        assert_eq!(detect(tsv), Some(b'\t'));
+        assert_eq!(decode(&encode(tsv, b'\t').unwrap()).unwrap(), tsv);
+    }
+
+    #[test]
+    fn declines_what_isnt_a_table() {
+        assert_eq!(detect("one line, only"), None);
+        assert_eq!(encode("a,b\n1,2,3\n", b','), None);
+        assert_eq!(encode("a,b\r\n1,2\n", b','), None);
+        assert_eq!(encode("a\nb\n", b','), None);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        // No rows and a width near u64::MAX, which used to pass the bound on
+        // rows times width and overflow the columns' capacity
+        let mut data = vec![VERSION, b',', 0, 0];
+        varint::put(&mut data, u64::MAX);
+        assert!(decode(&data).is_err());
+
+        let mut data = vec![VERSION, b',', 0];
+        varint::put(&mut data, 1);
+        varint::put(&mut data, 1 << 40);
+        assert!(decode(&data).is_err());
+
+        // Two columns of two rows each, the second column's stream cut short
+        let mut data = vec![VERSION, b',', 0, 2, 2, COLUMN_INT, 2, 2, 2, COLUMN_INT, 1, 2];
+        assert!(decode(&data).is_err());
+        data[10] = 2;
+        data.push(0);
+        assert_eq!(decode(&data).unwrap(), "1,1\n2,1");
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[VERSION + 1]).is_err());
+    }
+}
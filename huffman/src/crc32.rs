@@ -0,0 +1,66 @@
+//! CRC-32 as used by zip, gzip and PNG (the IEEE polynomial, reflected,
+//! with the register inverted before and after), for catching corrupted
+//! data.
+//!
+//! ```
+//! use huffman::crc32::{crc32, Crc32};
+//!
+//! assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+//!
+//! let mut crc = Crc32::new();
+//! crc.update(b"1234");
+//! crc.update(b"56789");
+//! assert_eq!(crc.finish(), 0xcbf4_3926);
+//! ```
+
+const POLY: u32 = 0xedb8_8320;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { POLY ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// A running checksum, for data that arrives in pieces.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = TABLE[((self.0 ^ byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    /// The checksum of everything passed to [`update`](Crc32::update).
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+/// The checksum of `data` in one go.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
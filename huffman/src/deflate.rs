@@ -0,0 +1,726 @@
+//! DEFLATE (RFC 1951), the format inside gzip, zlib and zip, so output can
+//! be read by standard tools and their output read here.
+//!
+//! Compression finds matches with the same hash chains as [`lz77`](crate::lz77),
+//! within DEFLATE's 32 KiB window and 258-byte matches, and codes the
+//! literals, lengths and distances with Huffman codes built from their
+//! frequencies in each block. Every block goes out in whichever of the three
+//! block types is smallest for it: stored, fixed codes, or dynamic codes
+//! with their lengths in the header. Decompression takes any valid DEFLATE
+//! stream.
+//!
+//! This is raw DEFLATE, without the gzip or zlib framing.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::deflate::Deflate;
+//!
+//! let data = b"Deflate late, deflate early, deflate every day".repeat(10);
+//! let compressed = Deflate.compress(&data);
+//! assert!(compressed.len() < data.len() / 4);
+//! assert_eq!(Deflate.decompress(&compressed).unwrap(), data);
+//!
+//! // A fixed-code block holding "a"
+//! assert_eq!(Deflate.decompress(&[0x4b, 0x04, 0x00]).unwrap(), b"a");
+//! ```
+
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bitio::{BitReader, BitWriter};
+use crate::codec::{Compressor, Decompressor};
+use crate::codes;
+use crate::io;
+use crate::lz77::{MatchFinder, DEFAULT_CHAIN, MAX_CHAIN};
+
+pub const WINDOW: usize = 32 * 1024;
+const MAX_MATCH: usize = 258;
+const MIN_MATCH: usize = 3;
+
+// Tokens per block: each block gets codes fitted to its own statistics.
+const BLOCK_TOKENS: usize = 64 * 1024;
+const MAX_STORED: usize = 65535;
+
+const END_OF_BLOCK: usize = 256;
+const LITLEN_CODES: usize = 286;
+const DIST_CODES: usize = 30;
+const MAX_BITS: u8 = 15;
+const MAX_CODE_LENGTH_BITS: u8 = 7;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// The order code length code lengths are sent in, most used first.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Raw DEFLATE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Deflate;
+
+impl Deflate {
+    /// DEFLATE trying `chain` earlier occurrences for each match, where
+    /// [`Deflate`] tries [`DEFAULT_CHAIN`]: fewer is faster, more finds
+    /// longer matches. Fails unless `chain` is 1 to [`MAX_CHAIN`]. The
+    /// output is DEFLATE like any other, and decompresses with [`Deflate`].
+    pub fn with_chain(chain: usize) -> io::Result<DeflateWithChain> {
+        if !(1..=MAX_CHAIN).contains(&chain) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "DEFLATE chain length out of range"));
+        }
+        Ok(DeflateWithChain { chain })
+    }
+
+    /// DEFLATE with `dictionary` as a preset dictionary.
+    pub fn with_dictionary(dictionary: &[u8]) -> DeflateWithDictionary {
+        DeflateWithDictionary {
+            window: dictionary[dictionary.len().saturating_sub(WINDOW)..].to_vec(),
+            chain: DEFAULT_CHAIN,
+        }
+    }
+}
+
+/// Raw DEFLATE with a preset dictionary: bytes both sides have already,
+/// like a sample of the files being compressed, that matches can refer back
+/// into as if they came just before the data. Small files with much in
+/// common with the dictionary then shrink as if they were part of one
+/// large file. Only the last 32 KiB of the dictionary is in reach, and the
+/// same dictionary is needed to decompress.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeflateWithDictionary {
+    window: Vec<u8>,
+    chain: usize,
+}
+
+/// Raw DEFLATE with its own match-finding effort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeflateWithChain {
+    chain: usize,
+}
+
+impl DeflateWithChain {
+    /// The earlier occurrences tried for each match.
+    pub fn chain(&self) -> usize {
+        self.chain
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, dist: u16 },
+}
+
+// The code for a length or distance, found by its base.
+fn code_for(base: &[u16], value: u16) -> usize {
+    base.partition_point(|&b| b <= value) - 1
+}
+
+fn fixed_lengths() -> (Vec<u8>, Vec<u8>) {
+    let mut litlen = vec![8u8; 288];
+    litlen[144..256].fill(9);
+    litlen[256..280].fill(7);
+    (litlen, vec![5u8; 32])
+}
+
+/// Huffman code lengths for `freqs`, none over `limit` bits. Where the
+/// Huffman code is deeper, which only very skewed counts make it, the
+/// lengths come from package-merge instead.
+fn limited_lengths(freqs: &[usize], limit: u8) -> Vec<u8> {
+    let table: Vec<(usize, usize)> = freqs.iter().copied().enumerate().filter(|&(_, f)| f > 0).collect();
+    let mut lengths = vec![0u8; freqs.len()];
+    let coded = match table.len() {
+        0 => Vec::new(),
+        _ => codes::code_lengths(&codes::build_huffman_tree(&table)),
+    };
+    let coded = match coded.iter().all(|&(_, len)| len <= limit) {
+        true => coded,
+        false => codes::package_merge_lengths(&table, limit),
+    };
+    for (symbol, len) in coded {
+        lengths[symbol] = len;
+    }
+    lengths
+}
+
+/// Canonical codes for `lengths`, bit-reversed: DEFLATE sends a code's
+/// first bit first but packs bits from the bottom of each byte.
+fn reversed_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut count = [0u16; 16];
+    for &len in lengths.iter().filter(|&&len| len > 0) {
+        count[len as usize] += 1;
+    }
+    let mut next = [0u16; 16];
+    for bits in 1..16 {
+        next[bits] = (next[bits - 1] + count[bits - 1]) << 1;
+    }
+    lengths
+        .iter()
+        .map(|&len| match len {
+            0 => 0,
+            _ => {
+                let code = next[len as usize];
+                next[len as usize] += 1;
+                code.reverse_bits() >> (16 - len)
+            }
+        })
+        .collect()
+}
+
+// Code lengths for the dynamic header, run-length coded: (symbol, extra
+// bits value).
+fn run_length_code(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == len).count();
+        if len == 0 && run >= 11 {
+            let n = run.min(138);
+            out.push((18, (n - 11) as u8));
+            i += n;
+        } else if len == 0 && run >= 3 {
+            out.push((17, (run - 3) as u8));
+            i += run;
+        } else if len != 0 && run >= 4 {
+            // The first one as is, then repeats of it.
+            out.push((len, 0));
+            let n = (run - 1).min(6);
+            out.push((16, (n - 3) as u8));
+            i += 1 + n;
+        } else {
+            out.push((len, 0));
+            i += 1;
+        }
+    }
+    out
+}
+
+struct Codes {
+    litlen: Vec<u8>,
+    dist: Vec<u8>,
+}
+
+impl Codes {
+    fn data_bits(&self, litlen_freqs: &[usize], dist_freqs: &[usize]) -> u64 {
+        let litlen: u64 = litlen_freqs
+            .iter()
+            .enumerate()
+            .map(|(s, &f)| {
+                let extra = if s > END_OF_BLOCK { LENGTH_EXTRA[s - 257] } else { 0 };
+                f as u64 * (self.litlen[s] + extra) as u64
+            })
+            .sum();
+        let dist: u64 = dist_freqs.iter().enumerate().map(|(s, &f)| f as u64 * (self.dist[s] + DIST_EXTRA[s]) as u64).sum();
+        litlen + dist
+    }
+
+    fn write_data(&self, out: &mut BitWriter, tokens: &[Token]) {
+        let litlen_codes = reversed_codes(&self.litlen);
+        let dist_codes = reversed_codes(&self.dist);
+        let put = |out: &mut BitWriter, symbol: usize| out.write_bits(litlen_codes[symbol] as u64, self.litlen[symbol] as u32);
+        for &token in tokens {
+            match token {
+                Token::Literal(b) => put(out, b as usize),
+                Token::Match { len, dist } => {
+                    let l = code_for(&LENGTH_BASE, len);
+                    put(out, 257 + l);
+                    out.write_bits((len - LENGTH_BASE[l]) as u64, LENGTH_EXTRA[l] as u32);
+                    let d = code_for(&DIST_BASE, dist);
+                    out.write_bits(dist_codes[d] as u64, self.dist[d] as u32);
+                    out.write_bits((dist - DIST_BASE[d]) as u64, DIST_EXTRA[d] as u32);
+                }
+            }
+        }
+        put(out, END_OF_BLOCK);
+    }
+}
+
+// A dynamic block's header: how many of each code's lengths it sends, and
+// those lengths, run-length coded and then Huffman coded.
+struct Header {
+    hlit: usize,
+    hdist: usize,
+    hclen: usize,
+    runs: Vec<(u8, u8)>,
+    code_length_lengths: Vec<u8>,
+}
+
+impl Header {
+    fn new(codes: &Codes) -> Header {
+        let hlit = 257.max(codes.litlen.iter().rposition(|&len| len > 0).map_or(0, |i| i + 1));
+        let hdist = 1.max(codes.dist.iter().rposition(|&len| len > 0).map_or(0, |i| i + 1));
+        let runs = run_length_code(&[&codes.litlen[..hlit], &codes.dist[..hdist]].concat());
+        let mut freqs = [0usize; 19];
+        for &(symbol, _) in &runs {
+            freqs[symbol as usize] += 1;
+        }
+        let code_length_lengths = limited_lengths(&freqs, MAX_CODE_LENGTH_BITS);
+        let hclen = 4.max(CODE_LENGTH_ORDER.iter().rposition(|&s| code_length_lengths[s] > 0).map_or(0, |i| i + 1));
+        Header {
+            hlit,
+            hdist,
+            hclen,
+            runs,
+            code_length_lengths,
+        }
+    }
+
+    fn extra_bits(symbol: u8) -> u32 {
+        match symbol {
+            16 => 2,
+            17 => 3,
+            18 => 7,
+            _ => 0,
+        }
+    }
+
+    fn bits(&self) -> u64 {
+        let runs: u64 = self.runs.iter().map(|&(s, _)| (self.code_length_lengths[s as usize] as u32 + Header::extra_bits(s)) as u64).sum();
+        5 + 5 + 4 + 3 * self.hclen as u64 + runs
+    }
+
+    fn write(&self, out: &mut BitWriter) {
+        out.write_bits((self.hlit - 257) as u64, 5);
+        out.write_bits((self.hdist - 1) as u64, 5);
+        out.write_bits((self.hclen - 4) as u64, 4);
+        for &s in &CODE_LENGTH_ORDER[..self.hclen] {
+            out.write_bits(self.code_length_lengths[s] as u64, 3);
+        }
+        let codes = reversed_codes(&self.code_length_lengths);
+        for &(symbol, extra) in &self.runs {
+            out.write_bits(codes[symbol as usize] as u64, self.code_length_lengths[symbol as usize] as u32);
+            out.write_bits(extra as u64, Header::extra_bits(symbol));
+        }
+    }
+}
+
+fn write_block(out: &mut BitWriter, tokens: &[Token], raw: &[u8], last: bool) {
+    let mut litlen_freqs = vec![0usize; LITLEN_CODES];
+    let mut dist_freqs = vec![0usize; DIST_CODES];
+    for &token in tokens {
+        match token {
+            Token::Literal(b) => litlen_freqs[b as usize] += 1,
+            Token::Match { len, dist } => {
+                litlen_freqs[257 + code_for(&LENGTH_BASE, len)] += 1;
+                dist_freqs[code_for(&DIST_BASE, dist)] += 1;
+            }
+        }
+    }
+    litlen_freqs[END_OF_BLOCK] = 1;
+
+    let mut dynamic = Codes {
+        litlen: limited_lengths(&litlen_freqs, MAX_BITS),
+        dist: limited_lengths(&dist_freqs, MAX_BITS),
+    };
+    // Some decoders reject a distance code with no codes at all.
+    if dynamic.dist.iter().all(|&len| len == 0) {
+        dynamic.dist[0] = 1;
+    }
+    let header = Header::new(&dynamic);
+    let dynamic_bits = header.bits() + dynamic.data_bits(&litlen_freqs, &dist_freqs);
+    let (litlen, dist) = fixed_lengths();
+    let fixed = Codes { litlen, dist };
+    let fixed_bits = fixed.data_bits(&litlen_freqs, &dist_freqs);
+    // Stored blocks are aligned, with a 16-bit length and its complement.
+    let chunks: Vec<&[u8]> = match raw.is_empty() {
+        true => vec![raw],
+        false => raw.chunks(MAX_STORED).collect(),
+    };
+    let stored_bits = (chunks.len() * (3 + 7 + 32) + raw.len() * 8) as u64;
+
+    if stored_bits <= dynamic_bits.min(fixed_bits) + 3 {
+        for (i, chunk) in chunks.iter().enumerate() {
+            out.write_bits((last && i + 1 == chunks.len()) as u64, 1);
+            out.write_bits(0, 2);
+            out.align();
+            out.write_bits(chunk.len() as u64, 16);
+            out.write_bits(!chunk.len() as u64, 16);
+            for &b in chunk.iter() {
+                out.write_bits(b as u64, 8);
+            }
+        }
+    } else if fixed_bits <= dynamic_bits {
+        out.write_bits(last as u64, 1);
+        out.write_bits(1, 2);
+        fixed.write_data(out, tokens);
+    } else {
+        out.write_bits(last as u64, 1);
+        out.write_bits(2, 2);
+        header.write(out);
+        dynamic.write_data(out, tokens);
+    }
+}
+
+// Compresses `data` from `start` on, with the bytes before it as history
+fn deflate(data: &[u8], start: usize, chain: usize) -> Vec<u8> {
+    let mut out = BitWriter::new();
+    let mut matches = MatchFinder::new(data, WINDOW).with_chain(chain);
+    let mut tokens = Vec::new();
+    let (mut pos, mut block_start) = (start, start);
+    while pos < data.len() {
+        match matches.longest(pos, MAX_MATCH).filter(|&(_, len)| len >= MIN_MATCH) {
+            Some((dist, len)) => {
+                tokens.push(Token::Match {
+                    len: len as u16,
+                    dist: dist as u16,
+                });
+                pos += len;
+            }
+            None => {
+                tokens.push(Token::Literal(data[pos]));
+                pos += 1;
+            }
+        }
+        if tokens.len() == BLOCK_TOKENS && pos < data.len() {
+            write_block(&mut out, &tokens, &data[block_start..pos], false);
+            tokens.clear();
+            block_start = pos;
+        }
+    }
+    write_block(&mut out, &tokens, &data[block_start..], true);
+    out.finish()
+}
+
+impl Compressor for Deflate {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        deflate(data, 0, DEFAULT_CHAIN)
+    }
+}
+
+impl Compressor for DeflateWithChain {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        deflate(data, 0, self.chain)
+    }
+}
+
+impl Compressor for DeflateWithDictionary {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        deflate(&[&self.window[..], data].concat(), self.window.len(), self.chain)
+    }
+}
+
+/// Decodes one canonical code, bit by bit: codes of one length are
+/// consecutive, so a subtraction after each bit tells whether the code is
+/// complete.
+struct Decoder {
+    // counts[n] is the number of codes of length n.
+    counts: [u16; 16],
+    // In code order, i.e. by (length, symbol).
+    symbols: Vec<u16>,
+}
+
+impl Decoder {
+    /// Fails if the lengths give out more codes than there are bit
+    /// patterns. Incomplete codes are accepted, even empty ones; reading a
+    /// missing code fails instead.
+    fn new(lengths: &[u8]) -> io::Result<Decoder> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(invalid("DEFLATE code lengths are over-subscribed"));
+            }
+        }
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|&s| lengths[s as usize] > 0).collect();
+        symbols.sort_by_key(|&s| lengths[s as usize]);
+        Ok(Decoder { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> io::Result<usize> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.read_bit()? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad DEFLATE code"))
+    }
+}
+
+fn read_dynamic_codes(bits: &mut BitReader) -> io::Result<(Decoder, Decoder)> {
+    let hlit = bits.read_bits(5)? as usize + 257;
+    let hdist = bits.read_bits(5)? as usize + 1;
+    let hclen = bits.read_bits(4)? as usize + 4;
+    if hlit > LITLEN_CODES || hdist > DIST_CODES {
+        return Err(invalid("too many DEFLATE codes"));
+    }
+    let mut code_length_lengths = [0u8; 19];
+    for &s in &CODE_LENGTH_ORDER[..hclen] {
+        code_length_lengths[s] = bits.read_bits(3)? as u8;
+    }
+    let code_length_decoder = Decoder::new(&code_length_lengths)?;
+    let mut lengths = Vec::with_capacity(hlit + hdist);
+    while lengths.len() < hlit + hdist {
+        let (len, repeat) = match code_length_decoder.decode(bits)? {
+            16 => (*lengths.last().ok_or_else(|| invalid("DEFLATE length repeat with nothing before it"))?, 3 + bits.read_bits(2)?),
+            17 => (0, 3 + bits.read_bits(3)?),
+            18 => (0, 11 + bits.read_bits(7)?),
+            len => (len as u8, 1),
+        };
+        if lengths.len() + repeat as usize > hlit + hdist {
+            return Err(invalid("DEFLATE code lengths run over"));
+        }
+        lengths.resize(lengths.len() + repeat as usize, len);
+    }
+    if lengths[END_OF_BLOCK] == 0 {
+        return Err(invalid("DEFLATE block has no end code"));
+    }
+    Ok((Decoder::new(&lengths[..hlit])?, Decoder::new(&lengths[hlit..])?))
+}
+
+fn inflate_block(bits: &mut BitReader, litlen: &Decoder, dist: &Decoder, out: &mut Vec<u8>) -> io::Result<()> {
+    loop {
+        let symbol = litlen.decode(bits)?;
+        if symbol < END_OF_BLOCK {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+        let l = symbol - 257;
+        if l >= LENGTH_BASE.len() {
+            return Err(invalid("bad DEFLATE length code"));
+        }
+        let len = LENGTH_BASE[l] as usize + bits.read_bits(LENGTH_EXTRA[l] as u32)? as usize;
+        let d = dist.decode(bits)?;
+        if d >= DIST_BASE.len() {
+            return Err(invalid("bad DEFLATE distance code"));
+        }
+        let distance = DIST_BASE[d] as usize + bits.read_bits(DIST_EXTRA[d] as u32)? as usize;
+        if distance > out.len() {
+            return Err(invalid("DEFLATE distance is too far back"));
+        }
+        // Byte by byte: a match may overlap what it produces.
+        let start = out.len() - distance;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+/// Decompresses a DEFLATE stream from the start of `data`, returning the
+/// output and how many bytes the stream took, for formats that put a
+/// trailer after it.
+pub fn inflate(data: &[u8]) -> io::Result<(Vec<u8>, usize)> {
+    inflate_after(&[], data)
+}
+
+// Matches may reach back into `history`, which the output leaves out
+fn inflate_after(history: &[u8], data: &[u8]) -> io::Result<(Vec<u8>, usize)> {
+    let mut bits = BitReader::new(data);
+    let mut out = history.to_vec();
+    loop {
+        let last = bits.read_bit()?;
+        match bits.read_bits(2)? {
+            0 => {
+                bits.align();
+                let len = bits.read_bits(16)?;
+                let nlen = bits.read_bits(16)?;
+                if len != !nlen & 0xffff {
+                    return Err(invalid("bad DEFLATE stored block length"));
+                }
+                for _ in 0..len {
+                    out.push(bits.read_bits(8)? as u8);
+                }
+            }
+            1 => {
+                let (litlen, dist) = fixed_lengths();
+                inflate_block(&mut bits, &Decoder::new(&litlen)?, &Decoder::new(&dist)?, &mut out)?;
+            }
+            2 => {
+                let (litlen, dist) = read_dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &litlen, &dist, &mut out)?;
+            }
+            _ => return Err(invalid("bad DEFLATE block type")),
+        }
+        if last {
+            bits.align();
+            out.drain(..history.len());
+            return Ok((out, data.len() - bits.rest().len()));
+        }
+    }
+}
+
+impl Decompressor for Deflate {
+    /// Fails on anything after the end of the stream, too.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let (out, len) = inflate(data)?;
+        if len != data.len() {
+            return Err(invalid("data after the end of the DEFLATE stream"));
+        }
+        Ok(out)
+    }
+}
+
+impl DeflateWithDictionary {
+    /// The same dictionary, trying `chain` earlier occurrences for each
+    /// match as [`Deflate::with_chain`] does. Fails unless `chain` is 1 to
+    /// [`MAX_CHAIN`]; decompressing doesn't need it.
+    pub fn with_chain(self, chain: usize) -> io::Result<DeflateWithDictionary> {
+        Ok(DeflateWithDictionary { chain: Deflate::with_chain(chain)?.chain, ..self })
+    }
+
+    /// [`inflate`] for a stream compressed with this dictionary.
+    pub fn inflate(&self, data: &[u8]) -> io::Result<(Vec<u8>, usize)> {
+        inflate_after(&self.window, data)
+    }
+}
+
+impl Decompressor for DeflateWithChain {
+    /// The chain only matters compressing; this is [`Deflate`]'s.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Deflate.decompress(data)
+    }
+}
+
+impl Decompressor for DeflateWithDictionary {
+    /// Output compressed with another dictionary decodes to garbage or
+    /// fails; see [`zlib`](crate::zlib) for a check of which was used.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let (out, len) = self.inflate(data)?;
+        if len != data.len() {
+            return Err(invalid("data after the end of the DEFLATE stream"));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_zlib_output() {
+        // zlib at level 9, raw, of the text below
+        let compressed = [
+            0xf3, 0x2c, 0x51, 0x28, 0x4f, 0x2c, 0x56, 0x28, 0xc9, 0x48, 0x55, 0x48, 0x4a, 0x2d, 0x2e, 0x51, 0xc8, 0x4f,
+            0x53, 0x28, 0xc9, 0xcc, 0x4d, 0x2d, 0xd6, 0x51, 0xc8, 0x44, 0xc8, 0x94, 0xe7, 0x17, 0xe1, 0x92, 0x4a, 0x4c,
+            0x4f, 0x05, 0x49, 0x94, 0x67, 0x16, 0xa7, 0xe4, 0xe7, 0x7a, 0x0e, 0x5a, 0xc3, 0x00,
+        ];
+        let text = "It was the best of times, it was the worst of times, it was the age of wisdom".repeat(3);
+        assert_eq!(Deflate.decompress(&compressed).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn picks_the_smallest_block_type() {
+        let block_type = |compressed: &[u8]| compressed[0] >> 1 & 3;
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let noise: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let mut text = Vec::new();
+        for i in 0..2000u32 {
+            text.extend_from_slice(format!("{} {} {}\n", i, i * i % 7919, ["red", "green", "blue"][i as usize % 3]).as_bytes());
+        }
+        for (data, expected) in [(&noise[..], 0), (&b"hello, hello"[..], 1), (&text[..], 2)] {
+            let compressed = Deflate.compress(data);
+            assert_eq!(block_type(&compressed), expected);
+            assert_eq!(Deflate.decompress(&compressed).unwrap(), data);
+        }
+        // More than one stored block's worth, and nothing at all
+        assert_eq!(Deflate.decompress(&Deflate.compress(&noise[..70_000])).unwrap(), &noise[..70_000]);
+        assert_eq!(Deflate.decompress(&Deflate.compress(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn preset_dictionaries_reach_before_the_data() {
+        let mut records = Vec::new();
+        for i in 0..2000u32 {
+            records.extend_from_slice(format!("{{\"id\": {}, \"kind\": \"{}\"}}\n", i, ["order", "refund"][i as usize % 2]).as_bytes());
+        }
+        // Only the last 32 KiB of a longer dictionary counts
+        let (dictionary, record) = records.split_at(records.len() - 30);
+        let preset = Deflate::with_dictionary(dictionary);
+        assert_eq!(preset, Deflate::with_dictionary(&dictionary[dictionary.len() - WINDOW..]));
+        let compressed = preset.compress(record);
+        assert!(compressed.len() < Deflate.compress(record).len() / 2);
+        assert_eq!(preset.decompress(&compressed).unwrap(), record);
+        assert!(Deflate.decompress(&compressed).is_err());
+        for data in [&b""[..], &records] {
+            assert_eq!(preset.decompress(&preset.compress(data)).unwrap(), data);
+        }
+        // The chain changes how hard compressing looks, not the dictionary
+        let quick = preset.clone().with_chain(1).unwrap();
+        assert_eq!(preset.decompress(&quick.compress(&records)).unwrap(), records);
+        assert_eq!(preset.clone().with_chain(DEFAULT_CHAIN).unwrap(), preset);
+        assert!(preset.with_chain(0).is_err());
+    }
+
+    #[test]
+    fn longer_chains_find_more() {
+        let mut text = Vec::new();
+        for i in 0..3000u32 {
+            text.extend_from_slice(format!("{} {}\n", i % 97, ["alpha", "beta", "gamma", "delta"][i as usize % 4]).as_bytes());
+        }
+        let sizes: Vec<usize> = [1, 8, DEFAULT_CHAIN, 4096]
+            .iter()
+            .map(|&chain| {
+                let compressed = Deflate::with_chain(chain).unwrap().compress(&text);
+                assert_eq!(Deflate.decompress(&compressed).unwrap(), text);
+                compressed.len()
+            })
+            .collect();
+        assert!(sizes.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", sizes);
+        assert!(sizes[0] > sizes[3]);
+        assert_eq!(Deflate::with_chain(DEFAULT_CHAIN).unwrap().compress(&text), Deflate.compress(&text));
+        assert!(Deflate::with_chain(0).is_err());
+    }
+
+    #[test]
+    fn limits_codes_for_skewed_data() {
+        // Byte i appearing fib(i) times would want codes of up to 24 bits
+        let mut freqs = vec![1usize, 1];
+        while freqs.len() < 25 {
+            freqs.push(freqs[freqs.len() - 2] + freqs[freqs.len() - 1]);
+        }
+        let lengths = limited_lengths(&freqs, MAX_BITS);
+        assert_eq!(lengths.iter().max(), Some(&MAX_BITS));
+        assert_eq!(lengths.iter().map(|&len| 1.0 / (1u32 << len) as f64).sum::<f64>(), 1.0);
+        let data: Vec<u8> = freqs.iter().enumerate().flat_map(|(b, &f)| std::iter::repeat_n(b as u8, f)).collect();
+        assert_eq!(Deflate.decompress(&Deflate.compress(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        // A fixed block starting with a match, which has nothing to copy
+        let (litlen, _) = fixed_lengths();
+        let mut match_first = BitWriter::new();
+        match_first.write_bits(0b011, 3);
+        match_first.write_bits(reversed_codes(&litlen)[257] as u64, 7);
+        match_first.write_bits(0, 5);
+        let match_first = match_first.finish();
+
+        // Block type 3, a stored length that doesn't match its complement,
+        // and trailing data
+        for bad in [&[0x07][..], &[0x01, 0x05, 0x00, 0xfa, 0xfe], &match_first, &[0x4b, 0x04, 0x00, 0x00]] {
+            assert!(Deflate.decompress(bad).is_err(), "{:02x?}", bad);
+        }
+    }
+}
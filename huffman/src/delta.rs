@@ -0,0 +1,296 @@
+//! rsync-style delta encoding between two versions of a file. The old file
+//! is indexed in fixed-size blocks by a weak rolling checksum; the new file
+//! is scanned a byte at a time and every block-aligned match becomes a copy,
+//! so only the differing bytes end up in the patch.
+//!
+//! The patch records the length and CRC-32 of both files. Applying it to
+//! anything but the file it was made from fails up front, and the rebuilt
+//! file is checked against the CRC-32 of the one it should be. Patches are
+//! not compressed here: the inserted bytes are plain, for the caller to
+//! code along with the rest.
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::crc32::crc32;
+
+pub const MAGIC: &[u8; 4] = b"HZDL";
+// Version 1 had no checksums
+const VERSION: u8 = 2;
+
+const OP_COPY: u8 = 0;
+const OP_INSERT: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// Copy `len` bytes from `offset` in the old file.
+    Copy { offset: u64, len: u64 },
+    /// Bytes that appear only in the new file.
+    Insert(Vec<u8>),
+}
+
+/// Block size used for a base file of `len` bytes: about its square root,
+/// which balances index size against match granularity.
+pub fn block_size(len: usize) -> usize {
+    ((len as f64).sqrt() as usize).clamp(64, 64 * 1024)
+}
+
+// The rsync weak checksum: two 16-bit running sums that can be rolled one
+// byte forward in constant time.
+#[derive(Clone, Copy)]
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(window: &[u8]) -> Rolling {
+        let mut a: u32 = 0;
+        let mut b: u32 = 0;
+        let len = window.len() as u32;
+        for (i, &x) in window.iter().enumerate() {
+            a = a.wrapping_add(x as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(x as u32));
+        }
+        Rolling {
+            a: a & 0xffff,
+            b: b & 0xffff,
+            len,
+        }
+    }
+
+    fn roll(&mut self, out: u8, into: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(into as u32) & 0xffff;
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a)
+            & 0xffff;
+    }
+
+    fn digest(&self) -> u32 {
+        self.a | (self.b << 16)
+    }
+}
+
+fn push_copy(ops: &mut Vec<Op>, offset: u64, len: u64) {
+    // Consecutive blocks of the old file collapse into one copy.
+    if let Some(Op::Copy { offset: o, len: l }) = ops.last_mut() {
+        if *o + *l == offset {
+            *l += len;
+            return;
+        }
+    }
+    ops.push(Op::Copy { offset, len });
+}
+
+fn flush_insert(ops: &mut Vec<Op>, pending: &mut Vec<u8>) {
+    if !pending.is_empty() {
+        ops.push(Op::Insert(std::mem::take(pending)));
+    }
+}
+
+/// Computes the operations that turn `old` into `new`.
+pub fn ops(old: &[u8], new: &[u8], block: usize) -> Vec<Op> {
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, chunk) in old.chunks_exact(block).enumerate() {
+        index
+            .entry(Rolling::new(chunk).digest())
+            .or_default()
+            .push(i * block);
+    }
+
+    let mut ops = Vec::new();
+    let mut pending = Vec::new();
+    let mut pos = 0;
+    let mut rolling = None;
+    while pos + block <= new.len() {
+        let window = &new[pos..pos + block];
+        let sum = *rolling.get_or_insert_with(|| Rolling::new(window));
+        let found = index
+            .get(&sum.digest())
+            .and_then(|offsets| offsets.iter().find(|&&o| &old[o..o + block] == window));
+        if let Some(&offset) = found {
+            flush_insert(&mut ops, &mut pending);
+            push_copy(&mut ops, offset as u64, block as u64);
+            pos += block;
+            rolling = None;
+            continue;
+        }
+        pending.push(new[pos]);
+        if pos + block < new.len() {
+            if let Some(r) = rolling.as_mut() {
+                r.roll(new[pos], new[pos + block]);
+            }
+        }
+        pos += 1;
+    }
+    pending.extend_from_slice(&new[pos..]);
+    flush_insert(&mut ops, &mut pending);
+    ops
+}
+
+/// Produces a serialized patch from `old` to `new`.
+pub fn diff(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&(old.len() as u64).to_le_bytes());
+    out.extend_from_slice(&(new.len() as u64).to_le_bytes());
+    out.extend_from_slice(&crc32(old).to_le_bytes());
+    out.extend_from_slice(&crc32(new).to_le_bytes());
+    for op in ops(old, new, block_size(old.len())) {
+        match op {
+            Op::Copy { offset, len } => {
+                out.push(OP_COPY);
+                out.extend_from_slice(&offset.to_le_bytes());
+                out.extend_from_slice(&len.to_le_bytes());
+            }
+            Op::Insert(bytes) => {
+                out.push(OP_INSERT);
+                out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+                out.extend_from_slice(&bytes);
+            }
+        }
+    }
+    out
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn take<'a>(patch: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if patch.len() < n {
+        return Err(invalid("patch is truncated"));
+    }
+    let (head, rest) = patch.split_at(n);
+    *patch = rest;
+    Ok(head)
+}
+
+fn take_u64(patch: &mut &[u8]) -> io::Result<u64> {
+    let bytes = take(patch, 8)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn take_u32(patch: &mut &[u8]) -> io::Result<u32> {
+    let bytes = take(patch, 4)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Rebuilds the new file from `old` and a patch produced by [`diff`].
+pub fn apply(old: &[u8], mut patch: &[u8]) -> io::Result<Vec<u8>> {
+    if take(&mut patch, 4)? != MAGIC {
+        return Err(invalid("not a delta patch"));
+    }
+    let version = take(&mut patch, 1)?[0];
+    if !(1..=VERSION).contains(&version) {
+        return Err(invalid("unsupported delta patch version"));
+    }
+    let old_len = take_u64(&mut patch)?;
+    let new_len = take_u64(&mut patch)?;
+    let (old_crc, new_crc) = match version {
+        1 => (None, None),
+        _ => (Some(take_u32(&mut patch)?), Some(take_u32(&mut patch)?)),
+    };
+    if old_len != old.len() as u64 || old_crc.is_some_and(|c| c != crc32(old)) {
+        return Err(invalid("patch was made against a different base file"));
+    }
+
+    let mut out = Vec::new();
+    while !patch.is_empty() {
+        match take(&mut patch, 1)?[0] {
+            OP_COPY => {
+                let offset = take_u64(&mut patch)?;
+                let len = take_u64(&mut patch)?;
+                let range = offset
+                    .checked_add(len)
+                    .filter(|&end| end <= old.len() as u64)
+                    .map(|end| offset as usize..end as usize)
+                    .ok_or_else(|| invalid("copy reaches past the end of the base file"))?;
+                out.extend_from_slice(&old[range]);
+            }
+            OP_INSERT => {
+                let len = take_u64(&mut patch)?;
+                let len = usize::try_from(len).map_err(|_| invalid("insert is too long"))?;
+                out.extend_from_slice(take(&mut patch, len)?);
+            }
+            _ => return Err(invalid("unknown patch operation")),
+        }
+        // Copies can repeat, so a small patch could otherwise grow the
+        // output far past its stated length before the final check.
+        if out.len() as u64 > new_len {
+            return Err(invalid("patch writes past the end of the output"));
+        }
+    }
+    if out.len() as u64 != new_len {
+        return Err(invalid("patched output has the wrong length"));
+    }
+    if new_crc.is_some_and(|c| c != crc32(&out)) {
+        return Err(invalid("patched output fails its checksum"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions() -> (Vec<u8>, Vec<u8>) {
+        let old: Vec<u8> = (0..20_000u32).flat_map(|i| (i * 7919 % 65_521).to_le_bytes()).collect();
+        let mut new = old.clone();
+        new.splice(30_000..30_010, *b"inserted here");
+        new.drain(60_000..61_000);
+        new.extend_from_slice(b"and at the end");
+        (old, new)
+    }
+
+    #[test]
+    fn round_trips() {
+        let (old, new) = versions();
+        let patch = diff(&old, &new);
+        assert_eq!(apply(&old, &patch).unwrap(), new);
+        // Most of the new file is copied rather than stored
+        assert!(patch.len() < new.len() / 10, "{} bytes", patch.len());
+        for (old, new) in [(&b""[..], &b""[..]), (b"", b"all new"), (b"all gone", b"")] {
+            assert_eq!(apply(old, &diff(old, new)).unwrap(), new);
+        }
+    }
+
+    #[test]
+    fn refuses_a_different_base_of_the_same_length() {
+        let (old, new) = versions();
+        let patch = diff(&old, &new);
+        let mut other = old.clone();
+        other[12_345] ^= 1;
+        let e = apply(&other, &patch).unwrap_err();
+        assert!(e.to_string().contains("different base file"), "{}", e);
+    }
+
+    #[test]
+    fn checks_the_rebuilt_file() {
+        let (old, new) = versions();
+        let mut patch = diff(&old, &new);
+        // The last insert's last byte, which the lengths can't catch
+        *patch.last_mut().unwrap() ^= 1;
+        let e = apply(&old, &patch).unwrap_err();
+        assert!(e.to_string().contains("checksum"), "{}", e);
+    }
+
+    #[test]
+    fn reads_version_1_patches() {
+        let mut patch = MAGIC.to_vec();
+        patch.push(1);
+        patch.extend_from_slice(&5u64.to_le_bytes());
+        patch.extend_from_slice(&7u64.to_le_bytes());
+        patch.push(OP_COPY);
+        patch.extend_from_slice(&1u64.to_le_bytes());
+        patch.extend_from_slice(&4u64.to_le_bytes());
+        patch.push(OP_INSERT);
+        patch.extend_from_slice(&3u64.to_le_bytes());
+        patch.extend_from_slice(b"!!!");
+        assert_eq!(apply(b"hello", &patch).unwrap(), b"ello!!!");
+    }
+}
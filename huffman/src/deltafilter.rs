@@ -0,0 +1,113 @@
+//! Delta filter: each byte is replaced by its difference, mod 256, from the
+//! byte `stride` places before it. Data that changes slowly, like sorted
+//! numbers, sensor readings and the rows of a bitmap, becomes mostly small
+//! differences that repeat, which an entropy coder run after it codes in
+//! far fewer bits. The stride should be the width of the data's records:
+//! 4 for little-endian u32s, 3 for RGB pixels, a row for a bitmap. The
+//! output is the stride as a byte, then as many bytes as the input.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor, Huffman, Pipeline};
+//! use huffman::deltafilter::DeltaFilter;
+//!
+//! let readings: Vec<u8> = (0..5000u32).flat_map(|i| (1_000_000 + 3 * i).to_le_bytes()).collect();
+//! let pipeline = Pipeline::new().then(DeltaFilter::with_stride(4).unwrap()).then(Huffman::new());
+//! let compressed = pipeline.compress(&readings);
+//! assert!(compressed.len() < Huffman::new().compress(&readings).len() / 4);
+//! assert_eq!(pipeline.decompress(&compressed).unwrap(), readings);
+//! ```
+
+use std::io;
+
+use crate::codec::{Compressor, Decompressor};
+
+/// The stride is stored in a byte.
+pub const MAX_STRIDE: usize = 255;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Delta filtering of bytes `stride` apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaFilter {
+    stride: usize,
+}
+
+impl DeltaFilter {
+    /// Differences between neighbouring bytes.
+    pub fn new() -> DeltaFilter {
+        DeltaFilter { stride: 1 }
+    }
+
+    /// Fails unless `stride` is from 1 to [`MAX_STRIDE`].
+    pub fn with_stride(stride: usize) -> io::Result<DeltaFilter> {
+        if !(1..=MAX_STRIDE).contains(&stride) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("delta stride must be from 1 to {}", MAX_STRIDE)));
+        }
+        Ok(DeltaFilter { stride })
+    }
+}
+
+impl Default for DeltaFilter {
+    fn default() -> DeltaFilter {
+        DeltaFilter::new()
+    }
+}
+
+impl Compressor for DeltaFilter {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 1);
+        out.push(self.stride as u8);
+        // The first `stride` bytes have nothing before them, so stay as
+        // they are
+        out.extend_from_slice(&data[..self.stride.min(data.len())]);
+        out.extend(data.iter().zip(data.iter().skip(self.stride)).map(|(&before, &b)| b.wrapping_sub(before)));
+        out
+    }
+}
+
+impl Decompressor for DeltaFilter {
+    /// The stride is read back from the data, and any bytes after it are
+    /// valid differences.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let (&stride, deltas) = data.split_first().ok_or_else(|| invalid("delta filtered data has no stride"))?;
+        if stride == 0 {
+            return Err(invalid("delta filtered data has a stride of 0"));
+        }
+        let stride = stride as usize;
+        let mut out = deltas.to_vec();
+        for i in stride..out.len() {
+            out[i] = out[i].wrapping_add(out[i - stride]);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_stride() {
+        let data: Vec<u8> = (0..=255u8).chain((0..=255).rev()).chain([9; 100]).collect();
+        for stride in 1..=MAX_STRIDE {
+            let filter = DeltaFilter::with_stride(stride).unwrap();
+            for data in [&b""[..], b"x", &data] {
+                let filtered = filter.compress(data);
+                assert_eq!(filtered.len(), data.len() + 1);
+                assert_eq!(filter.decompress(&filtered).unwrap(), data);
+            }
+        }
+        assert_eq!(DeltaFilter::new().compress(&[10, 12, 15, 9]), [1, 10, 2, 3, 250]);
+        assert_eq!(DeltaFilter::with_stride(2).unwrap().compress(&[10, 12, 15, 9]), [2, 10, 12, 5, 253]);
+        assert!(DeltaFilter::with_stride(0).is_err());
+        assert!(DeltaFilter::with_stride(MAX_STRIDE + 1).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert!(DeltaFilter::new().decompress(&[]).is_err());
+        assert!(DeltaFilter::new().decompress(&[0, 1, 2]).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Golomb and Rice codes, for integers that follow a geometric
+//! distribution, like run lengths and prediction residuals: small values
+//! are common and each larger one somewhat rarer.
+//!
+//! A Golomb code with parameter `m` writes `n / m` in unary, as that many
+//! 1 bits and a 0, then `n % m` in truncated binary, which spends one bit
+//! fewer on the smaller remainders when `m` isn't a power of two. Rice
+//! codes are the Golomb codes where it is, `m = 2^k`, and the remainder is
+//! just the low `k` bits, which makes them cheaper to code and nearly as
+//! good. Either is optimal for a geometric distribution when the parameter
+//! suits its mean, so it should come from the data:
+//! [`rice_parameter`] picks the best `k` for some values and
+//! [`golomb_parameter`] estimates `m` from their mean. A value far above
+//! what the parameter suits takes a lot of unary bits.
+//!
+//! ```
+//! use huffman::bitio::{BitReader, BitWriter};
+//! use huffman::golomb;
+//!
+//! let residuals = [3u64, 0, 7, 2, 12, 5, 1, 4];
+//! let k = golomb::rice_parameter(&residuals);
+//! assert_eq!(k, 2);
+//!
+//! let mut writer = BitWriter::new();
+//! for &n in &residuals {
+//!     golomb::write_rice(&mut writer, n, k);
+//! }
+//! let bytes = writer.finish();
+//! assert_eq!(bytes.len(), 4);
+//!
+//! let mut reader = BitReader::new(&bytes);
+//! let decoded: Vec<u64> = (0..residuals.len()).map(|_| golomb::read_rice(&mut reader, k).unwrap()).collect();
+//! assert_eq!(decoded, residuals);
+//! ```
+
+use std::io;
+
+use crate::bitio::{BitReader, BitWriter};
+
+/// Rice parameters go up to 63, as the remainder has to fit in a u64.
+pub const MAX_RICE_PARAMETER: u32 = 63;
+
+fn write_unary(out: &mut BitWriter, mut n: u64) {
+    while n >= 64 {
+        out.write_bits(u64::MAX, 64);
+        n -= 64;
+    }
+    out.write_bits((1 << n) - 1, n as u32);
+    out.write_bit(false);
+}
+
+fn read_unary(reader: &mut BitReader) -> io::Result<u64> {
+    let mut n = 0u64;
+    while reader.read_bit()? {
+        n = n.checked_add(1).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unary code is too long"))?;
+    }
+    Ok(n)
+}
+
+/// Writes `n` with the Rice code of parameter `k`: `n >> k` in unary, then
+/// the low `k` bits of `n`.
+///
+/// # Panics
+///
+/// If `k` is above [`MAX_RICE_PARAMETER`].
+pub fn write_rice(out: &mut BitWriter, n: u64, k: u32) {
+    assert!(k <= MAX_RICE_PARAMETER, "Rice parameter {} is too large", k);
+    write_unary(out, n >> k);
+    out.write_bits(n & ((1 << k) - 1), k);
+}
+
+/// Reads a value written by [`write_rice`] with the same `k`.
+pub fn read_rice(reader: &mut BitReader, k: u32) -> io::Result<u64> {
+    assert!(k <= MAX_RICE_PARAMETER, "Rice parameter {} is too large", k);
+    let q = read_unary(reader)?;
+    let r = reader.read_bits(k)?;
+    q.checked_mul(1 << k)
+        .and_then(|high| high.checked_add(r))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Rice code is out of range"))
+}
+
+/// Bits [`write_rice`] spends on `n`, or `u64::MAX` if more.
+pub fn rice_len(n: u64, k: u32) -> u64 {
+    (n >> k).saturating_add(1 + k as u64)
+}
+
+/// The `k` that codes `values` in the fewest bits, 0 if there are none.
+pub fn rice_parameter(values: &[u64]) -> u32 {
+    (0..=MAX_RICE_PARAMETER)
+        .min_by_key(|&k| values.iter().map(|&n| rice_len(n, k)).fold(0u64, u64::saturating_add))
+        .unwrap()
+}
+
+/// Writes `n` with the Golomb code of parameter `m`.
+///
+/// # Panics
+///
+/// If `m` is zero.
+pub fn write_golomb(out: &mut BitWriter, n: u64, m: u64) {
+    assert!(m > 0, "Golomb parameter must be positive");
+    write_unary(out, n / m);
+    let r = n % m;
+    let (bits, short) = truncated_binary(m);
+    if r < short {
+        write_msb_first(out, r, bits - 1);
+    } else {
+        write_msb_first(out, r + short, bits);
+    }
+}
+
+/// Reads a value written by [`write_golomb`] with the same `m`.
+pub fn read_golomb(reader: &mut BitReader, m: u64) -> io::Result<u64> {
+    assert!(m > 0, "Golomb parameter must be positive");
+    let q = read_unary(reader)?;
+    let (bits, short) = truncated_binary(m);
+    let mut r = 0;
+    if bits > 0 {
+        r = read_msb_first(reader, bits - 1)?;
+        if r >= short {
+            r = (r << 1 | reader.read_bit()? as u64) - short;
+        }
+    }
+    q.checked_mul(m)
+        .and_then(|high| high.checked_add(r))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Golomb code is out of range"))
+}
+
+// The remainder's bits, and how many remainders take one bit less: the
+// first 2^bits - m, which leaves room for the rest
+fn truncated_binary(m: u64) -> (u32, u64) {
+    let bits = 64 - (m - 1).leading_zeros();
+    (bits, ((1u128 << bits) - m as u128) as u64)
+}
+
+// Truncated binary codes are told apart a bit at a time, so they go out
+// most significant bit first whatever the stream's order
+fn write_msb_first(out: &mut BitWriter, value: u64, bits: u32) {
+    for i in (0..bits).rev() {
+        out.write_bit(value >> i & 1 == 1);
+    }
+}
+
+fn read_msb_first(reader: &mut BitReader, bits: u32) -> io::Result<u64> {
+    let mut value = 0;
+    for _ in 0..bits {
+        value = value << 1 | reader.read_bit()? as u64;
+    }
+    Ok(value)
+}
+
+/// The Golomb parameter that suits a geometric distribution with the mean
+/// of `values`: the least `m` with `p^m + p^(m+1) <= 1`, so `p^m` is about
+/// 1/2, where `p = mean / (mean + 1)` is the chance of a value going on
+/// past any given point. 1 if there are no values.
+pub fn golomb_parameter(values: &[u64]) -> u64 {
+    if values.is_empty() {
+        return 1;
+    }
+    let mean = values.iter().map(|&n| n as f64).sum::<f64>() / values.len() as f64;
+    let p = mean / (mean + 1.0);
+    if p <= 0.0 {
+        return 1;
+    }
+    ((-(1.0 + p).log2() / p.log2()).ceil() as u64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golomb_codes_round_trip() {
+        for m in [1, 2, 3, 5, 7, 8, 10, 1000, u64::MAX] {
+            let values = [0, 1, 2, m - 1, m, m.saturating_add(1), m.saturating_mul(3) / 2, 1000, u64::MAX];
+            let mut writer = BitWriter::new();
+            for &n in &values {
+                if n / m < 10_000 {
+                    write_golomb(&mut writer, n, m);
+                }
+            }
+            let bytes = writer.finish();
+            let mut reader = BitReader::new(&bytes);
+            for &n in values.iter().filter(|&&n| n / m < 10_000) {
+                assert_eq!(read_golomb(&mut reader, m).unwrap(), n, "m = {}", m);
+            }
+        }
+        // With m = 5, remainders 0 to 2 take 2 bits and 3 and 4 take 3
+        let mut writer = BitWriter::new();
+        for n in 0..5 {
+            write_golomb(&mut writer, n, 5);
+        }
+        assert_eq!(writer.bit_len(), 5 + 3 * 2 + 2 * 3);
+    }
+
+    #[test]
+    fn parameters_suit_the_data() {
+        assert_eq!(rice_parameter(&[]), 0);
+        assert_eq!(rice_parameter(&[0, 0, 1, 0]), 0);
+        assert_eq!(rice_parameter(&[1000, 1100, 900]), 9);
+        assert_eq!(golomb_parameter(&[0, 0, 0]), 1);
+        // A mean of 10 wants m = 7, and Rice's nearest is 8
+        let mean_ten: Vec<u64> = (0..=20).collect();
+        assert_eq!(golomb_parameter(&mean_ten), 7);
+        assert_eq!(rice_parameter(&[u64::MAX; 3]), MAX_RICE_PARAMETER);
+    }
+
+    #[test]
+    fn rejects_bad_codes() {
+        assert!(read_rice(&mut BitReader::new(&[0xff; 3]), 2).is_err());
+        let mut writer = BitWriter::new();
+        write_rice(&mut writer, u64::MAX, MAX_RICE_PARAMETER);
+        let bytes = writer.finish();
+        assert_eq!(read_rice(&mut BitReader::new(&bytes), MAX_RICE_PARAMETER).unwrap(), u64::MAX);
+
+        // A quotient of 2 with k = 63 is past u64::MAX
+        let mut writer = BitWriter::new();
+        writer.write_bits(0b011, 3);
+        writer.write_bits(0, MAX_RICE_PARAMETER);
+        assert!(read_rice(&mut BitReader::new(&writer.finish()), MAX_RICE_PARAMETER).is_err());
+    }
+}
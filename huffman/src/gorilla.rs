@@ -0,0 +1,204 @@
+//! XOR compression of floating-point series, after Facebook's Gorilla.
+//! Neighbouring samples of a smooth signal share sign, exponent and the top
+//! of the mantissa, so XORing each value with the previous one leaves a
+//! word that is mostly zeros. Each value then costs:
+//!
+//! - `0` if it repeats the previous value exactly
+//! - `10` and the meaningful bits, if they fit inside the previous window
+//!   of leading and trailing zeros
+//! - `11`, 5 bits of leading-zero count, 6 bits of length and the
+//!   meaningful bits otherwise
+//!
+//! Values are handled by bit pattern, so NaN payloads and negative zero
+//! survive the round trip. Bits are packed MSB-first, as in the paper.
+
+use std::io;
+
+use crate::bitio::{BitOrder, BitReader, BitWriter};
+use crate::varint::{self, Reader};
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// Core codec over `width`-bit words (32 or 64).
+fn encode(words: impl ExactSizeIterator<Item = u64>, width: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    varint::put(&mut out, words.len() as u64);
+    let mut bits = BitWriter::with_order(BitOrder::MsbFirst);
+    let mut prev = 0u64;
+    // Window of the last explicitly described XOR, as (leading, trailing).
+    let mut window: Option<(u32, u32)> = None;
+    for (i, word) in words.enumerate() {
+        if i == 0 {
+            bits.write_bits(word, width);
+            prev = word;
+            continue;
+        }
+        let xor = word ^ prev;
+        prev = word;
+        if xor == 0 {
+            bits.write_bit(false);
+            continue;
+        }
+        // The leading count field is 5 bits wide.
+        let leading = (xor.leading_zeros() - (64 - width)).min(31);
+        let trailing = xor.trailing_zeros();
+        match window {
+            Some((l, t)) if leading >= l && trailing >= t => {
+                bits.write_bit(true);
+                bits.write_bit(false);
+                bits.write_bits(xor >> t, width - l - t);
+            }
+            _ => {
+                let len = width - leading - trailing;
+                bits.write_bit(true);
+                bits.write_bit(true);
+                bits.write_bits(leading as u64, 5);
+                bits.write_bits(len as u64 - 1, 6);
+                bits.write_bits(xor >> trailing, len);
+                window = Some((leading, trailing));
+            }
+        }
+    }
+    out.extend_from_slice(&bits.finish());
+    out
+}
+
+fn decode(data: &[u8], width: u32) -> io::Result<Vec<u64>> {
+    let mut input = Reader::new(data);
+    let count = input.varint()?;
+    // Every value after the first takes at least one bit.
+    if count > data.len() as u64 * 8 {
+        return Err(invalid("float count is inconsistent"));
+    }
+    let mut bits = BitReader::with_order(input.rest(), BitOrder::MsbFirst);
+    let mut words = Vec::with_capacity(count as usize);
+    let mut prev = 0u64;
+    let mut window = (0, 0);
+    for i in 0..count {
+        if i == 0 {
+            prev = bits.read_bits(width)?;
+        } else if bits.read_bit()? {
+            if bits.read_bit()? {
+                let leading = bits.read_bits(5)? as u32;
+                let len = bits.read_bits(6)? as u32 + 1;
+                if leading + len > width {
+                    return Err(invalid("bad float window"));
+                }
+                window = (leading, width - leading - len);
+            }
+            let (l, t) = window;
+            prev ^= bits.read_bits(width - l - t)? << t;
+        }
+        words.push(prev);
+    }
+    Ok(words)
+}
+
+/// Encodes a series of doubles, prefixed with its length.
+pub fn encode_f64(values: &[f64]) -> Vec<u8> {
+    encode(values.iter().map(|v| v.to_bits()), 64)
+}
+
+/// Inverse of [`encode_f64`].
+pub fn decode_f64(data: &[u8]) -> io::Result<Vec<f64>> {
+    Ok(decode(data, 64)?.into_iter().map(f64::from_bits).collect())
+}
+
+/// Same as [`encode_f64`] for single-precision values.
+pub fn encode_f32(values: &[f32]) -> Vec<u8> {
+    encode(values.iter().map(|v| v.to_bits() as u64), 32)
+}
+
+/// Inverse of [`encode_f32`].
+pub fn decode_f32(data: &[u8]) -> io::Result<Vec<f32>> {
+    Ok(decode(data, 32)?.into_iter().map(|w| f32::from_bits(w as u32)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // By bit pattern, as NaN never equals itself
+    fn round_trips_f64(values: &[f64]) {
+        let decoded = decode_f64(&encode_f64(values)).unwrap();
+        let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&decoded), bits(values));
+    }
+
+    fn round_trips_f32(values: &[f32]) {
+        let decoded = decode_f32(&encode_f32(values)).unwrap();
+        let bits = |values: &[f32]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&decoded), bits(values));
+    }
+
+    #[test]
+    fn round_trips_special_values() {
+        let quiet_nan_with_payload = f64::from_bits(0x7ff8_0000_dead_beef);
+        let signalling_nan = f64::from_bits(0x7ff0_0000_0000_0001);
+        let specials = [
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+            -f64::NAN,
+            quiet_nan_with_payload,
+            signalling_nan,
+            f64::MIN_POSITIVE,
+            f64::from_bits(1),
+            f64::MAX,
+            f64::MIN,
+            1.0,
+        ];
+        round_trips_f64(&specials);
+        let mut reversed = specials;
+        reversed.reverse();
+        round_trips_f64(&reversed);
+        round_trips_f32(&[0.0, -0.0, f32::INFINITY, f32::NEG_INFINITY, f32::NAN, f32::from_bits(0x7fc0_1234), f32::MAX, f32::from_bits(1)]);
+    }
+
+    #[test]
+    fn codes_identical_runs_in_a_bit_each() {
+        for value in [0.0, -0.0, f64::NAN, f64::INFINITY, 21.5] {
+            let values = vec![value; 8001];
+            let encoded = encode_f64(&values);
+            // Count, the first value whole, then a 0 bit per repeat
+            assert_eq!(encoded.len(), 2 + 8 + 1000);
+            round_trips_f64(&values);
+        }
+        // Zero and negative zero differ in the sign bit alone
+        round_trips_f64(&[0.0, -0.0, 0.0, 0.0, -0.0, -0.0]);
+    }
+
+    #[test]
+    fn round_trips_series() {
+        round_trips_f64(&[]);
+        round_trips_f64(&[3.25]);
+        let smooth: Vec<f64> = (0..1000).map(|i| 20.0 + (i as f64 / 50.0).sin()).collect();
+        round_trips_f64(&smooth);
+        // Windows that shrink, grow and span the whole word
+        let jumpy: Vec<f64> = (0..1000).map(|i: u64| f64::from_bits(i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (i % 64))).collect();
+        round_trips_f64(&jumpy);
+        let smooth: Vec<f32> = (0..1000).map(|i| 20.0 + (i as f32 / 50.0).sin()).collect();
+        round_trips_f32(&smooth);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let encoded = encode_f64(&[1.0, 2.0, 3.5]);
+        assert!(decode_f64(&encoded[..encoded.len() - 1]).is_err());
+        assert!(decode_f64(&[]).is_err());
+        // More values than there are bits for
+        assert!(decode_f64(&[0xff, 0x01, 0]).is_err());
+        // A window wider than the word: 31 leading zeros and 33 bits
+        let mut bits = BitWriter::with_order(BitOrder::MsbFirst);
+        bits.write_bits(0, 32);
+        bits.write_bits(0b11, 2);
+        bits.write_bits(31, 5);
+        bits.write_bits(32, 6);
+        let data = [&[2][..], &bits.finish()].concat();
+        assert!(decode_f32(&data).is_err());
+    }
+}
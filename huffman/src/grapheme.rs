@@ -0,0 +1,60 @@
+//! Grapheme-cluster segmentation for text coding. Treating a user-perceived
+//! character (an emoji with its skin tone and ZWJ joins, a letter with its
+//! combining accents, a flag) as one symbol keeps such text from splitting
+//! into many rare code points.
+//!
+//! The clusters are UAX #29's extended grapheme clusters, as found by the
+//! `unicode-segmentation` crate. Segments always concatenate back to the
+//! input, so data coded with one Unicode version's rules decodes under any
+//! other.
+//!
+//! ```
+//! use huffman::grapheme::graphemes;
+//!
+//! assert_eq!(graphemes("e\u{301}te\u{301}"), ["e\u{301}", "t", "e\u{301}"]);
+//! assert_eq!(graphemes("\u{1F1EB}\u{1F1F7}!"), ["\u{1F1EB}\u{1F1F7}", "!"]);
+//! ```
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits `text` into extended grapheme clusters.
+pub fn graphemes(text: &str) -> Vec<&str> {
+    text.graphemes(true).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_clusters_whole() {
+        // CR LF, a combining accent, a skin tone, a ZWJ family and a flag
+        assert_eq!(graphemes("a\r\nb"), ["a", "\r\n", "b"]);
+        assert_eq!(graphemes("cafe\u{301}"), ["c", "a", "f", "e\u{301}"]);
+        assert_eq!(graphemes("\u{1F44B}\u{1F3FD}."), ["\u{1F44B}\u{1F3FD}", "."]);
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(graphemes(&format!("{}x", family)), [family, "x"]);
+        assert_eq!(graphemes("\u{1F1EC}\u{1F1E7}\u{1F1EB}\u{1F1F7}"), ["\u{1F1EC}\u{1F1E7}", "\u{1F1EB}\u{1F1F7}"]);
+    }
+
+    #[test]
+    fn follows_the_rules_a_short_table_got_wrong() {
+        // Devanagari and Tamil vowel signs, and a Hangul syllable built
+        // from jamo
+        assert_eq!(graphemes("\u{915}\u{93F}\u{924}"), ["\u{915}\u{93F}", "\u{924}"]);
+        assert_eq!(graphemes("\u{BA4}\u{BBF}"), ["\u{BA4}\u{BBF}"]);
+        assert_eq!(graphemes("\u{1100}\u{1161}\u{11A8}a"), ["\u{1100}\u{1161}\u{11A8}", "a"]);
+        // Not extenders: a Bengali letter, and Thai characters that stand
+        // alone
+        assert_eq!(graphemes("\u{9B8}\u{9AC}"), ["\u{9B8}", "\u{9AC}"]);
+        assert_eq!(graphemes("\u{E01}\u{E02}"), ["\u{E01}", "\u{E02}"]);
+    }
+
+    #[test]
+    fn concatenates_back_to_the_input() {
+        for text in ["", "plain ascii", "\u{301}leading mark", "\u{200D}\u{200D}", "\u{1F1E6}\u{1F1E6}\u{1F1E6}", "\r\r\n\n"] {
+            assert_eq!(graphemes(text).concat(), text);
+            assert!(graphemes(text).iter().all(|g| !g.is_empty()));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! The gzip file format (RFC 1952): a [DEFLATE](crate::deflate) stream
+//! between a small header and a trailer with the CRC-32 and length of the
+//! original data, as read and written by `gzip` and `gunzip`.
+//!
+//! Output is a single member with no file name and no timestamp. Input may
+//! be several members back to back, as `cat a.gz b.gz` makes, which
+//! decompress to their contents one after another; optional header fields
+//! are skipped, and the header checksum, if there is one, is checked.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::gzip::Gzip;
+//!
+//! let compressed = Gzip.compress(b"hello, gzip");
+//! assert!(compressed.starts_with(&huffman::gzip::MAGIC));
+//! assert_eq!(Gzip.decompress(&compressed).unwrap(), b"hello, gzip");
+//!
+//! let twice = [&compressed[..], &compressed[..]].concat();
+//! assert_eq!(Gzip.decompress(&twice).unwrap(), b"hello, gziphello, gzip");
+//! ```
+
+use std::io;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::crc32::crc32;
+use crate::deflate::{self, Deflate};
+
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+const METHOD_DEFLATE: u8 = 8;
+// The operating system the file was written on, which gunzip ignores.
+const OS_UNKNOWN: u8 = 255;
+
+const FLAG_HCRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+const RESERVED_FLAGS: u8 = 0xe0;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(invalid("gzip member is truncated"));
+    }
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+    Ok(taken)
+}
+
+/// gzip files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Gzip;
+
+impl Compressor for Gzip {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        // No flags, no timestamp, no extra flags
+        out.extend_from_slice(&[METHOD_DEFLATE, 0, 0, 0, 0, 0, 0, OS_UNKNOWN]);
+        out.extend_from_slice(&Deflate.compress(data));
+        out.extend_from_slice(&crc32(data).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out
+    }
+}
+
+// Decodes the member at the start of `data`, moving past it.
+fn read_member(data: &mut &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+    let start = *data;
+    let header = take(data, 10)?;
+    if header[..2] != MAGIC || header[2] != METHOD_DEFLATE {
+        return Err(invalid("not a gzip member"));
+    }
+    let flags = header[3];
+    if flags & RESERVED_FLAGS != 0 {
+        return Err(invalid("unknown gzip flags"));
+    }
+    if flags & FLAG_EXTRA != 0 {
+        let len = take(data, 2)?;
+        take(data, u16::from_le_bytes([len[0], len[1]]) as usize)?;
+    }
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let end = data.iter().position(|&b| b == 0).ok_or_else(|| invalid("gzip member is truncated"))?;
+            take(data, end + 1)?;
+        }
+    }
+    if flags & FLAG_HCRC != 0 {
+        let header_len = start.len() - data.len();
+        let expected = take(data, 2)?;
+        if crc32(&start[..header_len]) as u16 != u16::from_le_bytes([expected[0], expected[1]]) {
+            return Err(invalid("gzip header checksum mismatch"));
+        }
+    }
+    let (decompressed, len) = deflate::inflate(data)?;
+    take(data, len)?;
+    let trailer = take(data, 8)?;
+    if crc32(&decompressed) != u32::from_le_bytes(trailer[..4].try_into().unwrap()) {
+        return Err(invalid("gzip checksum mismatch, the file is corrupt"));
+    }
+    if decompressed.len() as u32 != u32::from_le_bytes(trailer[4..].try_into().unwrap()) {
+        return Err(invalid("gzip length mismatch, the file is corrupt"));
+    }
+    out.extend_from_slice(&decompressed);
+    Ok(())
+}
+
+impl Decompressor for Gzip {
+    /// Fails on anything but gzip members, including empty input.
+    fn decompress(&self, mut data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        loop {
+            read_member(&mut data, &mut out)?;
+            if data.is_empty() {
+                return Ok(out);
+            }
+        }
+    }
+}
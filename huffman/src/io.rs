@@ -0,0 +1,93 @@
+//! The error and reader types the codecs use. With the `std` feature, the
+//! default, they are std's own, so errors pass through `?` into any other
+//! `std::io` code. Without it the crate builds for `#![no_std]` targets with
+//! an allocator, and these stand in: the same names, kinds and messages,
+//! with only what the codecs need of them, and [`Read`] for byte slices.
+//!
+//! ```
+//! use huffman::codec::{Decompressor, Huffman};
+//! use huffman::io::ErrorKind;
+//!
+//! let error = Huffman::new().decompress(&[3]).unwrap_err();
+//! assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+//! ```
+
+#[cfg(feature = "std")]
+pub use std::io::{Error, ErrorKind, Read, Result};
+
+#[cfg(not(feature = "std"))]
+pub use self::alloc_only::{Error, ErrorKind, Read, Result};
+
+#[cfg(not(feature = "std"))]
+mod alloc_only {
+    use alloc::boxed::Box;
+    use alloc::string::String;
+    use core::fmt;
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// The kinds of error the codecs report, a subset of std's.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        InvalidData,
+        InvalidInput,
+        UnexpectedEof,
+    }
+
+    /// A kind and a message, as `std::io::Error::new` makes them.
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        message: Box<str>,
+    }
+
+    impl Error {
+        pub fn new(kind: ErrorKind, message: impl Into<String>) -> Error {
+            Error { kind, message: message.into().into_boxed_str() }
+        }
+
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(&self.message)
+        }
+    }
+
+    impl core::error::Error for Error {}
+
+    /// Reading bytes from a source, which for the codecs is a slice.
+    pub trait Read {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf)? {
+                    0 => return Err(Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer")),
+                    n => buf = &mut buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = buf.len().min(self.len());
+            let (read, rest) = self.split_at(n);
+            buf[..n].copy_from_slice(read);
+            *self = rest;
+            Ok(n)
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            (**self).read(buf)
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Without the default `std` feature only the Huffman and LZ codecs are
+//! built, with `#![no_std]` and `alloc`, for targets with an allocator and
+//! no operating system: [`codec`]'s Huffman, [`codes`], [`bitio`], [`lz77`],
+//! [`lzss`], [`lz78`], [`lz4`], [`rle`], [`deflate`] and [`zlib`]. Their
+//! errors are then [`io`]'s stand-ins for std's, and multi-stream Huffman
+//! data is decoded on one thread.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod adaptive;
+#[cfg(feature = "std")]
+pub mod arithmetic;
+#[cfg(feature = "std")]
+pub mod bigbit;
+pub mod bitio;
+#[cfg(feature = "std")]
+pub mod blocksort;
+#[cfg(feature = "std")]
+pub mod bsdiff;
+#[cfg(feature = "std")]
+pub mod bwt;
+#[cfg(feature = "std")]
+pub mod chunk;
+pub mod codec;
+pub mod codes;
+#[cfg(feature = "std")]
+pub mod columnar;
+pub mod crc32;
+pub mod deflate;
+#[cfg(feature = "std")]
+pub mod delta;
+#[cfg(feature = "std")]
+pub mod deltafilter;
+#[cfg(feature = "std")]
+pub mod golomb;
+#[cfg(feature = "std")]
+pub mod gorilla;
+#[cfg(feature = "std")]
+pub mod grapheme;
+#[cfg(feature = "std")]
+pub mod gzip;
+pub mod io;
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod logtok;
+pub mod lz4;
+pub mod lz77;
+pub mod lz78;
+pub mod lzss;
+#[cfg(feature = "std")]
+pub mod model;
+#[cfg(feature = "std")]
+pub mod mtf;
+#[cfg(feature = "std")]
+pub mod ppm;
+#[cfg(feature = "std")]
+pub mod protobuf;
+#[cfg(feature = "std")]
+pub mod rangecoder;
+#[cfg(feature = "std")]
+pub mod rans;
+pub mod rle;
+#[cfg(feature = "std")]
+pub mod sniff;
+#[cfg(feature = "std")]
+pub mod snappy;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod tans;
+#[cfg(feature = "std")]
+pub mod timeseries;
+#[cfg(feature = "std")]
+pub mod universal_codes;
+pub mod varint;
+pub mod zlib;
+
+#[cfg(feature = "std")]
+pub use sniff::{sniff, ContentKind};
+
+/// Tables keyed by symbol or phrase: hashed with std, ordered without it,
+/// as alloc has no hash map.
+#[cfg(feature = "std")]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+pub type Map<K, V> = alloc::collections::BTreeMap<K, V>;
@@ -0,0 +1,134 @@
+//! Tokenizer for line-oriented log files. Log lines repeat the same
+//! message templates over and over, with only the variable parts changing,
+//! and those are mostly numbers. So the text between numbers, such as
+//! `" INFO request "`, is one token wherever it repeats often, and words are
+//! kept whole elsewhere: both then get short codes. Digits are split out one
+//! by one, so timestamps and counters are coded from digit statistics rather
+//! than modelled as times or counts.
+//!
+//! ```
+//! use huffman::logtok::tokenize;
+//!
+//! assert_eq!(
+//!     tokenize(&"12:05 WARN disk_usage high\n".repeat(8))[..6],
+//!     ["1", "2", ":", "0", "5", " WARN disk_usage high\n"],
+//! );
+//! assert_eq!(
+//!     tokenize("12:05 WARN disk_usage high\n"),
+//!     ["1", "2", ":", "0", "5", " ", "WARN", " ", "disk_usage", " ", "high", "\n"],
+//! );
+//! ```
+
+use std::collections::HashMap;
+
+// How often a stretch has to occur to be a token of its own. Rarer ones
+// would cost more in the code table than they save.
+const MIN_REPEATS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Word,
+    Digit,
+    Space,
+    Other,
+}
+
+fn class(c: char) -> Class {
+    if c.is_ascii_digit() {
+        Class::Digit
+    } else if c.is_alphabetic() || c == '_' {
+        Class::Word
+    } else if c == ' ' || c == '\t' {
+        Class::Space
+    } else {
+        Class::Other
+    }
+}
+
+/// Splits `text` into single digits, the stretches between them that occur
+/// often, and word runs, space runs and single punctuation in the
+/// rest. The tokens concatenate back to `text`.
+pub fn tokenize(text: &str) -> Vec<&str> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for stretch in text.split(|c: char| c.is_ascii_digit()).filter(|s| !s.is_empty()) {
+        *counts.entry(stretch).or_default() += 1;
+    }
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            tokens.push(&rest[..1]);
+            rest = &rest[1..];
+            continue;
+        }
+        let end = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let stretch = &rest[..end];
+        match counts[stretch] {
+            0..MIN_REPEATS => tokens.extend(words(stretch)),
+            _ => tokens.push(stretch),
+        }
+        rest = &rest[end..];
+    }
+    tokens
+}
+
+// Word runs, space runs, and single digits and punctuation
+fn words(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut prev = None;
+    for (i, c) in text.char_indices() {
+        let cls = class(c);
+        let extends = prev == Some(cls) && matches!(cls, Class::Word | Class::Space);
+        if i > start && !extends {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        prev = Some(cls);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_repeated_templates_whole() {
+        let log: String = (0..MIN_REPEATS).map(|i| format!("2024-03-01 12:00:0{} INFO request 100{} served in {}ms\n", i, i, 7 * i)).collect();
+        let tokens = tokenize(&log);
+        assert_eq!(tokens.iter().filter(|&&t| t == " INFO request ").count(), MIN_REPEATS);
+        assert_eq!(tokens.iter().filter(|&&t| t == " served in ").count(), MIN_REPEATS);
+        assert_eq!(tokens.iter().filter(|&&t| t == "ms\n").count(), MIN_REPEATS);
+        assert_eq!(tokens.concat(), log);
+        // Once fewer, it is words again
+        let log = &log[..log.len() - log.lines().last().unwrap().len() - 1];
+        assert!(!tokenize(log).contains(&" INFO request "));
+    }
+
+    #[test]
+    fn splits_one_off_text_into_words() {
+        assert_eq!(
+            tokenize("7 user alice logged in\n8 user bob logged out\n"),
+            ["7", " ", "user", " ", "alice", " ", "logged", " ", "in", "\n", "8", " ", "user", " ", "bob", " ", "logged", " ", "out", "\n"],
+        );
+        assert_eq!(tokenize("état_1 ok"), ["état_", "1", " ", "ok"]);
+    }
+
+    #[test]
+    fn splits_numbers_into_digits() {
+        assert_eq!(tokenize("1760000000"), ["1", "7", "6", "0", "0", "0", "0", "0", "0", "0"]);
+        assert_eq!(tokenize("0x1f"), ["0", "x", "1", "f"]);
+        assert_eq!(tokenize(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn concatenates_back_to_the_input() {
+        for text in ["", "\n\n", "a", "1", "ts=1 ts=2 ts=3", "\t  mixed\tspace  \n", "日本語 1 ログ 2 日本語 3", "ends in a digit 9"] {
+            assert_eq!(tokenize(text).concat(), text);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! LZ4's block format: LZ77 built for speed rather than ratio. Matches
+//! are found by a single probe into a hash table of the positions where
+//! each 4 bytes were last seen, and everything stays byte aligned, with no
+//! entropy coding after it, so both directions run at memory speed while
+//! the others here are still counting frequencies.
+//!
+//! The data is a series of sequences, each some literal bytes and then a
+//! match. A sequence starts with a token byte: the literal count in its
+//! high 4 bits and the match length less 4 in its low ones, 15 meaning more
+//! follows in bytes that add up until one is less than 255. The literals
+//! come next, then the match's offset back as 2 bytes, little-endian, then
+//! any more of its length. The last sequence is literals alone, and as LZ4
+//! requires, the last 5 bytes are always literals and no match starts in
+//! the last 12.
+//!
+//! The output is the byte count as a varint, then the block, which LZ4
+//! libraries decode given that count.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::lz4::Lz4;
+//!
+//! let log = "GET /index.html 200\nGET /style.css 200\nGET /index.html 304\n".repeat(100);
+//! let compressed = Lz4.compress(log.as_bytes());
+//! assert!(compressed.len() < log.len() / 20);
+//! assert_eq!(Lz4.decompress(&compressed).unwrap(), log.as_bytes());
+//! ```
+
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::io;
+use crate::varint::{self, Reader};
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = u16::MAX as usize;
+// The end of the block that is only literals, and the end no match starts in
+const LAST_LITERALS: usize = 5;
+const MATCH_LIMIT: usize = 12;
+const HASH_BITS: u32 = 16;
+// Positions searched with no match before the step forward grows by a byte
+const SKIP_TRIGGER: u32 = 6;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Compression in LZ4's block format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Lz4;
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn hash(v: u32) -> usize {
+    (v.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+// A length of 15 or more in a token's field, and the bytes after it
+fn put_length(out: &mut Vec<u8>, mut len: usize) {
+    len -= 15;
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+fn put_sequence(out: &mut Vec<u8>, literals: &[u8], offset: usize, match_len: usize) {
+    let extra = match_len - MIN_MATCH;
+    out.push((literals.len().min(15) as u8) << 4 | extra.min(15) as u8);
+    if literals.len() >= 15 {
+        put_length(out, literals.len());
+    }
+    out.extend_from_slice(literals);
+    out.extend_from_slice(&(offset as u16).to_le_bytes());
+    if extra >= 15 {
+        put_length(out, extra);
+    }
+}
+
+fn put_last_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    out.push((literals.len().min(15) as u8) << 4);
+    if literals.len() >= 15 {
+        put_length(out, literals.len());
+    }
+    out.extend_from_slice(literals);
+}
+
+fn read_length(input: &mut Reader, nibble: u8) -> io::Result<usize> {
+    let mut len = nibble as usize;
+    if nibble == 15 {
+        loop {
+            let b = input.byte()?;
+            len = len.checked_add(b as usize).ok_or_else(|| invalid("LZ4 length is too long"))?;
+            if b < 255 {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+// Calls `found` with the start, offset and length of each match the
+// hash probe finds, in order, keeping to LZ4's rules for the block's end
+pub(crate) fn find_matches(data: &[u8], mut found: impl FnMut(usize, usize, usize)) {
+    if data.len() <= MATCH_LIMIT {
+        return;
+    }
+    // Positions plus one, 0 for none yet
+    let mut table = vec![0u32; 1 << HASH_BITS];
+    let match_end = data.len() - LAST_LITERALS;
+    let mut anchor = 0;
+    let mut pos = 0;
+    let mut misses = 0;
+    while pos < data.len() - MATCH_LIMIT {
+        let v = read_u32(data, pos);
+        let h = hash(v);
+        let candidate = table[h] as usize;
+        table[h] = pos as u32 + 1;
+        if candidate == 0 || pos + 1 - candidate > MAX_OFFSET || read_u32(data, candidate - 1) != v {
+            pos += 1 + (misses >> SKIP_TRIGGER);
+            misses += 1;
+            continue;
+        }
+        misses = 0;
+        let (mut start, mut from) = (pos, candidate - 1);
+        while start > anchor && from > 0 && data[start - 1] == data[from - 1] {
+            start -= 1;
+            from -= 1;
+        }
+        let len = MIN_MATCH
+            + data[pos + MIN_MATCH..match_end]
+                .iter()
+                .zip(&data[candidate - 1 + MIN_MATCH..])
+                .take_while(|(a, b)| a == b)
+                .count();
+        let end = pos + len;
+        found(start, start - from, end - start);
+        anchor = end;
+        pos = end;
+        // So the next match can start inside this one's end
+        if end - 2 < data.len() - MATCH_LIMIT {
+            table[hash(read_u32(data, end - 2))] = (end - 2) as u32 + 1;
+        }
+    }
+}
+
+impl Compressor for Lz4 {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() / 2 + 16);
+        varint::put(&mut out, data.len() as u64);
+        let mut anchor = 0;
+        find_matches(data, |start, offset, len| {
+            put_sequence(&mut out, &data[anchor..start], offset, len);
+            anchor = start + len;
+        });
+        put_last_literals(&mut out, &data[anchor..]);
+        out
+    }
+}
+
+impl Decompressor for Lz4 {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut input = Reader::new(data);
+        let len = input.varint()?;
+        // Each byte of the block makes at most 255 of output
+        if len > (input.rest().len() as u64).saturating_mul(255) {
+            return Err(invalid("LZ4 data is truncated"));
+        }
+        let len = len as usize;
+        let mut out = Vec::with_capacity(len);
+        loop {
+            let token = input.byte()?;
+            let literals = read_length(&mut input, token >> 4)?;
+            if literals > len - out.len() {
+                return Err(invalid("LZ4 data is longer than its length"));
+            }
+            out.extend_from_slice(input.take(literals as u64)?);
+            if input.is_empty() {
+                break;
+            }
+            let offset = u16::from_le_bytes([input.byte()?, input.byte()?]) as usize;
+            let match_len = read_length(&mut input, token & 15)?.saturating_add(MIN_MATCH);
+            if offset == 0 || offset > out.len() {
+                return Err(invalid("bad LZ4 match offset"));
+            }
+            if match_len > len - out.len() {
+                return Err(invalid("LZ4 data is longer than its length"));
+            }
+            let start = out.len() - offset;
+            if offset >= match_len {
+                out.extend_from_within(start..start + match_len);
+            } else {
+                // Byte by byte: the match overlaps what it produces
+                for i in 0..match_len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+        if out.len() != len {
+            return Err(invalid("LZ4 data is shorter than its length"));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let mut log = Vec::new();
+        for i in 0..3000u32 {
+            log.extend_from_slice(format!("GET /item/{} HTTP/1.1 {}\n", i % 41, i * 7 % 1000).as_bytes());
+        }
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let runs = [&[0u8; 70_000][..], b"abc", &[1; 300]].concat();
+        for data in [&b""[..], b"a", b"0123456789abc", b"aaaaaaaaaaaaaaaaaaaaaaa", &log, &noise, &runs] {
+            assert_eq!(Lz4.decompress(&Lz4.compress(data)).unwrap(), data);
+        }
+        assert!(Lz4.compress(&log).len() < log.len() / 3);
+        assert!(Lz4.compress(&noise).len() < noise.len() + noise.len() / 200 + 16);
+    }
+
+    #[test]
+    fn keeps_to_the_block_rules() {
+        let data = b"abcdabcdabcdabcdabcdabcd";
+        // Literals "abcd", a match of 15 (length field 11) at offset 4, then
+        // the last 5 bytes as literals
+        let mut expected = vec![data.len() as u8, 0x4b];
+        expected.extend_from_slice(b"abcd");
+        expected.extend_from_slice(&[4, 0, 0x50]);
+        expected.extend_from_slice(b"dabcd");
+        assert_eq!(Lz4.compress(data), expected);
+    }
+
+    #[test]
+    fn rejects_bad_blocks() {
+        assert!(Lz4.decompress(&[4, 0x40, b'a', b'b', b'c']).is_err(), "truncated literals");
+        assert!(Lz4.decompress(&[8, 0x10, b'a', 2, 0]).is_err(), "offset before the start");
+        assert!(Lz4.decompress(&[2, 0x10, b'a', 1, 0]).is_err(), "longer than the length");
+        assert!(Lz4.decompress(&[5, 0x10, b'a']).is_err(), "shorter than the length");
+        assert!(Lz4.decompress(&[]).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! LZ77: repeats are replaced by references to an earlier occurrence within
+//! a sliding window, as (offset, length) pairs.
+//!
+//! The output starts with the window and lookahead sizes as varints, so the
+//! decoder can check references against them. Then come tokens, each a
+//! varint offset back from the current position, for a match a varint
+//! length, and the literal byte that follows. Offset 0 means no match, just
+//! the literal. The last token's literal is left out when a match runs to
+//! the end of the input.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::lz77::Lz77;
+//!
+//! let data = b"blah blah blah blah blah!";
+//! let lz77 = Lz77::with_sizes(1024, 32).unwrap();
+//! let compressed = lz77.compress(data);
+//! assert!(compressed.len() < data.len());
+//! assert_eq!(lz77.decompress(&compressed).unwrap(), data);
+//! ```
+
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::io;
+use crate::varint::{self, Reader};
+
+pub const DEFAULT_WINDOW: usize = 32 * 1024;
+pub const DEFAULT_LOOKAHEAD: usize = 258;
+pub const MAX_WINDOW: usize = 16 * 1024 * 1024;
+pub const MAX_LOOKAHEAD: usize = 64 * 1024;
+
+// Shorter matches take more bytes as a reference than as literals.
+pub(crate) const MIN_MATCH: usize = 3;
+
+/// Candidates tried per position; more finds longer matches, slower.
+pub const DEFAULT_CHAIN: usize = 128;
+pub const MAX_CHAIN: usize = 1 << 16;
+
+const HASH_BITS: u32 = 15;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Finds the longest earlier occurrence of the bytes at each position,
+/// through hash chains over their first [`MIN_MATCH`] bytes.
+pub(crate) struct MatchFinder<'a> {
+    data: &'a [u8],
+    window: usize,
+    head: Vec<usize>,
+    prev: Vec<usize>,
+    // Positions below this are in the chains.
+    inserted: usize,
+    chain: usize,
+}
+
+impl<'a> MatchFinder<'a> {
+    pub(crate) fn new(data: &'a [u8], window: usize) -> MatchFinder<'a> {
+        MatchFinder {
+            data,
+            window,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            prev: vec![usize::MAX; data.len()],
+            inserted: 0,
+            chain: DEFAULT_CHAIN,
+        }
+    }
+
+    /// Tries `chain` candidates per position instead of [`DEFAULT_CHAIN`].
+    pub(crate) fn with_chain(self, chain: usize) -> MatchFinder<'a> {
+        MatchFinder { chain, ..self }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let v = u32::from_le_bytes([self.data[pos], self.data[pos + 1], self.data[pos + 2], 0]);
+        (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    // Adds every position before `pos` to the chains.
+    fn insert_up_to(&mut self, pos: usize) {
+        while self.inserted < pos {
+            let i = self.inserted;
+            if i + MIN_MATCH <= self.data.len() {
+                let h = self.hash(i);
+                self.prev[i] = self.head[h];
+                self.head[h] = i;
+            }
+            self.inserted += 1;
+        }
+    }
+
+    /// The (offset, length) of the longest match for `pos`, at most
+    /// `max_len` long, if there is one of at least [`MIN_MATCH`] bytes.
+    pub(crate) fn longest(&mut self, pos: usize, max_len: usize) -> Option<(usize, usize)> {
+        self.insert_up_to(pos);
+        let max_len = max_len.min(self.data.len() - pos);
+        if max_len < MIN_MATCH {
+            return None;
+        }
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(pos)];
+        for _ in 0..self.chain {
+            if candidate == usize::MAX || pos - candidate > self.window {
+                break;
+            }
+            let len = self.data[candidate..].iter().zip(&self.data[pos..pos + max_len]).take_while(|(a, b)| a == b).count();
+            if len >= MIN_MATCH && best.is_none_or(|(_, best_len)| len > best_len) {
+                best = Some((pos - candidate, len));
+                if len == max_len {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate];
+        }
+        best
+    }
+}
+
+/// LZ77 with a window of `window` bytes and matches of up to `lookahead`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lz77 {
+    window: usize,
+    lookahead: usize,
+}
+
+impl Lz77 {
+    pub fn new() -> Lz77 {
+        Lz77 {
+            window: DEFAULT_WINDOW,
+            lookahead: DEFAULT_LOOKAHEAD,
+        }
+    }
+
+    /// Fails unless `window` is 1 to [`MAX_WINDOW`] and `lookahead` 1 to
+    /// [`MAX_LOOKAHEAD`].
+    pub fn with_sizes(window: usize, lookahead: usize) -> io::Result<Lz77> {
+        if !(1..=MAX_WINDOW).contains(&window) || !(1..=MAX_LOOKAHEAD).contains(&lookahead) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "LZ77 window or lookahead out of range"));
+        }
+        Ok(Lz77 { window, lookahead })
+    }
+}
+
+impl Default for Lz77 {
+    fn default() -> Lz77 {
+        Lz77::new()
+    }
+}
+
+impl Compressor for Lz77 {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint::put(&mut out, self.window as u64);
+        varint::put(&mut out, self.lookahead as u64);
+        let mut matches = MatchFinder::new(data, self.window);
+        let mut pos = 0;
+        while pos < data.len() {
+            match matches.longest(pos, self.lookahead) {
+                Some((offset, len)) => {
+                    varint::put(&mut out, offset as u64);
+                    varint::put(&mut out, len as u64);
+                    pos += len;
+                }
+                None => varint::put(&mut out, 0),
+            }
+            if let Some(&literal) = data.get(pos) {
+                out.push(literal);
+                pos += 1;
+            }
+        }
+        out
+    }
+}
+
+impl Decompressor for Lz77 {
+    /// Reads the sizes from the data itself, so any `Lz77` decodes the
+    /// output of any other.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut input = Reader::new(data);
+        let window = input.varint()?;
+        let lookahead = input.varint()?;
+        if !(1..=MAX_WINDOW as u64).contains(&window) || !(1..=MAX_LOOKAHEAD as u64).contains(&lookahead) {
+            return Err(invalid("bad LZ77 window or lookahead"));
+        }
+        let mut out: Vec<u8> = Vec::new();
+        while !input.is_empty() {
+            let offset = input.varint()?;
+            if offset > 0 {
+                let len = input.varint()?;
+                if offset > window || offset > out.len() as u64 || len == 0 || len > lookahead {
+                    return Err(invalid("bad LZ77 match"));
+                }
+                // Byte by byte: a match may overlap what it produces.
+                let start = out.len() - offset as usize;
+                for i in 0..len as usize {
+                    out.push(out[start + i]);
+                }
+                if input.is_empty() {
+                    break;
+                }
+            }
+            out.push(input.byte()?);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(lz77: Lz77, data: &[u8]) -> usize {
+        let compressed = lz77.compress(data);
+        assert_eq!(lz77.decompress(&compressed).unwrap(), data);
+        compressed.len()
+    }
+
+    #[test]
+    fn round_trips_text() {
+        let text = "It was the best of times, it was the worst of times, it was the age of wisdom, \
+                    it was the age of foolishness, it was the epoch of belief, it was the epoch of incredulity";
+        let len = round_trip(Lz77::new(), text.as_bytes());
+        assert!(len < text.len());
+    }
+
+    #[test]
+    fn round_trips_binary() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut data = Vec::new();
+        for i in 0..20_000u32 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // Noise with repeated records mixed in
+            match state % 4 {
+                0 => data.extend_from_slice(&i.to_le_bytes()),
+                1 => data.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 0]),
+                _ => data.push(state as u8),
+            }
+        }
+        round_trip(Lz77::new(), &data);
+        round_trip(Lz77::with_sizes(16, 4).unwrap(), &data);
+        round_trip(Lz77::new(), &[]);
+        round_trip(Lz77::new(), &[7]);
+    }
+
+    #[test]
+    fn respects_window_and_lookahead() {
+        // A run is one literal and then a match overlapping itself, cut
+        // into lookahead-sized pieces.
+        let run = [b'x'; 1000];
+        assert!(round_trip(Lz77::new(), &run) < 30);
+        assert!(round_trip(Lz77::with_sizes(8, 10).unwrap(), &run) > 200);
+
+        // The repeat is too far back for a small window.
+        let data: Vec<u8> = (0..=255u8).chain(0..=255u8).collect();
+        assert!(round_trip(Lz77::new(), &data) + 200 < round_trip(Lz77::with_sizes(100, 258).unwrap(), &data));
+
+        assert!(Lz77::with_sizes(0, 10).is_err());
+        assert!(Lz77::with_sizes(10, MAX_LOOKAHEAD + 1).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_references() {
+        let mut data = Vec::new();
+        varint::put(&mut data, 16);
+        varint::put(&mut data, 8);
+        data.extend_from_slice(&[0, b'a', 2, 1, b'b']);
+        assert!(Lz77::new().decompress(&data).is_err(), "offset before the start");
+        data.truncate(3);
+        data.extend_from_slice(&[1, 9]);
+        assert!(Lz77::new().decompress(&data).is_err(), "longer than the lookahead");
+    }
+}
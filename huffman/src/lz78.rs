@@ -0,0 +1,162 @@
+//! LZ78: the input is cut into phrases, each an earlier phrase extended by
+//! one byte, and every phrase joins a dictionary the decoder rebuilds as it
+//! goes.
+//!
+//! The output starts with the dictionary size as a varint. Then come tokens,
+//! each the varint index of the earlier phrase (0 for none, otherwise the
+//! phrase's position in the dictionary, from 1) and the byte that extends
+//! it. When the input ends partway into a known phrase, the last token is
+//! the index alone. A full dictionary is cleared and starts over, so the
+//! code adapts to changes in the input.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::lz78::Lz78;
+//!
+//! let data = b"abababababababababababababababab";
+//! let compressed = Lz78::new().compress(data);
+//! assert!(compressed.len() < data.len());
+//! assert_eq!(Lz78::new().decompress(&compressed).unwrap(), data);
+//! ```
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::io;
+use crate::Map;
+use crate::varint::{self, Reader};
+
+pub const DEFAULT_DICTIONARY: usize = 64 * 1024;
+pub const MAX_DICTIONARY: usize = 16 * 1024 * 1024;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// LZ78 with a dictionary of up to `dictionary` phrases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lz78 {
+    dictionary: usize,
+}
+
+impl Lz78 {
+    pub fn new() -> Lz78 {
+        Lz78 {
+            dictionary: DEFAULT_DICTIONARY,
+        }
+    }
+
+    /// Fails unless `dictionary` is 1 to [`MAX_DICTIONARY`].
+    pub fn with_dictionary(dictionary: usize) -> io::Result<Lz78> {
+        if !(1..=MAX_DICTIONARY).contains(&dictionary) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "LZ78 dictionary size out of range"));
+        }
+        Ok(Lz78 { dictionary })
+    }
+}
+
+impl Default for Lz78 {
+    fn default() -> Lz78 {
+        Lz78::new()
+    }
+}
+
+impl Compressor for Lz78 {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint::put(&mut out, self.dictionary as u64);
+        // (phrase, next byte) -> the longer phrase
+        let mut phrases: Map<(usize, u8), usize> = Map::new();
+        let mut phrase = 0;
+        for &b in data {
+            if let Some(&longer) = phrases.get(&(phrase, b)) {
+                phrase = longer;
+                continue;
+            }
+            varint::put(&mut out, phrase as u64);
+            out.push(b);
+            if phrases.len() + 1 == self.dictionary {
+                phrases.clear();
+            } else {
+                phrases.insert((phrase, b), phrases.len() + 1);
+            }
+            phrase = 0;
+        }
+        if phrase != 0 {
+            varint::put(&mut out, phrase as u64);
+        }
+        out
+    }
+}
+
+impl Decompressor for Lz78 {
+    /// Reads the dictionary size from the data itself, so any `Lz78`
+    /// decodes the output of any other.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut input = Reader::new(data);
+        let dictionary = input.varint()?;
+        if !(1..=MAX_DICTIONARY as u64).contains(&dictionary) {
+            return Err(invalid("bad LZ78 dictionary size"));
+        }
+        // Each phrase was written out whole, so it is a (start, length)
+        // range of the output.
+        let mut phrases: Vec<(usize, usize)> = Vec::new();
+        let mut out: Vec<u8> = Vec::new();
+        while !input.is_empty() {
+            let index = input.varint()?;
+            let (start, len) = match index {
+                0 => (out.len(), 0),
+                i if i <= phrases.len() as u64 => phrases[i as usize - 1],
+                _ => return Err(invalid("bad LZ78 phrase")),
+            };
+            let phrase_start = out.len();
+            out.extend_from_within(start..start + len);
+            if input.is_empty() {
+                if index == 0 {
+                    return Err(invalid("bad LZ78 phrase"));
+                }
+                break;
+            }
+            out.push(input.byte()?);
+            if phrases.len() as u64 + 1 == dictionary {
+                phrases.clear();
+            } else {
+                phrases.push((phrase_start, len + 1));
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_with_dictionary_resets() {
+        let mut data = Vec::new();
+        for i in 0..5000u32 {
+            data.extend_from_slice(format!("line {} of {}\n", i % 97, i % 13).as_bytes());
+        }
+        for lz78 in [Lz78::new(), Lz78::with_dictionary(1).unwrap(), Lz78::with_dictionary(50).unwrap()] {
+            let compressed = lz78.compress(&data);
+            assert_eq!(lz78.decompress(&compressed).unwrap(), data);
+        }
+        for data in [&b""[..], b"a", b"aa", b"aaa"] {
+            assert_eq!(Lz78::new().decompress(&Lz78::new().compress(data)).unwrap(), data);
+        }
+        assert!(Lz78::with_dictionary(0).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_phrases() {
+        let mut data = Vec::new();
+        varint::put(&mut data, 16);
+        data.extend_from_slice(&[0, b'a', 2, b'b']);
+        assert!(Lz78::new().decompress(&data).is_err(), "phrase not in the dictionary yet");
+        data.truncate(3);
+        data.push(0);
+        assert!(Lz78::new().decompress(&data).is_err(), "empty phrase at the end");
+    }
+}
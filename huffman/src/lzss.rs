@@ -0,0 +1,194 @@
+//! LZSS: LZ77 where each token is either a literal byte or a match, told
+//! apart by a flag bit, rather than always a match and a literal. Matches
+//! shorter than a minimum length are not worth a reference and are coded
+//! as literals.
+//!
+//! The output starts with the window size, the lookahead and the minimum
+//! match length as varints. Then come groups of up to eight tokens, each
+//! group led by a byte of flags, the lowest bit for the first token: 0 for
+//! a literal byte, 1 for a match, as a varint offset back from the current
+//! position and a varint of its length less the minimum.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::lzss::Lzss;
+//!
+//! let data = b"blah blah blah blah blah!";
+//! let lzss = Lzss::with_sizes(1024, 32, 4).unwrap();
+//! let compressed = lzss.compress(data);
+//! assert!(compressed.len() < data.len());
+//! assert_eq!(lzss.decompress(&compressed).unwrap(), data);
+//! ```
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::io;
+use crate::lz77::{MatchFinder, DEFAULT_LOOKAHEAD, DEFAULT_WINDOW, MAX_LOOKAHEAD, MAX_WINDOW, MIN_MATCH};
+use crate::varint::{self, Reader};
+
+/// The default minimum match length, and the smallest: the match finder
+/// doesn't look for shorter matches.
+pub const DEFAULT_MIN_MATCH: usize = MIN_MATCH;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn valid_sizes(window: u64, lookahead: u64, min_match: u64) -> bool {
+    (1..=MAX_WINDOW as u64).contains(&window)
+        && (1..=MAX_LOOKAHEAD as u64).contains(&lookahead)
+        && (MIN_MATCH as u64..=lookahead).contains(&min_match)
+}
+
+/// LZSS with a window of `window` bytes, matches of up to `lookahead` and
+/// of at least `min_match`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lzss {
+    window: usize,
+    lookahead: usize,
+    min_match: usize,
+}
+
+impl Lzss {
+    pub fn new() -> Lzss {
+        Lzss {
+            window: DEFAULT_WINDOW,
+            lookahead: DEFAULT_LOOKAHEAD,
+            min_match: DEFAULT_MIN_MATCH,
+        }
+    }
+
+    /// Fails unless `window` is 1 to [`MAX_WINDOW`], `lookahead` 1 to
+    /// [`MAX_LOOKAHEAD`], and `min_match` [`DEFAULT_MIN_MATCH`] to
+    /// `lookahead`.
+    pub fn with_sizes(window: usize, lookahead: usize, min_match: usize) -> io::Result<Lzss> {
+        if !valid_sizes(window as u64, lookahead as u64, min_match as u64) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "LZSS window, lookahead or minimum match out of range"));
+        }
+        Ok(Lzss {
+            window,
+            lookahead,
+            min_match,
+        })
+    }
+}
+
+impl Default for Lzss {
+    fn default() -> Lzss {
+        Lzss::new()
+    }
+}
+
+impl Compressor for Lzss {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint::put(&mut out, self.window as u64);
+        varint::put(&mut out, self.lookahead as u64);
+        varint::put(&mut out, self.min_match as u64);
+        let mut matches = MatchFinder::new(data, self.window);
+        let mut pos = 0;
+        let mut flags_at = 0;
+        let mut token = 0;
+        while pos < data.len() {
+            if token % 8 == 0 {
+                flags_at = out.len();
+                out.push(0);
+            }
+            match matches.longest(pos, self.lookahead).filter(|&(_, len)| len >= self.min_match) {
+                Some((offset, len)) => {
+                    out[flags_at] |= 1 << (token % 8);
+                    varint::put(&mut out, offset as u64);
+                    varint::put(&mut out, (len - self.min_match) as u64);
+                    pos += len;
+                }
+                None => {
+                    out.push(data[pos]);
+                    pos += 1;
+                }
+            }
+            token += 1;
+        }
+        out
+    }
+}
+
+impl Decompressor for Lzss {
+    /// Reads the sizes from the data itself, so any `Lzss` decodes the
+    /// output of any other.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut input = Reader::new(data);
+        let window = input.varint()?;
+        let lookahead = input.varint()?;
+        let min_match = input.varint()?;
+        if !valid_sizes(window, lookahead, min_match) {
+            return Err(invalid("bad LZSS window, lookahead or minimum match"));
+        }
+        let mut out: Vec<u8> = Vec::new();
+        while !input.is_empty() {
+            let flags = input.byte()?;
+            for bit in 0..8 {
+                if flags & (1 << bit) == 0 {
+                    // The last group may be short.
+                    if input.is_empty() {
+                        break;
+                    }
+                    out.push(input.byte()?);
+                    continue;
+                }
+                let offset = input.varint()?;
+                let len = input.varint()?.saturating_add(min_match);
+                if offset == 0 || offset > window || offset > out.len() as u64 || len > lookahead {
+                    return Err(invalid("bad LZSS match"));
+                }
+                // Byte by byte: a match may overlap what it produces.
+                let start = out.len() - offset as usize;
+                for i in 0..len as usize {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_with_any_minimum() {
+        let mut data = Vec::new();
+        for i in 0..1000u32 {
+            data.extend_from_slice(format!("GET /item/{} HTTP/1.1 {}\n", i % 41, i * 7 % 1000).as_bytes());
+        }
+        let mut sizes = Vec::new();
+        for min_match in [3, 4, 8, 32] {
+            let lzss = Lzss::with_sizes(DEFAULT_WINDOW, DEFAULT_LOOKAHEAD, min_match).unwrap();
+            let compressed = lzss.compress(&data);
+            assert_eq!(Lzss::new().decompress(&compressed).unwrap(), data);
+            sizes.push(compressed.len());
+        }
+        // Long minimums leave shorter repeats as literals.
+        assert!(sizes[0] < sizes[3]);
+        for data in [&b""[..], b"a", b"aaaaaaaaaaaaaaaaa"] {
+            assert_eq!(Lzss::new().decompress(&Lzss::new().compress(data)).unwrap(), data);
+        }
+        assert!(Lzss::with_sizes(16, 8, 2).is_err());
+        assert!(Lzss::with_sizes(16, 8, 9).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_references() {
+        let mut data = Vec::new();
+        varint::put(&mut data, 16);
+        varint::put(&mut data, 8);
+        varint::put(&mut data, 3);
+        data.extend_from_slice(&[0b10, b'a', 2, 0]);
+        assert!(Lzss::new().decompress(&data).is_err(), "offset before the start");
+        data.truncate(5);
+        data.extend_from_slice(&[1, 6]);
+        assert!(Lzss::new().decompress(&data).is_err(), "longer than the lookahead");
+    }
+}
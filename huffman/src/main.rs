@@ -29,7 +29,7 @@ impl Eq for Node {}
 
 impl PartialOrd for Node {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.freq.partial_cmp(&other.freq)
+        Some(self.cmp(other))
     }
 }
 impl Ord for Node {
@@ -39,7 +39,7 @@ impl Ord for Node {
 }
 
 
-fn printTree(pot_node: Option<Box<Node>>, encoding: Vec<u8>) {
+fn print_tree(pot_node: Option<Box<Node>>, encoding: Vec<u8>) {
 
     if let Some(node) = pot_node {
         if node.left.is_none() && node.right.is_none() {
@@ -53,8 +53,8 @@ fn printTree(pot_node: Option<Box<Node>>, encoding: Vec<u8>) {
         let mut right_encoding = encoding.clone();
         right_encoding.push(1);
 
-        printTree(node.left, left_encoding);
-        printTree(node.right, right_encoding);
+        print_tree(node.left, left_encoding);
+        print_tree(node.right, right_encoding);
     }
 
 }
@@ -89,7 +89,7 @@ fn main() {
     }
 
     if let Some(Reverse(node)) = nodes.pop() {
-        printTree(Some(Box::new(node)), vec![]);
+        print_tree(Some(Box::new(node)), vec![]);
     }
 
 }
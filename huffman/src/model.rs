@@ -0,0 +1,203 @@
+//! Byte frequencies scaled to a fixed total, the model the arithmetic
+//! coders work from. It is built from the same counts as a Huffman code
+//! ([`codes::build_frequency_table`]), so coders can be compared on equal
+//! terms: what differs is only how close each gets to the model's entropy.
+//!
+//! Every byte that occurs keeps a frequency of at least 1, however rare it
+//! is, and bytes that don't occur get none. The scaled frequencies are
+//! stored with the data, since the decoder needs exactly the same ones.
+//!
+//! ```
+//! use huffman::model::{FrequencyModel, TOTAL};
+//!
+//! let model = FrequencyModel::from_data(b"abracadabra");
+//! assert_eq!((0..=255).map(|b| model.freq(b)).sum::<u32>(), TOTAL);
+//! assert!(model.freq(b'a') > model.freq(b'b'));
+//! assert_eq!(model.freq(b'z'), 0);
+//!
+//! let mut table = Vec::new();
+//! model.write(&mut table);
+//! let read = FrequencyModel::read(&mut huffman::varint::Reader::new(&table)).unwrap();
+//! assert_eq!(read, model);
+//! ```
+
+use std::io;
+
+use crate::codes;
+use crate::varint::{self, Reader};
+
+/// Frequencies add up to 2^`PROB_BITS`.
+pub const PROB_BITS: u32 = 15;
+pub const TOTAL: u32 = 1 << PROB_BITS;
+/// The most bytes a model with [only one byte](FrequencyModel::only_symbol)
+/// is trusted to stand for. Longer runs of one byte are given a second,
+/// unused one, so that they are coded and take space in proportion.
+pub const MAX_RUN: u64 = 1 << 20;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Scaled frequencies of the 256 byte values, and where each starts in
+/// `0..TOTAL`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrequencyModel {
+    freqs: [u32; 256],
+    starts: [u32; 257],
+}
+
+impl FrequencyModel {
+    /// The frequencies of the bytes in `data`. Empty data gives a model
+    /// with no bytes in it, which can code nothing.
+    pub fn from_data(data: &[u8]) -> FrequencyModel {
+        let mut freqs = [0u32; 256];
+        for (b, count) in codes::build_byte_frequency_table(data) {
+            // At least 1, and scaled in u64 since count * TOTAL overflows
+            // a u32 on large input
+            freqs[b as usize] = ((count as u64 * TOTAL as u64 / data.len() as u64) as u32).max(1);
+        }
+        if data.len() as u64 > MAX_RUN && freqs.iter().filter(|&&f| f > 0).count() == 1 {
+            freqs[(data[0] ^ 1) as usize] = 1;
+        }
+        if !data.is_empty() {
+            // Rounding and the minimum leave the sum a little off; the
+            // commonest bytes can best afford to make up the difference
+            let mut sum: u32 = freqs.iter().sum();
+            while sum != TOTAL {
+                let commonest = (0..256).max_by_key(|&b| (freqs[b], std::cmp::Reverse(b))).unwrap();
+                if sum > TOTAL {
+                    let excess = (sum - TOTAL).min(freqs[commonest] / 2).max(1);
+                    freqs[commonest] -= excess;
+                    sum -= excess;
+                } else {
+                    freqs[commonest] += TOTAL - sum;
+                    sum = TOTAL;
+                }
+            }
+        }
+        FrequencyModel::with_freqs(freqs)
+    }
+
+    fn with_freqs(freqs: [u32; 256]) -> FrequencyModel {
+        let mut starts = [0u32; 257];
+        for b in 0..256 {
+            starts[b + 1] = starts[b] + freqs[b];
+        }
+        FrequencyModel { freqs, starts }
+    }
+
+    /// Whether no byte has a frequency, as for empty data.
+    pub fn is_empty(&self) -> bool {
+        self.starts[256] == 0
+    }
+
+    pub fn freq(&self, b: u8) -> u32 {
+        self.freqs[b as usize]
+    }
+
+    /// The total of the frequencies of the bytes below `b`.
+    pub fn start(&self, b: u8) -> u32 {
+        self.starts[b as usize]
+    }
+
+    /// The byte, if only one has a frequency. It takes no bits at all to
+    /// code, so the coded length says nothing about how many there are, and
+    /// decoders may as well not decode.
+    pub fn only_symbol(&self) -> Option<u8> {
+        let mut present = (0..=255).filter(|&b| self.freq(b) > 0);
+        match (present.next(), present.next()) {
+            (Some(b), None) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Whether `len` bytes could have been coded in `coded_len` bytes, for
+    /// decoders to refuse corrupt lengths before they start. Unless there
+    /// is [only one byte](FrequencyModel::only_symbol), each byte takes over
+    /// 2^-`PROB_BITS` bits, and coders waste at most a few bytes; one byte
+    /// alone is never more than [`MAX_RUN`] of them.
+    pub fn fits(&self, len: u64, coded_len: usize) -> bool {
+        match self.only_symbol() {
+            Some(_) => len <= MAX_RUN,
+            None => len <= ((coded_len as u64 + 8) * 8) << PROB_BITS,
+        }
+    }
+
+    /// A table from each value in `0..TOTAL` to the byte whose range holds
+    /// it, for decoders.
+    pub fn symbol_table(&self) -> Vec<u8> {
+        let mut table = Vec::with_capacity(self.starts[256] as usize);
+        for b in 0..=255u8 {
+            table.resize(table.len() + self.freq(b) as usize, b);
+        }
+        table
+    }
+
+    /// Appends the number of bytes with a frequency, then each byte and
+    /// its frequency as a varint.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let present: Vec<u8> = (0..=255).filter(|&b| self.freq(b) > 0).collect();
+        varint::put(out, present.len() as u64);
+        for b in present {
+            out.push(b);
+            varint::put(out, self.freq(b) as u64);
+        }
+    }
+
+    /// Reads back what [`write`](FrequencyModel::write) wrote, failing
+    /// unless the frequencies add up to [`TOTAL`].
+    pub fn read(reader: &mut Reader) -> io::Result<FrequencyModel> {
+        let count = reader.varint()?;
+        if count > 256 {
+            return Err(invalid("frequency table is too long"));
+        }
+        let mut freqs = [0u32; 256];
+        let mut sum = 0u64;
+        let mut last = None;
+        for _ in 0..count {
+            let b = reader.byte()?;
+            if last.is_some_and(|last| b <= last) {
+                return Err(invalid("frequency table is out of order"));
+            }
+            last = Some(b);
+            let freq = reader.varint()?;
+            if freq == 0 || freq > TOTAL as u64 {
+                return Err(invalid("bad frequency in table"));
+            }
+            freqs[b as usize] = freq as u32;
+            sum += freq;
+        }
+        if count > 0 && sum != TOTAL as u64 {
+            return Err(invalid("frequencies don't add up"));
+        }
+        Ok(FrequencyModel::with_freqs(freqs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rare_bytes_keep_a_frequency() {
+        let data: Vec<u8> = std::iter::repeat_n(0, 1_000_000).chain(0..=255).collect();
+        let model = FrequencyModel::from_data(&data);
+        assert!((0..=255).all(|b| model.freq(b) >= 1));
+        assert_eq!(model.start(255) + model.freq(255), TOTAL);
+        assert_eq!(model.symbol_table().len(), TOTAL as usize);
+
+        let single = FrequencyModel::from_data(b"zzzz");
+        assert_eq!(single.freq(b'z'), TOTAL);
+        let long = FrequencyModel::from_data(&vec![b'z'; MAX_RUN as usize + 1]);
+        assert_eq!((long.freq(b'z'), long.freq(b'{')), (TOTAL - 1, 1));
+        assert_eq!(long.only_symbol(), None);
+        assert!(FrequencyModel::from_data(b"").is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_tables() {
+        for table in [&[1, b'a', 5][..], &[2, b'b', 1, b'a', 0xff, 0xff, 1], &[1, b'a', 0], &[0xff, 0x7f], &[1]] {
+            assert!(FrequencyModel::read(&mut Reader::new(table)).is_err(), "{:?}", table);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Prediction by partial matching (PPM): each byte is coded by the
+//! [arithmetic coder](crate::arithmetic) with probabilities learned from
+//! what followed the same preceding bytes earlier in the data. On text,
+//! which is very predictable from the last few characters, this gives the
+//! best ratio of any codec here, for the price of being slow and memory
+//! hungry.
+//!
+//! The model tries the longest context first, the last `order` bytes. If
+//! the byte has followed it before, it is coded with a probability in
+//! proportion to how often it did. If not, an escape symbol is coded
+//! instead, with the weight of the number of different bytes seen there
+//! (PPM method C), and the next shorter context is tried, leaving out the
+//! bytes the longer one already ruled out. Past the empty context, every
+//! byte not yet ruled out is equally likely. Both sides update the counts
+//! of every context after each byte, halving them now and then so recent
+//! data weighs more. Contexts that have never been seen, or where every
+//! byte has been ruled out, are skipped without an escape.
+//!
+//! The output is the order as a byte, the byte count as a varint and the
+//! arithmetic coded bits.
+//!
+//! ```
+//! use huffman::arithmetic::Arithmetic;
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::ppm::Ppm;
+//!
+//! let text = "the cat sat on the mat; the cat ate the rat; the rat sat on the cat. ".repeat(10);
+//! let compressed = Ppm::new().compress(text.as_bytes());
+//! assert!(compressed.len() < Arithmetic.compress(text.as_bytes()).len() / 3);
+//! assert_eq!(Ppm::new().decompress(&compressed).unwrap(), text.as_bytes());
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::arithmetic::{Decoder, Encoder};
+use crate::codec::{Compressor, Decompressor};
+use crate::varint::{self, Reader};
+
+pub const DEFAULT_ORDER: usize = 4;
+/// Contexts are looked up by their bytes packed into a u64.
+pub const MAX_ORDER: usize = 8;
+
+// Counts in a context are halved when they add up to more than this,
+// which with the escape keeps totals within what the coder can take
+const MAX_COUNT: u32 = 1 << 14;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// PPM compression with contexts of up to `order` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ppm {
+    order: usize,
+}
+
+impl Ppm {
+    pub fn new() -> Ppm {
+        Ppm { order: DEFAULT_ORDER }
+    }
+
+    /// Fails unless `order` is at most [`MAX_ORDER`]. Longer contexts
+    /// predict better once they have been seen, but take longer to learn
+    /// and more memory.
+    pub fn with_order(order: usize) -> io::Result<Ppm> {
+        if order > MAX_ORDER {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("PPM order must be at most {}", MAX_ORDER)));
+        }
+        Ok(Ppm { order })
+    }
+}
+
+impl Default for Ppm {
+    fn default() -> Ppm {
+        Ppm::new()
+    }
+}
+
+#[derive(Default)]
+struct Context {
+    // Bytes in the order they were first seen, with their counts
+    counts: Vec<(u8, u32)>,
+    total: u32,
+}
+
+impl Context {
+    fn update(&mut self, b: u8) {
+        match self.counts.iter_mut().find(|(c, _)| *c == b) {
+            Some((_, count)) => *count += 1,
+            None => self.counts.push((b, 1)),
+        }
+        self.total += 1;
+        if self.total > MAX_COUNT {
+            self.total = 0;
+            for (_, count) in &mut self.counts {
+                *count = count.div_ceil(2);
+                self.total += *count;
+            }
+        }
+    }
+}
+
+// The counts of the bytes a context allows, and its escape
+struct Choices {
+    counts: Vec<(u8, u32)>,
+    total: u32,
+}
+
+impl Choices {
+    fn escape(&self) -> u32 {
+        self.counts.len() as u32
+    }
+}
+
+struct Model {
+    order: usize,
+    // By context length, then by the context's bytes
+    contexts: Vec<HashMap<u64, Context>>,
+    excluded: [bool; 256],
+}
+
+impl Model {
+    fn new(order: usize) -> Model {
+        Model {
+            order,
+            contexts: (0..=order).map(|_| HashMap::new()).collect(),
+            excluded: [false; 256],
+        }
+    }
+
+    // The lengths of context `history` has, longest first
+    fn orders(&self, history: &[u8]) -> impl Iterator<Item = usize> {
+        (0..=self.order.min(history.len())).rev()
+    }
+
+    fn key(history: &[u8], order: usize) -> u64 {
+        history[history.len() - order..].iter().fold(0, |key, &b| key << 8 | b as u64)
+    }
+
+    // What the context of length `order` offers, less the bytes ruled out,
+    // or None if that leaves nothing
+    fn choices(&self, history: &[u8], order: usize) -> Option<Choices> {
+        let context = self.contexts[order].get(&Model::key(history, order))?;
+        let counts: Vec<(u8, u32)> = context.counts.iter().copied().filter(|&(b, _)| !self.excluded[b as usize]).collect();
+        let total = counts.iter().map(|&(_, count)| count).sum();
+        (!counts.is_empty()).then_some(Choices { counts, total })
+    }
+
+    fn exclude(&mut self, choices: &Choices) {
+        for &(b, _) in &choices.counts {
+            self.excluded[b as usize] = true;
+        }
+    }
+
+    // The bytes left for the order -1 model
+    fn remaining(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=255).filter(|&b| !self.excluded[b as usize])
+    }
+
+    fn update(&mut self, history: &[u8], b: u8) {
+        for order in self.orders(history) {
+            self.contexts[order].entry(Model::key(history, order)).or_default().update(b);
+        }
+        self.excluded = [false; 256];
+    }
+}
+
+impl Compressor for Ppm {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = vec![self.order as u8];
+        varint::put(&mut out, data.len() as u64);
+        let mut model = Model::new(self.order);
+        let mut encoder = Encoder::new();
+        for (pos, &b) in data.iter().enumerate() {
+            let history = &data[..pos];
+            let mut coded = false;
+            for order in model.orders(history) {
+                let Some(choices) = model.choices(history, order) else {
+                    continue;
+                };
+                let total = choices.total + choices.escape();
+                let mut start = 0;
+                for &(c, count) in &choices.counts {
+                    if c == b {
+                        encoder.encode(start, count, total);
+                        coded = true;
+                        break;
+                    }
+                    start += count;
+                }
+                if coded {
+                    break;
+                }
+                encoder.encode(choices.total, choices.escape(), total);
+                model.exclude(&choices);
+            }
+            if !coded {
+                let start = model.remaining().take_while(|&c| c < b).count() as u32;
+                encoder.encode(start, 1, model.remaining().count() as u32);
+            }
+            model.update(history, b);
+        }
+        out.extend_from_slice(&encoder.finish());
+        out
+    }
+}
+
+impl Decompressor for Ppm {
+    /// Any order is read back from the data. Corruption in the coded bits
+    /// goes unnoticed unless it leaves too few of them.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = Reader::new(data);
+        let order = reader.byte()? as usize;
+        if order > MAX_ORDER {
+            return Err(invalid("bad PPM order"));
+        }
+        let len = reader.varint()?;
+        let coded = reader.rest();
+        // Every byte takes some part of a bit, as the escape always has a
+        // weight against totals of at most 2^16
+        if len > ((coded.len() as u64 + 8) * 8) << 16 {
+            return Err(invalid("PPM data is truncated"));
+        }
+        let mut model = Model::new(order);
+        let mut decoder = Decoder::new(coded);
+        let mut out = Vec::new();
+        while (out.len() as u64) < len {
+            let mut decoded = None;
+            for order in model.orders(&out) {
+                let Some(choices) = model.choices(&out, order) else {
+                    continue;
+                };
+                let total = choices.total + choices.escape();
+                let target = decoder.target(total);
+                let mut start = 0;
+                for &(c, count) in &choices.counts {
+                    if target < start + count {
+                        decoder.consume(start, count, total);
+                        decoded = Some(c);
+                        break;
+                    }
+                    start += count;
+                }
+                if decoded.is_some() {
+                    break;
+                }
+                decoder.consume(choices.total, choices.escape(), total);
+                model.exclude(&choices);
+            }
+            let b = match decoded {
+                Some(b) => b,
+                None => {
+                    // Only corrupt data escapes from all 256 bytes
+                    let total = model.remaining().count() as u32;
+                    if total == 0 {
+                        return Err(invalid("PPM data is corrupt"));
+                    }
+                    let target = decoder.target(total);
+                    decoder.consume(target, 1, total);
+                    model.remaining().nth(target as usize).unwrap()
+                }
+            };
+            model.update(&out, b);
+            out.push(b);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::Arithmetic;
+
+    #[test]
+    fn round_trips_every_order() {
+        let text = "It was the best of times, it was the worst of times, it was the age of wisdom, \
+                    it was the age of foolishness, it was the epoch of belief. "
+            .repeat(5);
+        let all_bytes: Vec<u8> = (0..=255).chain((0..=255).rev()).collect();
+        for order in 0..=MAX_ORDER {
+            let ppm = Ppm::with_order(order).unwrap();
+            for data in [&b""[..], b"x", &[9; 3000], text.as_bytes(), &all_bytes] {
+                assert_eq!(ppm.decompress(&ppm.compress(data)).unwrap(), data, "order {}", order);
+            }
+        }
+        // Order 0 is a frequency model like the static one, without the table
+        let order0 = Ppm::with_order(0).unwrap().compress(text.as_bytes()).len();
+        assert!(order0 < Arithmetic.compress(text.as_bytes()).len());
+        assert!(Ppm::new().compress(text.as_bytes()).len() < order0 / 3);
+        assert!(Ppm::with_order(MAX_ORDER + 1).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert!(Ppm::new().decompress(&[MAX_ORDER as u8 + 1, 0]).is_err());
+        assert!(Ppm::new().decompress(&[4, 0xff, 0xff, 0xff, 0xff, 0x7f]).is_err());
+        assert!(Ppm::new().decompress(&[]).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Schema-less regrouping of protobuf wire-format data. Serialized messages
+//! interleave field tags, varints, fixed-width numbers and byte payloads,
+//! which gives an entropy coder one muddled distribution. Splitting them
+//! into separate streams lets each be modelled on its own: tags repeat in
+//! a few patterns, varints are mostly small, and payloads are often text.
+//!
+//! Both a single message and a length-delimited sequence of messages (as
+//! written by `writeDelimitedTo`) are recognised. Only canonical encodings
+//! are accepted, so re-encoding the parsed values reproduces the input
+//! exactly; anything else makes [`split`] decline.
+
+use std::io;
+
+use crate::varint::{self, Reader};
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_I64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_I32: u64 = 5;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Streams {
+    /// Whether the input was a length-delimited sequence of messages.
+    pub delimited: bool,
+    /// Message lengths, for delimited input.
+    pub frames: Vec<u8>,
+    pub tags: Vec<u8>,
+    pub varints: Vec<u8>,
+    /// Lengths of length-delimited fields.
+    pub lengths: Vec<u8>,
+    /// Fixed 32- and 64-bit fields.
+    pub fixed: Vec<u8>,
+    /// Contents of length-delimited fields (strings, bytes, sub-messages).
+    pub payloads: Vec<u8>,
+}
+
+// Reads a varint, rejecting encodings that wouldn't re-encode to the same
+// bytes: over-long ones, and ten-byte ones whose last byte holds more than
+// the one bit a u64 has left.
+fn canonical_varint(input: &mut Reader) -> Option<u64> {
+    let start = input.rest();
+    let v = input.varint().ok()?;
+    let read = &start[..start.len() - input.rest().len()];
+    let mut again = Vec::with_capacity(read.len());
+    varint::put(&mut again, v);
+    (again == read).then_some(v)
+}
+
+fn split_message(data: &[u8], streams: &mut Streams) -> Option<()> {
+    let mut input = Reader::new(data);
+    while !input.is_empty() {
+        let tag = canonical_varint(&mut input)?;
+        if tag >> 3 == 0 || tag >> 3 > u32::MAX as u64 >> 3 {
+            return None;
+        }
+        varint::put(&mut streams.tags, tag);
+        match tag & 7 {
+            WIRE_VARINT => varint::put(&mut streams.varints, canonical_varint(&mut input)?),
+            WIRE_I64 => streams.fixed.extend_from_slice(input.take(8).ok()?),
+            WIRE_I32 => streams.fixed.extend_from_slice(input.take(4).ok()?),
+            WIRE_LEN => {
+                let len = canonical_varint(&mut input)?;
+                varint::put(&mut streams.lengths, len);
+                streams.payloads.extend_from_slice(input.take(len).ok()?);
+            }
+            // Groups are deprecated and rare enough not to bother with.
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+fn split_delimited(data: &[u8]) -> Option<Streams> {
+    let mut streams = Streams {
+        delimited: true,
+        ..Streams::default()
+    };
+    let mut input = Reader::new(data);
+    while !input.is_empty() {
+        let len = canonical_varint(&mut input)?;
+        varint::put(&mut streams.frames, len);
+        split_message(input.take(len).ok()?, &mut streams)?;
+    }
+    Some(streams)
+}
+
+/// Splits `data` into streams, or returns `None` if it isn't canonical
+/// protobuf wire format.
+pub fn split(data: &[u8]) -> Option<Streams> {
+    if data.is_empty() {
+        return None;
+    }
+    split_delimited(data).or_else(|| {
+        let mut streams = Streams::default();
+        split_message(data, &mut streams)?;
+        Some(streams)
+    })
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+struct Readers<'a> {
+    tags: Reader<'a>,
+    varints: Reader<'a>,
+    lengths: Reader<'a>,
+    fixed: Reader<'a>,
+    payloads: Reader<'a>,
+}
+
+impl Readers<'_> {
+    fn field(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        let tag = self.tags.varint()?;
+        varint::put(out, tag);
+        match tag & 7 {
+            WIRE_VARINT => varint::put(out, self.varints.varint()?),
+            WIRE_I64 => out.extend_from_slice(self.fixed.take(8)?),
+            WIRE_I32 => out.extend_from_slice(self.fixed.take(4)?),
+            WIRE_LEN => {
+                let len = self.lengths.varint()?;
+                varint::put(out, len);
+                out.extend_from_slice(self.payloads.take(len)?);
+            }
+            _ => return Err(invalid("unknown protobuf wire type")),
+        }
+        Ok(())
+    }
+}
+
+/// Reassembles the original bytes from the output of [`split`].
+pub fn join(streams: &Streams) -> io::Result<Vec<u8>> {
+    let mut readers = Readers {
+        tags: Reader::new(&streams.tags),
+        varints: Reader::new(&streams.varints),
+        lengths: Reader::new(&streams.lengths),
+        fixed: Reader::new(&streams.fixed),
+        payloads: Reader::new(&streams.payloads),
+    };
+    let mut out = Vec::new();
+    if streams.delimited {
+        let mut frames = Reader::new(&streams.frames);
+        while !frames.is_empty() {
+            let len = frames.varint()?;
+            varint::put(&mut out, len);
+            let end = (out.len() as u64)
+                .checked_add(len)
+                .ok_or_else(|| invalid("protobuf message length is out of range"))?;
+            while (out.len() as u64) < end {
+                readers.field(&mut out)?;
+            }
+            if out.len() as u64 != end {
+                return Err(invalid("protobuf message overruns its length"));
+            }
+        }
+    } else {
+        while !readers.tags.is_empty() {
+            readers.field(&mut out)?;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        // Field 1 = 150, field 2 = "testing", field 3 = fixed64, field 4 = fixed32
+        let mut message = vec![0x08, 0x96, 0x01, 0x12, 0x07];
+        message.extend_from_slice(b"testing");
+        message.push(0x19);
+        message.extend_from_slice(&1.5f64.to_le_bytes());
+        message.push(0x25);
+        message.extend_from_slice(&7u32.to_le_bytes());
+        let streams = split(&message).unwrap();
+        assert!(!streams.delimited);
+        assert_eq!(streams.payloads, b"testing");
+        assert_eq!(join(&streams).unwrap(), message);
+
+        let mut delimited = Vec::new();
+        for _ in 0..3 {
+            varint::put_bytes(&mut delimited, &message);
+        }
+        let streams = split(&delimited).unwrap();
+        assert!(streams.delimited);
+        assert_eq!(join(&streams).unwrap(), delimited);
+    }
+
+    #[test]
+    fn declines_what_would_not_re_encode() {
+        // Over-long: 1 in two bytes
+        assert_eq!(split(&[0x08, 0x81, 0x00]), None);
+        // A tenth byte past the u64's last bit, which reads as u64::MAX but
+        // re-encodes with a last byte of 0x01
+        let overflowing = [0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(split(&overflowing), None);
+        let max = [0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(join(&split(&max).unwrap()).unwrap(), max);
+        // Field 0, and the deprecated groups
+        assert_eq!(split(&[0x00, 0x01]), None);
+        assert_eq!(split(&[0x0b, 0x0c]), None);
+        assert_eq!(split(&[]), None);
+    }
+}
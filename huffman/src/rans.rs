@@ -0,0 +1,159 @@
+//! Range asymmetric numeral systems (rANS): an entropy coder as close to
+//! the [frequency model](crate::model)'s entropy as arithmetic coding, with
+//! a single integer of state and no carries, so decoding is a table lookup,
+//! a multiply and the odd byte read per symbol.
+//!
+//! The state `x` is kept in `[L, 256 L)`. Coding byte `b` takes it to
+//! about `x / p(b)`, pushing bytes out first to keep it in range; decoding
+//! reads `b` from the low bits of `x` and undoes that exactly. Decoding pops
+//! bytes in the reverse order coding pushed them, so the encoder works
+//! through the data backwards, and the output is its bytes reversed, final
+//! state first. The decoder must arrive back at the initial state, which
+//! catches most corruption.
+//!
+//! The output is the frequency model, the byte count as a varint and the
+//! coded bytes.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor, Huffman};
+//! use huffman::rans::Rans;
+//!
+//! let data = [&[b'.'; 10_000][..], b"a few other bytes"].concat();
+//! let compressed = Rans.compress(&data);
+//! assert!(compressed.len() < Huffman::new().compress(&data).len() / 5);
+//! assert_eq!(Rans.decompress(&compressed).unwrap(), data);
+//! ```
+
+use std::io;
+
+use crate::codec::{Compressor, Decompressor, EntropyCoder};
+use crate::model::{FrequencyModel, MAX_RUN, PROB_BITS, TOTAL};
+use crate::varint::{self, Reader};
+
+// The bottom of the state's range
+const L: u32 = 1 << 23;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// rANS coding of bytes with a static frequency model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rans;
+
+impl EntropyCoder for Rans {}
+
+impl Compressor for Rans {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let model = FrequencyModel::from_data(data);
+        let mut out = Vec::new();
+        model.write(&mut out);
+        varint::put(&mut out, data.len() as u64);
+        if data.is_empty() {
+            return out;
+        }
+        let mut coded = Vec::new();
+        let mut x = L;
+        for &b in data.iter().rev() {
+            let (start, freq) = (model.start(b), model.freq(b));
+            // Past this, the new state would leave the range
+            let x_max = ((L >> PROB_BITS) << 8) * freq;
+            while x >= x_max {
+                coded.push(x as u8);
+                x >>= 8;
+            }
+            x = ((x / freq) << PROB_BITS) + x % freq + start;
+        }
+        coded.extend_from_slice(&x.to_le_bytes());
+        out.extend(coded.iter().rev());
+        out
+    }
+}
+
+impl Decompressor for Rans {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = Reader::new(data);
+        let model = FrequencyModel::read(&mut reader)?;
+        let len = reader.varint()?;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        if model.is_empty() {
+            return Err(invalid("rANS coded data has no frequency table"));
+        }
+        if let Some(b) = model.only_symbol() {
+            if len > MAX_RUN {
+                return Err(invalid("rANS coded run is too long"));
+            }
+            return Ok(vec![b; len as usize]);
+        }
+        if !model.fits(len, reader.rest().len()) {
+            return Err(invalid("rANS coded data is truncated"));
+        }
+        let symbols = model.symbol_table();
+        let mut x = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+        if !(L..L << 8).contains(&x) {
+            return Err(invalid("bad rANS state"));
+        }
+        let mut out = Vec::new();
+        for _ in 0..len {
+            let slot = x & (TOTAL - 1);
+            let b = symbols[slot as usize];
+            x = model.freq(b) * (x >> PROB_BITS) + slot - model.start(b);
+            while x < L {
+                x = x << 8 | reader.byte()? as u32;
+            }
+            out.push(b);
+        }
+        if x != L || !reader.is_empty() {
+            return Err(invalid("rANS coded data is corrupt"));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::Arithmetic;
+
+    #[test]
+    fn round_trips_close_to_arithmetic_coding() {
+        let mut state = 11u32;
+        let skewed: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((state >> 16) as u16).trailing_zeros() as u8
+            })
+            .collect();
+        for data in [&b""[..], b"x", &[7; 5000], b"abracadabra", &skewed, &(0..=255).collect::<Vec<u8>>()] {
+            let compressed = Rans.compress(data);
+            assert_eq!(Rans.decompress(&compressed).unwrap(), data);
+            assert!(compressed.len() <= Arithmetic.compress(data).len() + 8, "{} bytes", data.len());
+        }
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let text = b"some bytes to code, some bytes to code".repeat(10);
+        let compressed = Rans.compress(&text);
+        let mut flipped = compressed.clone();
+        *flipped.last_mut().unwrap() ^= 0x10;
+        assert!(Rans.decompress(&flipped).is_err());
+        assert!(Rans.decompress(&compressed[..compressed.len() - 1]).is_err());
+        assert!(Rans.decompress(&[&compressed[..], &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn codes_long_runs_rather_than_trust_their_length() {
+        // As fuzzing found: a bare table and a length of gigabytes
+        let mut header = Vec::new();
+        FrequencyModel::from_data(b"x").write(&mut header);
+        varint::put(&mut header, 1 << 32);
+        assert!(Rans.decompress(&header).is_err());
+        let run = vec![7; MAX_RUN as usize + 1];
+        let compressed = Rans.compress(&run);
+        assert!(compressed.len() < 100, "{} bytes", compressed.len());
+        assert_eq!(Rans.decompress(&compressed).unwrap(), run);
+    }
+}
@@ -0,0 +1,71 @@
+//! Run-length encoding, as in bzip2's first stage: a run of four equal
+//! bytes is followed by a count of up to 251 more, and anything else is
+//! copied through. Short runs cost nothing and long ones shrink about 50
+//! times, which suits input with long runs, or as a stage before a coder
+//! that can't code a symbol in under a bit, like Huffman.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::rle::Rle;
+//!
+//! let data = [&[0u8; 1000][..], b"abc", &[0xff; 300]].concat();
+//! let compressed = Rle.compress(&data);
+//! assert!(compressed.len() < 50);
+//! assert_eq!(Rle.decompress(&compressed).unwrap(), data);
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::io;
+
+// Equal bytes before a count.
+const RUN: usize = 4;
+const MAX_EXTRA: usize = 251;
+
+/// Run-length encoding of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rle;
+
+impl Compressor for Rle {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        let mut pos = 0;
+        while pos < data.len() {
+            let b = data[pos];
+            let len = data[pos..].iter().take(RUN + MAX_EXTRA).take_while(|&&c| c == b).count();
+            if len < RUN {
+                out.extend_from_slice(&data[pos..pos + len]);
+            } else {
+                out.extend_from_slice(&[b; RUN]);
+                out.push((len - RUN) as u8);
+            }
+            pos += len;
+        }
+        out
+    }
+}
+
+impl Decompressor for Rle {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(data.len());
+        let mut input = data.iter();
+        let mut run = 0;
+        while let Some(&b) = input.next() {
+            run = match out.last() {
+                Some(&last) if last == b => run + 1,
+                _ => 1,
+            };
+            out.push(b);
+            if run == RUN {
+                let &extra = input.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "run length is missing"))?;
+                if extra as usize > MAX_EXTRA {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "run is too long"));
+                }
+                out.resize(out.len() + extra as usize, b);
+                run = 0;
+            }
+        }
+        Ok(out)
+    }
+}
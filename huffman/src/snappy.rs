@@ -0,0 +1,209 @@
+//! Snappy's block format, for data to or from Snappy libraries in other
+//! languages. Like [LZ4](crate::lz4), which finds the matches here too,
+//! it trades ratio for speed and keeps everything byte aligned.
+//!
+//! A block is the byte count as a varint, then elements, each led by a tag
+//! byte whose low 2 bits give its kind:
+//!
+//! - 0, literal bytes: their count less one in the tag's high 6 bits, or
+//!   for 60 and more in the 1 to 4 bytes after it, little-endian, the high
+//!   bits then being 59 plus that many bytes.
+//! - 1, a copy of 4 to 11 bytes from up to 2047 back: the length less 4 in
+//!   bits 2 to 4, the offset's high 3 bits in bits 5 to 7 and its low 8 in
+//!   the next byte.
+//! - 2 and 3, a copy of 1 to 64 bytes, its length less one in the high 6
+//!   bits, and the offset in the next 2 or 4 bytes, little-endian.
+//!
+//! This is the raw format of Snappy's `compress` and `uncompress`, not its
+//! framing format for streams.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::snappy::Snappy;
+//!
+//! assert_eq!(Snappy.compress(b"hello"), [5, 4 << 2, b'h', b'e', b'l', b'l', b'o']);
+//!
+//! let log = "GET /index.html 200\nGET /style.css 200\nGET /index.html 304\n".repeat(100);
+//! let compressed = Snappy.compress(log.as_bytes());
+//! assert!(compressed.len() < log.len() / 10);
+//! assert_eq!(Snappy.decompress(&compressed).unwrap(), log.as_bytes());
+//! ```
+
+use std::io;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::lz4;
+use crate::varint::{self, Reader};
+
+const LITERAL: u8 = 0;
+const COPY_1: u8 = 1;
+const COPY_2: u8 = 2;
+// And 3 for copies with 4-byte offsets, which nothing needs to write
+const MAX_COPY: usize = 64;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Compression in Snappy's block format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Snappy;
+
+fn put_literal(out: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return;
+    }
+    let n = literal.len() - 1;
+    if n < 60 {
+        out.push((n as u8) << 2 | LITERAL);
+    } else {
+        let bytes = (n.ilog2() / 8 + 1) as usize;
+        out.push((59 + bytes as u8) << 2 | LITERAL);
+        out.extend_from_slice(&(n as u32).to_le_bytes()[..bytes]);
+    }
+    out.extend_from_slice(literal);
+}
+
+// As Snappy's own encoder does: copies of 64 while at least 4 would be
+// left, which the shortest copies need
+fn put_copy(out: &mut Vec<u8>, offset: usize, mut len: usize) {
+    while len >= MAX_COPY + 4 {
+        put_copy_2(out, offset, MAX_COPY);
+        len -= MAX_COPY;
+    }
+    if len > MAX_COPY {
+        put_copy_2(out, offset, MAX_COPY - 4);
+        len -= MAX_COPY - 4;
+    }
+    if len < 12 && offset < 2048 {
+        out.push(((offset >> 8) as u8) << 5 | ((len - 4) as u8) << 2 | COPY_1);
+        out.push(offset as u8);
+    } else {
+        put_copy_2(out, offset, len);
+    }
+}
+
+fn put_copy_2(out: &mut Vec<u8>, offset: usize, len: usize) {
+    out.push(((len - 1) as u8) << 2 | COPY_2);
+    out.extend_from_slice(&(offset as u16).to_le_bytes());
+}
+
+fn read_le(input: &mut Reader, bytes: usize) -> io::Result<usize> {
+    let mut le = [0u8; 4];
+    le[..bytes].copy_from_slice(input.take(bytes as u64)?);
+    Ok(u32::from_le_bytes(le) as usize)
+}
+
+impl Compressor for Snappy {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() / 2 + 16);
+        varint::put(&mut out, data.len() as u64);
+        let mut anchor = 0;
+        lz4::find_matches(data, |start, offset, len| {
+            put_literal(&mut out, &data[anchor..start]);
+            put_copy(&mut out, offset, len);
+            anchor = start + len;
+        });
+        put_literal(&mut out, &data[anchor..]);
+        out
+    }
+}
+
+impl Decompressor for Snappy {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut input = Reader::new(data);
+        let len = input.varint()?;
+        // Snappy's lengths are 32-bit, and the longest copy is 64 bytes
+        // from 3, so no block grows by more than 22 times
+        if len > u32::MAX as u64 || len > (input.rest().len() as u64).saturating_mul(22) {
+            return Err(invalid("bad Snappy length"));
+        }
+        let len = len as usize;
+        let mut out = Vec::with_capacity(len);
+        while !input.is_empty() {
+            let tag = input.byte()?;
+            let (offset, copy_len) = match tag & 3 {
+                LITERAL => {
+                    let n = match tag >> 2 {
+                        n @ 0..=59 => n as usize,
+                        bytes => read_le(&mut input, bytes as usize - 59)?,
+                    };
+                    if n >= len - out.len() {
+                        return Err(invalid("Snappy data is longer than its length"));
+                    }
+                    out.extend_from_slice(input.take(n as u64 + 1)?);
+                    continue;
+                }
+                COPY_1 => ((tag as usize >> 5) << 8 | input.byte()? as usize, (tag as usize >> 2 & 7) + 4),
+                COPY_2 => (read_le(&mut input, 2)?, (tag as usize >> 2) + 1),
+                _ => (read_le(&mut input, 4)?, (tag as usize >> 2) + 1),
+            };
+            if offset == 0 || offset > out.len() {
+                return Err(invalid("bad Snappy copy offset"));
+            }
+            if copy_len > len - out.len() {
+                return Err(invalid("Snappy data is longer than its length"));
+            }
+            let start = out.len() - offset;
+            if offset >= copy_len {
+                out.extend_from_within(start..start + copy_len);
+            } else {
+                // Byte by byte: the copy overlaps what it produces
+                for i in 0..copy_len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+        if out.len() != len {
+            return Err(invalid("Snappy data is shorter than its length"));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let mut log = Vec::new();
+        for i in 0..3000u32 {
+            log.extend_from_slice(format!("GET /item/{} HTTP/1.1 {}\n", i % 41, i * 7 % 1000).as_bytes());
+        }
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let runs = [&[0u8; 70_000][..], b"abc", &[1; 300]].concat();
+        for data in [&b""[..], b"a", b"0123456789abc", b"aaaaaaaaaaaaaaaaaaaaaaa", &log, &noise, &runs] {
+            assert_eq!(Snappy.decompress(&Snappy.compress(data)).unwrap(), data);
+        }
+        assert!(Snappy.compress(&log).len() < log.len() / 3);
+    }
+
+    #[test]
+    fn reads_every_element() {
+        // A 2-byte literal, copies of 1 and 2 bytes from 1 and 3 back with
+        // 2 and 4 byte offsets, and one of 5 with a 1 byte offset, all
+        // overlapping what they produce but the second
+        let mut data = vec![10, 1 << 2, b'a', b'b'];
+        data.extend_from_slice(&[COPY_2, 1, 0, 1 << 2 | 3, 3, 0, 0, 0, 1 << 2 | COPY_1, 3]);
+        assert_eq!(Snappy.decompress(&data).unwrap(), b"abbabbabba");
+        // A literal of 61 bytes, its length in one more byte
+        let literal = [&[61, 60 << 2, 60][..], &[b'x'; 61]].concat();
+        assert_eq!(Snappy.decompress(&literal).unwrap(), [b'x'; 61]);
+    }
+
+    #[test]
+    fn rejects_bad_blocks() {
+        assert!(Snappy.decompress(&[4, 3 << 2, b'a', b'b', b'c']).is_err(), "truncated literal");
+        assert!(Snappy.decompress(&[8, 0, b'a', 1 << 2 | COPY_1, 2]).is_err(), "offset before the start");
+        assert!(Snappy.decompress(&[2, 0, b'a', 1 << 2 | COPY_1, 1]).is_err(), "longer than the length");
+        assert!(Snappy.decompress(&[5, 0, b'a']).is_err(), "shorter than the length");
+        assert!(Snappy.decompress(&[]).is_err());
+    }
+}
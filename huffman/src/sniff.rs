@@ -0,0 +1,194 @@
+//! Content sniffing: guesses whether a block is text, arbitrary binary, or
+//! data that has already been compressed, so callers can decide how (or
+//! whether) to preprocess it before entropy coding.
+//!
+//! ```
+//! use huffman::{sniff, ContentKind};
+//!
+//! assert_eq!(sniff(b"plain old text\n"), ContentKind::Text);
+//! assert_eq!(sniff(b"\x1f\x8b\x08\x00rest of a gzip member"), ContentKind::Compressed);
+//! ```
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Text,
+    Binary,
+    Compressed,
+}
+
+// Signatures of common compressed/encoded formats, as (offset, magic).
+const MAGIC: &[(usize, &[u8])] = &[
+    (0, b"\x1f\x8b"),           // gzip
+    (0, b"PK\x03\x04"),         // zip, jar, docx
+    (0, b"BZh"),                // bzip2
+    (0, b"\xfd7zXZ\x00"),       // xz
+    (0, b"\x28\xb5\x2f\xfd"),   // zstd
+    (0, b"7z\xbc\xaf\x27\x1c"), // 7z
+    (0, b"\x89PNG\r\n\x1a\n"),  // png
+    (0, b"\xff\xd8\xff"),       // jpeg
+    (0, b"GIF8"),               // gif
+    (0, b"OggS"),               // ogg
+    (0, b"fLaC"),               // flac
+    (0, b"ID3"),                // mp3
+    (4, b"ftyp"),               // mp4, mov
+    (0, b"\x04\x22\x4d\x18"),   // lz4 frame
+];
+
+/// How much of the input [`sniff`] looks at.
+pub const SNIFF_BYTES: usize = 64 * 1024;
+
+// Above this many bits per byte a block is treated as incompressible.
+const COMPRESSED_ENTROPY: f64 = 7.5;
+
+// Fraction of bytes that may be non-printable before text becomes binary.
+const MAX_CONTROL_RATIO: f64 = 0.05;
+
+/// True if the block starts with the signature of a known compressed format.
+pub fn has_known_magic(block: &[u8]) -> bool {
+    MAGIC.iter().any(|&(offset, magic)| {
+        block.len() >= offset + magic.len() && &block[offset..offset + magic.len()] == magic
+    })
+}
+
+/// Shannon entropy of the byte distribution, in bits per byte (0.0 to 8.0).
+pub fn entropy(block: &[u8]) -> f64 {
+    if block.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &b in block {
+        counts[b as usize] += 1;
+    }
+    let len = block.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn looks_like_text(block: &[u8]) -> bool {
+    // A truncated multi-byte sequence at the end of a block is still text.
+    let valid = match std::str::from_utf8(block) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if !valid {
+        return false;
+    }
+    let control = block
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c))
+        .count();
+    (control as f64) <= MAX_CONTROL_RATIO * block.len() as f64
+}
+
+/// Classifies a block using magic bytes first, then text and entropy heuristics.
+pub fn classify(block: &[u8]) -> ContentKind {
+    if has_known_magic(block) {
+        return ContentKind::Compressed;
+    }
+    if looks_like_text(block) {
+        return ContentKind::Text;
+    }
+    // Short blocks don't have enough samples for a meaningful entropy estimate.
+    if block.len() >= 256 && entropy(block) > COMPRESSED_ENTROPY {
+        return ContentKind::Compressed;
+    }
+    ContentKind::Binary
+}
+
+/// Classifies a whole input from its first [`SNIFF_BYTES`] bytes, which is
+/// enough for the heuristics and keeps the cost flat for large files.
+pub fn sniff(data: &[u8]) -> ContentKind {
+    classify(&data[..data.len().min(SNIFF_BYTES)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 1u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn knows_each_signature() {
+        for &(offset, magic) in MAGIC {
+            let mut block = vec![b'a'; offset];
+            block.extend_from_slice(magic);
+            block.extend_from_slice(b"rest of the file");
+            assert_eq!(classify(&block), ContentKind::Compressed, "{:?}", magic);
+            // Cut short, the signature is no longer there
+            assert!(!has_known_magic(&block[..offset + magic.len() - 1]), "{:?}", magic);
+        }
+        // Only at its own offset
+        assert_eq!(classify(b"xxPK\x03\x04 and then text"), ContentKind::Binary);
+        assert_eq!(classify(b"isoftyp"), ContentKind::Text);
+    }
+
+    #[test]
+    fn tells_text() {
+        assert_eq!(classify(b"plain old text\n"), ContentKind::Text);
+        assert_eq!(classify("naïve café, 東京\tand tabs\r\n".as_bytes()), ContentKind::Text);
+        // A multi-byte character cut off by the end of the block
+        assert_eq!(classify(&"東京".as_bytes()[..5]), ContentKind::Text);
+        // A few control characters are allowed, as in text with form feeds
+        // or escape sequences
+        let mut text = b"a line of text\n".repeat(10);
+        text.push(0x1b);
+        assert_eq!(classify(&text), ContentKind::Text);
+    }
+
+    #[test]
+    fn tells_binary() {
+        // Not UTF-8
+        assert_eq!(classify(b"caf\xe9 au lait"), ContentKind::Binary);
+        // UTF-8, but too many control bytes
+        assert_eq!(classify(&[0, 1, 2, 3, b'a', b'b', 0, 0]), ContentKind::Binary);
+        // Low entropy, however long
+        let program: Vec<u8> = (0..10_000u32).map(|i| [0x00, 0x48, 0x89, 0xe5, 0xc3, 0xff][i as usize % 6]).collect();
+        assert_eq!(classify(&program), ContentKind::Binary);
+    }
+
+    #[test]
+    fn tells_compressed_from_entropy() {
+        assert_eq!(classify(&noise(4096)), ContentKind::Compressed);
+        // Too short to judge by entropy
+        assert_eq!(classify(&noise(255)), ContentKind::Binary);
+    }
+
+    #[test]
+    fn handles_empty_and_tiny_blocks() {
+        assert_eq!(classify(b""), ContentKind::Text);
+        assert_eq!(entropy(b""), 0.0);
+        assert_eq!(classify(b"\x1f"), ContentKind::Binary);
+        assert_eq!(classify(b"PK"), ContentKind::Text);
+        assert_eq!(classify(b"\xff"), ContentKind::Binary);
+    }
+
+    #[test]
+    fn measures_entropy() {
+        assert_eq!(entropy(&[7; 100]), 0.0);
+        assert_eq!(entropy(b"abab"), 1.0);
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy(&all), 8.0);
+    }
+
+    #[test]
+    fn sniffs_only_the_start() {
+        let mut data = b"text ".repeat(SNIFF_BYTES / 5 + 1);
+        data.extend_from_slice(&noise(1 << 20));
+        assert_eq!(sniff(&data), ContentKind::Text);
+        assert_eq!(classify(&data), ContentKind::Compressed);
+    }
+}
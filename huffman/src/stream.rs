@@ -0,0 +1,193 @@
+//! Huffman compression of data as it flows through, behind `std::io::Write`
+//! and `std::io::Read`.
+//!
+//! A Huffman code needs symbol frequencies before it can code anything, so
+//! the stream is cut into blocks of at most [`BLOCK_SIZE`] bytes, each coded
+//! on its own by [`codec::Huffman`](crate::codec::Huffman). A block is its
+//! coded length as a varint followed by the coded bytes; a zero length ends
+//! the stream, which tells a clean end from a truncated one. Neither side
+//! holds more than a block at a time.
+//!
+//! ```
+//! use std::io::{Read, Write};
+//! use huffman::stream::{HuffmanDecoder, HuffmanEncoder};
+//!
+//! let mut encoder = HuffmanEncoder::new(Vec::new());
+//! encoder.write_all(b"to be or not to be, ").unwrap();
+//! encoder.write_all(b"that is the question").unwrap();
+//! let compressed = encoder.finish().unwrap();
+//!
+//! let mut text = String::new();
+//! HuffmanDecoder::new(&compressed[..]).read_to_string(&mut text).unwrap();
+//! assert_eq!(text, "to be or not to be, that is the question");
+//! ```
+
+use std::io::{self, Read, Write};
+
+use crate::codec::{Compressor, Decompressor, Huffman};
+use crate::varint;
+
+/// The most input coded as one block.
+pub const BLOCK_SIZE: usize = 128 * 1024;
+
+// A Huffman code never does worse than the plain 8-bit code, so a block's
+// coded bytes are at most its input plus a padding byte. The table has up
+// to 256 one-byte symbols with their lengths, after its u32 count, and the
+// byte count is a u64.
+const MAX_CODED_BLOCK: u64 = 4 + 256 * 2 + 8 + BLOCK_SIZE as u64 + 1;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// The end of the input anywhere before the end marker means it was cut off.
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(io::ErrorKind::UnexpectedEof, "stream is truncated"),
+        _ => e,
+    })
+}
+
+/// Compresses what is written to it into `W`. Call [`finish`] at the end;
+/// dropping the encoder finishes it too, but ignores errors.
+///
+/// [`finish`]: HuffmanEncoder::finish
+pub struct HuffmanEncoder<W: Write> {
+    // None once finished.
+    inner: Option<W>,
+    block: Vec<u8>,
+}
+
+impl<W: Write> HuffmanEncoder<W> {
+    pub fn new(inner: W) -> HuffmanEncoder<W> {
+        HuffmanEncoder {
+            inner: Some(inner),
+            block: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let coded = Huffman::new().compress(&self.block);
+        let mut frame = Vec::with_capacity(coded.len() + 10);
+        varint::put_bytes(&mut frame, &coded);
+        self.inner.as_mut().unwrap().write_all(&frame)?;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Codes what is left, ends the stream and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        let inner = self.inner.as_mut().unwrap();
+        inner.write_all(&[0])?;
+        inner.flush()
+    }
+}
+
+impl<W: Write> Write for HuffmanEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..n]);
+        if self.block.len() == BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    /// Codes the data written so far as a block of its own, so a reader
+    /// can decode it, and flushes the writer. Frequent flushes make for
+    /// small blocks, each with its own table.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for HuffmanEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// Decompresses the stream read from `R`.
+pub struct HuffmanDecoder<R: Read> {
+    inner: R,
+    block: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> HuffmanDecoder<R> {
+    pub fn new(inner: R) -> HuffmanDecoder<R> {
+        HuffmanDecoder {
+            inner,
+            block: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_varint(&mut self) -> io::Result<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let mut b = [0u8];
+            read_exact(&mut self.inner, &mut b)?;
+            v |= ((b[0] & 0x7f) as u64) << shift;
+            if b[0] & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(invalid("varint is too long"))
+    }
+
+    // Reads blocks until one has data or the stream ends.
+    fn next_block(&mut self) -> io::Result<()> {
+        while self.pos == self.block.len() && !self.done {
+            let len = self.read_varint()?;
+            if len == 0 {
+                self.done = true;
+                break;
+            }
+            if len > MAX_CODED_BLOCK {
+                return Err(invalid("block is too long"));
+            }
+            let mut coded = vec![0u8; len as usize];
+            read_exact(&mut self.inner, &mut coded)?;
+            let block = Huffman::new().decompress(&coded)?;
+            if block.len() > BLOCK_SIZE {
+                return Err(invalid("block is too long"));
+            }
+            self.block = block;
+            self.pos = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for HuffmanDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.next_block()?;
+        let n = buf.len().min(self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
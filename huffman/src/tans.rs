@@ -0,0 +1,213 @@
+//! Tabled asymmetric numeral systems (tANS), the coder behind zstd's FSE:
+//! [rANS](crate::rans) with its arithmetic worked out in advance into
+//! tables, so each symbol costs a lookup and a few bits read, as with a
+//! Huffman decoding table, while still coding in fractions of a bit.
+//!
+//! The tables come from the same [frequency model](crate::model) as the
+//! other coders, with one decoding state per unit of frequency: each byte
+//! is spread over the table as many times as its frequency, at positions
+//! a fixed odd step apart so that the bytes interleave evenly. A state
+//! names the byte it decodes to, how many bits to read next and the state
+//! those bits are added to.
+//!
+//! As with rANS, the encoder works backwards; its bits are written in
+//! reverse, its final state first, and the decoder must end in the state
+//! the encoder started from. The output is the frequency model, the byte
+//! count as a varint and the bits, least significant first.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor, Huffman};
+//! use huffman::tans::Tans;
+//!
+//! let data = [&[b'.'; 10_000][..], b"a few other bytes"].concat();
+//! let compressed = Tans.compress(&data);
+//! assert!(compressed.len() < Huffman::new().compress(&data).len() / 5);
+//! assert_eq!(Tans.decompress(&compressed).unwrap(), data);
+//! ```
+
+use std::io;
+
+use crate::bitio::{BitReader, BitWriter};
+use crate::codec::{Compressor, Decompressor, EntropyCoder};
+use crate::model::{FrequencyModel, MAX_RUN, PROB_BITS, TOTAL};
+use crate::varint::{self, Reader};
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// tANS coding of bytes with a static frequency model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tans;
+
+impl EntropyCoder for Tans {}
+
+// The byte each state decodes to. Stepping by about 5/8 of the table
+// visits every slot, since the step is odd and the size a power of two
+fn spread(model: &FrequencyModel) -> Vec<u8> {
+    let step = (TOTAL >> 1) + (TOTAL >> 3) + 3;
+    let mut table = vec![0u8; TOTAL as usize];
+    let mut pos = 0;
+    for b in 0..=255u8 {
+        for _ in 0..model.freq(b) {
+            table[pos as usize] = b;
+            pos = (pos + step) & (TOTAL - 1);
+        }
+    }
+    table
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DecodeEntry {
+    byte: u8,
+    bits: u8,
+    base: u16,
+}
+
+fn decoding_table(model: &FrequencyModel) -> Vec<DecodeEntry> {
+    let mut next: Vec<u32> = (0..=255).map(|b| model.freq(b)).collect();
+    spread(model)
+        .into_iter()
+        .map(|byte| {
+            // Between the byte's frequency and twice that
+            let x = next[byte as usize];
+            next[byte as usize] += 1;
+            let bits = PROB_BITS - x.ilog2();
+            DecodeEntry {
+                byte,
+                bits: bits as u8,
+                base: ((x << bits) - TOTAL) as u16,
+            }
+        })
+        .collect()
+}
+
+// For each byte, from its start in the model, the states that decode to
+// it, in the order the decoding table numbers them
+fn encoding_table(model: &FrequencyModel) -> Vec<u16> {
+    let mut next: Vec<u32> = (0..=255).map(|b| model.start(b)).collect();
+    let mut table = vec![0u16; TOTAL as usize];
+    for (state, b) in spread(model).into_iter().enumerate() {
+        table[next[b as usize] as usize] = state as u16;
+        next[b as usize] += 1;
+    }
+    table
+}
+
+impl Compressor for Tans {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let model = FrequencyModel::from_data(data);
+        let mut out = Vec::new();
+        model.write(&mut out);
+        varint::put(&mut out, data.len() as u64);
+        if data.is_empty() {
+            return out;
+        }
+        let table = encoding_table(&model);
+        // The state plus TOTAL, in [TOTAL, 2 TOTAL)
+        let mut x = TOTAL;
+        let mut chunks = Vec::with_capacity(data.len());
+        for &b in data.iter().rev() {
+            let freq = model.freq(b);
+            // Shift x down into [freq, 2 freq)
+            let mut bits = PROB_BITS - freq.ilog2();
+            if x >> bits < freq {
+                bits -= 1;
+            }
+            chunks.push((x & ((1 << bits) - 1), bits));
+            x = TOTAL + table[(model.start(b) + (x >> bits) - freq) as usize] as u32;
+        }
+        let mut writer = BitWriter::new();
+        writer.write_bits((x - TOTAL) as u64, PROB_BITS);
+        for &(value, bits) in chunks.iter().rev() {
+            writer.write_bits(value as u64, bits);
+        }
+        out.extend_from_slice(&writer.finish());
+        out
+    }
+}
+
+impl Decompressor for Tans {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = Reader::new(data);
+        let model = FrequencyModel::read(&mut reader)?;
+        let len = reader.varint()?;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        if model.is_empty() {
+            return Err(invalid("tANS coded data has no frequency table"));
+        }
+        if let Some(b) = model.only_symbol() {
+            if len > MAX_RUN {
+                return Err(invalid("tANS coded run is too long"));
+            }
+            return Ok(vec![b; len as usize]);
+        }
+        if !model.fits(len, reader.rest().len()) {
+            return Err(invalid("tANS coded data is truncated"));
+        }
+        let table = decoding_table(&model);
+        let mut bits = BitReader::new(reader.rest());
+        let mut state = bits.read_bits(PROB_BITS)? as usize;
+        let mut out = Vec::new();
+        for _ in 0..len {
+            let entry = table[state];
+            out.push(entry.byte);
+            state = entry.base as usize + bits.read_bits(entry.bits as u32)? as usize;
+        }
+        if state != 0 || bits.bits_left() >= 8 {
+            return Err(invalid("tANS coded data is corrupt"));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::Arithmetic;
+
+    #[test]
+    fn round_trips_close_to_arithmetic_coding() {
+        let mut state = 13u32;
+        let skewed: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((state >> 16) as u16).trailing_zeros() as u8
+            })
+            .collect();
+        for data in [&b""[..], b"x", &[7; 5000], b"abracadabra", &skewed, &(0..=255).collect::<Vec<u8>>()] {
+            let compressed = Tans.compress(data);
+            assert_eq!(Tans.decompress(&compressed).unwrap(), data);
+            // Spreading costs a little against exact arithmetic
+            assert!(compressed.len() as f64 <= Arithmetic.compress(data).len() as f64 * 1.01 + 8.0, "{} bytes", data.len());
+        }
+    }
+
+    #[test]
+    fn every_state_is_used_once() {
+        let model = FrequencyModel::from_data(b"abracadabra, abracadabra");
+        let mut states = encoding_table(&model);
+        states.sort_unstable();
+        assert!(states.iter().enumerate().all(|(i, &s)| s as usize == i));
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let text = b"some bytes to code, some bytes to code".repeat(10);
+        let compressed = Tans.compress(&text);
+        assert!(Tans.decompress(&compressed[..compressed.len() - 2]).is_err());
+        assert!(Tans.decompress(&[&compressed[..], &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn codes_long_runs_rather_than_trust_their_length() {
+        // Found by fuzzing: 14 bytes claiming 2.7 GB of one byte
+        assert!(Tans.decompress(&[1, 0x80, 0x80, 0x80, 2, 0xa0, 0x80, 0x80, 0x80, 0x0a, 0x80, 0x80, 0x80, 0x80]).is_err());
+        let run = vec![7; MAX_RUN as usize + 1];
+        let compressed = Tans.compress(&run);
+        assert!(compressed.len() < 100, "{} bytes", compressed.len());
+        assert_eq!(Tans.decompress(&compressed).unwrap(), run);
+    }
+}
@@ -0,0 +1,157 @@
+//! Delta-of-delta transform for integer timestamps, as in Facebook's
+//! Gorilla. Samples taken at a (nearly) fixed interval have a constant
+//! delta, so the second difference is almost always zero or tiny; zigzag
+//! mapping then turns those small signed values into small unsigned ones
+//! that [`encode`] codes in a few bits each, and a zero in one.
+//!
+//! ```
+//! use huffman::timeseries::{delta_of_delta, undo_delta_of_delta};
+//!
+//! let ts = [1_760_000_000, 1_760_000_010, 1_760_000_020, 1_760_000_031];
+//! let residuals = delta_of_delta(&ts);
+//! assert_eq!(&residuals[2..], [0, 2]);
+//! assert_eq!(undo_delta_of_delta(&residuals), ts);
+//! ```
+
+use std::io;
+
+use crate::bitio::{BitReader, BitWriter};
+use crate::universal_codes;
+use crate::varint::{self, Reader};
+
+/// First value, first delta, then the change in delta at every later step,
+/// all zigzag mapped. Arithmetic wraps, so any `i64` series round-trips.
+pub fn delta_of_delta(values: &[i64]) -> Vec<u64> {
+    let mut out = Vec::with_capacity(values.len());
+    let mut prev = 0i64;
+    let mut prev_delta = 0i64;
+    for (i, &v) in values.iter().enumerate() {
+        let delta = v.wrapping_sub(prev);
+        out.push(match i {
+            0 => varint::zigzag(v),
+            1 => varint::zigzag(delta),
+            _ => varint::zigzag(delta.wrapping_sub(prev_delta)),
+        });
+        prev = v;
+        prev_delta = delta;
+    }
+    out
+}
+
+/// Inverse of [`delta_of_delta`].
+pub fn undo_delta_of_delta(residuals: &[u64]) -> Vec<i64> {
+    let mut out = Vec::with_capacity(residuals.len());
+    let mut prev = 0i64;
+    let mut delta = 0i64;
+    for (i, &r) in residuals.iter().enumerate() {
+        let r = varint::unzigzag(r);
+        let v = match i {
+            0 => r,
+            1 => {
+                delta = r;
+                prev.wrapping_add(delta)
+            }
+            _ => {
+                delta = delta.wrapping_add(r);
+                prev.wrapping_add(delta)
+            }
+        };
+        out.push(v);
+        prev = v;
+    }
+    out
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// A residual's bit length in Elias gamma, then the bits below its leading
+// 1: zero, the usual residual, takes one bit, yet the first value (often a
+// whole Unix timestamp) or a jump in the interval still fits.
+fn write_residual(out: &mut BitWriter, r: u64) {
+    let bits = 64 - r.leading_zeros();
+    universal_codes::write_gamma(out, bits as u64 + 1);
+    if bits > 1 {
+        out.write_bits(r, bits - 1);
+    }
+}
+
+fn read_residual(reader: &mut BitReader) -> io::Result<u64> {
+    let bits = universal_codes::read_gamma(reader)? - 1;
+    match bits {
+        0 => Ok(0),
+        1..=64 => Ok(1 << (bits - 1) | reader.read_bits(bits as u32 - 1)?),
+        _ => Err(invalid("series residual is too long")),
+    }
+}
+
+/// Transforms a series and codes the residuals in bits: a varint count,
+/// then each residual as above, so a fixed-interval series costs a bit a
+/// sample after its first two.
+pub fn encode(values: &[i64]) -> Vec<u8> {
+    let mut out = Vec::new();
+    varint::put(&mut out, values.len() as u64);
+    let mut writer = BitWriter::new();
+    for r in delta_of_delta(values) {
+        write_residual(&mut writer, r);
+    }
+    out.extend_from_slice(&writer.finish());
+    out
+}
+
+/// Inverse of [`encode`].
+pub fn decode(data: &[u8]) -> io::Result<Vec<i64>> {
+    let mut input = Reader::new(data);
+    let count = input.varint()?;
+    let mut reader = BitReader::new(input.rest());
+    // Each residual takes at least one bit.
+    if count > reader.bits_left() {
+        return Err(invalid("series length is inconsistent"));
+    }
+    let residuals = (0..count).map(|_| read_residual(&mut reader)).collect::<io::Result<Vec<u64>>>()?;
+    Ok(undo_delta_of_delta(&residuals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let series: [&[i64]; 6] = [
+            &[],
+            &[42],
+            &[1_760_000_000, 1_760_000_010],
+            &[1_760_000_000, 1_760_000_010, 1_760_000_020, 1_760_000_031, 1_760_000_029, 1_760_900_000],
+            &[i64::MIN, i64::MAX, i64::MIN, 0, -1, i64::MAX],
+            &[0, 0, 0, 0, 0],
+        ];
+        for values in series {
+            assert_eq!(decode(&encode(values)).unwrap(), values);
+        }
+    }
+
+    #[test]
+    fn codes_a_fixed_interval_in_a_bit_a_sample() {
+        let values: Vec<i64> = (0..8000).map(|i| 1_760_000_000 + 15 * i).collect();
+        let encoded = encode(&values);
+        // Count, first value and interval, then 7998 one-bit zeros
+        assert!(encoded.len() < 2 + 10 + 1000 + 2, "{} bytes", encoded.len());
+        assert_eq!(decode(&encoded).unwrap(), values);
+    }
+
+    #[test]
+    fn rejects_bad_series() {
+        let encoded = encode(&[1_760_000_000, 1_760_000_010, 1_760_000_020]);
+        assert!(decode(&encoded[..encoded.len() - 2]).is_err());
+        assert!(decode(&[]).is_err());
+        // More residuals than there are bits for
+        assert!(decode(&[0x80, 0x01, 0xff]).is_err());
+        // A bit length over 64
+        let mut writer = BitWriter::new();
+        universal_codes::write_gamma(&mut writer, 66);
+        let bytes = [&[1][..], &writer.finish()].concat();
+        assert!(decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Elias gamma and delta codes: universal codes for positive integers with
+//! no upper bound and no parameter to choose, for bit streams where a
+//! fixed-width field would either waste bits or cap the value, like
+//! lengths in headers and offsets in LZ tokens. Each takes a little over
+//! the value's bit length: small numbers stay short and large ones are
+//! still possible.
+//!
+//! Gamma writes the number of bits after the leading 1 as that many zeros,
+//! then the 1 and those bits: `2 log2(n) + 1` bits. Delta writes the bit
+//! length in gamma instead, then the bits after the leading 1, which is
+//! never shorter below 32 but shorter from there on. Neither codes zero;
+//! code `n + 1` for values that can be zero.
+//!
+//! ```
+//! use huffman::bitio::{BitReader, BitWriter};
+//! use huffman::universal_codes::{self, read_delta, read_gamma, write_delta, write_gamma};
+//!
+//! assert_eq!(universal_codes::gamma_len(1), 1);
+//! assert_eq!(universal_codes::gamma_len(1000), 19);
+//! assert_eq!(universal_codes::delta_len(1000), 16);
+//!
+//! let mut writer = BitWriter::new();
+//! write_gamma(&mut writer, 5);
+//! write_delta(&mut writer, 1_000_000);
+//! let bytes = writer.finish();
+//!
+//! let mut reader = BitReader::new(&bytes);
+//! assert_eq!(read_gamma(&mut reader).unwrap(), 5);
+//! assert_eq!(read_delta(&mut reader).unwrap(), 1_000_000);
+//! ```
+
+use std::io;
+
+use crate::bitio::{BitReader, BitWriter};
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// Bits after the leading 1
+fn tail_bits(n: u64) -> u32 {
+    63 - n.leading_zeros()
+}
+
+/// Writes `n` in the Elias gamma code.
+///
+/// # Panics
+///
+/// If `n` is zero.
+pub fn write_gamma(out: &mut BitWriter, n: u64) {
+    assert!(n > 0, "Elias codes start at 1");
+    let bits = tail_bits(n);
+    out.write_bits(0, bits);
+    out.write_bit(true);
+    out.write_bits(n, bits);
+}
+
+/// Reads a value written by [`write_gamma`].
+pub fn read_gamma(reader: &mut BitReader) -> io::Result<u64> {
+    let mut bits = 0;
+    while !reader.read_bit()? {
+        bits += 1;
+        if bits > 63 {
+            return Err(invalid("Elias gamma code is too long"));
+        }
+    }
+    Ok(1 << bits | reader.read_bits(bits)?)
+}
+
+/// Bits [`write_gamma`] spends on `n`.
+pub fn gamma_len(n: u64) -> u32 {
+    2 * tail_bits(n) + 1
+}
+
+/// Writes `n` in the Elias delta code.
+///
+/// # Panics
+///
+/// If `n` is zero.
+pub fn write_delta(out: &mut BitWriter, n: u64) {
+    assert!(n > 0, "Elias codes start at 1");
+    let bits = tail_bits(n);
+    write_gamma(out, bits as u64 + 1);
+    out.write_bits(n, bits);
+}
+
+/// Reads a value written by [`write_delta`].
+pub fn read_delta(reader: &mut BitReader) -> io::Result<u64> {
+    let bits = read_gamma(reader)? - 1;
+    if bits > 63 {
+        return Err(invalid("Elias delta code is too long"));
+    }
+    Ok(1 << bits | reader.read_bits(bits as u32)?)
+}
+
+/// Bits [`write_delta`] spends on `n`.
+pub fn delta_len(n: u64) -> u32 {
+    let bits = tail_bits(n);
+    gamma_len(bits as u64 + 1) + bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let values: Vec<u64> = (1..=300).chain((6..64).map(|i| (1 << i) - 1)).chain((6..64).map(|i| 1 << i)).chain([u64::MAX]).collect();
+        let mut writer = BitWriter::new();
+        for &n in &values {
+            write_gamma(&mut writer, n);
+            write_delta(&mut writer, n);
+        }
+        assert_eq!(writer.bit_len(), values.iter().map(|&n| (gamma_len(n) + delta_len(n)) as u64).sum::<u64>());
+        let bytes = writer.finish();
+        let mut reader = BitReader::new(&bytes);
+        for &n in &values {
+            assert_eq!(read_gamma(&mut reader).unwrap(), n);
+            assert_eq!(read_delta(&mut reader).unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_codes() {
+        // 64 zeros
+        assert!(read_gamma(&mut BitReader::new(&[0; 9])).is_err());
+        // A bit length of 65
+        let mut writer = BitWriter::new();
+        write_gamma(&mut writer, 66);
+        writer.write_bits(0, 64);
+        writer.write_bits(0, 1);
+        assert!(read_delta(&mut BitReader::new(&writer.finish())).is_err());
+        assert!(read_gamma(&mut BitReader::new(&[0b1000_0000])).is_err(), "truncated");
+    }
+}
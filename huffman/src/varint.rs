@@ -0,0 +1,96 @@
+//! LEB128 variable-length integers, the encoding protobuf uses for its
+//! varints: 7 bits per byte, least significant group first, high bit set on
+//! every byte but the last.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::io;
+
+/// Appends `v` as a varint.
+pub fn put(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+/// Appends a varint length followed by the bytes themselves.
+pub fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Maps signed integers to unsigned so small magnitudes stay small:
+/// 0, -1, 1, -2, ... become 0, 1, 2, 3, ...
+pub fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+pub fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+/// Number of bytes `v` takes as a varint.
+pub fn len(v: u64) -> usize {
+    (64 - (v | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Cursor over a byte slice for reading back what the `put` functions wrote.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The bytes not consumed yet.
+    pub fn rest(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn byte(&mut self) -> io::Result<u8> {
+        let (&b, rest) = self.data.split_first().ok_or_else(|| invalid("data is truncated"))?;
+        self.data = rest;
+        Ok(b)
+    }
+
+    pub fn varint(&mut self) -> io::Result<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            v |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(invalid("varint is too long"))
+    }
+
+    pub fn take(&mut self, len: u64) -> io::Result<&'a [u8]> {
+        if len > self.data.len() as u64 {
+            return Err(invalid("data is truncated"));
+        }
+        let (head, rest) = self.data.split_at(len as usize);
+        self.data = rest;
+        Ok(head)
+    }
+
+    /// Reads a length-prefixed byte string written by [`put_bytes`].
+    pub fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.varint()?;
+        self.take(len)
+    }
+}
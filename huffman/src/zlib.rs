@@ -0,0 +1,162 @@
+//! The zlib format (RFC 1950): a [DEFLATE](crate::deflate) stream behind a
+//! two-byte header and followed by the Adler-32 checksum of the original
+//! data. It is what PNG, HTTP's `deflate` encoding and many network
+//! protocols carry.
+//!
+//! Output declares the 32 KiB window DEFLATE here uses. Streams that need
+//! a preset dictionary name it by its Adler-32 checksum; [`Zlib`] refuses
+//! them, and [`Zlib::with_dictionary`] reads and writes them.
+//!
+//! ```
+//! use huffman::codec::{Compressor, Decompressor};
+//! use huffman::zlib::{self, Zlib};
+//!
+//! let compressed = Zlib.compress(b"hello, zlib");
+//! assert_eq!(compressed[..2], [0x78, 0x9c]);
+//! assert_eq!(Zlib.decompress(&compressed).unwrap(), b"hello, zlib");
+//! assert_eq!(zlib::adler32(b"Wikipedia"), 0x11e6_0398);
+//!
+//! let dictionary = b"{\"user\": \"\", \"action\": \"login\", \"status\": \"ok\"}";
+//! let record = br#"{"user": "ada", "action": "login", "status": "ok"}"#;
+//! let preset = Zlib::with_dictionary(dictionary);
+//! let compressed = preset.compress(record);
+//! assert!(compressed.len() < Zlib.compress(record).len() / 2);
+//! assert_eq!(preset.decompress(&compressed).unwrap(), record);
+//! assert!(Zlib.decompress(&compressed).is_err());
+//! ```
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::codec::{Compressor, Decompressor};
+use crate::deflate::{self, Deflate, DeflateWithDictionary};
+use crate::io;
+
+const METHOD_DEFLATE: u8 = 8;
+// log2 of the window size, less 8.
+const MAX_WINDOW_INFO: u8 = 7;
+const FLAG_DICTIONARY: u8 = 1 << 5;
+// The compression level field: the default level.
+const LEVEL_DEFAULT: u8 = 2 << 6;
+
+const ADLER_MODULUS: u32 = 65521;
+// Bytes that can be summed before the sums could overflow a u32.
+const ADLER_BLOCK: usize = 5552;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// The Adler-32 checksum of `data`.
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for block in data.chunks(ADLER_BLOCK) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= ADLER_MODULUS;
+        b %= ADLER_MODULUS;
+    }
+    b << 16 | a
+}
+
+/// Whether `header` is a valid zlib header: DEFLATE, a window of at most
+/// 32 KiB, and a check value that works out.
+pub fn is_header(header: &[u8]) -> bool {
+    match header {
+        [cmf, flg, ..] => {
+            cmf & 0x0f == METHOD_DEFLATE && cmf >> 4 <= MAX_WINDOW_INFO && (*cmf as u16 * 256 + *flg as u16).is_multiple_of(31)
+        }
+        _ => false,
+    }
+}
+
+/// zlib streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Zlib;
+
+impl Zlib {
+    /// zlib with `dictionary` as a preset dictionary.
+    pub fn with_dictionary(dictionary: &[u8]) -> ZlibWithDictionary {
+        ZlibWithDictionary {
+            deflate: Deflate::with_dictionary(dictionary),
+            id: adler32(dictionary),
+        }
+    }
+}
+
+/// zlib streams with a preset dictionary, as
+/// [`DeflateWithDictionary`] describes. Streams without one decode too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZlibWithDictionary {
+    deflate: DeflateWithDictionary,
+    id: u32,
+}
+
+fn header(flags: u8) -> [u8; 2] {
+    let cmf = MAX_WINDOW_INFO << 4 | METHOD_DEFLATE;
+    let flg = LEVEL_DEFAULT | flags;
+    [cmf, flg | (31 - (cmf as u16 * 256 + flg as u16) % 31) as u8]
+}
+
+// Checks the trailer after the DEFLATE stream, `len` bytes long
+fn finish(decompressed: Vec<u8>, len: usize, data: &[u8]) -> io::Result<Vec<u8>> {
+    match &data[len..] {
+        [a, b, c, d] if u32::from_be_bytes([*a, *b, *c, *d]) == adler32(&decompressed) => Ok(decompressed),
+        [_, _, _, _] => Err(invalid("zlib checksum mismatch, the data is corrupt")),
+        _ => Err(invalid("zlib stream has a bad trailer")),
+    }
+}
+
+impl Compressor for Zlib {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = header(0).to_vec();
+        out.extend_from_slice(&Deflate.compress(data));
+        out.extend_from_slice(&adler32(data).to_be_bytes());
+        out
+    }
+}
+
+impl Compressor for ZlibWithDictionary {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = header(FLAG_DICTIONARY).to_vec();
+        out.extend_from_slice(&self.id.to_be_bytes());
+        out.extend_from_slice(&self.deflate.compress(data));
+        out.extend_from_slice(&adler32(data).to_be_bytes());
+        out
+    }
+}
+
+impl Decompressor for Zlib {
+    /// Fails on anything after the checksum, too.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        if !is_header(data) {
+            return Err(invalid("not a zlib stream"));
+        }
+        if data[1] & FLAG_DICTIONARY != 0 {
+            return Err(invalid("zlib stream needs a preset dictionary"));
+        }
+        let (decompressed, len) = deflate::inflate(&data[2..])?;
+        finish(decompressed, 2 + len, data)
+    }
+}
+
+impl Decompressor for ZlibWithDictionary {
+    /// Fails if the stream needs another dictionary.
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        if !is_header(data) {
+            return Err(invalid("not a zlib stream"));
+        }
+        if data[1] & FLAG_DICTIONARY == 0 {
+            return Zlib.decompress(data);
+        }
+        match data.get(2..6) {
+            Some(id) if id == self.id.to_be_bytes() => {}
+            Some(_) => return Err(invalid("zlib stream needs a different preset dictionary")),
+            None => return Err(invalid("zlib stream is truncated")),
+        }
+        let (decompressed, len) = self.deflate.inflate(&data[6..])?;
+        finish(decompressed, 6 + len, data)
+    }
+}
@@ -0,0 +1,124 @@
+// Every codec, on generated inputs: whatever goes in must come back out.
+// proptest draws the inputs, shrinks a failing one to a small case and
+// saves it under proptest-regressions/ to be tried first from then on.
+//
+// PROPTEST_CASES=<n> runs more cases. New codecs go in the list at the
+// bottom to be checked too.
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use huffman::adaptive::AdaptiveHuffman;
+use huffman::arithmetic::Arithmetic;
+use huffman::blocksort::BlockSort;
+use huffman::bwt::Bwt;
+use huffman::codec::{Compressor, Decompressor, Huffman, Pipeline, ShannonFano};
+use huffman::deflate::Deflate;
+use huffman::deltafilter::DeltaFilter;
+use huffman::gzip::Gzip;
+use huffman::lz4::Lz4;
+use huffman::lz77::Lz77;
+use huffman::lz78::Lz78;
+use huffman::lzss::Lzss;
+use huffman::mtf::Mtf;
+use huffman::ppm::Ppm;
+use huffman::rangecoder::RangeCoder;
+use huffman::rans::Rans;
+use huffman::rle::Rle;
+use huffman::snappy::Snappy;
+use huffman::tans::Tans;
+use huffman::zlib::Zlib;
+
+const MAX_LEN: usize = 4096;
+
+// Any bytes at all, mostly noise to a codec
+fn arbitrary() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 0..=MAX_LEN)
+}
+
+// A lone symbol, which leaves the codes nothing to tell apart
+fn single_symbol() -> impl Strategy<Value = Vec<u8>> {
+    (any::<u8>(), 1..=MAX_LEN).prop_map(|(byte, len)| vec![byte; len])
+}
+
+// Every byte value, in any order and any number of times over
+fn all_values() -> impl Strategy<Value = Vec<u8>> {
+    let all: Vec<u8> = (0..=255).collect();
+    (Just(all).prop_shuffle(), 1..=16usize).prop_map(|(all, times)| all.repeat(times))
+}
+
+// Runs and literals from a few symbols, which is what the matchers and
+// context models have something to find in
+fn repetitive() -> impl Strategy<Value = Vec<u8>> {
+    let piece = (vec(any::<u8>(), 1..=8), 1..=300usize, any::<bool>());
+    vec(piece, 1..=32).prop_map(|pieces| {
+        let mut data = Vec::new();
+        for (symbols, len, run) in pieces {
+            match run {
+                true => data.extend(std::iter::repeat_n(symbols[0], len)),
+                false => data.extend(symbols.iter().cycle().take(len)),
+            }
+        }
+        data.truncate(MAX_LEN);
+        data
+    })
+}
+
+fn inputs() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        Just(Vec::new()),
+        single_symbol(),
+        all_values(),
+        arbitrary(),
+        repetitive(),
+    ]
+}
+
+fn round_trip(codec: &(impl Compressor + Decompressor), data: &[u8]) -> Result<(), TestCaseError> {
+    let decompressed = codec.decompress(&codec.compress(data));
+    let decompressed = decompressed.map_err(|e| TestCaseError::fail(format!("decompressing failed: {}", e)))?;
+    prop_assert_eq!(decompressed, data);
+    Ok(())
+}
+
+macro_rules! round_trips {
+    ($($name:ident: $codec:expr,)*) => {
+        proptest! {
+            $(
+                #[test]
+                fn $name(data in inputs()) {
+                    round_trip(&$codec, &data)?;
+                }
+            )*
+        }
+    };
+}
+
+round_trips! {
+    huffman: Huffman::new(),
+    huffman_streams: Huffman::with_streams(3),
+    shannon_fano: ShannonFano,
+    adaptive: AdaptiveHuffman,
+    arithmetic: Arithmetic,
+    range: RangeCoder,
+    rans: Rans,
+    tans: Tans,
+    rle: Rle,
+    mtf: Mtf,
+    bwt: Bwt::new(),
+    block_sort: BlockSort::new(),
+    lz77: Lz77::new(),
+    lz78: Lz78::new(),
+    lzss: Lzss::new(),
+    lz4: Lz4,
+    snappy: Snappy,
+    deflate: Deflate,
+    deflate_chain: Deflate::with_chain(4).unwrap(),
+    deflate_dictionary: Deflate::with_dictionary(b"the quick brown fox"),
+    gzip: Gzip,
+    zlib: Zlib,
+    zlib_dictionary: Zlib::with_dictionary(b"the quick brown fox"),
+    ppm: Ppm::new(),
+    delta_filter: DeltaFilter::new(),
+    pipeline: Pipeline::new().then(Rle).then(Huffman::new()),
+}